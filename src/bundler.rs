@@ -1,93 +1,556 @@
+//! Bundling engine: reads the game and mods data, merges the mods and writes the bundle.
+//!
+//! The entry point is [`Bundle::builder`].
+
+mod assets;
+pub mod baseline;
+pub mod cache;
+pub mod classify;
+pub mod compare;
 mod deploy;
-mod diff;
-mod error;
-mod resolve;
-mod structures;
-
-use crate::loader::GlobalData;
-use cursive::{
-    traits::{Finder, Nameable},
-    views::{Dialog, LinearLayout, TextView},
-    Cursive,
+pub mod diff;
+pub(crate) mod encoding;
+pub mod error;
+pub mod preview;
+pub mod progress;
+pub mod report;
+pub mod resolve;
+pub mod rules;
+pub mod structures;
+#[cfg(feature = "ui")]
+mod ui;
+pub mod validate;
+
+#[cfg(feature = "ui")]
+pub use ui::{bundle, start};
+
+use crate::{
+    loader::{Mod, ModProject, ModSource},
+    paths::GameRoot,
+    profile::{MergeStrategy, Output, PreferRules, Resolutions},
+};
+use baseline::Baseline;
+use cache::{CacheRun, CacheStats, DiffCache, ModCache};
+use classify::{ModKind, ModSummary};
+use diff::{
+    DataNode, DataNodeContent, DataTree, DiffNode, DiffTree, DiffTreeExt, DiffTreesExt, ModContent,
 };
-use diff::{DataNode, DataTree, DataTreeExt, DiffTreeExt, ModContent, ResultDiffTressExt};
-use error::ExtractionError;
+use error::{BundlerError, DeploymentError, ExtractionError};
 use log::*;
+use progress::Progress;
+use rayon::prelude::*;
+use resolve::{FailOnConflict, ResolveBackend, Reviewed};
+use rules::ValueRule;
 use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
     fs::read_dir,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
 };
-use thiserror::Error;
-
-#[derive(Debug, Error)]
-#[error("Background thread panicked, stopping: {0}")]
-struct PanicError(String);
-
-pub fn bundle(cursive: &mut Cursive) {
-    let global_data: GlobalData = cursive.take_user_data().expect("No data was set");
-
-    crate::screen(
-        cursive,
-        Dialog::around(
-            LinearLayout::vertical()
-                // Space added so that the view is always rendered, even when this is not specified.
-                .child(TextView::new(" ").with_name("Loading part"))
-                .child(TextView::new(" ").with_name("Loading filename")),
+use validate::ValidationWarning;
+
+/// Merged mods, ready to be deployed.
+pub struct Bundle {
+    /// Files which differ from the vanilla game, keyed by their paths relative to the game root.
+    pub data: DataTree,
+    pub report: report::BundleReport,
+    /// Answers given while resolving the conflicts, to be reused on the next run.
+    pub resolutions: Resolutions,
+    /// Rules to prefer one mod over another, including the ones created in this run.
+    pub rules: PreferRules,
+    /// Changes calculated in this run, if the cache was used; see [`BundleBuilder::cache`].
+    pub cache: Option<DiffCache>,
+    pub cache_stats: CacheStats,
+    /// Game files changed by the bundled mods, to tell later whether the game was updated since this run.
+    pub baseline: Baseline,
+    /// What the bundle changes in the vanilla game, to be reviewed before it is deployed.
+    pub preview: preview::BundlePreview,
+    icon: Option<PathBuf>,
+    projects: Vec<ModProject>,
+    assets: assets::AssetsSummary,
+    progress: Progress,
+}
+
+/// Configuration of the bundling, created by [`Bundle::builder`].
+pub struct BundleBuilder {
+    game: Option<PathBuf>,
+    mods: Vec<Mod>,
+    strategy: MergeStrategy,
+    resolutions: Resolutions,
+    rules: PreferRules,
+    value_rules: Vec<ValueRule>,
+    backend: Box<dyn ResolveBackend>,
+    progress: Option<Progress>,
+    languages: Option<BTreeSet<String>>,
+    cache: Option<DiffCache>,
+    baseline: Option<Baseline>,
+    preview: Option<PathBuf>,
+    skip_broken: bool,
+    collect_unanswered: bool,
+}
+
+impl Bundle {
+    /// Starts the configuration of the bundling.
+    ///
+    /// ```no_run
+    /// use darkest_dungeon_mod_bundler::{profile::MergeStrategy, Bundle};
+    ///
+    /// let bundle = Bundle::builder()
+    ///     .game_root("/games/DarkestDungeon")
+    ///     .add_mod("/games/DarkestDungeon/mods/first")
+    ///     .add_mod("/games/DarkestDungeon/mods/second")
+    ///     .strategy(MergeStrategy::Priority)
+    ///     .run()?;
+    /// println!("{}", bundle.report.render());
+    /// # Ok::<(), darkest_dungeon_mod_bundler::bundler::error::BundlerError>(())
+    /// ```
+    pub fn builder() -> BundleBuilder {
+        BundleBuilder {
+            game: None,
+            mods: vec![],
+            strategy: MergeStrategy::default(),
+            resolutions: Resolutions::new(),
+            rules: PreferRules::new(),
+            value_rules: vec![],
+            backend: Box::new(FailOnConflict),
+            progress: None,
+            languages: None,
+            cache: None,
+            baseline: None,
+            preview: None,
+            skip_broken: false,
+            collect_unanswered: false,
+        }
+    }
+
+    /// Metadata of the bundled mods, in order of their priority.
+    pub fn projects(&self) -> &[ModProject] {
+        &self.projects
+    }
+
+    /// Short description of the binary assets in bundle.
+    pub fn assets_summary(&self) -> String {
+        self.assets.render()
+    }
+
+    /// Writes the bundle as the mod into `mods_root`, replacing the existing one in the same directory.
+    pub fn deploy(self, mods_root: &Path, output: &Output) -> Result<(), DeploymentError> {
+        self.progress.phase("Deploying");
+        deploy::deploy(
+            &self.progress,
+            mods_root,
+            self.data,
+            &self.projects,
+            &self.report,
+            output,
+            self.icon.as_deref(),
         )
-        .title("Loading vanilla game data...")
-        .with_name("Loading dialog"),
-    );
-    info!("Bundling progress dialog shown");
-
-    let on_file_read = cursive.cb_sink().clone();
-    let mut on_error = on_file_read.clone();
-    std::thread::spawn(move || {
-        info!("Starting background thread");
-        let thread = std::thread::spawn(|| {
-            let mut on_file_read = on_file_read;
-            if let Err(err) = do_bundle(&mut on_file_read, global_data) {
-                crate::run_update(&mut on_file_read, move |cursive| {
-                    crate::error(cursive, &err);
-                });
-                std::thread::yield_now(); // to let cursive run update immediately
-            };
-        });
-        info!("Waiting on the background thread");
-        if let Err(panic_info) = thread.join() {
-            let msg = match panic_info.downcast_ref::<&'static str>() {
-                Some(s) => *s,
-                None => match panic_info.downcast_ref::<String>() {
-                    Some(s) => &s[..],
-                    None => "Box<Any>",
+    }
+}
+
+impl BundleBuilder {
+    /// Game installation directory, which holds the vanilla data.
+    pub fn game_root(mut self, path: impl Into<PathBuf>) -> Self {
+        self.game = Some(path.into());
+        self
+    }
+
+    /// Adds the mod from the directory; mods added later override the earlier ones.
+    pub fn add_mod(mut self, path: impl Into<PathBuf>) -> Self {
+        self.mods.push(Mod::load(path.into(), ModSource::Local));
+        self
+    }
+
+    /// Adds the already loaded mods; mods added later override the earlier ones.
+    pub fn mods(mut self, mods: impl IntoIterator<Item = Mod>) -> Self {
+        self.mods.extend(mods);
+        self
+    }
+
+    pub fn strategy(mut self, strategy: MergeStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Answers saved in the previous runs, which are used instead of asking the backend.
+    pub fn resolutions(mut self, resolutions: Resolutions) -> Self {
+        self.resolutions = resolutions;
+        self
+    }
+
+    /// Rules to prefer one mod over another, which are consulted before asking the backend.
+    pub fn rules(mut self, rules: PreferRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Rules deciding the conflicting values by their paths, e.g. taking the largest one;
+    /// they are consulted before the mod priority and the other rules.
+    pub fn value_rules(mut self, rules: Vec<ValueRule>) -> Self {
+        self.value_rules = rules;
+        self
+    }
+
+    /// Source of answers for the conflicts; by default, any unanswered conflict fails the bundling.
+    pub fn backend(mut self, backend: impl ResolveBackend + 'static) -> Self {
+        self.backend = Box::new(backend);
+        self
+    }
+
+    pub fn progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Reuses the changes calculated in the previous run for the files which didn't change since then.
+    ///
+    /// The cache for the next run is returned in [`Bundle::cache`]; the result is the same as without the cache.
+    pub fn cache(mut self, previous: DiffCache) -> Self {
+        self.cache = Some(previous);
+        self
+    }
+
+    /// Game files recorded in the previous run (see [`Bundle::baseline`]), to be checked before anything is read.
+    ///
+    /// If any of them changed, the backend is asked whether to proceed; if so, the saved answers about
+    /// these files are dropped, and otherwise the bundling fails with [`BundlerError::BaselineDrift`].
+    pub fn baseline(mut self, baseline: Baseline) -> Self {
+        self.baseline = Some(baseline);
+        self
+    }
+
+    /// Goes on with the first option for every question about the conflicts which the backend can't answer,
+    /// so that the bundling fails with all of them listed in [`BundlerError::Unanswered`], not only the first one.
+    pub fn collect_unanswered(mut self, collect: bool) -> Self {
+        self.collect_unanswered = collect;
+        self
+    }
+
+    /// Leaves out the mod files which can't be parsed without asking the backend, listing them in the report.
+    ///
+    /// By default, the backend chooses whether to skip every such file, the whole mod or nothing.
+    pub fn skip_broken(mut self, skip: bool) -> Self {
+        self.skip_broken = skip;
+        self
+    }
+
+    /// Image to be used as the preview of the bundle in game; by default, a plain placeholder is used.
+    pub fn preview_icon(mut self, path: Option<PathBuf>) -> Self {
+        self.preview = path;
+        self
+    }
+
+    /// Languages to keep in the bundled localization; by default, every language is kept.
+    pub fn languages(mut self, languages: Option<BTreeSet<String>>) -> Self {
+        self.languages = languages;
+        self
+    }
+
+    /// Reads and merges everything, asking the backend about the conflicts.
+    pub fn run(self) -> Result<Bundle, BundlerError> {
+        let path = self.game.ok_or(BundlerError::NoGameRoot)?;
+        let game = GameRoot::new(&path).ok_or(BundlerError::NoGame(path))?;
+        // Nobody listens to the events if the progress wasn't requested explicitly.
+        let progress = self.progress.unwrap_or_else(|| Progress::new().0);
+        let mut backend = self.backend;
+        let mut resolutions = self.resolutions;
+        let drift = match &self.baseline {
+            Some(baseline) => check_baseline(backend.as_mut(), baseline, &game, &mut resolutions)?,
+            None => vec![],
+        };
+        // Problems found while reading the mods; they go into the report along with the validation results.
+        // The same problems in the game itself are only logged, since the user can't do anything about them.
+        let mut read_warnings = vec![];
+        let original_data = extract_game(&progress, &game, &mut vec![])?;
+
+        progress.phase("Loading mods data");
+        let mut known_paths = PathCase::new(&original_data);
+        let mut cache = self.cache.map(CacheRun::new);
+        let mut skipped = vec![];
+        let ask_broken = !self.skip_broken;
+        // File names depend on the previous mods, so they are listed one by one,
+        // but then every mod is compared with the game independently of the others.
+        let prepared: Vec<_> = self
+            .mods
+            .iter()
+            .map(|the_mod| {
+                info!("Extracting data from selected mod: {}", the_mod.name());
+                let no_skip = BTreeSet::new();
+                prepare_mod(
+                    the_mod,
+                    &original_data,
+                    &mut known_paths,
+                    cache.as_mut(),
+                    None,
+                    &no_skip,
+                )
+            })
+            .collect();
+        let first_pass: Vec<_> = prepared
+            .into_par_iter()
+            .map(|prepared| prepared?.extract(&progress, &original_data))
+            .collect();
+        // The results are taken in the mods order, so that the bundle doesn't depend on which mod was read first.
+        // Every mod is processed even if some of them fail, so that all the errors are reported at once.
+        // Mod directories by their names, to drop the cached changes of the mod removed while reviewing the conflicts.
+        let mod_paths: BTreeMap<String, PathBuf> = self
+            .mods
+            .iter()
+            .map(|the_mod| (the_mod.name().to_owned(), the_mod.path.clone()))
+            .collect();
+        let extracted: Vec<Option<(ModContent, ModSummary, ModProject)>> =
+            ExtractionError::collect(self.mods.into_iter().zip(first_pass).map(
+                |(the_mod, mut result)| {
+                    let project = the_mod.project().clone();
+                    let mut skip = BTreeSet::new();
+                    loop {
+                        let failed = match result {
+                            Ok(extracted) => {
+                                let (content, summary) =
+                                    extracted.finish(cache.as_mut(), &mut read_warnings);
+                                return Ok(Some((content, summary, project)));
+                            }
+                            Err(failed) => failed,
+                        };
+                        if !skip_malformed(
+                            backend.as_mut(),
+                            ask_broken,
+                            the_mod.name(),
+                            failed.error,
+                            &mut skip,
+                            &mut skipped,
+                        )? {
+                            return Ok(None);
+                        }
+                        result = prepare_mod(
+                            &the_mod,
+                            &original_data,
+                            &mut known_paths,
+                            cache.as_mut(),
+                            failed.cache,
+                            &skip,
+                        )
+                        .map_err(FailedMod::from)
+                        .and_then(|prepared| prepared.extract(&progress, &original_data));
+                    }
                 },
+            ))?;
+        let (mut cache, cache_stats) = match cache {
+            Some(cache) => {
+                let (cache, stats) = cache.finish();
+                info!("Cache: {:?}", stats);
+                (Some(cache), stats)
             }
-            .to_string();
-            crate::run_update(&mut on_error, move |cursive| {
-                crate::error(cursive, &PanicError(msg));
+            None => (None, CacheStats::default()),
+        };
+
+        let mut mods = vec![];
+        let mut summaries = vec![];
+        let mut projects = vec![];
+        for (content, summary, project) in extracted.into_iter().flatten() {
+            mods.push(content);
+            summaries.push(summary);
+            projects.push(project);
+        }
+        // Mods are kept aside only if the user can remove some of them after seeing the conflicts.
+        let reviewing = self.strategy != MergeStrategy::Priority && backend.can_review();
+        let (mut report, baseline, merged, mut conflicts, collisions, mut resolver) = loop {
+            let mut report = report::BundleReport::new(&mods);
+            report.contents = summaries.clone();
+            report.skipped = skipped.clone();
+            report.drift = drift.clone();
+            let baseline = Baseline::record(game.path(), &report.files);
+            let kept = if reviewing { Some(mods.clone()) } else { None };
+            let (merged, mut conflicts) = mods.into_iter().merge_with_progress(&progress)?;
+            info!("Merged mods data, got {} conflicts", conflicts.len());
+
+            let collisions: Vec<_> = conflicts
+                .iter()
+                .filter_map(|(path, conflict)| {
+                    assets::AssetCollision::from_conflict(path, conflict)
+                })
+                .collect();
+            let mut resolver = resolve::Resolver::new(
+                backend,
+                self.strategy,
+                resolutions.clone(),
+                self.rules.clone(),
+            )
+            .with_previews(&original_data, &merged, &conflicts)
+            .with_value_rules(self.value_rules.clone())
+            .collecting_unanswered(self.collect_unanswered);
+            let combined = resolve::combine_chains(&mut resolver, &original_data, &mut conflicts);
+            let merged = resolve::merge_resolved(merged, combined);
+            let decided = resolve::resolve_by_rules(&mut resolver, &mut conflicts);
+            let merged = resolve::merge_resolved(merged, decided);
+            match resolve::review(&mut resolver, &mut conflicts)? {
+                Reviewed::Resolved(reviewed) => {
+                    let merged = resolve::merge_resolved(merged, reviewed);
+                    break (report, baseline, merged, conflicts, collisions, resolver);
+                }
+                Reviewed::RemoveMod(name) => {
+                    info!("Merging again without {}", name);
+                    let mut kept = kept.expect("Mods are kept while reviewing the conflicts");
+                    let index = kept.iter().position(|the_mod| the_mod.name() == name);
+                    if let Some(index) = index {
+                        kept.remove(index);
+                        summaries.remove(index);
+                        projects.remove(index);
+                    }
+                    // Nothing of the removed mod may be left in the report or in the cache for the next run.
+                    skipped.retain(|file| file.mod_name != name);
+                    read_warnings.retain(|warning| warning.mods != [name.as_str()]);
+                    if let (Some(cache), Some(path)) = (cache.as_mut(), mod_paths.get(&name)) {
+                        cache.remove_mod(path);
+                    }
+                    mods = kept;
+                    backend = resolver.into_backend();
+                }
+            }
+        };
+        let assets = resolve::resolve_assets(&mut resolver, &mut conflicts)?;
+        let merged = resolve::merge_resolved(merged, assets);
+        let resolved = resolve::resolve(&mut resolver, conflicts)?;
+        let merged = resolve::merge_resolved(merged, resolved);
+        let unanswered = resolver.take_unanswered();
+        if !unanswered.is_empty() {
+            return Err(BundlerError::Unanswered(unanswered));
+        }
+        let (resolutions, rules, resolved) = resolver.finish();
+        report.conflicts = resolved;
+        let assets = assets::AssetsSummary::new(&merged, collisions);
+        info!("Assets summary:\n{}", assets.render());
+
+        info!("Applying patches");
+        let mut data = merged.apply_to(&original_data).map_err(|err| {
+            // Changes are already merged, so the culprits are the mods which changed the file.
+            let mods = report
+                .files
+                .iter()
+                .find(|file| file.path == err.context.target)
+                .map(|file| file.mods.clone())
+                .unwrap_or_default();
+            err.in_mods(mods.iter().map(String::as_str))
+        })?;
+        if let Some(languages) = &self.languages {
+            read_warnings.extend(retain_languages(&mut data, languages));
+        }
+
+        progress.check()?;
+        progress.phase("Validating merged data");
+        report.warnings = read_warnings;
+        let mut warnings = validate::validate(&original_data, &data);
+        for warning in &mut warnings {
+            if let Some(file) = report.files.iter().find(|file| file.path == warning.path) {
+                warning.mods = file.mods.clone();
+            }
+        }
+        report.warnings.extend(warnings);
+        let preview = preview::BundlePreview::new(&original_data, &data, &report);
+        Ok(Bundle {
+            data,
+            report,
+            resolutions,
+            rules,
+            cache,
+            cache_stats,
+            baseline,
+            preview,
+            icon: self.preview,
+            projects,
+            assets,
+            progress,
+        })
+    }
+}
+
+/// Filters the languages in every bundled string table, see [`StringsTable::retain_languages`].
+///
+/// [`StringsTable::retain_languages`]: structures::StringsTable::retain_languages
+fn retain_languages(data: &mut DataTree, languages: &BTreeSet<String>) -> Vec<ValidationWarning> {
+    let mut warnings = vec![];
+    for (path, node) in data.iter_mut() {
+        let text = match node.content() {
+            DataNodeContent::Text(text) if structures::is_strings_table(path) => text,
+            _ => continue,
+        };
+        let mut table = match structures::StringsTable::parse(text) {
+            Ok(table) => table,
+            Err(err) => {
+                warn!(
+                    "{:?}: failed to parse, keeping all languages: {}",
+                    path, err
+                );
+                continue;
+            }
+        };
+        for (language, ids) in table.retain_languages(languages) {
+            info!(
+                "{:?}: {} entries missing in {} are taken from English",
+                path,
+                ids.len(),
+                language
+            );
+            warnings.push(ValidationWarning {
+                path: path.clone(),
+                item: format!("[{}]", language),
+                message: format!(
+                    "{} entries are missing, English text is used: {}",
+                    ids.len(),
+                    ids.join(", ")
+                ),
+                mods: vec![],
             });
-        } else {
-            info!("Background thread exited successfully");
         }
-    });
+        *node = DataNode::new(node.absolute(), table.to_string());
+    }
+    warnings
+}
+
+/// Calculates the changes the single mod makes to the vanilla game (along with DLCs).
+pub fn diff_mod(
+    game_root: impl Into<PathBuf>,
+    mod_path: impl Into<PathBuf>,
+) -> Result<DiffTree, BundlerError> {
+    let path = game_root.into();
+    let game = GameRoot::new(&path).ok_or(BundlerError::NoGame(path))?;
+    let (progress, _) = Progress::new();
+    let mut warnings = vec![];
+    let original_data = extract_game(&progress, &game, &mut warnings)?;
+    let the_mod = Mod::load(mod_path.into(), ModSource::Local);
+    let mut known_paths = PathCase::new(&original_data);
+    let (the_mod, _) = extract_mod(
+        &progress,
+        &the_mod,
+        &original_data,
+        &mut known_paths,
+        None,
+        &mut warnings,
+        &BTreeSet::new(),
+    )?;
+    Ok(the_mod.into_diff())
 }
 
-fn do_bundle(
-    on_file_read: &mut cursive::CbSink,
-    global_data: GlobalData,
-) -> Result<(), error::BundlerError> {
-    let path = crate::paths::game(&global_data.base_path);
+/// Extracts the vanilla game data along with every installed DLC, to be used as the diff baseline.
+fn extract_game(
+    progress: &Progress,
+    game: &GameRoot,
+    warnings: &mut Vec<ValidationWarning>,
+) -> Result<DataTree, ExtractionError> {
+    let path = game.path();
     info!("Extracting data from game directory");
-    let mut original_data = extract_data(on_file_read, &path, &path, true)?;
+    progress.phase("Loading vanilla game data");
+    let mut original_data = extract_data(progress, path, warnings)?;
     info!("Vanilla game data extracted");
 
-    crate::run_update(on_file_read, |cursive| {
-        cursive.call_on_name("Loading dialog", |dialog: &mut Dialog| {
-            dialog.set_title("Loading DLC data...");
-        });
-    });
+    progress.phase("Loading DLC data");
 
     info!("Extracting DLC data");
-    let dlc_path = path.join("dlc");
+    let dlc_path = game.dlc();
+    if !dlc_path.is_dir() {
+        info!("No DLC directory found, using vanilla data only");
+        return Ok(original_data);
+    }
     for entry in read_dir(&dlc_path).map_err(ExtractionError::from_io(&dlc_path))? {
         let entry = entry.map_err(ExtractionError::from_io(&dlc_path))?;
         let path = entry.path();
@@ -105,95 +568,415 @@ fn do_bundle(
                     "<INVALID>".into()
                 })
                 .to_string();
-            crate::run_update(on_file_read, |cursive| {
-                cursive
-                    .call_on_name("Loading part", |text: &mut TextView| {
-                        text.set_content(dlc_dir_name);
-                    })
-                    .unwrap();
-            });
-            original_data.extend(extract_data(on_file_read, &path, &path, true)?);
+            progress.part(dlc_dir_name);
+            original_data.extend(extract_data(progress, &path, warnings)?);
         } else {
             warn!("Found non-directory item in DLC folder: {:?}", path);
         }
     }
     info!("DLC data extracted and merged into vanilla game");
+    Ok(original_data)
+}
 
-    crate::run_update(on_file_read, |cursive| {
-        cursive.call_on_name("Loading dialog", |dialog: &mut Dialog| {
-            dialog.set_title("Loading workshop data...");
-            dialog.call_on_name("Loading part", |text: &mut TextView| {
-                text.set_content(" ");
-            })
-        });
-    });
+/// Casing of every known file path, keyed by the normalized path.
+///
+/// Mods built on Windows often use different casing (e.g. `heroes/Crusader`), which the game doesn't care about,
+/// but which would make the same file look like two different ones for us.
+struct PathCase(HashMap<String, PathBuf>);
 
-    info!("Reading selected mods");
-    let mut for_mods_extract = on_file_read.clone();
-    let mods = global_data
-        .mods
-        .into_iter()
-        .inspect(|the_mod| info!("Reading mod: {:?}", the_mod))
-        .filter(|the_mod| the_mod.selected)
-        .map(|the_mod| {
-            info!("Extracting data from selected mod: {}", the_mod.name());
-            extract_mod(&mut for_mods_extract, the_mod, &original_data)
-        });
-
-    let (merged, conflicts) = mods.try_merge(Some(on_file_read))?;
-    info!("Merged mods data, got {} conflicts", conflicts.len());
-
-    let resolved = resolve::resolve(on_file_read, conflicts);
-    let merged = resolve::merge_resolved(merged, resolved);
-
-    info!("Applying patches");
-    let modded = merged.apply_to(original_data);
-
-    crate::run_update(on_file_read, |cursive| {
-        cursive.call_on_name("Loading dialog", |dialog: &mut Dialog| {
-            dialog.set_title("Deploying...");
-        });
-    });
+impl PathCase {
+    fn new(original_data: &DataTree) -> Self {
+        Self(
+            original_data
+                .keys()
+                .map(|path| (Self::normalize(path), path.clone()))
+                .collect(),
+        )
+    }
 
-    info!("Deploying generated mod to the \"mods\" directory");
-    let mods_path = path.join("mods");
-    deploy::deploy(on_file_read, &mods_path, modded)?;
+    fn normalize(path: &Path) -> String {
+        path.to_string_lossy().replace('\\', "/").to_lowercase()
+    }
 
-    crate::run_update(on_file_read, |cursive| {
-        crate::screen(
-            cursive,
-            Dialog::around(TextView::new("Bundle ready!")).button("OK", Cursive::quit),
-        );
-    });
-    Ok(())
+    /// Renames the mod files, given by their absolute paths, to the casing seen first -
+    /// in the game itself or in the previous mods.
+    fn apply(
+        &mut self,
+        mod_name: &str,
+        files: BTreeMap<PathBuf, PathBuf>,
+    ) -> BTreeMap<PathBuf, PathBuf> {
+        let mut renamed = BTreeMap::new();
+        for (path, absolute) in files {
+            let known = self
+                .0
+                .entry(Self::normalize(&path))
+                .or_insert_with(|| path.clone())
+                .clone();
+            if known != path && !renamed.contains_key(&known) {
+                debug!("Mod {}: using path {:?} for {:?}", mod_name, known, path);
+            }
+            if let Some(previous) = renamed.get(&known) {
+                warn!(
+                    "Mod {}: files {:?} and {:?} differ only by case, using the first one",
+                    mod_name, previous, absolute
+                );
+                continue;
+            }
+            renamed.insert(known, absolute);
+        }
+        renamed
+    }
 }
 
+/// Reads the mod and calculates its changes to the game.
 fn extract_mod(
-    on_file_read: &mut cursive::CbSink,
-    the_mod: crate::loader::Mod,
+    progress: &Progress,
+    the_mod: &Mod,
+    original_data: &DataTree,
+    known_paths: &mut PathCase,
+    mut cache: Option<&mut CacheRun>,
+    warnings: &mut Vec<ValidationWarning>,
+    skip: &BTreeSet<PathBuf>,
+) -> Result<(ModContent, ModSummary), ExtractionError> {
+    let prepared = prepare_mod(
+        the_mod,
+        original_data,
+        known_paths,
+        cache.as_deref_mut(),
+        None,
+        skip,
+    )?;
+    let extracted = prepared
+        .extract(progress, original_data)
+        .map_err(|failed| failed.error)?;
+    Ok(extracted.finish(cache, warnings))
+}
+
+/// Mod whose files are listed and named after the game and the previous mods, ready to be compared with the game.
+struct PreparedMod {
+    name: String,
+    files: BTreeMap<PathBuf, PathBuf>,
+    summary: ModSummary,
+    /// Whether the files are to be compared with the game, and not just copied.
+    compare: bool,
+    cache: Option<ModCache>,
+}
+
+/// Lists the mod files; this depends on the mods listed before, so the mods are prepared one by one.
+///
+/// If the mod is read again after the failed attempt, the cache of that attempt is passed as `restarted`,
+/// since its previous results were already taken from the shared cache.
+fn prepare_mod(
+    the_mod: &Mod,
     original_data: &DataTree,
-) -> Result<ModContent, ExtractionError> {
-    let title = the_mod.name().to_owned();
-    crate::run_update(on_file_read, move |cursive| {
-        cursive.call_on_name("Loading part", |text: &mut TextView| {
-            text.set_content(title);
-        });
+    known_paths: &mut PathCase,
+    cache: Option<&mut CacheRun>,
+    restarted: Option<Box<ModCache>>,
+    skip: &BTreeSet<PathBuf>,
+) -> Result<PreparedMod, ExtractionError> {
+    let files = mod_files(&the_mod.path)?;
+    let mut files = known_paths.apply(the_mod.name(), files);
+    files.retain(|path, _| !skip.contains(path));
+    let summary = ModSummary::new(the_mod.name(), files.keys().map(PathBuf::as_path));
+    info!("Mod {}: {}", the_mod.name(), summary.describe());
+    // Assets are never compared with the game, so there's nothing to calculate;
+    // unless the mod puts the binary over the game's text file, which is the error reported by diff.
+    let over_text = files.keys().any(|path| {
+        matches!(
+            original_data.get(path).map(DataNode::content),
+            Some(DataNodeContent::Text(_))
+        )
     });
-    let content = extract_data(on_file_read, &the_mod.path, &the_mod.path, true)?;
-    info!(
-        "Mod {}: Data successfully extracted, calculating patch",
-        the_mod.name()
+    let compare = summary.kind == ModKind::Data || over_text;
+    let cache = match cache {
+        Some(cache) if compare => Some(match restarted {
+            Some(restarted) => restarted.restart(),
+            None => cache.start_mod(&the_mod.path, original_data, files.keys()),
+        }),
+        _ => None,
+    };
+    Ok(PreparedMod {
+        name: the_mod.name().to_owned(),
+        files,
+        summary,
+        compare,
+        cache,
+    })
+}
+
+/// Changes made by the mod, along with the problems found while reading it.
+struct ExtractedMod {
+    content: ModContent,
+    summary: ModSummary,
+    warnings: Vec<ValidationWarning>,
+    cache: Option<ModCache>,
+}
+
+/// Mod which couldn't be read, along with its cache, to be reused if the mod is read again.
+struct FailedMod {
+    error: ExtractionError,
+    cache: Option<Box<ModCache>>,
+}
+
+impl From<ExtractionError> for FailedMod {
+    fn from(error: ExtractionError) -> Self {
+        Self { error, cache: None }
+    }
+}
+
+impl PreparedMod {
+    /// Calculates the changes to the game.
+    ///
+    /// Every file is compared with the game right after it is read, and then dropped, so that only the changes
+    /// are kept in memory, and never the whole mod.
+    fn extract(
+        self,
+        progress: &Progress,
+        original_data: &DataTree,
+    ) -> Result<ExtractedMod, FailedMod> {
+        let Self {
+            name,
+            files,
+            summary,
+            compare,
+            cache,
+        } = self;
+        progress.part(&name);
+        if !compare {
+            info!("Mod {}: No data files, copying everything as is", name);
+            let diff = files
+                .into_iter()
+                .map(|(path, absolute)| (path, DiffNode::Binary(absolute)))
+                .collect();
+            return Ok(ExtractedMod {
+                content: ModContent::new(&name, diff),
+                summary,
+                warnings: vec![],
+                cache,
+            });
+        }
+        info!("Mod {}: calculating patch file by file", name);
+        let total = files.len();
+        let done = AtomicUsize::new(0);
+        let results: Vec<_> = files
+            .into_par_iter()
+            .map(|(path, absolute)| {
+                progress.check()?;
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                let (node, file_warnings) = read_file(progress, &path, &absolute, (done, total))
+                    .map_err(ExtractionError::from_io(&absolute))?;
+                let compare = |node| diff::diff_file(original_data, &path, node);
+                let node = match &cache {
+                    Some(cache) => cache.diff(&path, node, compare),
+                    None => compare(node),
+                }?;
+                Ok((path, node, file_warnings))
+            })
+            .collect();
+        let results: Vec<_> = match ExtractionError::collect(results) {
+            Ok(results) => results,
+            Err(err) => {
+                return Err(FailedMod {
+                    error: err.in_mod(&name),
+                    cache: cache.map(Box::new),
+                })
+            }
+        };
+        let mut warnings = vec![];
+        let diff = results
+            .into_iter()
+            .map(|(path, node, file_warnings)| {
+                warnings.extend(file_warnings.into_iter().map(|mut warning| {
+                    warning.mods = vec![name.clone()];
+                    warning
+                }));
+                (path, node)
+            })
+            .collect();
+        info!("Mod {}: Patch calculated", name);
+        Ok(ExtractedMod {
+            content: ModContent::new(&name, diff),
+            summary,
+            warnings,
+            cache,
+        })
+    }
+}
+
+impl ExtractedMod {
+    /// Stores the results in the shared cache and warnings, which is done in the mods order.
+    fn finish(
+        self,
+        cache: Option<&mut CacheRun>,
+        warnings: &mut Vec<ValidationWarning>,
+    ) -> (ModContent, ModSummary) {
+        if let (Some(cache), Some(mod_cache)) = (cache, self.cache) {
+            cache.finish_mod(mod_cache);
+        }
+        warnings.extend(self.warnings);
+        (self.content, self.summary)
+    }
+}
+
+/// Lists the files of the mod, keyed by their paths relative to the mod directory.
+fn mod_files(dir: &Path) -> Result<BTreeMap<PathBuf, PathBuf>, ExtractionError> {
+    let mut files = vec![];
+    list_files(dir, true, &mut files)?;
+    Ok(files
+        .into_iter()
+        .filter_map(|path| Some((path.strip_prefix(dir).ok()?.to_owned(), path)))
+        .collect())
+}
+
+/// Asks what to do with the mod files which can't be parsed, e.g. since they were left half-downloaded.
+///
+/// Returns `true` if the mod should be read again without these files, and `false` if the whole mod is skipped.
+/// The error is returned as is if the mod has other problems, or if the user chose to abort.
+/// If the backend isn't to be asked, every such file is skipped.
+fn skip_malformed(
+    backend: &mut dyn ResolveBackend,
+    ask: bool,
+    mod_name: &str,
+    err: ExtractionError,
+    skip: &mut BTreeSet<PathBuf>,
+    skipped: &mut Vec<report::SkippedFile>,
+) -> Result<bool, ExtractionError> {
+    let malformed: Vec<_> = match err.malformed() {
+        Some(malformed) => malformed
+            .into_iter()
+            .map(|file| {
+                (
+                    file.context.target.clone(),
+                    file.kind.to_string(),
+                    file.to_string(),
+                )
+            })
+            .collect(),
+        None => return Err(err),
+    };
+    let options = [
+        "Skip this file".to_owned(),
+        "Skip the whole mod".to_owned(),
+        "Abort".to_owned(),
+    ];
+    let mut whole_mod = false;
+    for (_, _, description) in &malformed {
+        let question = format!(
+            "{}\n\nThe file might be damaged or left half-downloaded; try verifying the mod files.",
+            description
+        );
+        let choice = if ask {
+            backend.choose(&question, &options)
+        } else {
+            Ok(0)
+        };
+        match choice {
+            Ok(0) => {}
+            Ok(1) => {
+                whole_mod = true;
+                break;
+            }
+            _ => return Err(err),
+        }
+    }
+    if whole_mod {
+        warn!(
+            "Mod {}: skipped, since some of its files are broken",
+            mod_name
+        );
+    }
+    skipped.extend(malformed.into_iter().map(|(path, reason, _)| {
+        if !whole_mod {
+            warn!("Mod {}: skipped broken file {:?}", mod_name, path);
+            skip.insert(path.clone());
+        }
+        report::SkippedFile {
+            path,
+            mod_name: mod_name.to_owned(),
+            reason,
+            whole_mod,
+        }
+    }));
+    Ok(!whole_mod)
+}
+
+/// Checks whether the game files changed since the baseline was recorded, asking whether to proceed if so.
+///
+/// When proceeding, the saved answers about the changed files are dropped, so that they're asked again;
+/// the changes of the mods to these files are calculated again anyway, since the cache tracks the game files too.
+fn check_baseline(
+    backend: &mut dyn ResolveBackend,
+    baseline: &Baseline,
+    game: &GameRoot,
+    resolutions: &mut Resolutions,
+) -> Result<Vec<baseline::DriftedFile>, BundlerError> {
+    let drift = baseline.drift(game.path(), resolutions);
+    if drift.is_empty() {
+        return Ok(drift);
+    }
+    warn!(
+        "[baseline] {} game files changed since the last run:{}",
+        drift.len(),
+        baseline::render(&drift)
     );
-    Ok(ModContent::new(the_mod.name(), original_data.diff(content)))
+    let question = format!(
+        "These game files changed since the profile was saved, probably due to the game update:{}\n\n\
+        Changes of the listed mods to them will be calculated again, and the saved answers about them will be asked again.",
+        baseline::render(&drift)
+    );
+    let options = [
+        "Proceed with the changed game files".to_owned(),
+        "Abort".to_owned(),
+    ];
+    match backend.choose(&question, &options) {
+        Ok(0) => {
+            for file in &drift {
+                for question in &file.resolutions {
+                    info!("[baseline] Dropping saved answer: {:?}", question);
+                    resolutions.remove(question);
+                }
+            }
+            Ok(drift)
+        }
+        _ => Err(BundlerError::BaselineDrift(drift)),
+    }
 }
 
+/// Reads every data file in the directory in parallel, reporting the progress file-by-file.
 fn extract_data(
-    on_file_read: &mut cursive::CbSink,
+    progress: &Progress,
     base_path: &Path,
+    warnings: &mut Vec<ValidationWarning>,
+) -> Result<DataTree, ExtractionError> {
+    let mut files = vec![];
+    list_files(base_path, true, &mut files)?;
+    let total = files.len();
+    let done = AtomicUsize::new(0);
+    let results: Vec<_> = files
+        .into_par_iter()
+        .map(|path| {
+            progress.check()?;
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            extract_from_file(progress, base_path, &path, (done, total))
+                .map_err(ExtractionError::from_io(&path))
+        })
+        .collect();
+    let files: Vec<_> = ExtractionError::collect(results)?;
+    Ok(files
+        .into_iter()
+        .map(|(path, node, file_warnings)| {
+            warnings.extend(file_warnings);
+            (path, node)
+        })
+        .collect())
+}
+
+fn list_files(
     cur_path: &Path,
     root: bool,
-) -> Result<DataTree, ExtractionError> {
-    info!("Extracting data from: {:?}", cur_path);
+    files: &mut Vec<PathBuf>,
+) -> Result<(), ExtractionError> {
+    info!("Listing data files in: {:?}", cur_path);
     let items = read_dir(cur_path)
         .map_err(ExtractionError::from_io(cur_path))?
         .map(|entry| {
@@ -206,71 +989,30 @@ fn extract_data(
         })
         .collect::<Result<Vec<_>, _>>()
         .map_err(ExtractionError::from_io(cur_path))?;
-    let items = items
-        .into_iter()
-        .map(|(item_path, meta)| {
-            if meta.is_dir() {
-                if item_path.file_name().and_then(std::ffi::OsStr::to_str) == Some("dlc") {
-                    debug!("Skipping DLC directory");
-                    Ok(vec![])
-                } else {
-                    debug!("Descending into child directory {:?}", item_path);
-                    extract_data(on_file_read, base_path, &item_path, false)
-                        .map(|data| data.into_iter().collect())
-                }
-            } else if root {
-                debug!("Skipping file in root: {:?}", item_path);
-                // Special case - don't extract anything from root folder (there is no data there)
-                Ok(vec![])
+    for (item_path, meta) in items {
+        if meta.is_dir() {
+            if item_path.file_name().and_then(std::ffi::OsStr::to_str) == Some("dlc") {
+                debug!("Skipping DLC directory");
             } else {
-                extract_from_file(on_file_read, base_path, &item_path)
-                    .map(|(path, data)| vec![(path, data)])
-                    .map_err(ExtractionError::from_io(&item_path))
+                debug!("Descending into child directory {:?}", item_path);
+                list_files(&item_path, false, files)?;
             }
-        })
-        .collect::<Result<Vec<Vec<_>>, _>>()?;
-    Ok(items.into_iter().flatten().collect())
-}
-
-fn set_file_updated(
-    on_file_read: &mut cursive::CbSink,
-    prefix: impl Into<String>,
-    path: impl Into<String>,
-) {
-    const LOG_PATH_LEN: usize = 120;
-
-    let prefix = prefix.into();
-    let path = path.into();
-
-    crate::run_update(on_file_read, move |cursive: &mut Cursive| {
-        cursive.call_on_name("Loading filename", |text: &mut TextView| {
-            let mut path = path;
-            let log_path: String = if path.len() < LOG_PATH_LEN {
-                path.chars()
-                    .chain(std::iter::repeat(' '))
-                    .take(LOG_PATH_LEN)
-                    .collect()
-            } else {
-                // https://users.rust-lang.org/t/take-last-n-characters-from-string/44638
-                let len = path
-                    .char_indices()
-                    .rev()
-                    .nth((LOG_PATH_LEN - 3) - 1)
-                    .map_or(0, |(idx, _)| idx);
-                let _ = path.drain(0..len);
-                format!("...{}", path)
-            };
-            text.set_content(format!("{}: <ROOT>/{}", prefix, log_path));
-        });
-    });
+        } else if root {
+            debug!("Skipping file in root: {:?}", item_path);
+            // Special case - don't extract anything from root folder (there is no data there)
+        } else {
+            files.push(item_path);
+        }
+    }
+    Ok(())
 }
 
 fn extract_from_file(
-    on_file_read: &mut cursive::CbSink,
+    progress: &Progress,
     base_path: &Path,
     path: &Path,
-) -> std::io::Result<(PathBuf, DataNode)> {
-    info!("Reading file: {:?}", path);
+    (done, total): (usize, usize),
+) -> std::io::Result<(PathBuf, DataNode, Vec<ValidationWarning>)> {
     let rel_path = path.strip_prefix(base_path).map_err(|_| {
         std::io::Error::new(
             std::io::ErrorKind::InvalidInput,
@@ -280,39 +1022,242 @@ fn extract_from_file(
             ),
         )
     })?;
-    let log_path = rel_path.to_string_lossy();
-    set_file_updated(on_file_read, "Reading", log_path);
-
-    let content = match path.extension().and_then(std::ffi::OsStr::to_str) {
-        Some("js") | Some("darkest") | Some("xml") | Some("json") | Some("txt") => {
-            match std::fs::read_to_string(path).map(Some) {
-                Ok(s) => {
-                    debug!("Read successful: {:?}", path);
-                    s.as_ref().map(|s| {
-                        debug!(
-                            "Total {} lines, {} characters",
-                            s.lines().count(),
-                            s.chars().count()
-                        )
-                    });
-                    Ok(s)
-                }
-                Err(err) if err.kind() == std::io::ErrorKind::InvalidData => {
-                    debug!(
-                        "Read unsuccessful, non-UTF8 data; asserting that {:?} is a binary file",
-                        path
-                    );
-                    Ok(None)
-                }
-                err => err,
-            }?
-        }
-        _ => {
+    let (node, warnings) = read_file(progress, rel_path, path, (done, total))?;
+    Ok((rel_path.into(), node, warnings))
+}
+
+/// Reads the file as text, if it's one of the data files, or just records its path otherwise.
+fn read_file(
+    progress: &Progress,
+    rel_path: &Path,
+    path: &Path,
+    (done, total): (usize, usize),
+) -> std::io::Result<(DataNode, Vec<ValidationWarning>)> {
+    info!("Reading file: {:?}", path);
+    progress.file("Reading", rel_path.to_string_lossy(), done, total);
+
+    let extension = path.extension().and_then(std::ffi::OsStr::to_str);
+    let mut warnings = vec![];
+    let content = match extension {
+        _ if classify::is_data_file(path) => {
+            let decoded = encoding::decode(&std::fs::read(path)?, extension == Some("xml"));
+            debug!("Read successful: {:?}, encoding {}", path, decoded.encoding);
             debug!(
-                "File extension is not in white-list (js,json,xml,txt,darkest), loading as binary"
+                "Total {} lines, {} characters",
+                decoded.text.lines().count(),
+                decoded.text.chars().count()
             );
+            if decoded.guessed {
+                warn!(
+                    "{:?} is not valid UTF-8, decoded as {}",
+                    path, decoded.encoding
+                );
+                warnings.push(ValidationWarning {
+                    path: rel_path.into(),
+                    item: "-".into(),
+                    message: format!(
+                        "not valid UTF-8, decoded as {}; some characters may be wrong",
+                        decoded.encoding
+                    ),
+                    mods: vec![],
+                });
+            }
+            if structures::is_strings_table(rel_path) {
+                warnings.extend(strings_duplicates(rel_path, &decoded.text));
+            }
+            Some(decoded.text)
+        }
+        _ => {
+            if classify::is_asset(path) {
+                debug!("Not a data file, loading as asset");
+            } else {
+                debug!("Unrecognized file type, loading as binary");
+            }
             None
         }
     };
-    Ok((rel_path.into(), DataNode::new(path, content)))
+    Ok((DataNode::new(path, content), warnings))
+}
+
+/// Lists the strings which are defined several times with different text.
+///
+/// These are not conflicts, since the game always uses the first definition, but the author
+/// probably meant something else.
+fn strings_duplicates(path: &Path, text: &str) -> Vec<ValidationWarning> {
+    // Broken tables are reported when diffing, no need to do it twice.
+    let duplicates = match structures::StringsTable::parse_with_duplicates(text) {
+        Ok((_, duplicates)) => duplicates,
+        Err(_) => return vec![],
+    };
+    duplicates
+        .into_iter()
+        .map(|duplicate| {
+            warn!(
+                "{:?}: {} is defined several times, only the first one is used",
+                path, duplicate.path
+            );
+            ValidationWarning {
+                path: path.into(),
+                item: duplicate.path.to_string(),
+                message: format!(
+                    "defined several times, \"{}\" is used instead of \"{}\"",
+                    duplicate.kept, duplicate.ignored
+                ),
+                mods: vec![],
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(dir: &Path, files: usize) {
+        for index in 0..files {
+            let hero_dir = dir.join("heroes").join(format!("hero_{}", index / 10));
+            std::fs::create_dir_all(&hero_dir).unwrap();
+            let content: String = (0..500)
+                .map(|line| format!("combat_skill: .id \"skill_{}\" .level {}\n", line, index))
+                .collect();
+            std::fs::write(hero_dir.join(format!("file_{}.darkest", index)), content).unwrap();
+        }
+    }
+
+    #[test]
+    fn extraction_collects_all_errors() {
+        let (progress, _events) = Progress::new();
+        let results = vec![
+            Ok(1),
+            Err(ExtractionError::Io(
+                std::io::ErrorKind::NotFound.into(),
+                "first".into(),
+            )),
+            Err(ExtractionError::Io(
+                std::io::ErrorKind::NotFound.into(),
+                "second".into(),
+            )),
+        ];
+        match ExtractionError::collect::<_, Vec<_>>(results) {
+            Err(ExtractionError::Multiple(errors)) => assert_eq!(errors.len(), 2),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        progress.cancel();
+        let results = vec![Ok(1), Err(progress.check().unwrap_err().into())];
+        assert!(matches!(
+            ExtractionError::collect::<_, Vec<_>>(results),
+            Err(ExtractionError::Cancelled(_))
+        ));
+    }
+
+    #[test]
+    fn case_insensitive_paths() {
+        let dir = std::env::temp_dir().join("ddmb_case_insensitive_paths");
+        let _ = std::fs::remove_dir_all(&dir);
+        let write = |path: &str, text: &str| {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        };
+        write(
+            "game/heroes/crusader/crusader.info.darkest",
+            "hp: .amount 33\n",
+        );
+        write(
+            "first/heroes/Crusader/Crusader.info.darkest",
+            "hp: .amount 40\n",
+        );
+        write(
+            "first/heroes/NewHero/NewHero.info.darkest",
+            "hp: .amount 1\n",
+        );
+        write(
+            "first/heroes/NewHero/newhero.info.darkest",
+            "hp: .amount 2\n",
+        );
+        write(
+            "second/heroes/newhero/newhero.info.darkest",
+            "hp: .amount 3\n",
+        );
+
+        let (progress, _events) = Progress::new();
+        let game = extract_data(&progress, &dir.join("game"), &mut vec![]).unwrap();
+        let mut known = PathCase::new(&game);
+        let first = known.apply("first", mod_files(&dir.join("first")).unwrap());
+        let second = known.apply("second", mod_files(&dir.join("second")).unwrap());
+        let paths = |tree: &BTreeMap<PathBuf, PathBuf>| -> Vec<String> {
+            tree.keys()
+                .map(|path| path.to_string_lossy().replace('\\', "/"))
+                .collect()
+        };
+        assert_eq!(
+            paths(&first),
+            vec![
+                "heroes/NewHero/NewHero.info.darkest",
+                "heroes/crusader/crusader.info.darkest",
+            ]
+        );
+        assert_eq!(paths(&second), vec!["heroes/NewHero/NewHero.info.darkest"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parallel_extraction_matches_sequential() {
+        let dir = std::env::temp_dir().join("ddmb_parallel_extraction");
+        let _ = std::fs::remove_dir_all(&dir);
+        fixture(&dir, 40);
+        let (progress, _events) = Progress::new();
+        let contents = |tree: DataTree| -> Vec<(PathBuf, Option<String>)> {
+            tree.into_iter()
+                .map(|(path, node)| match node.into_content() {
+                    DataNodeContent::Text(text) => (path, Some(text)),
+                    DataNodeContent::Binary => (path, None),
+                })
+                .collect()
+        };
+
+        let sequential = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap()
+            .install(|| extract_data(&progress, &dir, &mut vec![]).unwrap());
+        let parallel = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap()
+            .install(|| extract_data(&progress, &dir, &mut vec![]).unwrap());
+        let sequential = contents(sequential);
+        assert_eq!(sequential.len(), 40);
+        assert_eq!(sequential, contents(parallel));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    /// Shows the speedup of the parallel extraction; run with `cargo test --release -- --ignored parallel_extraction_timing --nocapture`.
+    #[test]
+    #[ignore]
+    fn parallel_extraction_timing() {
+        let dir = std::env::temp_dir().join("ddmb_parallel_extraction_timing");
+        let _ = std::fs::remove_dir_all(&dir);
+        fixture(&dir, 400);
+        let (progress, _events) = Progress::new();
+        let timed = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let start = std::time::Instant::now();
+            let tree = pool.install(|| extract_data(&progress, &dir, &mut vec![]).unwrap());
+            (tree.len(), start.elapsed())
+        };
+
+        let threads = rayon::current_num_threads().max(2);
+        let (sequential_files, sequential_time) = timed(1);
+        let (parallel_files, parallel_time) = timed(threads);
+        println!(
+            "Extracted {} files: single thread {:?}, {} threads {:?}",
+            parallel_files, sequential_time, threads, parallel_time
+        );
+        assert_eq!(sequential_files, 400);
+        assert_eq!(parallel_files, sequential_files);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,150 @@
+use super::diff::{Conflict, DiffNode, DiffTree};
+use log::*;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::Hasher,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+fn file_hash(path: &Path) -> std::io::Result<u64> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.write(&buf[..read]);
+    }
+    Ok(hasher.finish())
+}
+
+/// Checks whether all the binary files have the same content.
+///
+/// Any IO error is treated as "not identical", so that the user would have to choose explicitly.
+pub fn identical(sources: &[&Path]) -> bool {
+    let mut hashes = sources.iter().map(|source| {
+        file_hash(source)
+            .map_err(|err| warn!("Failed to hash binary file {:?}: {}", source, err))
+            .ok()
+    });
+    match hashes.next() {
+        Some(Some(first)) => hashes.all(|hash| hash == Some(first)),
+        _ => false,
+    }
+}
+
+/// Short human-readable description of the file, to help user choose between the colliding assets.
+pub fn describe(source: &Path) -> String {
+    match std::fs::metadata(source) {
+        Ok(meta) => {
+            let modified = meta
+                .modified()
+                .map(|time| {
+                    chrono::DateTime::<chrono::Local>::from(time)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                })
+                .unwrap_or_else(|_| "unknown".into());
+            format!("{} bytes, modified {}", meta.len(), modified)
+        }
+        Err(err) => {
+            warn!("Failed to read metadata for {:?}: {}", source, err);
+            "metadata unavailable".into()
+        }
+    }
+}
+
+/// Asset file which was provided by several mods.
+pub struct AssetCollision {
+    path: PathBuf,
+    sources: Vec<(String, PathBuf)>,
+}
+
+impl AssetCollision {
+    pub fn from_conflict(path: &Path, conflict: &Conflict) -> Option<Self> {
+        let sources = conflict
+            .iter()
+            .map(|(name, node)| match node {
+                DiffNode::Binary(source) => Some((name.clone(), source.clone())),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            path: path.to_owned(),
+            sources,
+        })
+    }
+}
+
+pub struct AssetsSummary {
+    copied: usize,
+    overridden: Vec<(PathBuf, String, Vec<String>)>,
+}
+
+impl AssetsSummary {
+    /// Collects the summary from the final bundle contents and the collisions found during merge.
+    pub fn new(bundle: &DiffTree, collisions: Vec<AssetCollision>) -> Self {
+        let copied = bundle
+            .values()
+            .filter(|node| matches!(node, DiffNode::Binary(_)))
+            .count();
+        let overridden = collisions
+            .into_iter()
+            .filter_map(|collision| {
+                let used = match bundle.get(&collision.path) {
+                    Some(DiffNode::Binary(used)) => used,
+                    _ => return None,
+                };
+                let (winner, losers): (Vec<_>, Vec<_>) = collision
+                    .sources
+                    .into_iter()
+                    .partition(|(_, source)| source == used);
+                let winner = winner.into_iter().next()?.0;
+                let losers = losers.into_iter().map(|(name, _)| name).collect();
+                Some((collision.path, winner, losers))
+            })
+            .collect();
+        Self { copied, overridden }
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = format!("Assets copied: {}", self.copied);
+        if !self.overridden.is_empty() {
+            text.push_str(&format!(
+                "\nAssets provided by several mods: {}",
+                self.overridden.len()
+            ));
+            for (path, winner, losers) in &self.overridden {
+                text.push_str(&format!(
+                    "\n  {}: used from {}, overriding {}",
+                    path.to_string_lossy(),
+                    winner,
+                    losers.join(", ")
+                ));
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_files() {
+        let dir = std::env::temp_dir().join("ddmb_assets_identical");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (first, second, third) = (dir.join("first"), dir.join("second"), dir.join("third"));
+        std::fs::write(&first, b"sprite").unwrap();
+        std::fs::write(&second, b"sprite").unwrap();
+        std::fs::write(&third, b"other sprite").unwrap();
+        assert!(identical(&[&first, &second]));
+        assert!(!identical(&[&first, &second, &third]));
+        assert!(!identical(&[&first, &dir.join("missing")]));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
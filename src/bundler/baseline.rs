@@ -0,0 +1,163 @@
+//! Fingerprints of the game files the mods were compared with, telling which of them changed since then.
+
+use super::report::FileEntry;
+use crate::profile::{fingerprint, Resolutions};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Game file changed by some mods, as it was when the bundle was made.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BaselineFile {
+    pub fingerprint: String,
+    /// Mods whose changes were calculated against this version of the file.
+    pub mods: Vec<String>,
+}
+
+/// Every game file changed by the bundled mods, keyed by its path relative to the game root.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Baseline(pub BTreeMap<PathBuf, BaselineFile>);
+
+/// Game file which is not the same as when the [`Baseline`] was recorded.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DriftedFile {
+    pub path: PathBuf,
+    /// Whether the file is gone from the game, rather than changed.
+    pub removed: bool,
+    /// Mods whose changes to the file must be calculated again.
+    pub mods: Vec<String>,
+    /// Saved answers to the questions about this file, which can't be trusted anymore.
+    pub resolutions: Vec<String>,
+}
+
+impl std::fmt::Display for DriftedFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}) - changed by {}",
+            self.path.to_string_lossy(),
+            if self.removed { "removed" } else { "changed" },
+            self.mods.join(", ")
+        )?;
+        if !self.resolutions.is_empty() {
+            write!(f, "; {} saved answers invalidated", self.resolutions.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl Baseline {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Fingerprints the game files changed by the mods; the files added by mods have nothing to record.
+    pub fn record(game: &Path, files: &[FileEntry]) -> Self {
+        Self(
+            files
+                .iter()
+                .filter_map(|file| {
+                    let content = std::fs::read(game.join(&file.path)).ok()?;
+                    let entry = BaselineFile {
+                        fingerprint: fingerprint(&content),
+                        mods: file.mods.clone(),
+                    };
+                    Some((file.path.clone(), entry))
+                })
+                .collect(),
+        )
+    }
+
+    /// Compares the recorded files with the ones currently in the game.
+    ///
+    /// The saved answers are invalidated if they were given to the questions mentioning the changed file.
+    pub fn drift(&self, game: &Path, resolutions: &Resolutions) -> Vec<DriftedFile> {
+        self.0
+            .iter()
+            .filter_map(|(path, recorded)| {
+                let removed = match std::fs::read(game.join(path)) {
+                    Ok(content) if fingerprint(&content) == recorded.fingerprint => return None,
+                    Ok(_) => false,
+                    Err(err) => {
+                        debug!("[baseline] Failed to read {:?}: {}", path, err);
+                        true
+                    }
+                };
+                let name = path.to_string_lossy();
+                let resolutions = resolutions
+                    .keys()
+                    .filter(|question| question.contains(name.as_ref()))
+                    .cloned()
+                    .collect();
+                Some(DriftedFile {
+                    path: path.clone(),
+                    removed,
+                    mods: recorded.mods.clone(),
+                    resolutions,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Lists the changed files, one per indented line.
+pub fn render(drift: &[DriftedFile]) -> String {
+    drift.iter().map(|file| format!("\n  {}", file)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::report::FileChange;
+    use super::*;
+
+    #[test]
+    fn changed_files() {
+        let game = std::env::temp_dir().join("ddmb_baseline_drift");
+        std::fs::create_dir_all(game.join("heroes")).unwrap();
+        std::fs::write(game.join("heroes/a.info.darkest"), "hp: .amount 10").unwrap();
+        std::fs::write(game.join("heroes/b.info.darkest"), "hp: .amount 20").unwrap();
+        let file = |name: &str, the_mod: &str| FileEntry {
+            path: PathBuf::from("heroes").join(name),
+            change: FileChange::Patched,
+            mods: vec![the_mod.into()],
+            role: None,
+        };
+        let baseline = Baseline::record(
+            &game,
+            &[
+                file("a.info.darkest", "First"),
+                file("b.info.darkest", "Second"),
+                file("added.info.darkest", "Second"),
+            ],
+        );
+        assert_eq!(baseline.0.len(), 2);
+        assert!(baseline.drift(&game, &Resolutions::new()).is_empty());
+
+        std::fs::write(game.join("heroes/b.info.darkest"), "hp: .amount 25").unwrap();
+        let resolutions = vec![
+            (
+                "Conflict in file heroes/b.info.darkest".to_string(),
+                "First".to_string(),
+            ),
+            (
+                "Conflict in file heroes/a.info.darkest".to_string(),
+                "First".to_string(),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            baseline.drift(&game, &resolutions),
+            vec![DriftedFile {
+                path: "heroes/b.info.darkest".into(),
+                removed: false,
+                mods: vec!["Second".into()],
+                resolutions: vec!["Conflict in file heroes/b.info.darkest".into()],
+            }]
+        );
+        std::fs::remove_dir_all(&game).unwrap();
+    }
+}
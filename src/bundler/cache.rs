@@ -0,0 +1,219 @@
+//! Results of the previous run, reused for the mod files which didn't change since then.
+
+use super::{
+    diff::{DataNode, DataNodeContent, DataTree, DiffNode},
+    error::DataError,
+};
+use crate::profile::fingerprint;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// Changes made by the single mod file, along with the fingerprints of everything they were calculated from.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CachedDiff {
+    /// Fingerprint of the mod file, as it was decoded.
+    modded: String,
+    /// Fingerprint of the game file at the same path; `None` if the game has no such file.
+    original: Option<String>,
+    node: DiffNode,
+}
+
+/// How many files were taken from the cache and how many were compared with the game again.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub reused: usize,
+    pub compared: usize,
+}
+
+/// Changes calculated for every mod file, keyed by the mod directory and the file path in the game.
+///
+/// The files are still read on every run, but only the changed ones are compared with the game again;
+/// the game files are fingerprinted too, so that the game update invalidates everything depending on it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DiffCache {
+    /// Version of the bundler which made the cache; the cache of any other version is discarded.
+    version: String,
+    mods: BTreeMap<PathBuf, BTreeMap<PathBuf, CachedDiff>>,
+}
+
+impl Default for DiffCache {
+    fn default() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").into(),
+            mods: BTreeMap::new(),
+        }
+    }
+}
+
+impl DiffCache {
+    /// Reads the cache, starting with the empty one if it is missing, broken or made by another version.
+    pub fn load(path: &Path) -> Self {
+        let cache: Self = match std::fs::read_to_string(path) {
+            Ok(text) => match serde_json::from_str(&text) {
+                Ok(cache) => cache,
+                Err(err) => {
+                    warn!("Broken cache {:?}, starting from scratch: {}", path, err);
+                    return Self::default();
+                }
+            },
+            Err(err) => {
+                info!("No cache at {:?}, starting from scratch: {}", path, err);
+                return Self::default();
+            }
+        };
+        if cache.version != env!("CARGO_PKG_VERSION") {
+            info!(
+                "Cache {:?} was made by version {}, starting from scratch",
+                path, cache.version
+            );
+            return Self::default();
+        }
+        cache
+    }
+
+    /// Drops the changes of the mod, e.g. when it was left out of the bundle after all.
+    pub(super) fn remove_mod(&mut self, mod_path: &Path) {
+        self.mods.remove(mod_path);
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string(self)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        std::fs::write(path, json)
+    }
+}
+
+/// Cache of the single mod, shared by its files compared in parallel; see [`CacheRun::start_mod`].
+pub(super) struct ModCache {
+    mod_path: PathBuf,
+    previous: BTreeMap<PathBuf, CachedDiff>,
+    /// Fingerprints of the game files at the paths of the mod files.
+    originals: HashMap<PathBuf, Option<String>>,
+    current: Mutex<BTreeMap<PathBuf, CachedDiff>>,
+    reused: AtomicUsize,
+}
+
+impl ModCache {
+    /// Returns the changes made by the file, reusing the previous ones if neither this file nor the game's one
+    /// were changed since then, and calculating them with `diff` otherwise.
+    pub fn diff(
+        &self,
+        path: &Path,
+        node: DataNode,
+        diff: impl FnOnce(DataNode) -> Result<DiffNode, DataError>,
+    ) -> Result<DiffNode, DataError> {
+        let modded = match node.content() {
+            DataNodeContent::Text(text) => fingerprint(text.as_bytes()),
+            // Binary files are never compared, so there's nothing to cache.
+            DataNodeContent::Binary => return diff(node),
+        };
+        let original = self.originals.get(path).cloned().flatten();
+        let node = match self.previous.get(path) {
+            Some(entry) if entry.modded == modded && entry.original == original => {
+                debug!("[cache] {:?}: unchanged, reusing the changes", path);
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                entry.node.clone()
+            }
+            _ => {
+                debug!("[cache] {:?}: changed, comparing with the game", path);
+                diff(node)?
+            }
+        };
+        let entry = CachedDiff {
+            modded,
+            original,
+            node: node.clone(),
+        };
+        self.current
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(path.to_owned(), entry);
+        Ok(node)
+    }
+
+    /// Forgets what was compared by the failed attempt to read the mod, keeping the previous results
+    /// to be reused when the mod is read again, e.g. without its broken files.
+    pub fn restart(self) -> Self {
+        Self {
+            current: Mutex::new(BTreeMap::new()),
+            reused: AtomicUsize::new(0),
+            ..self
+        }
+    }
+}
+
+/// Cache used by the running bundling: the previous results are reused, the current ones are collected.
+pub(super) struct CacheRun {
+    previous: DiffCache,
+    next: DiffCache,
+    originals: HashMap<PathBuf, Option<String>>,
+    stats: CacheStats,
+}
+
+impl CacheRun {
+    pub fn new(previous: DiffCache) -> Self {
+        Self {
+            previous,
+            next: DiffCache::default(),
+            originals: HashMap::new(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Takes the previous results for the mod, to be consulted while its files are compared with the game.
+    pub fn start_mod<'a>(
+        &mut self,
+        mod_path: &Path,
+        original_data: &DataTree,
+        paths: impl IntoIterator<Item = &'a PathBuf>,
+    ) -> ModCache {
+        let originals = paths
+            .into_iter()
+            .map(|path| {
+                let original = self
+                    .originals
+                    .entry(path.clone())
+                    .or_insert_with(
+                        || match original_data.get(path).map(|node| node.content()) {
+                            Some(DataNodeContent::Text(text)) => Some(fingerprint(text.as_bytes())),
+                            _ => None,
+                        },
+                    )
+                    .clone();
+                (path.clone(), original)
+            })
+            .collect();
+        ModCache {
+            mod_path: mod_path.to_owned(),
+            previous: self.previous.mods.remove(mod_path).unwrap_or_default(),
+            originals,
+            current: Mutex::new(BTreeMap::new()),
+            reused: AtomicUsize::new(0),
+        }
+    }
+
+    /// Stores the results for the mod whose files were all compared.
+    pub fn finish_mod(&mut self, cache: ModCache) {
+        let current = cache
+            .current
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner());
+        let reused = cache.reused.into_inner();
+        self.stats.reused += reused;
+        self.stats.compared += current.len() - reused;
+        self.next.mods.insert(cache.mod_path, current);
+    }
+
+    /// Returns the cache for the next run, which holds only the mods used in this one.
+    pub fn finish(self) -> (DiffCache, CacheStats) {
+        (self.next, self.stats)
+    }
+}
@@ -0,0 +1,141 @@
+//! Rough classification of the mod by its files, made before anything is read.
+
+use super::error::ExtractionError;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the files read as text and compared with the game data; everything else is copied as is.
+const DATA_EXTENSIONS: &[&str] = &["js", "darkest", "xml", "json", "txt"];
+
+/// Extensions of the files known to be the game assets: images, animations, sounds, videos and fonts.
+const ASSET_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "bmp", "tga", "dds", "atlas", "skel", "bytes", "bank", "wav", "ogg",
+    "mp3", "bik", "webm", "mp4", "ttf", "otf", "fnt", "loc2",
+];
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(std::ffi::OsStr::to_str)
+}
+
+/// Checks whether the file is read as text and compared with the game data, judging by its extension.
+pub fn is_data_file(path: &Path) -> bool {
+    matches!(extension(path), Some(ext) if DATA_EXTENSIONS.contains(&ext))
+}
+
+/// Checks whether the file is one of the known game assets, judging by its extension.
+pub fn is_asset(path: &Path) -> bool {
+    matches!(extension(path), Some(ext) if ASSET_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// What the mod brings into the bundle.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModKind {
+    /// Changes the game data, so its files are compared with the game and merged.
+    Data,
+    /// Only replaces or adds assets (e.g. reskins or music packs), so its files are copied as is.
+    AssetsOnly,
+    /// Has no files the bundler knows about.
+    Empty,
+}
+
+impl ModKind {
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Data => "changes data",
+            Self::AssetsOnly => "assets only",
+            Self::Empty => "nothing recognized",
+        }
+    }
+}
+
+/// Classification of the mod, with the files which are neither data nor known assets.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ModSummary {
+    pub name: String,
+    pub kind: ModKind,
+    pub data_files: usize,
+    pub assets: usize,
+    /// Files still copied into the bundle, but not known to the bundler; these might need a new data type.
+    pub unrecognized: Vec<PathBuf>,
+}
+
+impl ModSummary {
+    /// Classifies the mod by the paths of its files, relative to the mod directory.
+    pub fn new<'a>(name: impl Into<String>, paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        let (mut data_files, mut assets, mut unrecognized) = (0, 0, vec![]);
+        for path in paths {
+            if is_data_file(path) {
+                data_files += 1;
+            } else if is_asset(path) {
+                assets += 1;
+            } else {
+                unrecognized.push(path.to_owned());
+            }
+        }
+        let kind = if data_files > 0 {
+            ModKind::Data
+        } else if assets > 0 {
+            ModKind::AssetsOnly
+        } else {
+            ModKind::Empty
+        };
+        Self {
+            name: name.into(),
+            kind,
+            data_files,
+            assets,
+            unrecognized,
+        }
+    }
+
+    /// Lists the mod directory and classifies the files the bundler would take from it.
+    pub fn scan(name: impl Into<String>, dir: &Path) -> Result<Self, ExtractionError> {
+        let mut files = vec![];
+        super::list_files(dir, true, &mut files)?;
+        let paths: Vec<_> = files
+            .iter()
+            .filter_map(|path| path.strip_prefix(dir).ok())
+            .collect();
+        Ok(Self::new(name, paths))
+    }
+
+    /// One-line description, e.g. `changes data: 3 data files, 10 assets`.
+    pub fn describe(&self) -> String {
+        let mut counts = vec![];
+        if self.data_files > 0 {
+            counts.push(format!("{} data files", self.data_files));
+        }
+        if self.assets > 0 {
+            counts.push(format!("{} assets", self.assets));
+        }
+        if !self.unrecognized.is_empty() {
+            counts.push(format!("{} unrecognized files", self.unrecognized.len()));
+        }
+        if counts.is_empty() {
+            self.kind.describe().to_owned()
+        } else {
+            format!("{}: {}", self.kind.describe(), counts.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classification() {
+        let summary = |paths: &[&str]| ModSummary::new("mod", paths.iter().map(Path::new));
+        let reskin = summary(&["heroes/crusader/anim/crusader.sprite.idle.png", "notes.md"]);
+        assert_eq!(reskin.kind, ModKind::AssetsOnly);
+        assert_eq!(reskin.unrecognized, vec![PathBuf::from("notes.md")]);
+        assert_eq!(
+            reskin.describe(),
+            "assets only: 1 assets, 1 unrecognized files"
+        );
+        let data = summary(&["heroes/crusader/crusader.info.darkest", "audio/music.bank"]);
+        assert_eq!(data.kind, ModKind::Data);
+        assert_eq!(summary(&["readme.md"]).kind, ModKind::Empty);
+        assert_eq!(summary(&[]).describe(), "nothing recognized");
+    }
+}
@@ -0,0 +1,264 @@
+//! Comparison of two deployed bundles, telling which mods are responsible for every changed item.
+
+use super::{
+    deploy::all_files,
+    diff::{DiffNode, ModContent},
+    error::CompareError,
+    structures::inspect,
+    validate::REPORT_FILE,
+};
+use crate::profile::fingerprint;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// File with the [`Contributions`] of the bundle, written along with the report.
+pub const CONTRIBUTIONS_FILE: &str = "contributions.json";
+
+/// Files written by the bundler itself, which are not the game data.
+const BUNDLE_FILES: &[&str] = &[
+    "project.xml",
+    "preview_icon.png",
+    "modfiles.txt",
+    "report.txt",
+    "report.json",
+    REPORT_FILE,
+    CONTRIBUTIONS_FILE,
+];
+
+/// Single change the mod makes to the game: the file, the item in it (`None` for the whole file)
+/// and the short form of the change, enough to tell whether it's the same in another bundle.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Contribution {
+    pub path: PathBuf,
+    pub item: Option<String>,
+    pub change: String,
+}
+
+/// Changes made by every bundled mod before they were merged, by mod name.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Contributions(pub BTreeMap<String, BTreeSet<Contribution>>);
+
+impl Contributions {
+    pub fn new(mods: &[ModContent]) -> Self {
+        Self(
+            mods.iter()
+                .map(|the_mod| {
+                    let changes = the_mod
+                        .diff()
+                        .iter()
+                        .flat_map(|(path, node)| contributions(path, node))
+                        .collect();
+                    (the_mod.name().to_owned(), changes)
+                })
+                .collect(),
+        )
+    }
+
+    pub fn load(dir: &Path) -> Result<Self, CompareError> {
+        let path = dir.join(CONTRIBUTIONS_FILE);
+        let text = std::fs::read_to_string(&path).map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => CompareError::NotABundle(dir.into()),
+            _ => CompareError::Io(path.clone(), err),
+        })?;
+        serde_json::from_str(&text).map_err(|err| CompareError::Contributions(path, err))
+    }
+
+    pub fn save(&self, dir: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string(self)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        std::fs::write(dir.join(CONTRIBUTIONS_FILE), json)
+    }
+
+    /// Changes made by the mod to the file, either to the single item or to the file as a whole.
+    fn of<'a>(
+        &'a self,
+        name: &str,
+        path: &'a Path,
+        item: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a Contribution> {
+        self.0.get(name).into_iter().flatten().filter(move |entry| {
+            entry.path == path && (item.is_none() || entry.item.as_deref() == item)
+        })
+    }
+}
+
+fn contributions(path: &Path, node: &DiffNode) -> Vec<Contribution> {
+    node.entries()
+        .into_iter()
+        .map(|entry| {
+            // Whole files are stored by their fingerprints, not to keep another copy of them.
+            let value = match node {
+                DiffNode::Binary(source) => std::fs::metadata(source)
+                    .ok()
+                    .and_then(|meta| {
+                        let modified = meta.modified().ok()?;
+                        Some(format!("{}-{:?}", meta.len(), modified))
+                    })
+                    .unwrap_or_default(),
+                DiffNode::AddedText(text) => fingerprint(text.as_bytes()),
+                _ => entry.value.unwrap_or_default(),
+            };
+            Contribution {
+                path: path.to_owned(),
+                item: entry.item,
+                change: format!("{} {}", entry.change, value),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl ChangeKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Added => "added",
+            Self::Removed => "removed",
+            Self::Changed => "changed",
+        }
+    }
+}
+
+/// Difference between two bundles in one item of the merged data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundleChange {
+    pub path: PathBuf,
+    /// Item inside the structured file; `None` if the file is compared as a whole.
+    pub item: Option<String>,
+    pub kind: ChangeKind,
+    /// Mods whose changes to this item (or to the file, if the item isn't known to any of them)
+    /// are different in the two bundles. Empty, if the difference comes from elsewhere,
+    /// e.g. from the game update or from another conflict resolution.
+    pub mods: Vec<String>,
+}
+
+/// Compares the merged data of two deployed bundles, item by item in the structured files
+/// and as the whole files otherwise, attributing every difference to the mods responsible for it.
+pub fn compare(old: &Path, new: &Path) -> Result<Vec<BundleChange>, CompareError> {
+    let (old_mods, new_mods) = (Contributions::load(old)?, Contributions::load(new)?);
+    let (old_files, new_files) = (data_files(old)?, data_files(new)?);
+    let mut changes = vec![];
+    for path in old_files.union(&new_files) {
+        let read = |dir: &Path| {
+            let file = dir.join(path);
+            std::fs::read(&file).map_err(|err| CompareError::Io(file, err))
+        };
+        let (old_content, new_content) = match (old_files.contains(path), new_files.contains(path))
+        {
+            (true, true) => (read(old)?, read(new)?),
+            (true, false) => {
+                changes.push((path.clone(), None, ChangeKind::Removed));
+                continue;
+            }
+            (false, _) => {
+                changes.push((path.clone(), None, ChangeKind::Added));
+                continue;
+            }
+        };
+        if old_content == new_content {
+            continue;
+        }
+        match (items(path, &old_content), items(path, &new_content)) {
+            (Some(old_items), Some(new_items)) => {
+                let keys: BTreeSet<_> = old_items.keys().chain(new_items.keys()).collect();
+                for key in keys {
+                    let kind = match (old_items.get(key), new_items.get(key)) {
+                        (Some(old_value), Some(new_value)) if old_value == new_value => continue,
+                        (Some(_), Some(_)) => ChangeKind::Changed,
+                        (Some(_), None) => ChangeKind::Removed,
+                        (None, _) => ChangeKind::Added,
+                    };
+                    changes.push((path.clone(), Some(key.clone()), kind));
+                }
+            }
+            _ => changes.push((path.clone(), None, ChangeKind::Changed)),
+        }
+    }
+    debug!("[compare] {} items differ", changes.len());
+
+    let names: BTreeSet<&String> = old_mods.0.keys().chain(new_mods.0.keys()).collect();
+    Ok(changes
+        .into_iter()
+        .map(|(path, item, kind)| {
+            let known = |item: Option<&str>| {
+                let touched = |mods: &Contributions| {
+                    names
+                        .iter()
+                        .any(|name| mods.of(name, &path, item).next().is_some())
+                };
+                touched(&old_mods) || touched(&new_mods)
+            };
+            // Items unknown to every mod come from the changes to the whole file, e.g. the added text.
+            let item_filter = item.as_deref().filter(|item| known(Some(*item)));
+            let mods = names
+                .iter()
+                .filter(|name| {
+                    old_mods
+                        .of(name, &path, item_filter)
+                        .ne(new_mods.of(name, &path, item_filter))
+                })
+                .map(|name| (*name).clone())
+                .collect();
+            BundleChange {
+                path,
+                item,
+                kind,
+                mods,
+            }
+        })
+        .collect())
+}
+
+/// Paths of the game data in the bundle, relative to the bundle directory.
+fn data_files(dir: &Path) -> Result<BTreeSet<PathBuf>, CompareError> {
+    let files = all_files(dir).map_err(|err| CompareError::Io(dir.into(), err))?;
+    Ok(files
+        .into_iter()
+        .filter_map(|path| path.strip_prefix(dir).ok().map(Path::to_path_buf))
+        .filter(|path| {
+            !BUNDLE_FILES
+                .iter()
+                .any(|name| path.as_path() == Path::new(name))
+        })
+        .collect())
+}
+
+/// Every value of the structured file, if the file is known to the bundler.
+fn items(path: &Path, content: &[u8]) -> Option<BTreeMap<String, String>> {
+    let text = std::str::from_utf8(content).ok()?;
+    inspect(path, text)
+        .ok()
+        .map(|items| items.into_iter().collect())
+}
+
+/// Renders the changes in stable, tab-separated format: `<path> <item> <change> <mods>`,
+/// one change per line, ordered by path and item; `-` stands for the empty field.
+pub fn render(changes: &[BundleChange]) -> String {
+    changes
+        .iter()
+        .map(|change| {
+            let mods = if change.mods.is_empty() {
+                "-".to_owned()
+            } else {
+                change.mods.join(", ")
+            };
+            format!(
+                "{}\t{}\t{}\t{}",
+                change.path.to_string_lossy().replace('\\', "/"),
+                change.item.as_deref().unwrap_or("-"),
+                change.kind.name(),
+                mods
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -1,73 +1,171 @@
 use super::{
     diff::{DataNodeContent, DataTree},
     error::DeploymentError,
+    progress::Progress,
+    report::BundleReport,
+    structures::is_strings_table,
 };
-use crossbeam_channel::{bounded, Sender};
-use cursive::{
-    traits::{Nameable, Resizable},
-    views::{Dialog, EditView, LinearLayout, Panel, TextView},
-    Cursive,
-};
-use indoc::indoc;
+use crate::{loader::ModProject, profile::Output};
 use log::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
 
-#[derive(Copy, Clone)]
-enum OverwriteChoice {
-    Overwrite,
-    Retry,
-    Cancel,
+fn project_xml(name: &str, mods: &[ModProject]) -> String {
+    let description = mods
+        .iter()
+        .map(|the_mod| match the_mod.version() {
+            Some(version) => format!("- {} (version {})", the_mod.title, version),
+            None => format!("- {}", the_mod.title),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<project>
+    <Title>{}</Title>
+    <ItemDescription>Bundle of the following mods:
+{}</ItemDescription>
+    <PreviewIconFile>{}</PreviewIconFile>
+    <UpdateDetails>Built with Darkest Dungeon mod bundler {}</UpdateDetails>
+</project>"#,
+        escape_xml(name),
+        escape_xml(&description),
+        PREVIEW_ICON,
+        env!("CARGO_PKG_VERSION")
+    )
 }
 
+const PREVIEW_ICON: &str = "preview_icon.png";
+/// Single dark red pixel, used as the preview when none is given; the game scales it as needed.
+const PLACEHOLDER_ICON: &[u8] = &[
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x02, 0x00, 0x00, 0x00, 0x90, 0x77, 0x53,
+    0xde, 0x00, 0x00, 0x00, 0x0c, 0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x63, 0x88, 0x92, 0x93, 0x03,
+    0x00, 0x01, 0x6c, 0x00, 0x97, 0xc0, 0x5c, 0x42, 0x5b, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e,
+    0x44, 0xae, 0x42, 0x60, 0x82,
+];
+
+const MODFILES: &str = "modfiles.txt";
+
+/// Every file in the directory and its subdirectories, sorted.
+pub(super) fn all_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                walk(&path, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+    let mut files = vec![];
+    walk(dir, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Lists every file of the mod for the game's mod system: three lines per file - the path relative
+/// to the mod directory, the size in bytes and the modification time in seconds since the Unix epoch.
+fn modfiles(mod_path: &Path) -> std::io::Result<String> {
+    let mut text = String::new();
+    for path in all_files(mod_path)? {
+        let relative = path.strip_prefix(mod_path).unwrap_or(&path);
+        if relative == Path::new(MODFILES) {
+            continue;
+        }
+        let meta = std::fs::metadata(&path)?;
+        let modified = meta
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+        text.push_str(&format!(
+            "{}\n{}\n{}\n",
+            relative.to_string_lossy().replace('\\', "/"),
+            meta.len(),
+            modified
+        ));
+    }
+    Ok(text)
+}
+
+/// Writes the bundle into the `mods_root`, replacing the existing directory, if any.
 pub fn deploy(
-    sink: &mut cursive::CbSink,
-    mod_path: &Path,
+    progress: &Progress,
+    mods_root: &Path,
     bundle: DataTree,
+    mods: &[ModProject],
+    report: &BundleReport,
+    output: &Output,
+    preview: Option<&Path>,
 ) -> Result<(), DeploymentError> {
-    let (name, dir) = ask_for_props(sink);
-    let mod_path = mod_path.join(dir);
-
+    let Output { name, directory } = output;
+    let mod_path = mods_root.join(directory);
     info!("Mod is being deployed to {:?}", mod_path);
-    // This is possibly subject for TOCTOU attack, but in this case the user seems to have a problem somewhere else
-    if mod_path.exists() {
-        match ask_for_overwrite(sink, &mod_path) {
-            OverwriteChoice::Overwrite => {
-                info!("Overwriting existing mod bundle");
-                std::fs::remove_dir_all(&mod_path).map_err(DeploymentError::from_io(&mod_path))?
-            }
-            OverwriteChoice::Cancel => return Err(DeploymentError::AlreadyExists),
-            OverwriteChoice::Retry => {
-                if mod_path.exists() {
-                    return Err(DeploymentError::AlreadyExists);
-                }
-            }
+
+    // Everything is written into the staging directory first, so that the cancelled
+    // or failed deployment doesn't leave the half-written mod behind.
+    let staging = mods_root.join(format!(".{}.partial", directory));
+    if staging.exists() {
+        info!("Removing leftovers of the previous deployment");
+        std::fs::remove_dir_all(&staging).map_err(DeploymentError::from_io(&staging))?;
+    }
+    std::fs::create_dir(&staging).map_err(DeploymentError::from_io(&staging))?;
+    if let Err(err) = write_bundle(progress, &staging, bundle, name, mods, report, preview) {
+        warn!("Deployment failed, removing staging directory: {}", err);
+        if let Err(err) = std::fs::remove_dir_all(&staging) {
+            warn!("Failed to remove staging directory {:?}: {}", staging, err);
         }
+        return Err(err);
     }
 
-    std::fs::create_dir(&mod_path).map_err(DeploymentError::from_io(&mod_path))?;
+    if mod_path.exists() {
+        info!("Removing existing mod bundle");
+        std::fs::remove_dir_all(&mod_path).map_err(DeploymentError::from_io(&mod_path))?;
+    }
+    std::fs::rename(&staging, &mod_path).map_err(DeploymentError::from_io(&mod_path))
+}
 
+fn write_bundle(
+    progress: &Progress,
+    mod_path: &Path,
+    bundle: DataTree,
+    name: &str,
+    mods: &[ModProject],
+    report: &BundleReport,
+    preview: Option<&Path>,
+) -> Result<(), DeploymentError> {
     let project_xml_path = mod_path.join("project.xml");
-    let project_xml = format!(
-        r#"<?xml version="1.0" encoding="utf-8"?>
-<project>
-    <Title>{}</Title>
-</project>"#,
-        name
-    );
+    let project_xml = project_xml(name, mods);
     std::fs::write(&project_xml_path, project_xml)
         .map_err(DeploymentError::from_io(&project_xml_path))?;
     info!("Written project.xml");
+    report
+        .write(mod_path)
+        .map_err(DeploymentError::from_io(mod_path))?;
+    info!("Written bundle report");
 
-    for (path, item) in bundle {
+    let total = bundle.len();
+    for (index, (path, item)) in bundle.into_iter().enumerate() {
+        progress.check()?;
         info!("Writing mod file to relative path {:?}", path);
-        super::set_file_updated(sink, "Deploying", path.to_string_lossy());
+        progress.file("Deploying", path.to_string_lossy(), index + 1, total);
         let (source, content) = item.into_parts();
-        let target = mod_path.join(path);
+        let target = mod_path.join(&path);
         let dir = target.parent().unwrap();
         std::fs::create_dir_all(dir).map_err(DeploymentError::from_io(&dir))?;
         match content {
             DataNodeContent::Binary => {
                 info!("Copying binary file from {:?}", source);
+                // Copying the content only (unlike `fs::copy`), so that the read-only permissions
+                // of the source file aren't carried over into the bundle.
                 let mut source =
                     std::fs::File::open(&source).map_err(DeploymentError::from_io(&source))?;
                 let mut target =
@@ -79,101 +177,63 @@ pub fn deploy(
                     "Writing text file, first 100 chars = \"{}\"",
                     text.chars().take(100).collect::<String>()
                 );
-                std::fs::write(&target, text)
+                if is_strings_table(&path) {
+                    // The game's localization tool expects the byte order mark.
+                    std::fs::write(&target, format!("\u{FEFF}{}", text))
+                } else {
+                    std::fs::write(&target, text)
+                }
             }
         }
         .map_err(DeploymentError::from_io(&target))?;
     }
+
+    // Written after the data, so that the bundled mods can't replace the icon of the bundle.
+    let icon_path = mod_path.join(PREVIEW_ICON);
+    match preview {
+        Some(preview) => {
+            info!("Copying preview icon from {:?}", preview);
+            std::fs::copy(preview, &icon_path)
+                .map(|_| {})
+                .map_err(DeploymentError::from_io(preview))?
+        }
+        None => std::fs::write(&icon_path, PLACEHOLDER_ICON)
+            .map_err(DeploymentError::from_io(&icon_path))?,
+    }
+    // Must be the last one, since it lists everything else.
+    let modfiles_path = mod_path.join(MODFILES);
+    let modfiles = modfiles(mod_path).map_err(DeploymentError::from_io(mod_path))?;
+    std::fs::write(&modfiles_path, modfiles).map_err(DeploymentError::from_io(&modfiles_path))?;
+    info!("Written files manifest");
     Ok(())
 }
 
-fn ask_for_props(sink: &mut cursive::CbSink) -> (String, String) {
-    let (sender, receiver) = bounded(0);
-
-    crate::run_update(sink, move |cursive| {
-        crate::push_screen(
-            cursive,
-            Dialog::around(
-                LinearLayout::vertical()
-                    .child(
-                        Panel::new(
-                            EditView::new()
-                                .on_edit(|cursive, name, _| {
-                                    cursive.call_on_name("Mod directory", |edit: &mut EditView| {
-                                        edit.set_content(name.to_lowercase().replace(' ', "_"));
-                                    });
-                                })
-                                .content("Generated bundle")
-                                .with_name("Mod name")
-                                .full_width(),
-                        )
-                        .title("Mod name"),
-                    )
-                    .child(
-                        Panel::new(
-                            EditView::new()
-                                .content("generated_bundle")
-                                .with_name("Mod directory")
-                                .full_width(),
-                        )
-                        .title("Mod directory"),
-                    ),
-            )
-            .title("Deployment parameters")
-            .button("Clear", |cursive| {
-                let _ =
-                    cursive.call_on_name("Mod name", |view: &mut EditView| view.set_content(""));
-                let _ = cursive
-                    .call_on_name("Mod directory", |view: &mut EditView| view.set_content(""));
-            })
-            .button("Deploy!", move |cursive| {
-                let name = cursive
-                    .call_on_name("Mod name", |view: &mut EditView| view.get_content())
-                    .unwrap();
-                let dir = cursive
-                    .call_on_name("Mod directory", |view: &mut EditView| view.get_content())
-                    .unwrap();
-                sender.send((name.to_string(), dir.to_string())).unwrap();
-            }),
-        )
-    });
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    receiver
-        .recv()
-        .expect("Sender was dropped without sending anything")
-}
-
-fn send_choice(sender: &Sender<OverwriteChoice>, choice: OverwriteChoice) -> impl Fn(&mut Cursive) {
-    let sender = sender.clone();
-    move |cursive| {
-        cursive.pop_layer();
-        let _ = sender.send(choice);
+    #[test]
+    fn generated_project() {
+        let mods: Vec<ModProject> = vec![];
+        let xml = project_xml("Heroes & Villains <bundle>", &mods);
+        assert!(xml.contains("<Title>Heroes &amp; Villains &lt;bundle&gt;</Title>"));
+        assert!(xml.contains("<PreviewIconFile>preview_icon.png</PreviewIconFile>"));
     }
-}
 
-fn ask_for_overwrite(sink: &mut cursive::CbSink, path: &Path) -> OverwriteChoice {
-    use OverwriteChoice::*;
-    let (sender, receiver) = bounded(0);
-    let path = path.to_owned();
-    crate::run_update(sink, move |cursive| {
-        crate::push_screen(
-            cursive,
-            Dialog::around(TextView::new(format!(
-                "Target directory {} already exists!
-Choose your action:
-- overwrite existing folder;
-- rename/move it manually and retry deploying (it will fail if folder still exists);
-- cancel mod bundling process entirely.",
-                path.to_string_lossy()
-            )))
-            .button("Overwrite", send_choice(&sender, Overwrite))
-            .button("Retry", send_choice(&sender, Retry))
-            .button("Cancel", send_choice(&sender, Cancel))
-            .h_align(cursive::align::HAlign::Center),
-        )
-    });
-
-    receiver
-        .recv()
-        .expect("Sender was dropped without sending anything")
+    #[test]
+    fn files_manifest() {
+        let dir = std::env::temp_dir().join("ddmb_deploy_modfiles");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("heroes")).unwrap();
+        std::fs::write(dir.join("project.xml"), "<project/>").unwrap();
+        std::fs::write(dir.join("heroes/hero.darkest"), "hp: .amount 1\n").unwrap();
+        std::fs::write(dir.join(MODFILES), "stale").unwrap();
+        let manifest = modfiles(&dir).unwrap();
+        let lines: Vec<_> = manifest.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(&lines[0..2], &["heroes/hero.darkest", "14"]);
+        assert_eq!(&lines[3..5], &["project.xml", "10"]);
+        assert!(lines[2].parse::<u64>().unwrap() > 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -1,14 +1,18 @@
-use cursive::{
-    traits::Finder,
-    views::{Dialog, TextView},
+use super::{
+    error::{DataError, DataErrorKind, ExtractionError},
+    progress::{Cancelled, Progress},
+    structures::{is_buffs_library, is_library, is_override, merge_added, StructureChangeset},
 };
 use difference::{Changeset, Difference};
 use log::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::{
     cell::RefCell,
-    collections::{BTreeMap, HashMap, HashSet},
-    path::PathBuf,
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
     rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 pub type DataTree = BTreeMap<PathBuf, DataNode>;
@@ -31,6 +35,12 @@ impl DataNode {
     pub fn into_content(self) -> DataNodeContent {
         self.content
     }
+    pub fn absolute(&self) -> &Path {
+        &self.absolute
+    }
+    pub fn content(&self) -> &DataNodeContent {
+        &self.content
+    }
 }
 
 #[derive(Debug)]
@@ -53,6 +63,7 @@ impl From<Option<String>> for DataNodeContent {
     }
 }
 
+#[derive(Clone)]
 pub struct ModContent {
     name: String,
     diff: DiffTree,
@@ -64,17 +75,26 @@ impl ModContent {
             diff,
         }
     }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn diff(&self) -> &DiffTree {
+        &self.diff
+    }
+    pub fn into_diff(self) -> DiffTree {
+        self.diff
+    }
 }
 
 pub type DiffTree = BTreeMap<PathBuf, DiffNode>;
 // FIXME: this makes it possible for multiple mods with the same name to collide!
 pub type Conflict = Vec<(String, DiffNode)>;
-pub type Conflicts = HashMap<PathBuf, Conflict>;
+pub type Conflicts = BTreeMap<PathBuf, Conflict>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LinesChangeset(pub Vec<Option<LineChange>>);
 impl LinesChangeset {
-    fn diff(first: &str, second: &str) -> Self {
+    fn diff(first: &str, second: &str) -> Result<Self, DataErrorKind> {
         let lines_count = first.split('\n').count();
         info!("Diff: {} lines in original file", lines_count);
         let mut inner = Vec::with_capacity(lines_count);
@@ -174,13 +194,18 @@ impl LinesChangeset {
         }
         debug!("Pushed pending removals, length = {}", removed.len());
         inner.extend(removed);
-        debug_assert!(inner.len() == lines_count);
+        if inner.len() != lines_count {
+            return Err(DataErrorKind::LineCount {
+                expected: lines_count,
+                found: inner.len(),
+            });
+        }
         info!("Calculated patches for every line");
-        Self(inner)
+        Ok(Self(inner))
     }
 }
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub enum LineModification {
     Replaced(String),
     Added(String),
@@ -201,23 +226,25 @@ impl LineModification {
         .count()
     }
 }
-#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub enum LineChange {
     Removed,
     Modified(LineModification),
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum DiffNode {
     Binary(PathBuf),
     AddedText(String),
     ModifiedText(LinesChangeset),
+    ModifiedStructure(StructureChangeset),
 }
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum DiffNodeKind {
     Binary,
     AddedText,
     ModifiedText,
+    ModifiedStructure,
 }
 impl DiffNode {
     pub fn kind(&self) -> DiffNodeKind {
@@ -225,69 +252,174 @@ impl DiffNode {
             DiffNode::Binary(_) => DiffNodeKind::Binary,
             DiffNode::AddedText(_) => DiffNodeKind::AddedText,
             DiffNode::ModifiedText(_) => DiffNodeKind::ModifiedText,
+            DiffNode::ModifiedStructure(_) => DiffNodeKind::ModifiedStructure,
+        }
+    }
+
+    /// Lists the changes made to the file one by one, in the order of the items they change.
+    pub fn entries(&self) -> Vec<DiffEntry> {
+        let entry = |item: Option<String>, change, value: Option<&str>| DiffEntry {
+            item,
+            change,
+            value: value.map(str::to_owned),
+        };
+        match self {
+            DiffNode::Binary(_) => vec![entry(None, "binary", None)],
+            DiffNode::AddedText(text) => vec![entry(None, "added", Some(text))],
+            DiffNode::ModifiedText(changes) => changes
+                .0
+                .iter()
+                .enumerate()
+                .filter_map(|(index, change)| {
+                    let item = Some(format!("line {}", index + 1));
+                    match change.as_ref()? {
+                        LineChange::Removed => Some(entry(item, "removed", None)),
+                        LineChange::Modified(LineModification::Replaced(text)) => {
+                            Some(entry(item, "replaced", Some(text)))
+                        }
+                        LineChange::Modified(LineModification::Added(text)) => {
+                            Some(entry(item, "inserted", Some(text)))
+                        }
+                    }
+                })
+                .collect(),
+            DiffNode::ModifiedStructure(changes) => changes
+                .entries()
+                .into_iter()
+                .map(|(item, value)| match value {
+                    Some(value) => entry(Some(item), "set", Some(&value)),
+                    None => entry(Some(item), "removed", None),
+                })
+                .collect(),
         }
     }
 }
 
+/// Single change made to the file: the changed item (`None` for the file as a whole),
+/// the kind of change, e.g. `set` or `removed`, and the new value, if there is any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffEntry {
+    pub item: Option<String>,
+    pub change: &'static str,
+    pub value: Option<String>,
+}
+
 pub trait DataTreeExt {
-    fn diff(&self, other: DataTree) -> DiffTree;
+    fn diff(&self, other: DataTree) -> Result<DiffTree, DataError>;
+    /// Same as `diff`, but reports every compared file and stops if the bundling was cancelled.
+    fn diff_with_progress(
+        &self,
+        other: DataTree,
+        progress: &Progress,
+    ) -> Result<DiffTree, ExtractionError>;
 }
 impl DataTreeExt for DataTree {
-    fn diff(&self, other: DataTree) -> DiffTree {
-        use DataNodeContent::*;
-        other.into_iter().map(|(path, modded)| {
-            info!("Comparing data on path {:?}", path);
-            let value = match self.get(&path) {
-                Some(orig) => {
-                    info!("Mod is overwriting existing file {:?}", path);
-                    match (&orig.content, &modded.content) {
-                        (Binary, Binary) => {
-                            info!("{:?} is a binary file - skipping diff", path);
-                            DiffNode::Binary(modded.absolute)
+    fn diff(&self, other: DataTree) -> Result<DiffTree, DataError> {
+        other
+            .into_iter()
+            .map(|(path, modded)| {
+                let value = diff_file(self, &path, modded)?;
+                Ok((path, value))
+            })
+            .collect()
+    }
+
+    fn diff_with_progress(
+        &self,
+        other: DataTree,
+        progress: &Progress,
+    ) -> Result<DiffTree, ExtractionError> {
+        // Files are independent, so they are compared in parallel; merge must stay ordered, though.
+        let total = other.len();
+        let done = AtomicUsize::new(0);
+        other
+            .into_par_iter()
+            .map(|(path, modded)| {
+                progress.check()?;
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                progress.file("Comparing", path.to_string_lossy(), done, total);
+                let value = diff_file(self, &path, modded)?;
+                Ok((path, value))
+            })
+            .collect()
+    }
+}
+
+/// Compares the mod file with the game file at the same path, if there's one.
+pub(super) fn diff_file(
+    orig: &DataTree,
+    path: &Path,
+    modded: DataNode,
+) -> Result<DiffNode, DataError> {
+    use DataNodeContent::*;
+    info!("Comparing data on path {:?}", path);
+    let node = match orig.get(path) {
+        Some(orig) => {
+            info!("Mod is overwriting existing file {:?}", path);
+            let source = &modded.absolute;
+            match (&orig.content, &modded.content) {
+                (Binary, Binary) => {
+                    info!("{:?} is a binary file - skipping diff", path);
+                    DiffNode::Binary(modded.absolute)
+                }
+                (Text(orig), Text(modded)) => {
+                    let structured =
+                        StructureChangeset::diff(path, orig, modded).map_err(|failure| {
+                            let kind = DataErrorKind::Malformed(Box::new(failure));
+                            DataError::new(path, kind).with_source(source)
+                        })?;
+                    match structured {
+                        Some(changes) => {
+                            info!(
+                                "{:?} is a structured file - calculated structural diff",
+                                path
+                            );
+                            DiffNode::ModifiedStructure(changes)
                         }
-                        (Text(orig), Text(modded)) => {
+                        None => {
                             info!("{:?} is a text file - calculating diff", path);
-                            DiffNode::ModifiedText(LinesChangeset::diff(orig, modded))
+                            let changes = LinesChangeset::diff(orig, modded).map_err(|kind| {
+                                DataError::new(path, kind).with_source(source)
+                            })?;
+                            DiffNode::ModifiedText(changes)
                         }
-                        _ => {
-                            panic!(
-                                "Unexpected mismatch: original file {:?} and modded file {:?} have different kinds",
-                                orig.absolute,
-                                modded.absolute
-                            )
-                        },
                     }
                 }
-                None => {
-                    info!("Mod is introducing new file {:?}", path);
-                    match modded.content {
-                        Binary => DiffNode::Binary(modded.absolute),
-                        Text(modded) => DiffNode::AddedText(modded),
-                    }
+                (orig, modded) => {
+                    let kind = |content: &DataNodeContent| match content {
+                        Binary => "binary",
+                        Text(_) => "text",
+                    };
+                    let kind = DataErrorKind::KindMismatch {
+                        game: kind(orig),
+                        modded: kind(modded),
+                    };
+                    return Err(DataError::new(path, kind).with_source(source));
                 }
-            };
-            (path, value)
-        }).collect()
-    }
+            }
+        }
+        None => {
+            info!("Mod is introducing new file {:?}", path);
+            match modded.content {
+                Binary => DiffNode::Binary(modded.absolute),
+                Text(modded) => DiffNode::AddedText(modded),
+            }
+        }
+    };
+    Ok(node)
 }
 
-pub trait ResultDiffTressExt<E>: Iterator<Item = Result<ModContent, E>> + Sized {
-    fn try_merge(
-        self,
-        on_progress: Option<&mut cursive::CbSink>,
-    ) -> Result<(DiffTree, Conflicts), E> {
-        Ok(merge(try_prepare_merge(self)?, on_progress))
-    }
-}
-impl<I, E> ResultDiffTressExt<E> for I where I: Iterator<Item = Result<ModContent, E>> + Sized {}
 pub trait DiffTreesExt: Iterator<Item = ModContent> + Sized {
-    fn merge(self, on_progress: Option<&mut cursive::CbSink>) -> (DiffTree, Conflicts) {
-        merge(prepare_merge(self), on_progress)
+    fn merge(self) -> (DiffTree, Conflicts) {
+        merge(prepare_merge(self), None).expect("Merge can't be cancelled without progress")
+    }
+    fn merge_with_progress(self, progress: &Progress) -> Result<(DiffTree, Conflicts), Cancelled> {
+        merge(prepare_merge(self), Some(progress))
     }
 }
 impl<I> DiffTreesExt for I where I: Iterator<Item = ModContent> + Sized {}
 
-type UsagesMap = HashMap<PathBuf, Vec<Rc<RefCell<ModContent>>>>;
+type UsagesMap = BTreeMap<PathBuf, Vec<Rc<RefCell<ModContent>>>>;
 
 fn add_usage(usages: &mut UsagesMap, diff: ModContent) {
     info!("Filling the list of files touched by mod: {}", diff.name);
@@ -304,18 +436,8 @@ fn add_usage(usages: &mut UsagesMap, diff: ModContent) {
     }
 }
 
-fn try_prepare_merge<E>(
-    mods: impl IntoIterator<Item = Result<ModContent, E>>,
-) -> Result<UsagesMap, E> {
-    let mut usages = HashMap::new();
-    for diff in mods {
-        add_usage(&mut usages, diff?);
-    }
-    Ok(usages)
-}
-
 fn prepare_merge(mods: impl IntoIterator<Item = ModContent>) -> UsagesMap {
-    let mut usages = HashMap::new();
+    let mut usages = BTreeMap::new();
     for diff in mods {
         add_usage(&mut usages, diff);
     }
@@ -324,31 +446,22 @@ fn prepare_merge(mods: impl IntoIterator<Item = ModContent>) -> UsagesMap {
 
 fn merge(
     usages: UsagesMap,
-    mut on_progress: Option<&mut cursive::CbSink>,
-) -> (DiffTree, Conflicts) {
+    progress: Option<&Progress>,
+) -> Result<(DiffTree, Conflicts), Cancelled> {
     let mut conflicts = Conflicts::new();
     let mut merged = DiffTree::new();
 
-    if let Some(sink) = on_progress.as_mut() {
-        crate::run_update(sink, |cursive| {
-            cursive.call_on_name("Loading dialog", |dialog: &mut Dialog| {
-                dialog.set_title("Merging fetched mods...");
-                dialog.call_on_name("Loading part", |text: &mut TextView| {
-                    text.set_content(" ");
-                });
-                dialog.call_on_name("Loading file", |text: &mut TextView| {
-                    text.set_content(" ");
-                });
-            });
-        })
+    if let Some(progress) = progress {
+        progress.phase("Merging fetched mods");
     }
 
     // Now, we'll iterate over files.
-    for (path, mut mods) in usages {
-        let string_path = path.to_string_lossy();
+    let total = usages.len();
+    for (index, (path, mut mods)) in usages.into_iter().enumerate() {
         info!("[merge] {:?}: merging changes", path);
-        if let Some(sink) = on_progress.as_mut() {
-            super::set_file_updated(sink, "Merging", string_path)
+        if let Some(progress) = progress {
+            progress.check()?;
+            progress.file("Merging", path.to_string_lossy(), index + 1, total);
         }
 
         // Sanity check: mods vec shouldn't be empty.
@@ -386,18 +499,107 @@ fn merge(
                 path,
                 list.iter().map(|(name, _)| name).collect::<Vec<_>>()
             );
+            // Changes of different kinds can't be merged - e.g. when one mod's version of file
+            // can be parsed and another one's can't. User will have to choose one of them.
+            if list.iter().any(|(_, node)| node.kind() != kind) {
+                warn!(
+                    "[merge] {:?}: mods are changing file in incompatible ways - putting it to conflicts directly",
+                    path
+                );
+                conflicts.insert(path, list);
+                continue;
+            }
             match kind {
                 // Another simple case is when multiple mods modify (or create) one binary file.
+                // If they all are providing the same file, there's nothing to choose from;
+                // otherwise, the user will choose one of them.
+                DiffNodeKind::Binary => {
+                    let sources: Vec<&Path> = list
+                        .iter()
+                        .map(|(_, node)| match node {
+                            DiffNode::Binary(source) => source.as_path(),
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    if super::assets::identical(&sources) {
+                        info!(
+                            "[merge] {:?}: every mod is providing identical binary file",
+                            path
+                        );
+                        let (_, node) = list.into_iter().last().unwrap();
+                        merged.insert(path, node);
+                    } else {
+                        debug!(
+                            "[merge] {:?}: Diff is binary - putting it to conflicts directly",
+                            path
+                        );
+                        conflicts.insert(path, list);
+                    }
+                }
                 // For multiple mods adding the same text file, we want to ask user to choose one of them as "base",
                 // and then we'll run the diffing again, with "base" being the "vanilla" and all others being "mods".
                 // So, they are directly put into "conflicts", like the binaries.
-                kind @ DiffNodeKind::Binary | kind @ DiffNodeKind::AddedText => {
+                // Overrides, however, contain only what they change, so they can be merged entry by entry;
+                // the result is still an override, so that the game keeps layering it over its own info file.
+                // Buffs and other libraries of definitions are merged by the definition id, too.
+                DiffNodeKind::AddedText
+                    if is_override(&path) || is_buffs_library(&path) || is_library(&path) =>
+                {
+                    let texts: Vec<_> = list
+                        .iter()
+                        .map(|(name, node)| match node {
+                            DiffNode::AddedText(text) => (name.clone(), text.clone()),
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    match merge_added(&path, &texts) {
+                        Some(text) => {
+                            info!("[merge] {:?}: merged additions from every mod", path);
+                            merged.insert(path, DiffNode::AddedText(text));
+                        }
+                        None => {
+                            debug!(
+                                "[merge] {:?}: additions can't be merged - putting them to conflicts directly",
+                                path
+                            );
+                            conflicts.insert(path, list);
+                        }
+                    }
+                }
+                DiffNodeKind::AddedText => {
                     debug!(
-                        "[merge] {:?}: Diff is of kind {:?} - putting it to conflicts directly",
-                        path, kind
+                        "[merge] {:?}: Diff is adding text - putting it to conflicts directly",
+                        path
                     );
                     conflicts.insert(path, list);
                 }
+                // Structured files are merged by their inner paths, so that's also quite easy.
+                DiffNodeKind::ModifiedStructure => {
+                    debug!("[merge] {:?}: Diff is modifying structured file - merging by structure paths", path);
+                    let list = list
+                        .into_iter()
+                        .map(|(name, node)| match node {
+                            DiffNode::ModifiedStructure(changes) => (name, changes),
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    let (merged_changes, conflict_changes) = StructureChangeset::merge(list);
+                    if let Some(merged_changes) = merged_changes {
+                        info!("[merge] {:?}: outputting merged changes", path);
+                        merged.insert(path.clone(), DiffNode::ModifiedStructure(merged_changes));
+                    }
+                    if !conflict_changes.is_empty() {
+                        info!("[merge] {:?}: outputting conflicts", path);
+                        let conflict_changes = conflict_changes
+                            .into_iter()
+                            .map(|(key, changes)| {
+                                debug!("[merge] {:?}: conflicting changes from mod {}", path, key);
+                                (key, DiffNode::ModifiedStructure(changes))
+                            })
+                            .collect();
+                        conflicts.insert(path, conflict_changes);
+                    }
+                }
                 // Now that's getting tricky.
                 DiffNodeKind::ModifiedText => {
                     debug!("[merge] {:?}: Diff is modifying existing text - trying to merge line-by-line", path);
@@ -405,11 +607,12 @@ fn merge(
                     // And we want to merge all non-conflicting cases.
                     // So, we iterate over every changeset, to check which lines are
                     // changed by it.
-                    let mut line_changes: Vec<HashMap<String, LineChange>> = vec![];
-                    let mut conflict_changes = HashMap::new();
+                    // Conflicting changes are kept in the mods order, so that they are always listed the same way.
+                    let mut line_changes: Vec<BTreeMap<String, LineChange>> = vec![];
+                    let mut conflict_changes: Vec<(String, Vec<Option<LineChange>>)> = vec![];
                     for changes in &list {
                         if let (name, DiffNode::ModifiedText(changelist)) = changes {
-                            conflict_changes.insert(name.to_string(), vec![]);
+                            conflict_changes.push((name.to_string(), vec![]));
                             if line_changes.is_empty() {
                                 line_changes.resize_with(changelist.0.len(), Default::default);
                             }
@@ -432,7 +635,7 @@ fn merge(
                         // Trivial case - no changes
                         if line_change.is_empty() {
                             merged_changes.push(None);
-                            for change in conflict_changes.values_mut() {
+                            for (_, change) in &mut conflict_changes {
                                 change.push(None);
                             }
                         }
@@ -444,7 +647,7 @@ fn merge(
                                 path, index, name
                             );
                             merged_changes.push(Some(change));
-                            for change in conflict_changes.values_mut() {
+                            for (_, change) in &mut conflict_changes {
                                 change.push(None);
                             }
                         }
@@ -460,7 +663,7 @@ fn merge(
                                     path, index
                                 );
                                 merged_changes.push(Some(change));
-                                for change in conflict_changes.values_mut() {
+                                for (_, change) in &mut conflict_changes {
                                     change.push(None);
                                 }
                                 continue;
@@ -476,7 +679,7 @@ fn merge(
                                 line_change.keys().collect::<Vec<_>>()
                             );
                             let mut line_change = line_change;
-                            for (name, conflict) in conflict_changes.iter_mut() {
+                            for (name, conflict) in &mut conflict_changes {
                                 let change = line_change.remove(name);
                                 conflict.push(change);
                             }
@@ -490,7 +693,7 @@ fn merge(
                             DiffNode::ModifiedText(LinesChangeset(merged_changes)),
                         );
                     }
-                    conflict_changes.retain(|_, list| !list.iter().all(Option::is_none));
+                    conflict_changes.retain(|(_, list)| !list.iter().all(Option::is_none));
                     if !conflict_changes.is_empty() {
                         info!("[merge] {:?}: outputting conflicts", path);
                         let conflict_changes = conflict_changes
@@ -507,17 +710,33 @@ fn merge(
         }
     }
 
-    (merged, conflicts)
+    Ok((merged, conflicts))
 }
 
 pub trait DiffTreeExt: Sized {
-    fn apply_to(self, _: DataTree) -> DataTree;
+    fn apply_to(self, _: &DataTree) -> Result<DataTree, DataError>;
 }
+
+/// Finds the text of the game file which is being changed.
+fn original_text<'a>(original: &'a DataTree, path: &Path) -> Result<&'a str, DataError> {
+    match original.get(path).map(DataNode::content) {
+        Some(DataNodeContent::Text(text)) => Ok(text),
+        Some(DataNodeContent::Binary) => Err(DataError::new(
+            path,
+            DataErrorKind::KindMismatch {
+                game: "binary",
+                modded: "text",
+            },
+        )),
+        None => Err(DataError::new(path, DataErrorKind::MissingOriginal)),
+    }
+}
+
 impl DiffTreeExt for DiffTree {
-    fn apply_to(self, original: DataTree) -> DataTree {
+    fn apply_to(self, original: &DataTree) -> Result<DataTree, DataError> {
         info!("Applying calculated diff to the source tree");
         self.into_iter()
-            .map(|(path, changes)| match changes {
+            .map(|(path, changes)| Ok(match changes {
                 DiffNode::Binary(source) => {
                     debug!("[apply] {:?}: added binary file from {:?}", path, source);
                     (path, DataNode::new(source, None))
@@ -528,10 +747,15 @@ impl DiffTreeExt for DiffTree {
                 },
                 DiffNode::ModifiedText(changeset) => {
                     debug!("[apply] {:?}: modified text", path);
-                    let orig = match &original.get(&path).unwrap().content {
-                        DataNodeContent::Binary => unreachable!(),
-                        DataNodeContent::Text(text) => text,
-                    };
+                    let orig = original_text(original, &path)?;
+                    let expected = orig.split('\n').count();
+                    if changeset.0.len() != expected {
+                        let kind = DataErrorKind::LineCount {
+                            expected,
+                            found: changeset.0.len(),
+                        };
+                        return Err(DataError::new(path, kind));
+                    }
                     let text = orig
                         .lines()
                         .zip(changeset.0)
@@ -559,7 +783,15 @@ impl DiffTreeExt for DiffTree {
                         .join("\n");
                     (path, DataNode::new("", text))
                 }
-            })
+                DiffNode::ModifiedStructure(changes) => {
+                    debug!("[apply] {:?}: modified structure", path);
+                    let orig = original_text(original, &path)?;
+                    let text = changes
+                        .apply(&path, orig)
+                        .map_err(|kind| DataError::new(&path, kind))?;
+                    (path, DataNode::new("", text))
+                }
+            }))
             .collect()
     }
 }
@@ -0,0 +1,248 @@
+//! Decoding of the text files, which are not always saved as UTF-8.
+
+use std::{fmt::Display, ops::Range};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Utf8 => "UTF-8",
+            Self::Utf16Le => "UTF-16LE",
+            Self::Utf16Be => "UTF-16BE",
+            Self::Windows1252 => "Windows-1252",
+        })
+    }
+}
+
+impl Encoding {
+    fn from_label(label: &str) -> Option<Self> {
+        match label.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Self::Utf8),
+            // Without the byte order mark, UTF-16 is little-endian on every system the game runs on.
+            "utf-16" | "utf16" | "utf-16le" => Some(Self::Utf16Le),
+            "utf-16be" => Some(Self::Utf16Be),
+            "windows-1252" | "cp1252" | "iso-8859-1" | "latin1" => Some(Self::Windows1252),
+            _ => None,
+        }
+    }
+
+    /// Strictly decodes the bytes, returning `None` if they are not valid in this encoding.
+    fn decode(self, bytes: &[u8]) -> Option<String> {
+        let utf16 = |to_unit: fn([u8; 2]) -> u16| {
+            let pairs = bytes.chunks_exact(2);
+            if !pairs.remainder().is_empty() {
+                return None;
+            }
+            let units = pairs.map(|pair| to_unit([pair[0], pair[1]]));
+            std::char::decode_utf16(units)
+                .collect::<Result<String, _>>()
+                .ok()
+        };
+        match self {
+            Self::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+            Self::Utf16Le => utf16(u16::from_le_bytes),
+            Self::Utf16Be => utf16(u16::from_be_bytes),
+            Self::Windows1252 => Some(bytes.iter().map(|&byte| windows_1252(byte)).collect()),
+        }
+    }
+}
+
+/// Characters which Windows-1252 puts in place of the C1 control codes; the rest matches Latin-1.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+fn windows_1252(byte: u8) -> char {
+    match byte {
+        0x80..=0x9F => WINDOWS_1252_HIGH[(byte - 0x80) as usize],
+        _ => byte as char,
+    }
+}
+
+/// XML declaration, e.g. `<?xml version="1.0" encoding="utf-16"?>`, with the positions of its parts in the text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlDeclaration {
+    /// The whole declaration, from `<?xml` to `?>` inclusive.
+    pub range: Range<usize>,
+    /// Value of the `version` attribute, without quotes.
+    pub version: Option<Range<usize>>,
+    /// Value of the `encoding` attribute, without quotes.
+    pub encoding: Option<Range<usize>>,
+}
+
+impl XmlDeclaration {
+    /// Finds the declaration wherever it is, not only at the start of the text.
+    pub fn find(text: &str) -> Option<Self> {
+        let start = text.find("<?xml")?;
+        let end = start + text[start..].find("?>")? + "?>".len();
+        let attribute = |name: &str| {
+            let mut offset = start + "<?xml".len();
+            while let Some(found) = text[offset..end].find(name) {
+                let name_end = offset + found + name.len();
+                let rest = &text[name_end..end];
+                let after_eq = rest.trim_start().strip_prefix('=').map(str::trim_start);
+                if let Some(value) = after_eq {
+                    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+                    let value_start = end - value.len() + 1;
+                    let value_len = text[value_start..end].find(quote)?;
+                    return Some(value_start..value_start + value_len);
+                }
+                offset = name_end;
+            }
+            None
+        };
+        Some(Self {
+            range: start..end,
+            version: attribute("version"),
+            encoding: attribute("encoding"),
+        })
+    }
+}
+
+fn declared_encoding(text: &str) -> Option<&str> {
+    XmlDeclaration::find(text)?
+        .encoding
+        .map(|range| &text[range])
+}
+
+/// Text of the file along with the way it was decoded.
+#[derive(Debug)]
+pub struct Decoded {
+    pub text: String,
+    pub encoding: Encoding,
+    /// Whether the encoding was only a guess, so that some characters could be decoded incorrectly.
+    pub guessed: bool,
+}
+
+/// Decodes the file content, using (in this order) the byte order mark, the encoding declared
+/// in the XML prolog (for XML files), strict UTF-8 and, finally, Windows-1252.
+///
+/// The byte order mark is never included in the text. If the XML prolog declares an encoding
+/// and the text is converted from it, the declaration is changed to UTF-8, since that's what the text is now.
+pub fn decode(bytes: &[u8], xml: bool) -> Decoded {
+    let (bom, content) = match bytes {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => (Some(Encoding::Utf8), rest),
+        [0xFF, 0xFE, rest @ ..] => (Some(Encoding::Utf16Le), rest),
+        [0xFE, 0xFF, rest @ ..] => (Some(Encoding::Utf16Be), rest),
+        // UTF-16 XML without the byte order mark is still recognizable by its first characters, `<?`.
+        [0x3C, 0x00, 0x3F, 0x00, ..] if xml => (Some(Encoding::Utf16Le), bytes),
+        [0x00, 0x3C, 0x00, 0x3F, ..] if xml => (Some(Encoding::Utf16Be), bytes),
+        _ => (None, bytes),
+    };
+    let by_bom = bom.and_then(|encoding| encoding.decode(content).map(|text| (text, encoding)));
+    // The prolog is written in ASCII, so for UTF-16 it could be read only if it was right about the encoding;
+    // for the 8-bit encodings, however, the declaration is the only thing we can trust.
+    let bom_is_8bit = matches!(bom, None | Some(Encoding::Utf8));
+    if xml && bom_is_8bit {
+        let preview = Encoding::Windows1252.decode(content).unwrap();
+        let declared = declared_encoding(&preview)
+            .and_then(Encoding::from_label)
+            .filter(|declared| *declared == Encoding::Windows1252);
+        if let Some(declared) = declared {
+            if bom.is_some() {
+                log::warn!(
+                    "XML declares encoding {}, which contradicts the byte order mark; using the declared one",
+                    declared
+                );
+            }
+            return Decoded {
+                text: declare_utf8(declared.decode(content).unwrap(), declared),
+                encoding: declared,
+                guessed: false,
+            };
+        }
+    }
+    if let Some((text, encoding)) = by_bom {
+        let text = if xml {
+            declare_utf8(text, encoding)
+        } else {
+            text
+        };
+        return Decoded {
+            text,
+            encoding,
+            guessed: false,
+        };
+    }
+    match Encoding::Utf8.decode(content) {
+        Some(text) => Decoded {
+            text,
+            encoding: Encoding::Utf8,
+            guessed: false,
+        },
+        None => Decoded {
+            text: Encoding::Windows1252.decode(content).unwrap(),
+            encoding: Encoding::Windows1252,
+            guessed: true,
+        },
+    }
+}
+
+fn declare_utf8(mut text: String, encoding: Encoding) -> String {
+    if encoding != Encoding::Utf8 {
+        if let Some(range) = XmlDeclaration::find(&text).and_then(|decl| decl.encoding) {
+            text.replace_range(range, "utf-8");
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn declaration() {
+        let text = "\n<?xml version = '1.0' encoding=\"UTF-16\" ?>\n<root/>";
+        let decl = XmlDeclaration::find(text).unwrap();
+        assert_eq!(
+            &text[decl.range],
+            "<?xml version = '1.0' encoding=\"UTF-16\" ?>"
+        );
+        assert_eq!(&text[decl.version.unwrap()], "1.0");
+        assert_eq!(&text[decl.encoding.unwrap()], "UTF-16");
+
+        let decl = XmlDeclaration::find("<?xml version=\"1.0\"?><root/>").unwrap();
+        assert_eq!(decl.encoding, None);
+        assert_eq!(XmlDeclaration::find("<root/>"), None);
+    }
+
+    #[test]
+    fn encodings() {
+        let utf8_bom = decode(include_bytes!("encoding/utf8_bom.darkest"), false);
+        assert_eq!(utf8_bom.encoding, Encoding::Utf8);
+        assert_eq!(utf8_bom.text, "// Крестоносец\nhp: .amount 33\n");
+
+        let cp1252 = decode(include_bytes!("encoding/cp1252.darkest"), false);
+        assert_eq!(
+            (cp1252.encoding, cp1252.guessed),
+            (Encoding::Windows1252, true)
+        );
+        assert_eq!(cp1252.text, "// 90° “turn”\nhp: .amount 33\n");
+
+        let utf16 = decode(include_bytes!("encoding/utf16le.string_table.xml"), true);
+        assert_eq!(utf16.encoding, Encoding::Utf16Le);
+        assert!(utf16
+            .text
+            .starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>"));
+        assert!(utf16.text.contains("<![CDATA[重击]]>"));
+
+        let utf16be = decode(include_bytes!("encoding/utf16be.string_table.xml"), true);
+        assert_eq!(utf16be.encoding, Encoding::Utf16Be);
+        assert!(utf16be.text.contains("<![CDATA[重击]]>"));
+
+        // Saved as Windows-1252 by the editor, which kept the UTF-8 byte order mark.
+        let declared = decode(include_bytes!("encoding/declared.string_table.xml"), true);
+        assert_eq!(declared.encoding, Encoding::Windows1252);
+        assert!(!declared.guessed);
+        assert!(declared.text.contains("<![CDATA[Café]]>"));
+    }
+}
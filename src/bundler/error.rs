@@ -1,18 +1,79 @@
-use std::path::PathBuf;
+use super::{
+    baseline::{self, DriftedFile},
+    progress::Cancelled,
+    resolve::OpenQuestion,
+};
+use std::{fmt::Display, iter::FromIterator, path::PathBuf};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum BundlerError {
+    #[error("Game root was not set")]
+    NoGameRoot,
+    #[error("Darkest Dungeon installation not found in {0:?}")]
+    NoGame(PathBuf),
     #[error("Error while extracting data")]
     Extraction(#[from] ExtractionError),
     #[error("Error while deploying bundle")]
     Deployment(#[from] DeploymentError),
+    #[error("Error while applying merged changes")]
+    Data(#[from] DataError),
+    #[error("Conflict between mods was left unresolved")]
+    Unresolved(#[from] Unresolved),
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
+    #[error("Game files changed since the profile was saved:{}", baseline::render(.0))]
+    BaselineDrift(Vec<DriftedFile>),
+    #[error("{} questions about the conflicts were left unanswered:{}", .0.len(), render_questions(.0))]
+    Unanswered(Vec<OpenQuestion>),
+}
+
+/// Lists the questions by their first lines, one per indented line.
+fn render_questions(questions: &[OpenQuestion]) -> String {
+    questions
+        .iter()
+        .map(|question| format!("\n  {}", question.question.lines().next().unwrap_or("")))
+        .collect()
+}
+
+/// Returned by the resolve backend which can't (or won't) answer the question.
+#[derive(Debug, Error)]
+#[error("No answer for the question: {0}")]
+pub struct Unresolved(pub String);
+
+/// Failure to resolve the conflicts: either some question was left unanswered,
+/// or the versions of the file chosen to be merged can't be merged after all.
+#[derive(Debug, Error)]
+pub enum ResolveError {
+    #[error(transparent)]
+    Unresolved(#[from] Unresolved),
+    #[error(transparent)]
+    Data(#[from] DataError),
+}
+
+impl From<ResolveError> for BundlerError {
+    fn from(err: ResolveError) -> Self {
+        match err {
+            ResolveError::Unresolved(err) => Self::Unresolved(err),
+            ResolveError::Data(err) => Self::Data(err),
+        }
+    }
+}
+
+fn render_errors(errors: &[ExtractionError]) -> String {
+    errors.iter().map(|err| format!("\n  {}", err)).collect()
 }
 
 #[derive(Debug, Error)]
 pub enum ExtractionError {
     #[error("IO error encountered on path {1}")]
     Io(#[source] std::io::Error, PathBuf),
+    #[error("{} errors encountered while extracting data:{}", .0.len(), render_errors(.0))]
+    Multiple(Vec<ExtractionError>),
+    #[error(transparent)]
+    Data(#[from] DataError),
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
 }
 
 impl ExtractionError {
@@ -20,6 +81,49 @@ impl ExtractionError {
         let path = path.into();
         |err| Self::Io(err, path)
     }
+
+    /// Attaches the mod name to the data errors, which don't know where the data came from.
+    pub fn in_mod(self, name: &str) -> Self {
+        match self {
+            Self::Data(err) => Self::Data(err.in_mods(Some(name))),
+            Self::Multiple(errors) => {
+                Self::Multiple(errors.into_iter().map(|err| err.in_mod(name)).collect())
+            }
+            err => err,
+        }
+    }
+
+    /// Files which can't be parsed, if these are the only problems found.
+    pub fn malformed(&self) -> Option<Vec<&DataError>> {
+        match self {
+            Self::Data(err) if matches!(err.kind, DataErrorKind::Malformed(_)) => Some(vec![err]),
+            Self::Multiple(errors) => errors
+                .iter()
+                .map(Self::malformed)
+                .collect::<Option<Vec<_>>>()
+                .map(|nested| nested.into_iter().flatten().collect()),
+            _ => None,
+        }
+    }
+
+    /// Collects the results of independent operations, so that every failure is reported, not only the first one.
+    pub fn collect<T, C: FromIterator<T>>(
+        results: impl IntoIterator<Item = Result<T, Self>>,
+    ) -> Result<C, Self> {
+        let mut errors = vec![];
+        let values = results
+            .into_iter()
+            .filter_map(|result| result.map_err(|err| errors.push(err)).ok())
+            .collect();
+        if errors.iter().any(|err| matches!(err, Self::Cancelled(_))) {
+            return Err(Cancelled.into());
+        }
+        match errors.len() {
+            0 => Ok(values),
+            1 => Err(errors.remove(0)),
+            _ => Err(Self::Multiple(errors)),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -28,6 +132,8 @@ pub enum DeploymentError {
     Io(#[source] std::io::Error, PathBuf),
     #[error("User chose not to overwrite existing directory")]
     AlreadyExists,
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
 }
 
 impl DeploymentError {
@@ -36,3 +142,160 @@ impl DeploymentError {
         |err| Self::Io(err, path)
     }
 }
+
+/// Where the data which can't be merged or applied came from.
+#[derive(Debug, Clone, Default)]
+pub struct DataContext {
+    /// Mods which changed the file; empty if they are not known yet.
+    pub mods: Vec<String>,
+    /// File the data was read from, if it was read from the mod directly.
+    pub source: Option<PathBuf>,
+    /// Path of the file inside the game.
+    pub target: PathBuf,
+}
+
+impl Display for DataContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.target.to_string_lossy())?;
+        match self.mods.as_slice() {
+            [] => {}
+            [the_mod] => write!(f, " in mod {}", the_mod)?,
+            mods => write!(f, " in mods {}", mods.join(", "))?,
+        }
+        if let Some(source) = &self.source {
+            write!(f, " (read from {})", source.to_string_lossy())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DataErrorKind {
+    #[error("the game file is {game}, but the mod's one is {modded}")]
+    KindMismatch {
+        game: &'static str,
+        modded: &'static str,
+    },
+    #[error("the file is changed, but it doesn't exist in the game")]
+    MissingOriginal,
+    #[error("the original file can't be parsed anymore: {0}")]
+    Unparsable(String),
+    #[error("changes are made to {found} lines, but the file has {expected}")]
+    LineCount { expected: usize, found: usize },
+    #[error("the mod's version can't be parsed, though the game's one can: {0}")]
+    Malformed(Box<ParseFailure>),
+}
+
+/// Failure to parse the file, e.g. because it was truncated while downloading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFailure {
+    pub message: String,
+    /// Where the parser stopped; `None` if the text is well-formed, but doesn't have the expected structure.
+    pub location: Option<TextLocation>,
+}
+
+/// Position in the text, along with the start of the text which wasn't parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextLocation {
+    /// Byte offset from the start of the text.
+    pub offset: usize,
+    /// Line number, starting from 1.
+    pub line: usize,
+    /// Character number in the line, starting from 1.
+    pub column: usize,
+    /// Up to [`TextLocation::EXCERPT`] characters starting at the location; empty at the end of text.
+    pub remainder: String,
+}
+
+impl TextLocation {
+    pub const EXCERPT: usize = 80;
+
+    /// Locates the byte offset in the text; offsets beyond the end (or inside a character) are moved back.
+    pub fn at_offset(source: &str, offset: usize) -> Self {
+        let mut offset = offset.min(source.len());
+        while !source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line_start = source[..offset].rfind('\n').map_or(0, |pos| pos + 1);
+        Self {
+            offset,
+            line: source[..offset].matches('\n').count() + 1,
+            column: source[line_start..offset].chars().count() + 1,
+            remainder: source[offset..].chars().take(Self::EXCERPT).collect(),
+        }
+    }
+
+    /// Locates the line and column, both starting from 1.
+    pub fn at_line(source: &str, line: usize, column: usize) -> Self {
+        let line_start = source
+            .split_inclusive('\n')
+            .take(line.saturating_sub(1))
+            .map(str::len)
+            .sum::<usize>();
+        let offset = source[line_start..]
+            .char_indices()
+            .nth(column.saturating_sub(1))
+            .map_or(source.len(), |(offset, _)| line_start + offset);
+        Self::at_offset(source, offset)
+    }
+}
+
+impl Display for ParseFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            None => write!(f, "{}", self.message),
+            Some(location) if location.remainder.is_empty() => write!(
+                f,
+                "line {}, column {}: {} (at the end of file - is it truncated?)",
+                location.line, location.column, self.message
+            ),
+            Some(location) => write!(
+                f,
+                "line {}, column {}: {}; unparsed text starts with {:?}",
+                location.line, location.column, self.message, location.remainder
+            ),
+        }
+    }
+}
+
+/// Mod data which can't be merged or applied, along with the file and mod it came from.
+#[derive(Debug, Error)]
+#[error("{context}: {kind}")]
+pub struct DataError {
+    pub context: DataContext,
+    pub kind: DataErrorKind,
+}
+
+impl DataError {
+    pub fn new(target: impl Into<PathBuf>, kind: DataErrorKind) -> Self {
+        Self {
+            context: DataContext {
+                target: target.into(),
+                ..Default::default()
+            },
+            kind,
+        }
+    }
+
+    pub fn with_source(mut self, source: impl Into<PathBuf>) -> Self {
+        self.context.source = Some(source.into());
+        self
+    }
+
+    /// Attaches the names of mods which changed the file.
+    pub fn in_mods<'a>(mut self, mods: impl IntoIterator<Item = &'a str>) -> Self {
+        self.context.mods.extend(mods.into_iter().map(String::from));
+        self
+    }
+}
+
+/// Failure to compare two deployed bundles.
+#[derive(Debug, Error)]
+pub enum CompareError {
+    #[error("{0:?} is not a bundle made by this version of the bundler: it has no record of the mods changes")]
+    NotABundle(PathBuf),
+    #[error("Failed to read {0:?}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("Broken record of the mods changes in {0:?}")]
+    Contributions(PathBuf, #[source] serde_json::Error),
+}
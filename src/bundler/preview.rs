@@ -0,0 +1,442 @@
+//! Preview of the bundle: every value it changes in the game, compared with the vanilla data,
+//! along with the mods responsible for the change.
+
+use super::{
+    compare::Contribution,
+    diff::{DataNode, DataNodeContent, DataTree},
+    report::{BundleReport, Resolution},
+    structures::{align, is_strings_table, preview_values, Aligned, PreviewValue},
+};
+use difference::{Changeset, Difference};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Kind of the game data, by which the files are grouped in the preview.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DataKind {
+    Darkest,
+    Strings,
+    Json,
+    Text,
+    Assets,
+}
+
+impl DataKind {
+    fn of(path: &Path, node: &DataNode) -> Self {
+        if let DataNodeContent::Binary = node.content() {
+            return Self::Assets;
+        }
+        match path.extension().and_then(std::ffi::OsStr::to_str) {
+            Some("darkest") => Self::Darkest,
+            Some("json") => Self::Json,
+            Some("xml") if is_strings_table(path) => Self::Strings,
+            _ => Self::Text,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Self::Darkest => ".darkest files",
+            Self::Strings => "Strings tables",
+            Self::Json => "JSON files",
+            Self::Text => "Other text files",
+            Self::Assets => "Assets",
+        }
+    }
+}
+
+/// Change of one value of the structured file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueChange {
+    pub entity: String,
+    pub field: String,
+    /// Value in the vanilla game, split into its parts (e.g. the effects of the skill); `None` if it is added.
+    pub old: Option<Vec<String>>,
+    /// Value in the bundle; `None` if it is removed.
+    pub new: Option<Vec<String>>,
+    /// Mods whose change is kept in the bundle.
+    pub mods: Vec<String>,
+    /// Mods which changed the value too, but differently.
+    pub overridden: Vec<String>,
+    /// How the conflict over this value was resolved, if there was one.
+    pub resolution: Option<Resolution>,
+}
+
+/// Changes made by the bundle to one file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilePreview {
+    pub path: PathBuf,
+    pub kind: DataKind,
+    /// Whether the file is not in the vanilla game.
+    pub added: bool,
+    /// Every mod changing the file.
+    pub mods: Vec<String>,
+    /// Changed values, if the file has known structure, grouped by their entities.
+    pub values: Vec<ValueChange>,
+    /// Removed (`- `) and added (`+ `) lines of the other text files.
+    pub lines: Vec<String>,
+}
+
+/// Everything the bundle changes in the game, grouped by the kind of data and then by file.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BundlePreview {
+    pub files: Vec<FilePreview>,
+}
+
+impl BundlePreview {
+    /// Compares the merged data with the vanilla one; the files whose values are all left as they were
+    /// (e.g. only reformatted by the bundler) are not listed.
+    pub fn new(original: &DataTree, data: &DataTree, report: &BundleReport) -> Self {
+        let mut files: Vec<_> = data
+            .iter()
+            .filter_map(|(path, node)| file_preview(path, original.get(path), node, report))
+            .collect();
+        files.sort_by(|first, second| (first.kind, &first.path).cmp(&(second.kind, &second.path)));
+        Self { files }
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::from("Changes made by the bundle to the vanilla game:\n");
+        if self.files.is_empty() {
+            text.push_str("  none\n");
+        }
+        let mut last_kind = None;
+        for file in &self.files {
+            if last_kind != Some(file.kind) {
+                last_kind = Some(file.kind);
+                text.push_str(&format!("\n{}:\n", file.kind.title()));
+            }
+            text.push_str(&format!(
+                "  {} - {} by {}\n",
+                file.path.to_string_lossy().replace('\\', "/"),
+                if file.added { "added" } else { "changed" },
+                file.mods.join(", ")
+            ));
+            let mut last_entity = None;
+            for change in &file.values {
+                if last_entity != Some(&change.entity) {
+                    last_entity = Some(&change.entity);
+                    text.push_str(&format!("    {}\n", change.entity));
+                }
+                render_value(&mut text, change);
+            }
+            for line in &file.lines {
+                text.push_str(&format!("      {}\n", line));
+            }
+        }
+        text
+    }
+}
+
+fn render_value(text: &mut String, change: &ValueChange) {
+    let mut sources = vec![];
+    if !change.mods.is_empty() {
+        sources.push(change.mods.join(", "));
+    }
+    if !change.overridden.is_empty() {
+        sources.push(format!("also changed by {}", change.overridden.join(", ")));
+    }
+    if let Some(resolution) = &change.resolution {
+        sources.push(format!("resolved by {}", resolution));
+    }
+    let sources = if sources.is_empty() {
+        String::new()
+    } else {
+        format!("  [{}]", sources.join("; "))
+    };
+    let empty = vec![];
+    let (old, new) = (
+        change.old.as_ref().unwrap_or(&empty),
+        change.new.as_ref().unwrap_or(&empty),
+    );
+    // Lists are shown item by item, aligned against each other, so that the changed items stand out.
+    if old.len() > 1 || new.len() > 1 {
+        text.push_str(&format!("      {}:{}\n", change.field, sources));
+        for item in align(old, new) {
+            let (marker, item) = match item {
+                Aligned::Same(item) => (' ', item),
+                Aligned::Removed(item) => ('-', item),
+                Aligned::Added(item) => ('+', item),
+            };
+            text.push_str(&format!("        {} {}\n", marker, item));
+        }
+        return;
+    }
+    let value = match (&change.old, &change.new) {
+        (Some(old), Some(new)) => format!("{} -> {}", old.join(" "), new.join(" ")),
+        (None, Some(new)) => format!("+ {}", new.join(" ")),
+        (Some(old), None) => format!("- {}", old.join(" ")),
+        (None, None) => String::new(),
+    };
+    text.push_str(&format!("      {}: {}{}\n", change.field, value, sources));
+}
+
+fn file_preview(
+    path: &Path,
+    original: Option<&DataNode>,
+    node: &DataNode,
+    report: &BundleReport,
+) -> Option<FilePreview> {
+    let kind = DataKind::of(path, node);
+    let mods = report
+        .files
+        .iter()
+        .find(|file| file.path == path)
+        .map(|file| file.mods.clone())
+        .unwrap_or_default();
+    let mut preview = FilePreview {
+        path: path.to_owned(),
+        kind,
+        added: original.is_none(),
+        mods,
+        values: vec![],
+        lines: vec![],
+    };
+    let text = match node.content() {
+        DataNodeContent::Text(text) => text,
+        DataNodeContent::Binary => return Some(preview),
+    };
+    let original = original.and_then(|node| match node.content() {
+        DataNodeContent::Text(text) => Some(text.as_str()),
+        DataNodeContent::Binary => None,
+    });
+    match value_changes(path, original, text, report) {
+        Some(values) if values.is_empty() => return None,
+        Some(values) => preview.values = values,
+        None => match original {
+            Some(original) => {
+                preview.lines = changed_lines(original, text);
+                if preview.lines.is_empty() {
+                    return None;
+                }
+            }
+            None => preview.lines = vec![format!("{} lines", text.lines().count())],
+        },
+    }
+    Some(preview)
+}
+
+/// Changed values of the file, or `None` if its structure is unknown or it can't be parsed.
+fn value_changes(
+    path: &Path,
+    original: Option<&str>,
+    text: &str,
+    report: &BundleReport,
+) -> Option<Vec<ValueChange>> {
+    let new = preview_values(path, text).ok()?;
+    let old = match original {
+        Some(original) => preview_values(path, original).ok()?,
+        None => vec![],
+    };
+    let old_items: BTreeMap<_, _> = old.iter().map(|value| (&value.item, value)).collect();
+    let new_items: BTreeMap<_, _> = new.iter().map(|value| (&value.item, value)).collect();
+    // Entities are listed in the order of the merged file, followed by the removed ones.
+    let mut entities: Vec<&str> = vec![];
+    for value in new.iter().chain(&old) {
+        if !entities.contains(&value.entity.as_str()) {
+            entities.push(&value.entity);
+        }
+    }
+    let contributions: Vec<(&str, Vec<&Contribution>)> = report
+        .mods
+        .iter()
+        .map(|name| {
+            let changes = report.contributions.0.get(name).into_iter().flatten();
+            (
+                name.as_str(),
+                changes.filter(|change| change.path == path).collect(),
+            )
+        })
+        .collect();
+
+    let changed = new
+        .iter()
+        .filter(
+            |value| !matches!(old_items.get(&value.item), Some(old) if old.parts == value.parts),
+        )
+        .map(|value| (value, old_items.get(&value.item).copied(), Some(value)));
+    let removed = old
+        .iter()
+        .filter(|value| !new_items.contains_key(&value.item))
+        .map(|value| (value, Some(value), None));
+    let mut changes: Vec<_> = changed
+        .chain(removed)
+        .map(|(value, old, new)| {
+            let (mods, overridden) = attribute(&contributions, &value.item, new);
+            let resolution = report
+                .conflicts
+                .iter()
+                .find(|conflict| {
+                    conflict.path == path && conflict.item.as_deref() == Some(&value.item)
+                })
+                .map(|conflict| conflict.resolution);
+            ValueChange {
+                entity: value.entity.clone(),
+                field: value.field.clone(),
+                old: old.map(|old| old.parts.clone()),
+                new: new.map(|new| new.parts.clone()),
+                mods,
+                overridden,
+                resolution,
+            }
+        })
+        .collect();
+    changes.sort_by_key(|change| entities.iter().position(|entity| *entity == change.entity));
+    Some(changes)
+}
+
+/// Splits the mods changing the value into the ones whose change is kept in the bundle and the others.
+///
+/// If no change is kept exactly as the mod made it, e.g. for the set items or the combined chains,
+/// every mod changing the value is counted as kept.
+fn attribute(
+    contributions: &[(&str, Vec<&Contribution>)],
+    item: &str,
+    new: Option<&PreviewValue>,
+) -> (Vec<String>, Vec<String>) {
+    let expected = match new {
+        Some(value) => format!("set {}", value.value),
+        None => "removed ".to_owned(),
+    };
+    // The set items are addressed by the path of the value followed by the item.
+    let item_prefix = format!("{} ", item);
+    let mut kept = vec![];
+    let mut overridden = vec![];
+    for (name, changes) in contributions {
+        let touching: Vec<_> = changes
+            .iter()
+            .filter(|change| match change.item.as_deref() {
+                Some(changed) => changed == item || changed.starts_with(&item_prefix),
+                None => false,
+            })
+            .collect();
+        if touching
+            .iter()
+            .any(|change| change.item.as_deref() == Some(item) && change.change == expected)
+        {
+            kept.push(name.to_string());
+        } else if !touching.is_empty() {
+            overridden.push(name.to_string());
+        }
+    }
+    if kept.is_empty() {
+        return (overridden, vec![]);
+    }
+    (kept, overridden)
+}
+
+/// Lines removed from the vanilla text and added to it, in the text order.
+fn changed_lines(original: &str, text: &str) -> Vec<String> {
+    let mut lines = vec![];
+    for difference in Changeset::new(original, text, "\n").diffs {
+        let (marker, part) = match &difference {
+            Difference::Same(_) => continue,
+            Difference::Rem(part) => ('-', part),
+            Difference::Add(part) => ('+', part),
+        };
+        lines.extend(part.lines().map(|line| format!("{} {}", marker, line)));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::diff::{DataTreeExt, ModContent};
+    use super::*;
+
+    fn tree(files: &[(&str, &str)]) -> DataTree {
+        files
+            .iter()
+            .map(|(path, text)| (PathBuf::from(path), DataNode::new(path, text.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn values_are_attributed_to_mods() {
+        let hero = "heroes/vestal/vestal.info.darkest";
+        let notes = "scripts/notes.txt";
+        let original = tree(&[
+            (
+                hero,
+                "resistances: .stun 40% .poison 30%\ncombat_skill: .id \"judgement\" .effect \"Stun 1\" \"Bleed 1\" \"Mark\"\n",
+            ),
+            (notes, "first\nsecond\n"),
+        ]);
+        let stun = tree(&[(
+            hero,
+            "resistances: .stun 60% .poison 30%\ncombat_skill: .id \"judgement\" .effect \"Stun 1\" \"Bleed 1\" \"Mark\"\n",
+        )]);
+        let effects = tree(&[
+            (
+                hero,
+                "resistances: .stun 40% .poison 30%\ncombat_skill: .id \"judgement\" .effect \"Stun 1\" \"Bleed 2\" \"Mark\"\n",
+            ),
+            (notes, "first\nchanged\n"),
+        ]);
+        let mods = vec![
+            ModContent::new("stun", original.diff(stun).unwrap()),
+            ModContent::new("effects", original.diff(effects).unwrap()),
+        ];
+        let report = BundleReport::new(&mods);
+        let data = tree(&[
+            (
+                hero,
+                "resistances: .stun 60% .poison 30%\n\ncombat_skill: .id \"judgement\" .effect \"Stun 1\" \"Bleed 2\" \"Mark\"\n",
+            ),
+            (notes, "first\nchanged\n"),
+        ]);
+
+        let preview = BundlePreview::new(&original, &data, &report);
+        assert_eq!(preview.files.len(), 2);
+        assert_eq!(
+            preview.files[0].values[0],
+            ValueChange {
+                entity: "resistances: #0".into(),
+                field: ".stun".into(),
+                old: Some(vec!["40%".into()]),
+                new: Some(vec!["60%".into()]),
+                mods: vec!["stun".into()],
+                overridden: vec![],
+                resolution: None,
+            }
+        );
+        assert_eq!(
+            preview.render(),
+            "Changes made by the bundle to the vanilla game:
+
+.darkest files:
+  heroes/vestal/vestal.info.darkest - changed by stun, effects
+    resistances: #0
+      .stun: 40% -> 60%  [stun]
+    combat_skill: \"judgement\"
+      .effect:  [effects]
+          Stun 1
+        - Bleed 1
+        + Bleed 2
+          Mark
+
+Other text files:
+  scripts/notes.txt - changed by effects
+      - second
+      + changed
+"
+        );
+
+        // The resolved conflict is described in the words of the report.
+        let mut text = String::new();
+        let resolved = ValueChange {
+            mods: vec!["effects".into()],
+            overridden: vec!["stun".into()],
+            resolution: Some(Resolution::Priority),
+            ..preview.files[0].values[0].clone()
+        };
+        render_value(&mut text, &resolved);
+        assert!(
+            text.contains("[effects; also changed by stun; resolved by mod priority]"),
+            "{}",
+            text
+        );
+    }
+}
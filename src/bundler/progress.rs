@@ -0,0 +1,109 @@
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use log::*;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("Bundling was cancelled by user")]
+pub struct Cancelled;
+
+/// Events emitted by the bundling pipeline.
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    /// New stage of the pipeline, e.g. "Loading vanilla game data".
+    Phase(String),
+    /// Part of the current stage, e.g. the mod being loaded.
+    Part(String),
+    /// One more file was processed in the current part.
+    File {
+        action: &'static str,
+        path: String,
+        done: usize,
+        total: usize,
+    },
+}
+
+/// Reporting end of the progress channel, shared by every pipeline stage.
+#[derive(Clone)]
+pub struct Progress {
+    sender: Sender<ProgressEvent>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Progress {
+    pub fn new() -> (Self, Receiver<ProgressEvent>) {
+        let (sender, receiver) = unbounded();
+        let progress = Self {
+            sender,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        (progress, receiver)
+    }
+
+    fn send(&self, event: ProgressEvent) {
+        // The receiving side might be gone if the UI was closed; this is not our business.
+        let _ = self.sender.send(event);
+    }
+
+    pub fn phase(&self, name: impl Into<String>) {
+        let name = name.into();
+        info!("Pipeline phase: {}", name);
+        self.send(ProgressEvent::Phase(name));
+    }
+
+    pub fn part(&self, name: impl Into<String>) {
+        self.send(ProgressEvent::Part(name.into()));
+    }
+
+    pub fn file(&self, action: &'static str, path: impl Into<String>, done: usize, total: usize) {
+        self.send(ProgressEvent::File {
+            action,
+            path: path.into(),
+            done,
+            total,
+        });
+    }
+
+    /// Asks the pipeline to stop at the next checkpoint.
+    pub fn cancel(&self) {
+        info!("Cancellation requested");
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Checkpoint between the files; fails if the user has cancelled the bundling.
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancellation_is_shared() {
+        let (progress, events) = Progress::new();
+        let other = progress.clone();
+        other.file("Reading", "heroes/crusader/crusader.info.darkest", 1, 2);
+        assert!(progress.check().is_ok());
+        other.cancel();
+        assert!(progress.check().is_err());
+        drop((progress, other));
+        let events: Vec<_> = events.iter().collect();
+        assert!(matches!(
+            events.as_slice(),
+            [ProgressEvent::File {
+                done: 1,
+                total: 2,
+                ..
+            }]
+        ));
+    }
+}
@@ -0,0 +1,402 @@
+use super::{
+    baseline::{self, DriftedFile},
+    classify::ModSummary,
+    compare::Contributions,
+    diff::{DiffNode, DiffNodeKind, ModContent},
+    structures::is_override,
+    validate::{self, ValidationWarning},
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+/// How the file got into the bundle.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChange {
+    /// Taken from the single mod as is.
+    Copied,
+    /// Single mod's changes applied to the vanilla file.
+    Patched,
+    /// Changes from several mods merged together.
+    Merged,
+}
+
+/// Who made the decision on the conflict.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    User,
+    Priority,
+    /// Answer was taken from the profile.
+    Saved,
+    /// One of the mods is always preferred over the others.
+    Rule,
+    /// The value was chosen by the value rule from the profile, e.g. the largest one.
+    ValueRule,
+    /// Changes to the different parts of the ordered list, e.g. the chain of effects, were all applied.
+    Combined,
+}
+
+impl Display for Resolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::User => "the user",
+            Self::Priority => "mod priority",
+            Self::Saved => "the saved answer",
+            Self::Rule => "the mod rule",
+            Self::ValueRule => "the value rule",
+            Self::Combined => "combining the changes",
+        })
+    }
+}
+
+/// Role of the hero file: the game reads the `.info.darkest` first and then layers the `.override.darkest` over it.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeroFileRole {
+    /// Full definition of the hero.
+    Info,
+    /// Only the changed entries, layered over the game's own info file.
+    Override,
+    /// Only the changed entries, layered over the info file which is changed by the bundle, too.
+    OverrideOfBundledInfo,
+    /// Any other `.darkest` file of the hero, e.g. its `.art.darkest`.
+    Other,
+}
+
+impl HeroFileRole {
+    fn of(path: &Path, files: &BTreeMap<&Path, Vec<(&str, DiffNodeKind)>>) -> Option<Self> {
+        let name = path.to_string_lossy();
+        if is_override(path) {
+            let info = PathBuf::from(name.replace(".override.darkest", ".info.darkest"));
+            if files.contains_key(info.as_path()) {
+                Some(Self::OverrideOfBundledInfo)
+            } else {
+                Some(Self::Override)
+            }
+        } else if name.ends_with(".info.darkest") {
+            Some(Self::Info)
+        } else if path.starts_with("heroes") && name.ends_with(".darkest") {
+            Some(Self::Other)
+        } else {
+            None
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Info => "info: full hero definition, replacing the game's one",
+            Self::Override => {
+                "override: kept as override, so that the game updates to the hero still apply"
+            }
+            Self::OverrideOfBundledInfo => {
+                "override: kept as override, layered over the info file from the bundle"
+            }
+            Self::Other => "other hero data: merged entry by entry, like the info file",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub change: FileChange,
+    pub mods: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<HeroFileRole>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ConflictEntry {
+    pub path: PathBuf,
+    /// Path to the conflicting value inside the file, if the file was merged structurally.
+    pub item: Option<String>,
+    pub mods: Vec<String>,
+    pub resolution: Resolution,
+    pub value: String,
+    /// The value rule which decided the conflict, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+}
+
+impl ConflictEntry {
+    /// Creates the entries for the resolved conflict - one per changed value, if the file is structured.
+    pub fn from_resolved(
+        path: &Path,
+        mods: Vec<String>,
+        resolution: Resolution,
+        resolved: &DiffNode,
+    ) -> Vec<Self> {
+        let entry = |item, value| Self {
+            path: path.to_owned(),
+            item,
+            mods: mods.clone(),
+            resolution,
+            value,
+            rule: None,
+        };
+        match resolved {
+            DiffNode::ModifiedStructure(changes) => changes
+                .entries()
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = value.unwrap_or_else(|| "<removed>".into());
+                    entry(Some(key), value)
+                })
+                .collect(),
+            DiffNode::Binary(source) => vec![entry(None, source.to_string_lossy().into_owned())],
+            DiffNode::AddedText(text) => {
+                vec![entry(
+                    None,
+                    format!("{} lines of text", text.lines().count()),
+                )]
+            }
+            DiffNode::ModifiedText(changes) => {
+                let changed = changes.0.iter().filter(|change| change.is_some()).count();
+                vec![entry(None, format!("{} lines changed", changed))]
+            }
+        }
+    }
+}
+
+/// Mod file left out of the bundle, since it couldn't be parsed.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub mod_name: String,
+    pub reason: String,
+    /// Whether the whole mod was left out because of this file.
+    pub whole_mod: bool,
+}
+
+/// Record of everything the bundler did, stored alongside the bundle.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct BundleReport {
+    pub mods: Vec<String>,
+    /// What every mod brings into the bundle, in the same order as `mods`.
+    pub contents: Vec<ModSummary>,
+    pub files: Vec<FileEntry>,
+    pub conflicts: Vec<ConflictEntry>,
+    /// Problems found in the merged data; these don't prevent the bundle from being deployed.
+    pub warnings: Vec<ValidationWarning>,
+    pub skipped: Vec<SkippedFile>,
+    /// Game files changed since the previous run, whose changes were calculated again.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub drift: Vec<DriftedFile>,
+    /// Changes made by every mod, stored separately, to compare the bundle with another one.
+    #[serde(skip)]
+    pub contributions: Contributions,
+}
+
+impl BundleReport {
+    /// Collects the list of mods and the files they touch, before these mods are merged.
+    pub fn new(mods: &[ModContent]) -> Self {
+        let mut files: BTreeMap<&Path, Vec<(&str, DiffNodeKind)>> = BTreeMap::new();
+        for the_mod in mods {
+            for (path, node) in the_mod.diff() {
+                files
+                    .entry(path)
+                    .or_default()
+                    .push((the_mod.name(), node.kind()));
+            }
+        }
+        let files = files
+            .iter()
+            .map(|(path, touched)| {
+                let role = HeroFileRole::of(path, &files);
+                let change = match touched.as_slice() {
+                    [(_, DiffNodeKind::Binary)] | [(_, DiffNodeKind::AddedText)] => {
+                        FileChange::Copied
+                    }
+                    [_] => FileChange::Patched,
+                    _ => FileChange::Merged,
+                };
+                FileEntry {
+                    path: path.to_path_buf(),
+                    change,
+                    mods: touched.iter().map(|(name, _)| (*name).to_owned()).collect(),
+                    role,
+                }
+            })
+            .collect();
+        Self {
+            mods: mods
+                .iter()
+                .map(|the_mod| the_mod.name().to_owned())
+                .collect(),
+            contents: vec![],
+            files,
+            conflicts: vec![],
+            warnings: vec![],
+            skipped: vec![],
+            drift: vec![],
+            contributions: Contributions::new(mods),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::from("Bundled mods (in order of priority):\n");
+        for the_mod in &self.mods {
+            text.push_str(&format!("  {}", the_mod));
+            let summary = self
+                .contents
+                .iter()
+                .find(|summary| summary.name == *the_mod);
+            if let Some(summary) = summary {
+                text.push_str(&format!(" - {}", summary.describe()));
+                for path in &summary.unrecognized {
+                    text.push_str(&format!("\n    unrecognized: {}", path.to_string_lossy()));
+                }
+            }
+            text.push('\n');
+        }
+        text.push_str("\nFiles:\n");
+        for file in &self.files {
+            text.push_str(&format!(
+                "  {} - {:?} ({})",
+                file.path.to_string_lossy(),
+                file.change,
+                file.mods.join(", ")
+            ));
+            if let Some(role) = file.role {
+                text.push_str(&format!(" - {}", role.describe()));
+            }
+            text.push('\n');
+        }
+        text.push_str("\nConflicts:\n");
+        if self.conflicts.is_empty() {
+            text.push_str("  none\n");
+        }
+        for conflict in &self.conflicts {
+            text.push_str(&format!("  {}", conflict.path.to_string_lossy()));
+            if let Some(item) = &conflict.item {
+                text.push_str(&format!(" [{}]", item));
+            }
+            text.push_str(&format!(
+                " - mods: {}; resolved by {}",
+                conflict.mods.join(", "),
+                conflict.resolution
+            ));
+            if let Some(rule) = &conflict.rule {
+                text.push_str(&format!(" ({})", rule));
+            }
+            text.push_str(&format!(": {}\n", conflict.value));
+        }
+        if !self.skipped.is_empty() {
+            text.push_str("\nSkipped files:\n");
+            for file in &self.skipped {
+                text.push_str(&format!(
+                    "  {} in mod {}",
+                    file.path.to_string_lossy(),
+                    file.mod_name
+                ));
+                if file.whole_mod {
+                    text.push_str(" (whole mod skipped)");
+                }
+                text.push_str(&format!(": {}\n", file.reason));
+            }
+        }
+        if !self.drift.is_empty() {
+            text.push_str("\nGame files changed since the previous run:");
+            text.push_str(&baseline::render(&self.drift));
+            text.push('\n');
+        }
+        if !self.warnings.is_empty() {
+            text.push_str("\nWarnings:\n");
+            for warning in &self.warnings {
+                text.push_str(&format!("  {}\n", warning));
+            }
+        }
+        text
+    }
+
+    /// Writes both the human-readable and the machine-readable versions of report into the directory,
+    /// along with the changes made by every mod and, in a separate file, the warnings.
+    pub fn write(&self, dir: &Path) -> std::io::Result<()> {
+        std::fs::write(dir.join("report.txt"), self.render())?;
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        std::fs::write(dir.join("report.json"), json)?;
+        std::fs::write(
+            dir.join(validate::REPORT_FILE),
+            validate::render(&self.warnings),
+        )?;
+        self.contributions.save(dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        diff::{DataNode, DataTree, DataTreeExt, DiffTreesExt},
+        resolve::{resolve, FailOnConflict, Resolver},
+    };
+    use super::*;
+    use crate::profile::{MergeStrategy, PreferRules, Resolutions};
+
+    fn tree(path: &str, text: &str) -> DataTree {
+        vec![(PathBuf::from(path), DataNode::new(path, text.to_string()))]
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn two_mods_merge() {
+        let path = "heroes/crusader/crusader.info.darkest";
+        let orig = tree(path, "hp: .amount 33\nspeed: .amount 1\nweapon: .atk 0\n");
+        let mods = vec![
+            ModContent::new(
+                "First",
+                orig.diff(tree(
+                    path,
+                    "hp: .amount 40\nspeed: .amount 2\nweapon: .atk 0\n",
+                ))
+                .unwrap(),
+            ),
+            ModContent::new(
+                "Second",
+                orig.diff(tree(
+                    path,
+                    "hp: .amount 33\nspeed: .amount 3\nweapon: .atk 5\n",
+                ))
+                .unwrap(),
+            ),
+        ];
+        let mut report = BundleReport::new(&mods);
+        assert_eq!(report.mods, vec!["First", "Second"]);
+        assert_eq!(
+            report.files,
+            vec![FileEntry {
+                path: path.into(),
+                change: FileChange::Merged,
+                mods: vec!["First".into(), "Second".into()],
+                role: Some(HeroFileRole::Info),
+            }]
+        );
+
+        let (_, conflicts) = mods.into_iter().merge();
+        let mut resolver = Resolver::new(
+            Box::new(FailOnConflict),
+            MergeStrategy::Priority,
+            Resolutions::new(),
+            PreferRules::new(),
+        );
+        resolve(&mut resolver, conflicts).unwrap();
+        report.conflicts = resolver.finish().2;
+        assert_eq!(
+            report.conflicts,
+            vec![ConflictEntry {
+                path: path.into(),
+                item: Some("speed: #0 .amount".into()),
+                mods: vec!["First".into(), "Second".into()],
+                resolution: Resolution::Priority,
+                value: "3".into(),
+                rule: None,
+            }]
+        );
+        assert!(report
+            .render()
+            .contains("speed: #0 .amount] - mods: First, Second; resolved by mod priority: 3"));
+    }
+}
@@ -1,54 +1,1090 @@
-use super::diff::{
-    Conflict, Conflicts, DataNode, DataNodeContent, DataTree, DataTreeExt, DiffNode, DiffNodeKind,
-    DiffTree, DiffTreeExt, DiffTreesExt, LineChange, LineModification, LinesChangeset, ModContent,
-};
-use crossbeam_channel::bounded;
-use cursive::{
-    align::HAlign,
-    traits::{Nameable, Resizable},
-    views::{Button, Dialog, LinearLayout, Panel, SelectView, TextArea, TextView},
+use super::{
+    assets,
+    diff::{
+        Conflict, Conflicts, DataNode, DataNodeContent, DataTree, DataTreeExt, DiffNode,
+        DiffNodeKind, DiffTree, DiffTreeExt, DiffTreesExt, LineChange, LineModification,
+        LinesChangeset, ModContent,
+    },
+    error::{DataError, DataErrorKind, ResolveError, Unresolved},
+    report::{ConflictEntry, Resolution},
+    rules::{RulePath, RuleValue, ValueRule},
+    structures::{
+        is_chain, is_override, merge_chains, BuffPath, BuffsFile, DarkestPath, GenericDarkestFile,
+        LibraryFile, LibraryPath, LocPath, MergedOverrides, ModPatches, Patch, StructureChangeset,
+    },
 };
+use crate::profile::{MergeStrategy, PreferRules, Resolutions};
 use log::*;
-use std::fmt::Debug;
-use std::{collections::HashSet, path::PathBuf};
+use serde::Serialize;
+use std::fmt::{Debug, Display};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::{Path, PathBuf},
+};
 
-pub fn resolve(sink: &mut cursive::CbSink, conflicts: Conflicts) -> DiffTree {
-    conflicts
-        .into_iter()
-        .map(|(path, conflict)| {
+/// Answers the questions asked while resolving the conflicts between mods.
+///
+/// The interactive UI asks the user; library users can provide their own answers,
+/// or use [`FailOnConflict`] to stop at the first question.
+pub trait ResolveBackend: Send {
+    /// Chooses one of the options, returning its index.
+    fn choose(&mut self, question: &str, options: &[String]) -> Result<usize, Unresolved>;
+    /// Provides the line to be used instead of the conflicting ones, given as `(mod name, line)`.
+    ///
+    /// Empty string means that the line is removed.
+    fn edit_line(&mut self, title: &str, lines: &[(String, String)]) -> Result<String, Unresolved>;
+    /// Chooses one of the options, like [`choose`](Self::choose), when the question is about
+    /// the competing versions of localized string, given as `(mod name, text)`.
+    ///
+    /// By default, the strings are simply appended to the question.
+    fn compare_strings(
+        &mut self,
+        question: &str,
+        strings: &[(String, String)],
+        options: &[String],
+    ) -> Result<usize, Unresolved> {
+        let mut question = question.to_owned();
+        for (name, text) in strings {
+            question.push_str(&format!("\n\n{}:\n{}", name, text));
+        }
+        self.choose(&question, options)
+    }
+    /// Whether the backend can show the [`preview`](Self::preview) of the file being resolved.
+    fn can_preview(&self) -> bool {
+        false
+    }
+    /// Shows the file as it would be written with the choices made so far, returning when the user is done with it.
+    ///
+    /// `highlighted` are the indices of the lines still depending on the conflicts not resolved yet.
+    fn preview(&mut self, _title: &str, _text: &str, _highlighted: &[usize]) {}
+    /// Whether the backend can [compose the list](Self::compose_list) of effects item by item.
+    fn can_compose(&self) -> bool {
+        false
+    }
+    /// Builds the list of effects from the original one (`base`) and the ones proposed by mods,
+    /// given as `(mod name, list)`.
+    fn compose_list(
+        &mut self,
+        title: &str,
+        _base: &[String],
+        _proposals: &[(String, Vec<String>)],
+    ) -> Result<Vec<String>, Unresolved> {
+        Err(Unresolved(title.to_owned()))
+    }
+    /// Whether the backend can [review the conflicts](Self::review_conflicts) before they are resolved.
+    fn can_review(&self) -> bool {
+        false
+    }
+    /// Shows every file with conflicts, most conflicting first, and asks what to do before resolving them.
+    fn review_conflicts(&mut self, _files: &[ConflictSummary]) -> Result<Review, Unresolved> {
+        Ok(Review::Proceed)
+    }
+}
+
+/// Conflicts in one file, as they are shown before the resolution starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConflictSummary {
+    pub path: PathBuf,
+    /// Number of the conflicting items, e.g. values or lines; the file as a whole counts as one item.
+    pub items: usize,
+    pub mods: Vec<String>,
+}
+
+impl ConflictSummary {
+    /// Summarizes the conflicts, sorted by the number of conflicting items, largest first.
+    pub fn list(conflicts: &Conflicts) -> Vec<Self> {
+        let mut files: Vec<_> = conflicts
+            .iter()
+            .map(|(path, conflict)| {
+                let items: HashSet<_> = conflict
+                    .iter()
+                    .flat_map(|(_, node)| node.entries())
+                    .map(|entry| entry.item)
+                    .collect();
+                Self {
+                    path: path.clone(),
+                    items: items.len(),
+                    mods: conflict.iter().map(|(name, _)| name.clone()).collect(),
+                }
+            })
+            .collect();
+        files.sort_by(|a, b| b.items.cmp(&a.items).then_with(|| a.path.cmp(&b.path)));
+        files
+    }
+}
+
+/// What the user wants to do with the conflicts, after [reviewing](ResolveBackend::review_conflicts) them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Review {
+    /// Start resolving the conflicts left.
+    Proceed,
+    /// Resolve the conflicts in this file before the others.
+    First(PathBuf),
+    /// Use the changes from the mod for every conflicting value it changes in the file.
+    Prefer(PathBuf, String),
+    /// Drop the mod from the bundle and merge the rest again.
+    RemoveMod(String),
+}
+
+/// Question about the conflict which was left without an answer, with the options to choose from.
+///
+/// The answer is saved in the profile resolutions as `question: option`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OpenQuestion {
+    pub question: String,
+    pub options: Vec<String>,
+}
+
+/// Backend which never answers, so that any question not answered by the profile fails the bundling.
+pub struct FailOnConflict;
+
+impl ResolveBackend for FailOnConflict {
+    fn choose(&mut self, question: &str, _: &[String]) -> Result<usize, Unresolved> {
+        Err(Unresolved(question.to_owned()))
+    }
+    fn edit_line(&mut self, title: &str, _: &[(String, String)]) -> Result<String, Unresolved> {
+        Err(Unresolved(title.to_owned()))
+    }
+}
+
+/// State of the conflicts resolution, shared by every question asked during the bundling.
+pub struct Resolver {
+    backend: Box<dyn ResolveBackend>,
+    strategy: MergeStrategy,
+    /// Answers loaded from the profile.
+    saved: Resolutions,
+    /// Answers used in this run, to be stored in the profile.
+    chosen: Resolutions,
+    rules: PreferRules,
+    /// Number of questions the user had to answer, used to tell the user choices from the saved ones.
+    asked: usize,
+    report: Vec<ConflictEntry>,
+    /// Files which can be previewed while their conflicts are resolved.
+    previews: BTreeMap<PathBuf, PreviewSource>,
+    value_rules: Vec<ValueRule>,
+    /// Files to be resolved before the others, chosen while reviewing the conflicts.
+    first: Vec<PathBuf>,
+    /// Questions the backend couldn't answer, if they are collected instead of failing at the first one.
+    unanswered: Option<Vec<OpenQuestion>>,
+}
+
+/// What is needed to render the file with the tentative choices: the original text and the merged changes.
+struct PreviewSource {
+    original: String,
+    merged: Option<StructureChangeset>,
+}
+
+impl Resolver {
+    pub fn new(
+        backend: Box<dyn ResolveBackend>,
+        strategy: MergeStrategy,
+        saved: Resolutions,
+        rules: PreferRules,
+    ) -> Self {
+        Self {
+            backend,
+            strategy,
+            saved,
+            chosen: Resolutions::new(),
+            rules,
+            asked: 0,
+            report: vec![],
+            previews: BTreeMap::new(),
+            value_rules: vec![],
+            first: vec![],
+            unanswered: None,
+        }
+    }
+
+    /// Goes on with the first option for every question the backend can't answer, collecting these questions;
+    /// see [`take_unanswered`](Self::take_unanswered).
+    pub fn collecting_unanswered(mut self, collect: bool) -> Self {
+        self.unanswered = Some(vec![]).filter(|_| collect);
+        self
+    }
+
+    /// Returns the questions collected so far, if the resolver is [collecting](Self::collecting_unanswered) them.
+    pub fn take_unanswered(&mut self) -> Vec<OpenQuestion> {
+        self.unanswered
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Rules to decide the conflicting values by their paths, before anything is asked; see [`resolve_by_rules`].
+    pub fn with_value_rules(mut self, rules: Vec<ValueRule>) -> Self {
+        self.value_rules = rules;
+        self
+    }
+
+    /// Allows the backend to preview the structured files with conflicts, as they would be written.
+    ///
+    /// Has effect only if the backend [can show the preview](ResolveBackend::can_preview)
+    /// or [compose the lists](ResolveBackend::can_compose), which starts from the original values.
+    pub fn with_previews(
+        mut self,
+        original: &DataTree,
+        merged: &DiffTree,
+        conflicts: &Conflicts,
+    ) -> Self {
+        if !self.backend.can_preview() && !self.backend.can_compose() {
+            return self;
+        }
+        for (path, conflict) in conflicts {
+            let structured = conflict
+                .iter()
+                .all(|(_, node)| matches!(node, DiffNode::ModifiedStructure(_)));
+            let original = original.get(path).map(DataNode::content);
+            if let (true, Some(DataNodeContent::Text(original))) = (structured, original) {
+                let merged = match merged.get(path) {
+                    Some(DiffNode::ModifiedStructure(changes)) => Some(changes.clone()),
+                    _ => None,
+                };
+                let original = original.clone();
+                self.previews
+                    .insert(path.clone(), PreviewSource { original, merged });
+            }
+        }
+        self
+    }
+
+    /// Returns the answers and the rules to be saved in profile, and the report on every resolved conflict.
+    pub fn finish(self) -> (Resolutions, PreferRules, Vec<ConflictEntry>) {
+        (self.chosen, self.rules, self.report)
+    }
+
+    /// Gives the backend back, to start over with the different set of mods.
+    pub fn into_backend(self) -> Box<dyn ResolveBackend> {
+        self.backend
+    }
+
+    fn record(
+        &mut self,
+        path: &Path,
+        conflict: &Conflict,
+        resolution: Resolution,
+        resolved: &DiffNode,
+    ) {
+        let mods = conflict.iter().map(|(name, _)| name.clone()).collect();
+        self.report.extend(ConflictEntry::from_resolved(
+            path, mods, resolution, resolved,
+        ));
+    }
+
+    /// Checks whether this question was already answered, recording the answer for this run.
+    fn saved_answer(&mut self, question: &str) -> Option<String> {
+        let answer = self.saved.get(question)?.clone();
+        self.chosen.insert(question.to_owned(), answer.clone());
+        Some(answer)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum GroupChoice {
+    EachFile,
+    /// Use the changes from this mod for the whole group, wherever it changes the conflicting value.
+    Prefer(usize),
+    /// Same as `Prefer`, and store the rule to prefer this mod over the others.
+    Always(usize),
+}
+
+/// Asks how to resolve the conflicts between the same mods in several files at once.
+fn ask_for_group(
+    resolver: &mut Resolver,
+    mods: &[String],
+    files: usize,
+) -> Result<GroupChoice, Unresolved> {
+    let others = |index: usize| {
+        mods.iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .map(|(_, name)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let options = std::iter::once(("Decide for every file".to_string(), GroupChoice::EachFile))
+        .chain(mods.iter().enumerate().map(|(index, name)| {
+            (
+                format!("Prefer {} for all conflicts in these files", name),
+                GroupChoice::Prefer(index),
+            )
+        }))
+        .chain(mods.iter().enumerate().map(|(index, name)| {
+            (
+                format!(
+                    "Always prefer {} over {} for this bundle",
+                    name,
+                    others(index)
+                ),
+                GroupChoice::Always(index),
+            )
+        }));
+    let choice = ask_for_resolve(
+        resolver,
+        format!(
+            "Mods {} are conflicting in several files ({} now).
+You can decide once for all of them, or resolve every file separately",
+            mods.join(", "),
+            files
+        ),
+        options,
+    )?;
+    if let GroupChoice::Always(index) = choice {
+        for (other, name) in mods.iter().enumerate() {
+            if other != index {
+                info!("[resolve]: Always preferring {} over {}", mods[index], name);
+                resolver.rules.prefer(&mods[index], name);
+            }
+        }
+    }
+    Ok(choice)
+}
+
+pub fn resolve(
+    resolver: &mut Resolver,
+    mut conflicts: Conflicts,
+) -> Result<DiffTree, ResolveError> {
+    if resolver.strategy == MergeStrategy::Priority {
+        return Ok(conflicts
+            .into_iter()
+            .filter_map(|(path, conflict)| {
+                info!("[resolve] {:?}: Resolving conflict", path);
+                // Conflicts are listed in the mods order, so the last mod changing the value has the highest priority.
+                let (node, rest) = prefer_mods(
+                    &mut resolver.report,
+                    &path,
+                    conflict,
+                    Resolution::Priority,
+                    |names| names.len().checked_sub(1),
+                );
+                debug_assert!(rest.is_empty());
+                Some((path, node?))
+            })
+            .collect());
+    }
+
+    let mut resolved = DiffTree::new();
+    for path in std::mem::take(&mut resolver.first) {
+        let conflict = match conflicts.remove(&path) {
+            Some(conflict) => conflict,
+            None => continue,
+        };
+        info!("[resolve] {:?}: Resolving conflict first", path);
+        let asked = resolver.asked;
+        let node = resolve_conflict(resolver, path.clone(), conflict.clone())?;
+        let resolution = if resolver.asked > asked {
+            Resolution::User
+        } else {
+            Resolution::Saved
+        };
+        resolver.record(&path, &conflict, resolution, &node);
+        resolved.insert(path, node);
+    }
+
+    // Conflicts between the same mods usually come in batches, so they are resolved together.
+    let mut groups: BTreeMap<Vec<String>, Vec<(PathBuf, Conflict)>> = BTreeMap::new();
+    for (path, conflict) in conflicts {
+        let mods = conflict.iter().map(|(name, _)| name.clone()).collect();
+        groups.entry(mods).or_default().push((path, conflict));
+    }
+    for (mods, conflicts) in groups {
+        let asked = resolver.asked;
+        let choice = if conflicts.len() > 1 && resolver.rules.winner(&mods).is_none() {
+            ask_for_group(resolver, &mods, conflicts.len())?
+        } else {
+            GroupChoice::EachFile
+        };
+        let group_resolution = if resolver.asked > asked {
+            Resolution::User
+        } else {
+            Resolution::Saved
+        };
+        for (path, conflict) in conflicts {
             info!("[resolve] {:?}: Resolving conflict", path);
-            let kind = conflict[0].1.kind();
-            match kind {
-                DiffNodeKind::AddedText => {
-                    info!("[resolve] {:?}: Multiple added texts", path);
-                    let (base, changes) = resolve_added_text(sink, path.clone(), conflict);
-                    // Here, we have to do a little differently, since we're essentially resolving conflict
-                    // by applying two actions, but have to make them as one.
-                    let base: DataTree = vec![(path.clone(), DataNode::new(path.clone(), base))]
-                        .into_iter()
-                        .collect();
-                    let changes: DiffTree = vec![(path.clone(), DiffNode::ModifiedText(changes))]
-                        .into_iter()
-                        .collect();
-                    match changes.apply_to(base).remove(&path).unwrap().into_content() {
-                        DataNodeContent::Text(text) => (path, DiffNode::AddedText(text)),
-                        _ => unreachable!(),
+            // Preferences are applied to every value separately, since only some of the mods may change it;
+            // the values they don't decide are left to the next preference, or to the question.
+            let rules = &resolver.rules;
+            let (by_rule, conflict) = prefer_mods(
+                &mut resolver.report,
+                &path,
+                conflict,
+                Resolution::Rule,
+                |names| rules.winner(names),
+            );
+            let (preferred, conflict) = match choice {
+                GroupChoice::Prefer(index) | GroupChoice::Always(index) => prefer_mods(
+                    &mut resolver.report,
+                    &path,
+                    conflict,
+                    group_resolution,
+                    |names| names.iter().position(|name| *name == mods[index]),
+                ),
+                GroupChoice::EachFile => (None, conflict),
+            };
+            let mut parts: Vec<_> = by_rule.into_iter().chain(preferred).collect();
+            if !conflict.is_empty() {
+                let asked = resolver.asked;
+                let node = resolve_conflict(resolver, path.clone(), conflict.clone())?;
+                let resolution = if resolver.asked > asked {
+                    Resolution::User
+                } else {
+                    Resolution::Saved
+                };
+                resolver.record(&path, &conflict, resolution, &node);
+                parts.push(node);
+            }
+            resolved.extend(join(parts).map(|node| (path, node)));
+        }
+    }
+    Ok(resolved)
+}
+
+/// Joins the changes to the same file decided separately, e.g. some values by rule and the rest by the user.
+///
+/// Only the structured files are decided in parts, and these parts change different values.
+fn join(mut parts: Vec<DiffNode>) -> Option<DiffNode> {
+    if parts.len() <= 1 {
+        return parts.pop();
+    }
+    let changes = parts
+        .into_iter()
+        .map(|node| match node {
+            DiffNode::ModifiedStructure(changes) => (String::new(), changes),
+            _ => unreachable!(),
+        })
+        .collect();
+    let (joined, rest) = StructureChangeset::merge(changes);
+    debug_assert!(rest.is_empty());
+    joined.map(DiffNode::ModifiedStructure)
+}
+
+/// Takes the changes of the preferred mod for every conflicting value separately, so that the values
+/// it didn't change are left to the other mods: `prefer` chooses among the mods changing the value,
+/// given in the mods order, or leaves the value in conflict. Files which aren't structured are taken as a whole.
+///
+/// Returns the preferred changes, if there are any, and the rest of the conflict.
+fn prefer_mods(
+    report: &mut Vec<ConflictEntry>,
+    target: &Path,
+    conflict: Conflict,
+    resolution: Resolution,
+    prefer: impl Fn(&[String]) -> Option<usize>,
+) -> (Option<DiffNode>, Conflict) {
+    let structured = conflict
+        .iter()
+        .all(|(_, node)| matches!(node, DiffNode::ModifiedStructure(_)));
+    if !structured {
+        let names: Vec<_> = conflict.iter().map(|(name, _)| name.clone()).collect();
+        return match prefer(&names) {
+            Some(index) => {
+                info!(
+                    "[resolve] {:?}: Using changes from {}",
+                    target, names[index]
+                );
+                let node = conflict[index].1.clone();
+                report.extend(ConflictEntry::from_resolved(
+                    target, names, resolution, &node,
+                ));
+                (Some(node), vec![])
+            }
+            None => (None, conflict),
+        };
+    }
+    let changes: Vec<_> = conflict
+        .into_iter()
+        .map(|(name, node)| match node {
+            DiffNode::ModifiedStructure(changes) => (name, changes),
+            _ => unreachable!(),
+        })
+        .collect();
+    let (preferred, rest) = match changes.first() {
+        Some((_, StructureChangeset::Darkest(_))) => prefer_values(
+            report,
+            target,
+            changes,
+            resolution,
+            &prefer,
+            |changes| match changes {
+                StructureChangeset::Darkest(patch) => patch,
+                _ => unreachable!(),
+            },
+            StructureChangeset::Darkest,
+        ),
+        Some((_, StructureChangeset::Strings(_))) => prefer_values(
+            report,
+            target,
+            changes,
+            resolution,
+            &prefer,
+            |changes| match changes {
+                StructureChangeset::Strings(patch) => patch,
+                _ => unreachable!(),
+            },
+            StructureChangeset::Strings,
+        ),
+        Some((_, StructureChangeset::Buffs(_))) => prefer_values(
+            report,
+            target,
+            changes,
+            resolution,
+            &prefer,
+            |changes| match changes {
+                StructureChangeset::Buffs(patch) => patch,
+                _ => unreachable!(),
+            },
+            StructureChangeset::Buffs,
+        ),
+        Some((_, StructureChangeset::Library(_))) => prefer_values(
+            report,
+            target,
+            changes,
+            resolution,
+            &prefer,
+            |changes| match changes {
+                StructureChangeset::Library(patch) => patch,
+                _ => unreachable!(),
+            },
+            StructureChangeset::Library,
+        ),
+        None => (None, vec![]),
+    };
+    let rest = rest
+        .into_iter()
+        .map(|(name, changes)| (name, DiffNode::ModifiedStructure(changes)))
+        .collect();
+    (preferred.map(DiffNode::ModifiedStructure), rest)
+}
+
+fn prefer_values<K, V>(
+    report: &mut Vec<ConflictEntry>,
+    target: &Path,
+    changes: Vec<(String, StructureChangeset)>,
+    resolution: Resolution,
+    prefer: &impl Fn(&[String]) -> Option<usize>,
+    unwrap: impl Fn(StructureChangeset) -> Patch<K, V>,
+    variant: impl Fn(Patch<K, V>) -> StructureChangeset,
+) -> Decided
+where
+    K: Ord + Clone + Display,
+    V: Clone,
+{
+    let mut by_path: BTreeMap<K, Vec<(String, Option<V>)>> = BTreeMap::new();
+    // Values left in conflict stay in the mods order, since it defines their priority.
+    let mut rest: ModPatches<K, V> = vec![];
+    for (name, changes) in changes {
+        for (path, value) in unwrap(changes) {
+            by_path.entry(path).or_default().push((name.clone(), value));
+        }
+        rest.push((name, Patch::new()));
+    }
+    let mut preferred = Patch::new();
+    for (path, mut options) in by_path {
+        let names: Vec<_> = options.iter().map(|(name, _)| name.clone()).collect();
+        match prefer(&names) {
+            Some(index) => {
+                info!(
+                    "[resolve] {:?}: Using {} from {}",
+                    target, path, names[index]
+                );
+                let value = options.swap_remove(index).1;
+                let single = std::iter::once((path.clone(), value.clone())).collect();
+                report.extend(ConflictEntry::from_resolved(
+                    target,
+                    names,
+                    resolution,
+                    &DiffNode::ModifiedStructure(variant(single)),
+                ));
+                preferred.insert(path, value);
+            }
+            None => {
+                for (name, value) in options {
+                    if let Some((_, patch)) = rest.iter_mut().find(|(other, _)| *other == name) {
+                        patch.insert(path.clone(), value);
                     }
                 }
-                DiffNodeKind::Binary => {
-                    info!("[resolve] {:?}: Multiple binaries", path);
-                    let resolved = resolve_binary(sink, path.clone(), conflict);
-                    debug!("[resolve] {:?}: Using {:?}", path, resolved);
-                    (path, DiffNode::Binary(resolved))
+            }
+        }
+    }
+    let preferred = Some(preferred)
+        .filter(|preferred| !preferred.is_empty())
+        .map(&variant);
+    (
+        preferred,
+        rest.into_iter()
+            .filter(|(_, patch)| !patch.is_empty())
+            .map(|(name, patch)| (name, variant(patch)))
+            .collect(),
+    )
+}
+
+fn resolve_conflict(
+    resolver: &mut Resolver,
+    path: PathBuf,
+    conflict: Conflict,
+) -> Result<DiffNode, ResolveError> {
+    let kind = conflict[0].1.kind();
+    if conflict.iter().any(|(_, node)| node.kind() != kind) {
+        info!("[resolve] {:?}: Incompatible changes", path);
+        return Ok(resolve_incompatible(resolver, path, conflict)?);
+    }
+    Ok(match kind {
+        DiffNodeKind::AddedText if is_override(&path) => {
+            info!("[resolve] {:?}: Multiple added overrides", path);
+            DiffNode::AddedText(resolve_overrides(resolver, path, conflict)?)
+        }
+        DiffNodeKind::AddedText => {
+            info!("[resolve] {:?}: Multiple added texts", path);
+            DiffNode::AddedText(resolve_added_text(resolver, path, conflict)?)
+        }
+        DiffNodeKind::Binary => {
+            info!("[resolve] {:?}: Multiple binaries", path);
+            let resolved = resolve_binary(resolver, path.clone(), conflict)?;
+            debug!("[resolve] {:?}: Using {:?}", path, resolved);
+            DiffNode::Binary(resolved)
+        }
+        DiffNodeKind::ModifiedText => {
+            info!("[resolve] {:?}: Multiple text modifications", path);
+            DiffNode::ModifiedText(resolve_modified_text(resolver, path, conflict)?)
+        }
+        DiffNodeKind::ModifiedStructure => {
+            info!("[resolve] {:?}: Multiple structure modifications", path);
+            DiffNode::ModifiedStructure(resolve_modified_structure(resolver, path, conflict)?)
+        }
+    })
+}
+
+/// Outcome of the [`review`] of the conflicts.
+pub enum Reviewed {
+    /// Conflicts resolved by preferring some mod in the file.
+    Resolved(DiffTree),
+    /// The mod is to be removed; the bundling must start over without it.
+    RemoveMod(String),
+}
+
+/// Lets the user see every conflict left before anything is asked about them, if the backend can show them.
+///
+/// Values resolved by preferring some mod are removed from the `conflicts`, along with the conflicts left empty;
+/// the files chosen to be resolved first are resolved first by `resolve`.
+pub fn review(resolver: &mut Resolver, conflicts: &mut Conflicts) -> Result<Reviewed, Unresolved> {
+    let mut resolved = DiffTree::new();
+    if resolver.strategy == MergeStrategy::Priority || !resolver.backend.can_review() {
+        return Ok(Reviewed::Resolved(resolved));
+    }
+    while !conflicts.is_empty() {
+        let files = ConflictSummary::list(conflicts);
+        match resolver.backend.review_conflicts(&files)? {
+            Review::Proceed => break,
+            Review::First(path) => {
+                info!("[resolve] {:?}: Will be resolved first", path);
+                resolver.first.retain(|other| *other != path);
+                resolver.first.push(path);
+            }
+            Review::Prefer(path, name) => {
+                let conflict = match conflicts.remove(&path) {
+                    Some(conflict) => conflict,
+                    None => continue,
+                };
+                // The values the mod didn't change are left in conflict between the other mods.
+                let (preferred, rest) = prefer_mods(
+                    &mut resolver.report,
+                    &path,
+                    conflict,
+                    Resolution::User,
+                    |names| names.iter().position(|other| *other == name),
+                );
+                if !rest.is_empty() {
+                    conflicts.insert(path.clone(), rest);
                 }
-                DiffNodeKind::ModifiedText => {
-                    info!("[resolve] {:?}: Multiple text modifications", path);
-                    let resolved = resolve_modified_text(sink, path.clone(), conflict);
-                    (path, DiffNode::ModifiedText(resolved))
+                match preferred {
+                    Some(node) => {
+                        info!("[resolve] {:?}: Using changes from {}", path, name);
+                        resolver.asked += 1;
+                        let parts = resolved.remove(&path).into_iter().chain(Some(node));
+                        resolved.extend(join(parts.collect()).map(|node| (path, node)));
+                    }
+                    None => warn!("[resolve] {:?}: No conflicting changes from {}", path, name),
                 }
             }
+            Review::RemoveMod(name) => {
+                info!("[resolve] Removing {} from the bundle", name);
+                return Ok(Reviewed::RemoveMod(name));
+            }
+        }
+    }
+    Ok(Reviewed::Resolved(resolved))
+}
+
+/// Resolves collisions between the binary assets by mod priority, if the user wants it.
+///
+/// Resolved collisions are removed from the `conflicts`; the rest will be resolved by `resolve`.
+pub fn resolve_assets(
+    resolver: &mut Resolver,
+    conflicts: &mut Conflicts,
+) -> Result<DiffTree, Unresolved> {
+    let collisions: Vec<PathBuf> = conflicts
+        .iter()
+        .filter(|(_, conflict)| {
+            conflict
+                .iter()
+                .all(|(_, node)| node.kind() == DiffNodeKind::Binary)
+        })
+        .map(|(path, _)| path.clone())
+        .collect();
+    if collisions.is_empty() {
+        return Ok(DiffTree::new());
+    }
+    let by_priority = resolver.strategy == MergeStrategy::Priority
+        || ask_for_resolve(
+            resolver,
+            format!(
+                "{} asset files are provided by several mods with different content.
+You can use the mod priority (mods selected later override the ones selected earlier),
+or choose the file to use for every collision manually.",
+                collisions.len()
+            ),
+            vec![
+                ("Use mod priority".to_string(), true),
+                ("Choose manually".to_string(), false),
+            ],
+        )?;
+    if !by_priority {
+        return Ok(DiffTree::new());
+    }
+    Ok(collisions
+        .into_iter()
+        .map(|path| {
+            let conflict = conflicts.remove(&path).unwrap();
+            // Conflicts are listed in the mods order, so the last one has the highest priority.
+            let (name, node) = conflict.last().cloned().unwrap();
+            info!("[resolve] {:?}: Using binary file from {}", path, name);
+            resolver.record(&path, &conflict, Resolution::Priority, &node);
+            (path, node)
         })
-        .collect()
+        .collect())
+}
+
+/// Combines the chains of effects changed by several mods, if they change the different parts of the chain,
+/// e.g. every mod appends its own effect; see [`merge_chains`].
+///
+/// Combined chains are removed from the `conflicts`, along with the conflicts left empty;
+/// the rest will be resolved as usual.
+pub fn combine_chains(
+    resolver: &mut Resolver,
+    original: &DataTree,
+    conflicts: &mut Conflicts,
+) -> DiffTree {
+    let paths: Vec<_> = conflicts
+        .iter()
+        .filter(|(_, conflict)| {
+            conflict
+                .iter()
+                .all(|(_, node)| matches!(node, DiffNode::ModifiedStructure(_)))
+        })
+        .map(|(path, _)| path.clone())
+        .collect();
+    let mut resolved = DiffTree::new();
+    for path in paths {
+        let text = match original.get(&path).map(DataNode::content) {
+            Some(DataNodeContent::Text(text)) => text,
+            _ => continue,
+        };
+        let conflict = conflicts.remove(&path).unwrap();
+        let (combined, rest) = match conflict.first() {
+            Some((_, DiffNode::ModifiedStructure(StructureChangeset::Darkest(_)))) => {
+                let chains = GenericDarkestFile::parse(text)
+                    .map(|file| {
+                        file.paths()
+                            .filter(|(chain, _)| is_chain(&chain.key, &chain.subkey))
+                            .map(|(chain, value)| (chain, value.clone()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                combine_patches(
+                    resolver,
+                    &path,
+                    conflict,
+                    chains,
+                    |changes| match changes {
+                        StructureChangeset::Darkest(patch) => patch,
+                        _ => unreachable!(),
+                    },
+                    StructureChangeset::Darkest,
+                )
+            }
+            Some((_, DiffNode::ModifiedStructure(StructureChangeset::Library(_)))) => {
+                let chains = LibraryFile::parse_file(&path, text)
+                    .map(|file| {
+                        file.paths()
+                            .into_iter()
+                            .filter(|(chain, _)| {
+                                chain.item.is_none() && file.schema().is_chain(&chain.field)
+                            })
+                            .map(|(chain, value)| (chain, value.clone()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                // JSON objects are compared by their text, which is the same for the same objects, since the keys are sorted.
+                combine_patches(
+                    resolver,
+                    &path,
+                    conflict,
+                    chains,
+                    |changes| match changes {
+                        StructureChangeset::Library(patch) => patch,
+                        _ => unreachable!(),
+                    },
+                    StructureChangeset::Library,
+                )
+            }
+            _ => (None, conflict),
+        };
+        if let Some(combined) = combined {
+            resolved.insert(path.clone(), DiffNode::ModifiedStructure(combined));
+        }
+        if !rest.is_empty() {
+            conflicts.insert(path, rest);
+        }
+    }
+    resolved
+}
+
+/// Combines the conflicting changes to the `chains`, given with their original values, if the mods' versions can be merged.
+///
+/// Returns the combined chains and the conflict without them.
+fn combine_patches<K, V>(
+    resolver: &mut Resolver,
+    target: &Path,
+    conflict: Conflict,
+    chains: BTreeMap<K, V>,
+    unwrap: impl Fn(StructureChangeset) -> Patch<K, V>,
+    variant: impl Fn(Patch<K, V>) -> StructureChangeset,
+) -> (Option<StructureChangeset>, Conflict)
+where
+    K: Ord + Clone + Display,
+    V: Clone + ListValue,
+{
+    let mut patches: ModPatches<K, V> = conflict
+        .into_iter()
+        .map(|(name, node)| match node {
+            DiffNode::ModifiedStructure(changes) => (name, unwrap(changes)),
+            _ => unreachable!(),
+        })
+        .collect();
+    let changed: BTreeSet<_> = patches
+        .iter()
+        .flat_map(|(_, patch)| patch.keys())
+        .filter(|chain| chains.contains_key(*chain))
+        .cloned()
+        .collect();
+    let mut combined = Patch::new();
+    for chain in changed {
+        // The chain removed by some mod can't be combined with the changed ones.
+        let versions: Option<Vec<_>> = patches
+            .iter()
+            .filter_map(|(name, patch)| Some((name, patch.get(&chain)?.as_ref())))
+            .map(|(name, version)| Some((name.clone(), version?.items()?)))
+            .collect();
+        let original = chains[&chain].items();
+        let (versions, original) = match (versions, original) {
+            (Some(versions), Some(original)) => (versions, original),
+            _ => continue,
+        };
+        let lists: Vec<_> = versions
+            .iter()
+            .map(|(_, version)| version.as_slice())
+            .collect();
+        if let Some(merged) = merge_chains(&original, &lists) {
+            info!("[resolve] {:?}: combined the changes to {}", target, chain);
+            resolver.report.push(ConflictEntry {
+                path: target.to_owned(),
+                item: Some(chain.to_string()),
+                mods: versions.into_iter().map(|(name, _)| name).collect(),
+                resolution: Resolution::Combined,
+                value: describe_values(&merged),
+                rule: None,
+            });
+            for (_, patch) in &mut patches {
+                patch.remove(&chain);
+            }
+            combined.insert(chain, Some(V::from_items(merged)));
+        }
+    }
+    let rest = patches
+        .into_iter()
+        .filter(|(_, patch)| !patch.is_empty())
+        .map(|(name, patch)| (name, DiffNode::ModifiedStructure(variant(patch))))
+        .collect();
+    if combined.is_empty() {
+        return (None, rest);
+    }
+    // Previews of the other conflicting values must show the combined chains, too.
+    if let Some(source) = resolver.previews.get_mut(target) {
+        let mut merged = source.merged.take().map(&unwrap).unwrap_or_default();
+        merged.extend(combined.clone());
+        source.merged = Some(variant(merged));
+    }
+    (Some(variant(combined)), rest)
+}
+
+/// Decides the conflicting values of the structured files by the first matching value rule, if it can be applied.
+///
+/// Decided values are removed from the `conflicts`, along with the conflicts left empty;
+/// the rest will be resolved by `resolve`.
+pub fn resolve_by_rules(resolver: &mut Resolver, conflicts: &mut Conflicts) -> DiffTree {
+    if resolver.value_rules.is_empty() {
+        return DiffTree::new();
+    }
+    let mut paths: Vec<_> = conflicts
+        .iter()
+        .filter(|(_, conflict)| {
+            conflict
+                .iter()
+                .all(|(_, node)| matches!(node, DiffNode::ModifiedStructure(_)))
+        })
+        .map(|(path, _)| path.clone())
+        .collect();
+    paths.sort();
+    let mut resolved = DiffTree::new();
+    for path in paths {
+        let conflict = conflicts.remove(&path).unwrap();
+        let changes: Vec<_> = conflict
+            .into_iter()
+            .map(|(name, node)| match node {
+                DiffNode::ModifiedStructure(changes) => (name, changes),
+                _ => unreachable!(),
+            })
+            .collect();
+        let (decided, rest) = match changes.first() {
+            Some((_, StructureChangeset::Darkest(_))) => decide_by_rules(
+                resolver,
+                &path,
+                changes,
+                |changes| match changes {
+                    StructureChangeset::Darkest(patch) => patch,
+                    _ => unreachable!(),
+                },
+                |values| describe_values(values),
+                StructureChangeset::Darkest,
+            ),
+            Some((_, StructureChangeset::Strings(_))) => decide_by_rules(
+                resolver,
+                &path,
+                changes,
+                |changes| match changes {
+                    StructureChangeset::Strings(patch) => patch,
+                    _ => unreachable!(),
+                },
+                String::clone,
+                StructureChangeset::Strings,
+            ),
+            Some((_, StructureChangeset::Buffs(_))) => decide_by_rules(
+                resolver,
+                &path,
+                changes,
+                |changes| match changes {
+                    StructureChangeset::Buffs(patch) => patch,
+                    _ => unreachable!(),
+                },
+                ToString::to_string,
+                StructureChangeset::Buffs,
+            ),
+            Some((_, StructureChangeset::Library(_))) => decide_by_rules(
+                resolver,
+                &path,
+                changes,
+                |changes| match changes {
+                    StructureChangeset::Library(patch) => patch,
+                    _ => unreachable!(),
+                },
+                describe_json,
+                StructureChangeset::Library,
+            ),
+            None => continue,
+        };
+        if let Some(decided) = decided {
+            resolved.insert(path.clone(), DiffNode::ModifiedStructure(decided));
+        }
+        if !rest.is_empty() {
+            let rest = rest
+                .into_iter()
+                .map(|(name, changes)| (name, DiffNode::ModifiedStructure(changes)))
+                .collect();
+            conflicts.insert(path, rest);
+        }
+    }
+    resolved
+}
+
+type Decided = (
+    Option<StructureChangeset>,
+    Vec<(String, StructureChangeset)>,
+);
+
+fn decide_by_rules<K, V>(
+    resolver: &mut Resolver,
+    target: &Path,
+    changes: Vec<(String, StructureChangeset)>,
+    unwrap: impl Fn(StructureChangeset) -> Patch<K, V>,
+    describe: impl Fn(&V) -> String,
+    variant: impl Fn(Patch<K, V>) -> StructureChangeset,
+) -> Decided
+where
+    K: Ord + Clone + Display + RulePath,
+    V: RuleValue,
+{
+    let mut by_path: BTreeMap<K, Vec<(String, Option<V>)>> = BTreeMap::new();
+    // Undecided changes stay in the mods order, since it defines their priority.
+    let mut rest: Vec<(String, Patch<K, V>)> = vec![];
+    for (name, changes) in changes {
+        for (path, value) in unwrap(changes) {
+            by_path.entry(path).or_default().push((name.clone(), value));
+        }
+        rest.push((name, Patch::new()));
+    }
+    let file = target.to_string_lossy().replace('\\', "/");
+    let mut decided = Patch::new();
+    for (path, options) in by_path {
+        let rule_path = format!("{}/{}", file, path.rule_path());
+        let choice = resolver
+            .value_rules
+            .iter()
+            .find(|rule| rule.matches(&rule_path))
+            .and_then(|rule| Some((rule, rule.choose(&options)?)));
+        match choice {
+            Some((rule, value)) => {
+                info!("[resolve] {:?}: {} decided by rule {}", target, path, rule);
+                resolver.report.push(ConflictEntry {
+                    path: target.to_owned(),
+                    item: Some(path.to_string()),
+                    mods: options.iter().map(|(name, _)| name.clone()).collect(),
+                    resolution: Resolution::ValueRule,
+                    value: value
+                        .as_ref()
+                        .map(&describe)
+                        .unwrap_or_else(|| "<removed>".into()),
+                    rule: Some(rule.to_string()),
+                });
+                decided.insert(path, value);
+            }
+            None => {
+                for (name, value) in options {
+                    if let Some((_, patch)) = rest.iter_mut().find(|(other, _)| *other == name) {
+                        patch.insert(path.clone(), value);
+                    }
+                }
+            }
+        }
+    }
+    let decided = Some(decided)
+        .filter(|decided| !decided.is_empty())
+        .map(&variant);
+    (
+        decided,
+        rest.into_iter()
+            .filter(|(_, patch)| !patch.is_empty())
+            .map(|(name, patch)| (name, variant(patch)))
+            .collect(),
+    )
 }
 
 pub fn merge_resolved(merged: DiffTree, resolved: DiffTree) -> DiffTree {
@@ -57,50 +1093,82 @@ pub fn merge_resolved(merged: DiffTree, resolved: DiffTree) -> DiffTree {
         ModContent::new("resolved", resolved),
     ]
     .into_iter()
-    .merge(None);
+    .merge();
     debug_assert!(conflicts.is_empty());
     merged
 }
 
-fn ask_for_resolve<T: Debug + Send + Clone + 'static>(
-    sink: &mut cursive::CbSink,
+fn ask_for_resolve<T: Debug + Clone>(
+    resolver: &mut Resolver,
+    text: impl Into<String>,
+    options: impl IntoIterator<Item = (String, T)>,
+) -> Result<T, Unresolved> {
+    ask_for_resolve_with(resolver, text, options, |backend, text, names| {
+        backend.choose(text, names)
+    })
+}
+
+/// Same as `ask_for_resolve`, but lets the caller choose how the backend is asked.
+fn ask_for_resolve_with<T: Debug + Clone>(
+    resolver: &mut Resolver,
     text: impl Into<String>,
     options: impl IntoIterator<Item = (String, T)>,
-) -> T {
-    let (sender, receiver) = bounded(0);
+    ask: impl FnOnce(&mut dyn ResolveBackend, &str, &[String]) -> Result<usize, Unresolved>,
+) -> Result<T, Unresolved> {
     let text = text.into();
-    let options: Vec<_> = options.into_iter().collect();
+    let mut options: Vec<_> = options.into_iter().collect();
+    if let Some(saved) = resolver.saved_answer(&text) {
+        match options.iter().find(|(name, _)| *name == saved) {
+            Some((_, value)) => {
+                info!("[resolve]: Using saved resolution: {}", saved);
+                return Ok(value.clone());
+            }
+            None => warn!(
+                "[resolve]: Saved resolution {:?} is not available anymore, asking again",
+                saved
+            ),
+        }
+    }
+    let names: Vec<_> = options.iter().map(|(name, _)| name.clone()).collect();
     debug!(
         "[resolve]: Asking for source to be used, variants: {:?}",
-        options.iter().map(|(name, _)| name).collect::<Vec<_>>()
+        names
     );
-    crate::run_update(sink, move |cursive| {
-        crate::push_screen(
-            cursive,
-            Dialog::around(
-                LinearLayout::vertical()
-                    .child(TextView::new(text))
-                    .child(Panel::new(SelectView::new().with_all(options).on_submit(
-                        move |cursive, value| {
-                            cursive.pop_layer();
-                            let _ = sender.send(value.clone());
-                        },
-                    ))),
-            ),
-        );
-    });
-    receiver
-        .recv()
-        .expect("Sender was dropped without sending anything")
+    let index = match ask(resolver.backend.as_mut(), &text, &names) {
+        Ok(index) => index,
+        Err(err) => match resolver.unanswered.as_mut() {
+            // The first option stands in for the answer, so that the questions coming after this one are asked too.
+            Some(unanswered) => {
+                info!(
+                    "[resolve]: No answer for {:?}, going on with the first option",
+                    text
+                );
+                unanswered.push(OpenQuestion {
+                    question: text,
+                    options: names,
+                });
+                return Ok(options.swap_remove(0).1);
+            }
+            None => return Err(err),
+        },
+    };
+    let (name, value) = options.swap_remove(index);
+    resolver.asked += 1;
+    resolver.chosen.insert(text, name);
+    Ok(value)
 }
 
-fn resolve_binary(sink: &mut cursive::CbSink, target: PathBuf, conflict: Conflict) -> PathBuf {
+fn resolve_binary(
+    resolver: &mut Resolver,
+    target: PathBuf,
+    conflict: Conflict,
+) -> Result<PathBuf, Unresolved> {
     let variants = conflict.into_iter().map(|(name, node)| match node {
-        DiffNode::Binary(path) => (name, path),
+        DiffNode::Binary(path) => (format!("{} ({})", name, assets::describe(&path)), path),
         _ => unreachable!(),
     });
     ask_for_resolve(
-        sink,
+        resolver,
         format!(
             "Multiple mods are using the binary file {}. Please choose one you wish to use the file from",
             target.to_string_lossy()
@@ -109,72 +1177,41 @@ fn resolve_binary(sink: &mut cursive::CbSink, target: PathBuf, conflict: Conflic
     )
 }
 
-fn render_line_choice(line: String, mod_name: String) -> impl cursive::View {
-    Panel::new(
-        LinearLayout::horizontal()
-            .child(TextView::new(line.clone()).full_width())
-            .child(Button::new("Use this", move |cursive| {
-                let line = line.clone();
-                cursive.call_on_name("Line resolve edit", move |edit: &mut TextArea| {
-                    edit.set_content(line)
-                });
-            })),
-    )
-    .title(mod_name)
-    .title_position(HAlign::Left)
-}
-
 fn choose_line(
-    sink: &mut cursive::CbSink,
+    resolver: &mut Resolver,
     index: usize,
     file: impl Into<PathBuf>,
     lines: impl IntoIterator<Item = (String, String)>,
-) -> Option<String> {
+) -> Result<Option<String>, Unresolved> {
     let lines: Vec<_> = lines.into_iter().collect();
     let file = file.into();
-    let (sender, receiver) = bounded(0);
-
-    crate::run_update(sink, move |cursive| {
-        let mut layout = LinearLayout::vertical();
-        lines
-            .into_iter()
-            .for_each(|(name, line)| layout.add_child(render_line_choice(line, name)));
-        crate::push_screen(
-            cursive,
-            Dialog::around(
-                layout.child(TextArea::new().with_name("Line resolve edit").full_width()),
-            )
-            .title(format!(
-                "Resolving line {} in file {}",
-                index,
-                file.to_string_lossy()
-            ))
-            .button("Resolve", move |cursive| {
-                let value = cursive
-                    .call_on_name("Line resolve edit", |edit: &mut TextArea| {
-                        edit.get_content().to_owned()
-                    })
-                    .unwrap();
-                cursive.pop_layer();
-                let value = match value.as_str() {
-                    "" => None,
-                    val => Some(val.to_string()),
-                };
-                sender.send(value).unwrap();
-            })
-            .h_align(cursive::align::HAlign::Center),
+    let title = format!(
+        "Resolving line {} in file {}",
+        index,
+        file.to_string_lossy()
+    );
+    let to_line = |value: String| match value.as_str() {
+        "" => None,
+        val => Some(val.to_string()),
+    };
+    if let Some(saved) = resolver.saved_answer(&title) {
+        info!(
+            "[resolve]: Using saved resolution for line {} in {:?}",
+            index, file
         );
-    });
-    receiver
-        .recv()
-        .expect("Sender was dropped without sending anything")
+        return Ok(to_line(saved));
+    }
+    let value = resolver.backend.edit_line(&title, &lines)?;
+    resolver.asked += 1;
+    resolver.chosen.insert(title, value.clone());
+    Ok(to_line(value))
 }
 
 fn resolve_changes_manually(
-    sink: &mut cursive::CbSink,
+    resolver: &mut Resolver,
     target: PathBuf,
     conflict: Conflict,
-) -> LinesChangeset {
+) -> Result<LinesChangeset, Unresolved> {
     let changes: Vec<_> = conflict
         .into_iter()
         .map(|(name, node)| match node {
@@ -212,7 +1249,7 @@ fn resolve_changes_manually(
         .enumerate()
         .map(|(index, change)| {
             if change.is_empty() {
-                None
+                Ok(None)
             } else {
                 let options = change.into_iter().map(|(name, change)| {
                     (
@@ -229,21 +1266,23 @@ fn resolve_changes_manually(
                         },
                     )
                 });
-                Some(match choose_line(sink, index, &target, options) {
-                    Some(line) => LineChange::Modified(LineModification::Replaced(line)),
-                    None => LineChange::Removed,
-                })
+                Ok(Some(
+                    match choose_line(resolver, index, &target, options)? {
+                        Some(line) => LineChange::Modified(LineModification::Replaced(line)),
+                        None => LineChange::Removed,
+                    },
+                ))
             }
         })
-        .collect();
-    LinesChangeset(changes)
+        .collect::<Result<_, _>>()?;
+    Ok(LinesChangeset(changes))
 }
 
 fn resolve_modified_text(
-    sink: &mut cursive::CbSink,
+    resolver: &mut Resolver,
     target: PathBuf,
     conflict: Conflict,
-) -> LinesChangeset {
+) -> Result<LinesChangeset, Unresolved> {
     // Clone conflict, to use it later in manual resolution if necessary
     let variants = conflict
         .clone()
@@ -254,7 +1293,7 @@ fn resolve_modified_text(
         })
         .chain(std::iter::once(("Resolve manually".into(), None)));
     let changeset = ask_for_resolve(
-        sink,
+        resolver,
         format!(
             "Multiple mods are changing the text file {}.
 Non-conflicting changes were already merged.
@@ -263,20 +1302,634 @@ Please choose the file you wish to use for conflicting cases, or resolve changes
             target.to_string_lossy()
         ),
         variants,
-    );
+    )?;
     match changeset {
-        Some(changeset) => changeset,
-        None => resolve_changes_manually(sink, target, conflict),
+        Some(changeset) => Ok(changeset),
+        None => resolve_changes_manually(resolver, target, conflict),
+    }
+}
+
+/// Describes the value from the `.darkest` file; the set items have no value besides being present.
+fn describe_values(values: &[String]) -> String {
+    if values.is_empty() {
+        "<present>".into()
+    } else {
+        values.join(" ")
+    }
+}
+
+/// JSON value as shown in questions; the set items, which have no value of their own, are stored as `null`.
+fn describe_json(value: &serde_json::Value) -> String {
+    if value.is_null() {
+        "<present>".into()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Answer to the question about one conflicting entry.
+#[derive(Debug, Clone)]
+enum EntryChoice<V> {
+    Value(Option<V>),
+    Preview,
+    Compose,
+    Custom,
+}
+
+/// Checks whether the value is the list of effects, e.g. `.effects` of the skill or `.effect` of the trinket.
+fn is_effects_list(rule_path: &str) -> bool {
+    rule_path.ends_with("/effect") || rule_path.ends_with("/effects")
+}
+
+/// Value which might be the list of names, as the effects are.
+trait ListValue: Sized {
+    fn items(&self) -> Option<Vec<String>>;
+    fn from_items(items: Vec<String>) -> Self;
+}
+
+impl ListValue for Vec<String> {
+    fn items(&self) -> Option<Vec<String>> {
+        Some(self.clone())
+    }
+
+    fn from_items(items: Vec<String>) -> Self {
+        items
+    }
+}
+
+/// Lists of ids are listed as they are; the objects, e.g. camping skill effects, are listed as JSON text.
+impl ListValue for serde_json::Value {
+    fn items(&self) -> Option<Vec<String>> {
+        self.as_array()?
+            .iter()
+            .map(|item| match item {
+                Self::String(item) => Some(item.clone()),
+                Self::Object(_) => Some(item.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn from_items(items: Vec<String>) -> Self {
+        let item = |item: String| match serde_json::from_str(&item) {
+            Ok(object @ Self::Object(_)) => object,
+            _ => Self::String(item),
+        };
+        Self::Array(items.into_iter().map(item).collect())
+    }
+}
+
+/// Value which can be typed in by the user instead of the conflicting ones.
+trait CustomValue: Sized {
+    /// Reads the value, checking that it is of the same kind as the values proposed by mods, e.g. a number.
+    fn parse_custom(text: &str, proposed: &[&Self]) -> Result<Self, String>;
+}
+
+/// Path of the conflicting entry, whose value might be typed in by the user.
+trait CustomPath {
+    /// Checks whether the value at this path can be typed in; the values which can only be kept or removed can't.
+    fn takes_custom(&self) -> bool {
+        true
+    }
+}
+
+impl CustomPath for BuffPath {}
+
+impl CustomPath for LibraryPath {}
+
+/// Items of the set-like values have no value of their own, only the item in the path.
+impl CustomPath for DarkestPath {
+    fn takes_custom(&self) -> bool {
+        self.item.is_none()
+    }
+}
+
+/// Splits the `.darkest` value into items, like the game does: by spaces, except the quoted ones.
+fn split_items(text: &str) -> Result<Vec<String>, String> {
+    let mut items = vec![];
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let (item, tail) = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted
+                    .find('"')
+                    .ok_or_else(|| "unterminated quote".to_owned())?;
+                (&quoted[..end], &quoted[end + 1..])
+            }
+            None => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+        };
+        items.push(item.to_owned());
+        rest = tail.trim_start();
+    }
+    Ok(items)
+}
+
+impl CustomValue for Vec<String> {
+    fn parse_custom(text: &str, proposed: &[&Self]) -> Result<Self, String> {
+        let items = split_items(text)?;
+        let numeric = |value: &Vec<String>| value.iter().all(|item| item.number().is_some());
+        if !proposed.is_empty() && proposed.iter().all(|value| numeric(value)) && !numeric(&items) {
+            return Err("expected numbers".into());
+        }
+        Ok(items)
+    }
+}
+
+impl CustomValue for serde_json::Value {
+    fn parse_custom(text: &str, proposed: &[&Self]) -> Result<Self, String> {
+        use serde_json::Value;
+        let kind = |value: &Value| match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "list",
+            Value::Object(_) => "object",
+        };
+        let expected = proposed
+            .split_first()
+            .map(|(first, rest)| (kind(first), rest))
+            .filter(|(first, rest)| rest.iter().all(|value| kind(value) == *first))
+            .map(|(first, _)| first);
+        let value = match serde_json::from_str(text) {
+            Ok(value) => value,
+            // Strings are accepted without quotes, if nothing else is expected.
+            Err(_) if expected == Some("string") => Value::String(text.to_owned()),
+            Err(err) => return Err(err.to_string()),
+        };
+        match expected {
+            Some(expected) if kind(&value) != expected => Err(format!("expected {}", expected)),
+            _ => Ok(value),
+        }
+    }
+}
+
+/// Resolves the conflicting entries one by one.
+///
+/// `originals` are the values in the game file, used as the base when the list of effects is composed.
+fn resolve_patch_manually<K, V>(
+    resolver: &mut Resolver,
+    target: &Path,
+    conflict: ModPatches<K, V>,
+    originals: BTreeMap<K, V>,
+    describe: impl Fn(&V) -> String,
+    variant: impl Fn(Patch<K, V>) -> StructureChangeset,
+) -> Result<Patch<K, V>, Unresolved>
+where
+    K: Ord + Display + Clone + RulePath + CustomPath,
+    V: Debug + Clone + ListValue + CustomValue,
+{
+    // Just like with lines, we want to go from per-mod to per-path interpretation.
+    let mut pending: BTreeMap<K, Vec<(String, Option<V>)>> = BTreeMap::new();
+    for (name, patch) in conflict {
+        for (path, value) in patch {
+            pending.entry(path).or_default().push((name.clone(), value));
+        }
+    }
+    let can_preview = resolver.backend.can_preview() && resolver.previews.contains_key(target);
+    let mut decided = Patch::new();
+    while let Some((path, options)) = pending.pop_first() {
+        let choices = options.iter().map(|(name, value)| {
+            let label = match value {
+                Some(value) => format!("{}: {}", name, describe(value)),
+                None => format!("{}: <removed>", name),
+            };
+            (label, EntryChoice::Value(value.clone()))
+        });
+        let preview_choice =
+            Some(("Preview result".to_string(), EntryChoice::Preview)).filter(|_| can_preview);
+        let composable = resolver.backend.can_compose()
+            && is_effects_list(&path.rule_path())
+            && options
+                .iter()
+                .all(|(_, value)| value.as_ref().and_then(ListValue::items).is_some());
+        let compose_choice =
+            Some(("Compose the list".to_string(), EntryChoice::Compose)).filter(|_| composable);
+        let question = format!(
+            "Multiple mods are changing {} in file {}. Please choose the value to be used",
+            path,
+            target.to_string_lossy()
+        );
+        let custom_choice = Some(("Enter custom value".to_string(), EntryChoice::Custom))
+            .filter(|_| path.takes_custom());
+        let choices = choices
+            .chain(compose_choice)
+            .chain(custom_choice)
+            .chain(preview_choice);
+        match ask_for_resolve(resolver, question, choices)? {
+            EntryChoice::Value(value) => {
+                decided.insert(path, value);
+            }
+            EntryChoice::Compose => {
+                let base = originals
+                    .get(&path)
+                    .and_then(ListValue::items)
+                    .unwrap_or_default();
+                let proposals: Vec<_> = options
+                    .iter()
+                    .filter_map(|(name, value)| Some((name.clone(), value.as_ref()?.items()?)))
+                    .collect();
+                let title = format!("{} in {}", path, target.to_string_lossy());
+                let list = resolver.backend.compose_list(&title, &base, &proposals)?;
+                debug!("[resolve]: Composed {} from {:?}", path, list);
+                decided.insert(path, Some(V::from_items(list)));
+            }
+            EntryChoice::Custom => {
+                let value = ask_for_custom(resolver, target, &path, &options, &describe)?;
+                decided.insert(path, value);
+            }
+            EntryChoice::Preview => {
+                // The current entry is still pending, so it is asked again after the preview.
+                pending.insert(path.clone(), options);
+                let title = format!("Preview of {}", target.to_string_lossy());
+                let (text, highlighted) = match preview(
+                    &resolver.previews[target],
+                    target,
+                    &decided,
+                    &pending,
+                    &variant,
+                ) {
+                    Ok(preview) => preview,
+                    Err(err) => (format!("Failed to build the preview: {}", err), vec![]),
+                };
+                resolver.backend.preview(&title, &text, &highlighted);
+            }
+        }
+    }
+    Ok(decided)
+}
+
+/// Asks for the value typed in by the user until it is valid; the empty one means that the entry is removed.
+fn ask_for_custom<K: Display, V: CustomValue>(
+    resolver: &mut Resolver,
+    target: &Path,
+    path: &K,
+    options: &[(String, Option<V>)],
+    describe: impl Fn(&V) -> String,
+) -> Result<Option<V>, Unresolved> {
+    let lines: Vec<_> = options
+        .iter()
+        .map(|(name, value)| {
+            let text = value.as_ref().map(&describe).unwrap_or_default();
+            (name.clone(), text)
+        })
+        .collect();
+    let proposed: Vec<&V> = options
+        .iter()
+        .filter_map(|(_, value)| value.as_ref())
+        .collect();
+    let mut title = format!("{} in {}", path, target.to_string_lossy());
+    loop {
+        let text = resolver.backend.edit_line(&title, &lines)?;
+        if text.trim().is_empty() {
+            return Ok(None);
+        }
+        match V::parse_custom(text.trim(), &proposed) {
+            Ok(value) => return Ok(Some(value)),
+            Err(err) => {
+                warn!("[resolve]: Invalid value for {}: {}", path, err);
+                title = format!(
+                    "{} in {} (invalid value: {})",
+                    path,
+                    target.to_string_lossy(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+/// Renders the file with the merged changes and the choices made so far.
+///
+/// Every pending entry takes the value from the first mod changing it; the lines which would be different
+/// with any other mod's value are returned as highlighted.
+fn preview<K, V>(
+    source: &PreviewSource,
+    target: &Path,
+    decided: &Patch<K, V>,
+    pending: &BTreeMap<K, Vec<(String, Option<V>)>>,
+    variant: impl Fn(Patch<K, V>) -> StructureChangeset,
+) -> Result<(String, Vec<usize>), DataErrorKind>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    let merged = match source.merged.clone() {
+        Some(merged) => merged.apply(target, &source.original)?,
+        None => source.original.clone(),
+    };
+    let alternatives = pending.values().map(Vec::len).max().unwrap_or(1);
+    let texts = (0..alternatives)
+        .map(|index| {
+            let mut patch = decided.clone();
+            for (path, options) in pending {
+                let (_, value) = &options[index.min(options.len() - 1)];
+                patch.insert(path.clone(), value.clone());
+            }
+            variant(patch).apply(target, &merged)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let others: Vec<HashSet<&str>> = texts[1..]
+        .iter()
+        .map(|text| text.lines().collect())
+        .collect();
+    let highlighted = texts[0]
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| others.iter().any(|other| !other.contains(line)))
+        .map(|(index, _)| index)
+        .collect();
+    Ok((texts[0].clone(), highlighted))
+}
+
+fn resolve_modified_structure(
+    resolver: &mut Resolver,
+    target: PathBuf,
+    conflict: Conflict,
+) -> Result<StructureChangeset, Unresolved> {
+    let conflict: Vec<_> = conflict
+        .into_iter()
+        .map(|(name, node)| match node {
+            DiffNode::ModifiedStructure(changes) => (name, changes),
+            _ => unreachable!(),
+        })
+        .collect();
+    if let Some((_, StructureChangeset::Strings(_))) = conflict.first() {
+        let patches = conflict
+            .into_iter()
+            .map(|(name, changes)| match changes {
+                StructureChangeset::Strings(patch) => (name, patch),
+                _ => unreachable!(),
+            })
+            .collect();
+        return resolve_strings(resolver, &target, patches).map(StructureChangeset::Strings);
+    }
+    let variants = conflict
+        .clone()
+        .into_iter()
+        .map(|(name, changes)| (name, Some(changes)))
+        .chain(std::iter::once(("Resolve manually".into(), None)));
+    let changes = ask_for_resolve(
+        resolver,
+        format!(
+            "Multiple mods are changing the same entries in file {}.
+Non-conflicting changes were already merged.
+Please choose the mod you wish to use for conflicting cases, or resolve every entry manually
+",
+            target.to_string_lossy()
+        ),
+        variants,
+    )?;
+    match changes {
+        Some(changes) => Ok(changes),
+        None if matches!(conflict.first(), Some((_, StructureChangeset::Buffs(_)))) => {
+            let patches = conflict
+                .into_iter()
+                .map(|(name, changes)| match changes {
+                    StructureChangeset::Buffs(patch) => (name, patch),
+                    _ => unreachable!(),
+                })
+                .collect();
+            let originals = resolver
+                .previews
+                .get(&target)
+                .and_then(|source| BuffsFile::parse(&source.original).ok())
+                .map(|file| {
+                    file.definitions()
+                        .flat_map(|(id, fields)| {
+                            fields.iter().map(move |(field, value)| {
+                                let path = BuffPath {
+                                    id: id.to_owned(),
+                                    field: field.clone(),
+                                };
+                                (path, value.clone())
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            resolve_patch_manually(
+                resolver,
+                &target,
+                patches,
+                originals,
+                ToString::to_string,
+                StructureChangeset::Buffs,
+            )
+            .map(StructureChangeset::Buffs)
+        }
+        None if matches!(conflict.first(), Some((_, StructureChangeset::Library(_)))) => {
+            let patches = conflict
+                .into_iter()
+                .map(|(name, changes)| match changes {
+                    StructureChangeset::Library(patch) => (name, patch),
+                    _ => unreachable!(),
+                })
+                .collect();
+            let originals = resolver
+                .previews
+                .get(&target)
+                .and_then(|source| LibraryFile::parse_file(&target, &source.original).ok())
+                .map(|file| {
+                    file.paths()
+                        .into_iter()
+                        .map(|(path, value)| (path, value.clone()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            resolve_patch_manually(
+                resolver,
+                &target,
+                patches,
+                originals,
+                describe_json,
+                StructureChangeset::Library,
+            )
+            .map(StructureChangeset::Library)
+        }
+        None => {
+            let patches = conflict
+                .into_iter()
+                .map(|(name, changes)| match changes {
+                    StructureChangeset::Darkest(patch) => (name, patch),
+                    _ => unreachable!(),
+                })
+                .collect();
+            let originals = resolver
+                .previews
+                .get(&target)
+                .and_then(|source| GenericDarkestFile::parse(&source.original).ok())
+                .map(|file| {
+                    file.paths()
+                        .map(|(path, values)| (path, values.clone()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            resolve_patch_manually(
+                resolver,
+                &target,
+                patches,
+                originals,
+                |values| describe_values(values),
+                StructureChangeset::Darkest,
+            )
+            .map(StructureChangeset::Darkest)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum StringChoice {
+    /// Use the version from this mod.
+    One(usize),
+    /// Use the version from this mod, and for every following string in the same group.
+    All(usize),
+}
+
+/// Resolves the conflicts in strings table one string at a time.
+///
+/// String conflicts usually come in large batches from the same mods, so they are grouped
+/// by the conflicting mods and the language, and every question allows to take one mod's version
+/// for the rest of its group.
+fn resolve_strings(
+    resolver: &mut Resolver,
+    target: &Path,
+    conflict: ModPatches<LocPath, String>,
+) -> Result<Patch<LocPath, String>, Unresolved> {
+    type Versions = Vec<(String, Option<String>)>;
+    /// Conflicting mods and the language.
+    type Group = (Vec<String>, String);
+    let mut by_path: BTreeMap<LocPath, Versions> = BTreeMap::new();
+    for (name, patch) in conflict {
+        for (path, value) in patch {
+            by_path.entry(path).or_default().push((name.clone(), value));
+        }
+    }
+    let mut groups: BTreeMap<Group, Vec<(LocPath, Versions)>> = BTreeMap::new();
+    for (path, versions) in by_path {
+        let mods = versions.iter().map(|(name, _)| name.clone()).collect();
+        groups
+            .entry((mods, path.language.clone()))
+            .or_default()
+            .push((path, versions));
+    }
+
+    let mut resolved = Patch::new();
+    for ((mods, language), strings) in groups {
+        info!(
+            "[resolve] {:?}: {} conflicting strings in {} between {}",
+            target,
+            strings.len(),
+            language,
+            mods.join(", ")
+        );
+        let mut bulk = None;
+        for (path, mut versions) in strings {
+            let index = match bulk {
+                Some(index) => index,
+                None => {
+                    let options = mods
+                        .iter()
+                        .enumerate()
+                        .map(|(index, name)| (name.clone(), StringChoice::One(index)))
+                        .chain(mods.iter().enumerate().map(|(index, name)| {
+                            (
+                                format!("{} for all remaining conflicts in {}", name, language),
+                                StringChoice::All(index),
+                            )
+                        }));
+                    let texts: Vec<_> = versions
+                        .iter()
+                        .map(|(name, value)| {
+                            let text = value.clone().unwrap_or_else(|| "<removed>".into());
+                            (name.clone(), text)
+                        })
+                        .collect();
+                    let choice = ask_for_resolve_with(
+                        resolver,
+                        format!(
+                            "Multiple mods are changing the string {} in language {} in file {}.
+Please choose the version to be used",
+                            path.id,
+                            language,
+                            target.to_string_lossy()
+                        ),
+                        options,
+                        |backend, question, names| backend.compare_strings(question, &texts, names),
+                    )?;
+                    match choice {
+                        StringChoice::One(index) => index,
+                        StringChoice::All(index) => {
+                            bulk = Some(index);
+                            index
+                        }
+                    }
+                }
+            };
+            let (name, value) = versions.swap_remove(index);
+            debug!("[resolve] {:?}: Using {} from {}", target, path, name);
+            resolved.insert(path, value);
+        }
+    }
+    Ok(resolved)
+}
+
+fn resolve_incompatible(
+    resolver: &mut Resolver,
+    target: PathBuf,
+    conflict: Conflict,
+) -> Result<DiffNode, Unresolved> {
+    ask_for_resolve(
+        resolver,
+        format!(
+            "Multiple mods are changing the file {} in incompatible ways, so their changes can't be merged.
+Please choose the mod you wish to use the changes from",
+            target.to_string_lossy()
+        ),
+        conflict,
+    )
+}
+
+/// Resolves the overrides added by several mods value by value, keeping everything they don't disagree on.
+///
+/// Overrides which can't be parsed are resolved like any other added text.
+fn resolve_overrides(
+    resolver: &mut Resolver,
+    target: PathBuf,
+    conflict: Conflict,
+) -> Result<String, ResolveError> {
+    let texts: Vec<_> = conflict
+        .iter()
+        .map(|(name, node)| match node {
+            DiffNode::AddedText(text) => (name.clone(), text.clone()),
+            _ => unreachable!(),
+        })
+        .collect();
+    let overrides = match MergedOverrides::new(&target, &texts) {
+        Some(overrides) => overrides,
+        None => return resolve_added_text(resolver, target, conflict),
+    };
+    let conflict = overrides
+        .conflicts
+        .iter()
+        .map(|(name, patch)| {
+            let changes = StructureChangeset::Darkest(patch.clone());
+            (name.clone(), DiffNode::ModifiedStructure(changes))
+        })
+        .collect();
+    match resolve_modified_structure(resolver, target, conflict)? {
+        StructureChangeset::Darkest(resolved) => Ok(overrides.render(resolved)),
+        _ => unreachable!(),
     }
 }
 
 fn resolve_added_text(
-    sink: &mut cursive::CbSink,
+    resolver: &mut Resolver,
     target: PathBuf,
     conflict: Conflict,
-) -> (String, LinesChangeset) {
+) -> Result<String, ResolveError> {
     // First, store the data a little more appropriately.
-    let mut data: std::collections::HashMap<_, _> = conflict
+    let mut data: Vec<_> = conflict
         .into_iter()
         .map(|(name, node)| match node {
             DiffNode::AddedText(text) => (name, text),
@@ -284,9 +1937,9 @@ fn resolve_added_text(
         })
         .collect();
 
-    let variants = data.keys().cloned().map(|name| (name.clone(), name));
+    let variants = data.iter().map(|(name, _)| (name.clone(), name.clone()));
     let choice = ask_for_resolve(
-        sink,
+        resolver,
         format!(
             "Multiple mods are adding the text file {}.
 In this case, we treat one of them as if it is a part of vanilla game, and merge others based on it.
@@ -295,8 +1948,11 @@ Please choose one you wish to use as basic one.
             target.to_string_lossy()
         ),
         variants,
-    );
-    let chosen = data.remove(&choice).unwrap();
+    )?;
+    let index = data.iter().position(|(name, _)| *name == choice).unwrap();
+    let (_, chosen) = data.remove(index);
+    let mut names: Vec<String> = data.iter().map(|(name, _)| name.clone()).collect();
+    names.insert(0, choice);
     let base: DataTree = vec![(target.clone(), DataNode::new("", chosen.clone()))]
         .into_iter()
         .collect();
@@ -304,26 +1960,533 @@ Please choose one you wish to use as basic one.
     let (merged, conflicts) = data
         .into_iter()
         .map(|(name, content)| {
-            ModContent::new(
-                name.clone(),
-                base.diff(
-                    vec![(target.clone(), DataNode::new(name, content))]
-                        .into_iter()
-                        .collect(),
-                ),
-            )
+            let modded = vec![(target.clone(), DataNode::new(&name, content))];
+            let diff = base
+                .diff(modded.into_iter().collect())
+                .map_err(|err| err.in_mods(Some(name.as_str())))?;
+            Ok(ModContent::new(name, diff))
         })
-        .merge(None);
-    let resolved = resolve(sink, conflicts);
-    let mut merged = merge_resolved(merged, resolved);
+        .collect::<Result<Vec<_>, DataError>>()?
+        .into_iter()
+        .merge();
+    let resolved = resolve(resolver, conflicts)?;
+    let merged = merge_resolved(merged, resolved);
 
-    let changeset = match merged.remove(&target) {
-        Some(changes) => match changes {
-            DiffNode::ModifiedText(changeset) => changeset,
+    // Here, we have to do a little differently, since we're essentially resolving conflict
+    // by applying two actions, but have to make them as one.
+    let mut applied = merged
+        .apply_to(&base)
+        .map_err(|err| err.in_mods(names.iter().map(String::as_str)))?;
+    Ok(match applied.remove(&target) {
+        Some(node) => match node.into_content() {
+            DataNodeContent::Text(text) => text,
             _ => unreachable!(),
         },
-        None => unreachable!(),
-    };
+        // Every other mod is adding exactly the same content as the chosen one.
+        None => chosen,
+    })
+}
 
-    (chosen, changeset)
+#[cfg(test)]
+mod tests {
+    use super::super::structures::EntryId;
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Backend answering every question with the first option starting with the next prepared answer.
+    struct Scripted(Vec<&'static str>);
+
+    impl ResolveBackend for Scripted {
+        fn choose(&mut self, question: &str, options: &[String]) -> Result<usize, Unresolved> {
+            let answer = self.0.remove(0);
+            options
+                .iter()
+                .position(|option| option.starts_with(answer))
+                .ok_or_else(|| Unresolved(question.to_owned()))
+        }
+        fn edit_line(&mut self, title: &str, _: &[(String, String)]) -> Result<String, Unresolved> {
+            Err(Unresolved(title.to_owned()))
+        }
+    }
+
+    fn patch(language: &str, strings: &[(&str, &str)]) -> Patch<LocPath, String> {
+        strings
+            .iter()
+            .map(|(id, text)| {
+                let path = LocPath {
+                    language: language.into(),
+                    id: id.to_string(),
+                };
+                (path, Some(text.to_string()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strings_bulk_choice() {
+        let mut first = patch("english", &[("a", "A1"), ("b", "B1"), ("c", "C1")]);
+        first.extend(patch("russian", &[("a", "А1")]));
+        let mut second = patch("english", &[("a", "A2"), ("b", "B2"), ("c", "C2")]);
+        second.extend(patch("russian", &[("a", "А2")]));
+        let conflict = vec![("First".into(), first), ("Second".into(), second)];
+
+        let backend = Scripted(vec![
+            "First",
+            "Second for all remaining conflicts in english",
+            "First",
+        ]);
+        let mut resolver = Resolver::new(
+            Box::new(backend),
+            MergeStrategy::Ask,
+            Resolutions::new(),
+            PreferRules::new(),
+        );
+        let resolved =
+            resolve_strings(&mut resolver, Path::new("loc.string_table.xml"), conflict).unwrap();
+        let values: Vec<_> = resolved
+            .into_iter()
+            .map(|(path, value)| format!("{} {}", path, value.unwrap()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                "[english] a A1",
+                "[english] b B2",
+                "[english] c C2",
+                "[russian] a А1"
+            ]
+        );
+        // The bulk choice is saved as the answer to the question it was made on.
+        assert_eq!(resolver.finish().0.len(), 3);
+    }
+
+    fn conflict(path: &str, mods: &[&str]) -> (PathBuf, Conflict) {
+        let conflict = mods
+            .iter()
+            .map(|name| (name.to_string(), DiffNode::Binary(PathBuf::from(name))))
+            .collect();
+        (PathBuf::from(path), conflict)
+    }
+
+    #[test]
+    fn group_rules() {
+        let conflicts: Conflicts = vec![
+            conflict("a.png", &["QoL", "Rebalance"]),
+            conflict("b.png", &["QoL", "Rebalance"]),
+            conflict("c.png", &["QoL", "Other", "Rebalance"]),
+        ]
+        .into_iter()
+        .collect();
+        let backend = Scripted(vec![
+            "Other",
+            "Always prefer Rebalance over QoL for this bundle",
+        ]);
+        let mut resolver = Resolver::new(
+            Box::new(backend),
+            MergeStrategy::Ask,
+            Resolutions::new(),
+            PreferRules::new(),
+        );
+        let resolved = resolve(&mut resolver, conflicts).unwrap();
+        let sources: Vec<_> = resolved
+            .values()
+            .map(|node| match node {
+                DiffNode::Binary(source) => source.to_string_lossy().into_owned(),
+                _ => unreachable!(),
+            })
+            .collect();
+        // The rule doesn't order "Other", so its conflict is asked about.
+        assert_eq!(sources, vec!["Rebalance", "Rebalance", "Other"]);
+        let (_, rules, report) = resolver.finish();
+        assert_eq!(
+            rules.winner(&["QoL".to_string(), "Rebalance".to_string()]),
+            Some(1)
+        );
+        let resolutions: Vec<_> = report.iter().map(|entry| entry.resolution).collect();
+        assert_eq!(
+            resolutions,
+            vec![Resolution::User, Resolution::Rule, Resolution::Rule]
+        );
+    }
+
+    #[test]
+    fn group_preference_is_per_value() {
+        let original = "hp: .amount 33\nspeed: .amount 1\n";
+        let paths = ["heroes/a/a.info.darkest", "heroes/b/b.info.darkest"];
+        let tree = |text: &str| -> DataTree {
+            paths
+                .iter()
+                .map(|path| (PathBuf::from(path), DataNode::new(path, text.to_string())))
+                .collect()
+        };
+        let game = tree(original);
+        let (_, conflicts) = vec![
+            ("a", "hp: .amount 40\nspeed: .amount 1\n"),
+            ("b", "hp: .amount 50\nspeed: .amount 5\n"),
+            ("c", "hp: .amount 33\nspeed: .amount 9\n"),
+        ]
+        .into_iter()
+        .map(|(name, text)| ModContent::new(name, game.diff(tree(text)).unwrap()))
+        .merge();
+        // The preferred mod doesn't change the speed, so it is still asked about in every file.
+        let backend = Scripted(vec!["Prefer a", "c", "c"]);
+        let mut resolver = Resolver::new(
+            Box::new(backend),
+            MergeStrategy::Ask,
+            Resolutions::new(),
+            PreferRules::new(),
+        );
+        let resolved = resolve(&mut resolver, conflicts).unwrap();
+        for path in paths.iter().map(Path::new) {
+            let text = match &resolved[path] {
+                DiffNode::ModifiedStructure(changes) => {
+                    changes.clone().apply(path, original).unwrap()
+                }
+                _ => panic!("Conflict was not resolved into structure changes"),
+            };
+            assert_eq!(text, "hp: .amount 40\n\nspeed: .amount 9\n");
+        }
+        let report: Vec<_> = resolver
+            .finish()
+            .2
+            .into_iter()
+            .map(|entry| (entry.item.unwrap(), entry.mods.join(", ")))
+            .collect();
+        let expected = [("hp: #0 .amount", "a, b"), ("speed: #0 .amount", "b, c")];
+        let expected: Vec<_> = expected
+            .iter()
+            .chain(&expected)
+            .map(|(item, mods)| (item.to_string(), mods.to_string()))
+            .collect();
+        assert_eq!(report, expected);
+    }
+
+    #[test]
+    fn conflicts_summary() {
+        let lines = |changed: &[usize]| {
+            let changes = (0..3)
+                .map(|line| Some(LineChange::Removed).filter(|_| changed.contains(&line)))
+                .collect();
+            DiffNode::ModifiedText(LinesChangeset(changes))
+        };
+        let conflicts: Conflicts = vec![
+            conflict("b.png", &["QoL", "Rebalance"]),
+            conflict("a.png", &["Other", "Rebalance"]),
+            (
+                PathBuf::from("notes.txt"),
+                vec![
+                    ("QoL".into(), lines(&[0, 2])),
+                    ("Other".into(), lines(&[2])),
+                ],
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let files: Vec<_> = ConflictSummary::list(&conflicts)
+            .into_iter()
+            .map(|file| (file.path.to_string_lossy().into_owned(), file.items))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("notes.txt".into(), 2),
+                ("a.png".into(), 1),
+                ("b.png".into(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn overrides_are_resolved_by_values() {
+        let path = PathBuf::from("heroes/crusader/crusader.override.darkest");
+        let added = |name: &str, text: &str| (name.to_string(), DiffNode::AddedText(text.into()));
+        let conflicts: Conflicts = std::iter::once((
+            path.clone(),
+            vec![
+                added("First", "hp: .amount 40\nweapon: .atk 5\n"),
+                added("Second", "hp: .amount 50\nresistances: .stun 40%\n"),
+            ],
+        ))
+        .collect();
+        let backend = Scripted(vec!["Second"]);
+        let mut resolver = Resolver::new(
+            Box::new(backend),
+            MergeStrategy::Ask,
+            Resolutions::new(),
+            PreferRules::new(),
+        );
+        let resolved = resolve(&mut resolver, conflicts).unwrap();
+        // Only the value set by both mods is chosen, the rest is kept from every override.
+        match &resolved[&path] {
+            DiffNode::AddedText(text) => assert_eq!(
+                text,
+                "hp: .amount 50\n\nweapon: .atk 5\n\nresistances: .stun 40%\n"
+            ),
+            _ => panic!("Overrides were not resolved into one"),
+        }
+    }
+
+    /// Every preview shown, as the text and the highlighted lines.
+    type Shown = Arc<Mutex<Vec<(String, Vec<usize>)>>>;
+
+    /// Backend answering like `Scripted`, keeping every preview shown.
+    struct Previewing(Vec<&'static str>, Shown);
+
+    impl ResolveBackend for Previewing {
+        fn choose(&mut self, question: &str, options: &[String]) -> Result<usize, Unresolved> {
+            Scripted(vec![self.0.remove(0)]).choose(question, options)
+        }
+        fn edit_line(&mut self, title: &str, _: &[(String, String)]) -> Result<String, Unresolved> {
+            Err(Unresolved(title.to_owned()))
+        }
+        fn can_preview(&self) -> bool {
+            true
+        }
+        fn preview(&mut self, _: &str, text: &str, highlighted: &[usize]) {
+            self.1
+                .lock()
+                .unwrap()
+                .push((text.to_owned(), highlighted.to_vec()));
+        }
+    }
+
+    #[test]
+    fn preview_keeps_choices() {
+        let path = Path::new("heroes/crusader/crusader.info.darkest");
+        let original = "hp: .amount 33\nspeed: .amount 1\nweapon: .atk 0\n";
+        let changes = |name: &str, modded: &str| {
+            let changes = StructureChangeset::diff(path, original, modded)
+                .unwrap()
+                .unwrap();
+            (name.to_string(), DiffNode::ModifiedStructure(changes))
+        };
+        let conflicts: Conflicts = std::iter::once((
+            path.to_owned(),
+            vec![
+                changes(
+                    "First",
+                    "hp: .amount 40\nspeed: .amount 2\nweapon: .atk 0\n",
+                ),
+                changes(
+                    "Second",
+                    "hp: .amount 50\nspeed: .amount 3\nweapon: .atk 0\n",
+                ),
+            ],
+        ))
+        .collect();
+        let mut original_data = DataTree::new();
+        original_data.insert(path.to_owned(), DataNode::new(path, original.to_string()));
+
+        let previews = Arc::new(Mutex::new(vec![]));
+        let backend = Previewing(
+            vec!["Resolve manually", "First", "Preview", "Second"],
+            previews.clone(),
+        );
+        let mut resolver = Resolver::new(
+            Box::new(backend),
+            MergeStrategy::Ask,
+            Resolutions::new(),
+            PreferRules::new(),
+        )
+        .with_previews(&original_data, &DiffTree::new(), &conflicts);
+        let mut resolved = resolve(&mut resolver, conflicts).unwrap();
+        let text = match resolved.remove(path) {
+            Some(DiffNode::ModifiedStructure(changes)) => changes.apply(path, original).unwrap(),
+            _ => panic!("Conflict was not resolved into structure changes"),
+        };
+        assert_eq!(
+            text,
+            "hp: .amount 40\n\nspeed: .amount 3\n\nweapon: .atk 0\n"
+        );
+
+        // The preview has the choice already made and the first mod's value for the pending one.
+        let previews = previews.lock().unwrap();
+        assert_eq!(
+            *previews,
+            vec![(
+                "hp: .amount 40\n\nspeed: .amount 2\n\nweapon: .atk 0\n".to_string(),
+                vec![2]
+            )]
+        );
+        // Previewing is not an answer to be saved.
+        assert_eq!(resolver.finish().0.len(), 3);
+    }
+
+    /// Backend answering like `Scripted`, composing every list from the proposals in order, without repetitions.
+    struct Composing(Vec<&'static str>, Arc<Mutex<Vec<String>>>);
+
+    impl ResolveBackend for Composing {
+        fn choose(&mut self, question: &str, options: &[String]) -> Result<usize, Unresolved> {
+            Scripted(vec![self.0.remove(0)]).choose(question, options)
+        }
+        fn edit_line(&mut self, title: &str, _: &[(String, String)]) -> Result<String, Unresolved> {
+            Err(Unresolved(title.to_owned()))
+        }
+        fn can_compose(&self) -> bool {
+            true
+        }
+        fn compose_list(
+            &mut self,
+            _: &str,
+            base: &[String],
+            proposals: &[(String, Vec<String>)],
+        ) -> Result<Vec<String>, Unresolved> {
+            *self.1.lock().unwrap() = base.to_vec();
+            let mut list: Vec<String> = vec![];
+            for item in proposals.iter().flat_map(|(_, items)| items) {
+                if !list.contains(item) {
+                    list.push(item.clone());
+                }
+            }
+            Ok(list)
+        }
+    }
+
+    #[test]
+    fn effects_are_composed() {
+        let path = Path::new("heroes/crusader/crusader.info.darkest");
+        let original = "combat_skill: .id \"smite\" .effects \"Stun 1\" \"Bleed 1\" .atk 85%\n";
+        let changes = |name: &str, modded: &str| {
+            let changes = StructureChangeset::diff(path, original, modded)
+                .unwrap()
+                .unwrap();
+            (name.to_string(), DiffNode::ModifiedStructure(changes))
+        };
+        let conflicts: Conflicts = std::iter::once((
+            path.to_owned(),
+            vec![
+                changes(
+                    "First",
+                    "combat_skill: .id \"smite\" .effects \"Bleed 1\" \"Stun 1\" .atk 90%\n",
+                ),
+                changes(
+                    "Second",
+                    "combat_skill: .id \"smite\" .effects \"Stun 1\" \"Mark 1\" .atk 80%\n",
+                ),
+            ],
+        ))
+        .collect();
+        let mut original_data = DataTree::new();
+        original_data.insert(path.to_owned(), DataNode::new(path, original.to_string()));
+
+        let base = Arc::new(Mutex::new(vec![]));
+        // Only the list of effects can be composed; other values are chosen as usual.
+        let backend = Composing(vec!["Resolve manually", "Second", "Compose"], base.clone());
+        let mut resolver = Resolver::new(
+            Box::new(backend),
+            MergeStrategy::Ask,
+            Resolutions::new(),
+            PreferRules::new(),
+        )
+        .with_previews(&original_data, &DiffTree::new(), &conflicts);
+        let mut resolved = resolve(&mut resolver, conflicts).unwrap();
+        let text = match resolved.remove(path) {
+            Some(DiffNode::ModifiedStructure(changes)) => changes.apply(path, original).unwrap(),
+            _ => panic!("Conflict was not resolved into structure changes"),
+        };
+        assert_eq!(
+            text,
+            "combat_skill: .id \"smite\" .effects \"Bleed 1\" \"Stun 1\" \"Mark 1\" .atk 80%\n"
+        );
+        assert_eq!(*base.lock().unwrap(), vec!["Stun 1", "Bleed 1"]);
+    }
+
+    /// Backend answering like `Scripted`, typing in the prepared values one by one.
+    struct Typing(Vec<&'static str>, Vec<&'static str>);
+
+    impl ResolveBackend for Typing {
+        fn choose(&mut self, question: &str, options: &[String]) -> Result<usize, Unresolved> {
+            Scripted(vec![self.0.remove(0)]).choose(question, options)
+        }
+        fn edit_line(&mut self, _: &str, _: &[(String, String)]) -> Result<String, Unresolved> {
+            Ok(self.1.remove(0).to_owned())
+        }
+    }
+
+    #[test]
+    fn custom_values_are_validated() {
+        let path = Path::new("heroes/crusader/crusader.info.darkest");
+        let original = "weapon: .atk 0 .name \"sword\"\n";
+        let changes = |name: &str, modded: &str| {
+            let changes = StructureChangeset::diff(path, original, modded)
+                .unwrap()
+                .unwrap();
+            (name.to_string(), DiffNode::ModifiedStructure(changes))
+        };
+        let conflicts: Conflicts = std::iter::once((
+            path.to_owned(),
+            vec![
+                changes("First", "weapon: .atk 5 .name \"sword\"\n"),
+                changes("Second", "weapon: .atk 7 .name \"sword\"\n"),
+            ],
+        ))
+        .collect();
+        // The first value is not a number, so it's asked again.
+        let backend = Typing(
+            vec!["Resolve manually", "Enter custom value"],
+            vec!["fast", "6"],
+        );
+        let mut resolver = Resolver::new(
+            Box::new(backend),
+            MergeStrategy::Ask,
+            Resolutions::new(),
+            PreferRules::new(),
+        );
+        let mut resolved = resolve(&mut resolver, conflicts).unwrap();
+        let text = match resolved.remove(path) {
+            Some(DiffNode::ModifiedStructure(changes)) => changes.apply(path, original).unwrap(),
+            _ => panic!("Conflict was not resolved into structure changes"),
+        };
+        assert_eq!(text, "weapon: .atk 6 .name \"sword\"\n");
+
+        assert_eq!(
+            Vec::<String>::parse_custom("\"Stun 1\"  Bleed", &[]),
+            Ok(vec!["Stun 1".to_owned(), "Bleed".to_owned()])
+        );
+        let number = serde_json::json!(2);
+        assert!(serde_json::Value::parse_custom("fast", &[&number]).is_err());
+        let name = serde_json::json!("ZEAL");
+        assert_eq!(
+            serde_json::Value::parse_custom("RAGE", &[&name]),
+            Ok(serde_json::json!("RAGE"))
+        );
+    }
+
+    #[test]
+    fn set_items_are_only_kept_or_removed() {
+        let path = Path::new("heroes/crusader/crusader.info.darkest");
+        let item = DarkestPath {
+            key: "deaths_door".into(),
+            entry: EntryId::Index(0),
+            subkey: "buffs".into(),
+            item: Some("DD_X".into()),
+        };
+        let changes = |name: &str, value: Option<Vec<String>>| {
+            let patch = std::iter::once((item.clone(), value)).collect();
+            (
+                name.to_string(),
+                DiffNode::ModifiedStructure(StructureChangeset::Darkest(patch)),
+            )
+        };
+        let conflicts = || -> Conflicts {
+            std::iter::once((
+                path.to_owned(),
+                vec![changes("First", Some(vec![])), changes("Second", None)],
+            ))
+            .collect()
+        };
+        let resolver = |backend: Typing| {
+            Resolver::new(
+                Box::new(backend),
+                MergeStrategy::Ask,
+                Resolutions::new(),
+                PreferRules::new(),
+            )
+        };
+        // Typed in value would be lost, since the item has no value of its own.
+        let typed = Typing(vec!["Resolve manually", "Enter custom value"], vec!["DD_Y"]);
+        assert!(resolve(&mut resolver(typed), conflicts()).is_err());
+        let mut removing = resolver(Typing(vec!["Resolve manually", "Second"], vec![]));
+        assert!(resolve(&mut removing, conflicts()).is_ok());
+    }
 }
@@ -0,0 +1,308 @@
+//! User-configured rules deciding the conflicting values automatically, chosen by the path of the value.
+
+use super::structures::{BuffPath, DarkestPath, LibraryPath, LocPath};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// How the value is chosen among the conflicting ones.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValueStrategy {
+    /// The value from this mod, if it is among the conflicting ones.
+    PreferMod(String),
+    /// The largest value, if every value is a number.
+    Max,
+    /// The smallest value, if every value is a number.
+    Min,
+    /// The value from the mod with the lowest priority.
+    First,
+    /// The value from the mod with the highest priority.
+    Last,
+    /// Every item of every list, without repetitions, for the values which are lists (e.g. tags).
+    ConcatUnique,
+}
+
+impl Display for ValueStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PreferMod(name) => write!(f, "prefer-mod({})", name),
+            Self::Max => write!(f, "max"),
+            Self::Min => write!(f, "min"),
+            Self::First => write!(f, "first"),
+            Self::Last => write!(f, "last"),
+            Self::ConcatUnique => write!(f, "concat-unique"),
+        }
+    }
+}
+
+/// Strategy applied to the conflicting values whose path matches the glob.
+///
+/// The path of the value is the file path followed by the value address, all separated by `/`:
+/// e.g. `heroes/crusader/crusader.info.darkest/resistances/stun` for the `.darkest` files,
+/// `localization/heroes.string_table.xml/russian/str_crusader` for the strings tables,
+/// `shared/buffs/base.buffs.json/ZEAL/amount` for the buffs libraries and
+/// `trinkets/base.entries.trinkets.json/sun_ring/price` for the other libraries of definitions.
+/// The glob matches the end of this path, unless it starts with `/`; `*` matches any part
+/// of one path segment, and `**` matches any number of segments.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ValueRule {
+    pub path: String,
+    pub strategy: ValueStrategy,
+}
+
+impl Display for ValueRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} for {}", self.strategy, self.path)
+    }
+}
+
+impl ValueRule {
+    pub fn matches(&self, path: &str) -> bool {
+        let segments: Vec<_> = path.split('/').collect();
+        match self.path.strip_prefix('/') {
+            Some(anchored) => glob(&anchored.split('/').collect::<Vec<_>>(), &segments),
+            None => {
+                let pattern: Vec<_> = self.path.split('/').collect();
+                (0..=segments.len()).any(|start| glob(&pattern, &segments[start..]))
+            }
+        }
+    }
+
+    /// Chooses the value among the ones proposed by the mods, given as `(mod name, value)`,
+    /// `None` meaning that the mod removes the value.
+    ///
+    /// Returns `None` if the strategy can't be applied to these values, e.g. if some of them are not numbers
+    /// for [`ValueStrategy::Max`], so that the conflict is resolved as usual.
+    pub fn choose<V: RuleValue>(&self, options: &[(String, Option<V>)]) -> Option<Option<V>> {
+        let present = || {
+            options
+                .iter()
+                .map(|(_, value)| value.as_ref())
+                .collect::<Option<Vec<_>>>()
+        };
+        let numeric = |better: fn(f64, f64) -> bool| {
+            let values = present()?;
+            let mut best: Option<(f64, &V)> = None;
+            for value in values {
+                let number = value.number()?;
+                match best {
+                    Some((current, _)) if !better(number, current) => {}
+                    _ => best = Some((number, value)),
+                }
+            }
+            best.map(|(_, value)| Some(value.clone()))
+        };
+        match &self.strategy {
+            ValueStrategy::PreferMod(name) => options
+                .iter()
+                .find(|(mod_name, _)| mod_name == name)
+                .map(|(_, value)| value.clone()),
+            ValueStrategy::Max => numeric(|new, current| new > current),
+            ValueStrategy::Min => numeric(|new, current| new < current),
+            ValueStrategy::First => options.first().map(|(_, value)| value.clone()),
+            ValueStrategy::Last => options.last().map(|(_, value)| value.clone()),
+            ValueStrategy::ConcatUnique => {
+                let values = present()?;
+                let (first, rest) = values.split_first()?;
+                rest.iter()
+                    .try_fold((*first).clone(), |merged, value| {
+                        merged.concat_unique(value)
+                    })
+                    .map(Some)
+            }
+        }
+    }
+}
+
+fn glob(pattern: &[&str], segments: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => segments.is_empty(),
+        Some((&"**", rest)) => (0..=segments.len()).any(|skip| glob(rest, &segments[skip..])),
+        Some((part, rest)) => match segments.split_first() {
+            Some((segment, others)) => {
+                glob_segment(part.as_bytes(), segment.as_bytes()) && glob(rest, others)
+            }
+            None => false,
+        },
+    }
+}
+
+fn glob_segment(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| glob_segment(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && glob_segment(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob_segment(rest, &text[1..]),
+    }
+}
+
+/// Address of the value inside the file, as it is matched by the [`ValueRule`]s.
+pub trait RulePath {
+    fn rule_path(&self) -> String;
+}
+
+impl RulePath for DarkestPath {
+    /// Entry type and subkey, e.g. `resistances/stun`; entries of the same type are not told apart.
+    fn rule_path(&self) -> String {
+        format!("{}/{}", self.key, self.subkey)
+    }
+}
+
+impl RulePath for LocPath {
+    fn rule_path(&self) -> String {
+        format!("{}/{}", self.language, self.id)
+    }
+}
+
+impl RulePath for BuffPath {
+    fn rule_path(&self) -> String {
+        format!("{}/{}", self.id, self.field)
+    }
+}
+
+impl RulePath for LibraryPath {
+    /// Definition id and field, e.g. `sun_ring/price`; the set items are not told apart.
+    fn rule_path(&self) -> String {
+        format!("{}/{}", self.id, self.field)
+    }
+}
+
+/// Value which can be chosen by the [`ValueRule`]s.
+pub trait RuleValue: Clone {
+    /// The value as a number, if it is one; percents are counted as fractions.
+    fn number(&self) -> Option<f64>;
+    /// Both values combined, if they are lists.
+    fn concat_unique(self, other: &Self) -> Option<Self>;
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    match text.strip_suffix('%') {
+        Some(percent) => percent.parse::<f64>().ok().map(|value| value / 100.0),
+        None => text.parse().ok(),
+    }
+}
+
+impl RuleValue for Vec<String> {
+    fn number(&self) -> Option<f64> {
+        match self.as_slice() {
+            [single] => parse_number(single),
+            _ => None,
+        }
+    }
+
+    fn concat_unique(mut self, other: &Self) -> Option<Self> {
+        for item in other {
+            if !self.contains(item) {
+                self.push(item.clone());
+            }
+        }
+        Some(self)
+    }
+}
+
+impl RuleValue for String {
+    fn number(&self) -> Option<f64> {
+        parse_number(self)
+    }
+
+    fn concat_unique(self, _: &Self) -> Option<Self> {
+        None
+    }
+}
+
+impl RuleValue for serde_json::Value {
+    fn number(&self) -> Option<f64> {
+        self.as_f64()
+    }
+
+    fn concat_unique(self, other: &Self) -> Option<Self> {
+        match (self, other) {
+            (serde_json::Value::Array(mut items), serde_json::Value::Array(others)) => {
+                for item in others {
+                    if !items.contains(item) {
+                        items.push(item.clone());
+                    }
+                }
+                Some(serde_json::Value::Array(items))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::structures::EntryId;
+
+    fn rule(path: &str, strategy: ValueStrategy) -> ValueRule {
+        ValueRule {
+            path: path.into(),
+            strategy,
+        }
+    }
+
+    #[test]
+    fn glob_matching() {
+        let resistance = DarkestPath {
+            key: "resistances".into(),
+            entry: EntryId::Index(0),
+            subkey: "stun".into(),
+            item: None,
+        };
+        let path = format!(
+            "heroes/crusader/crusader.info.darkest/{}",
+            resistance.rule_path()
+        );
+        assert_eq!(
+            path,
+            "heroes/crusader/crusader.info.darkest/resistances/stun"
+        );
+        let matches = |glob: &str| rule(glob, ValueStrategy::Max).matches(&path);
+        assert!(matches("resistances/*"));
+        assert!(matches("*.info.darkest/resistances/st?n"));
+        assert!(matches("/heroes/**/resistances/*"));
+        assert!(matches("heroes/**"));
+        assert!(!matches("/resistances/*"));
+        assert!(!matches("resistances"));
+        assert!(!matches("heroes/*/resistances/*"));
+    }
+
+    #[test]
+    fn strategies() {
+        let values = |values: &[&str]| -> Vec<(String, Option<Vec<String>>)> {
+            values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    let value = value.split(' ').map(str::to_owned).collect();
+                    (format!("mod{}", index), Some(value))
+                })
+                .collect()
+        };
+        let choose = |strategy, options: &[&str]| {
+            rule("**", strategy)
+                .choose(&values(options))
+                .map(|value| value.unwrap().join(" "))
+        };
+        assert_eq!(
+            choose(ValueStrategy::Max, &["20%", "0.3", "25%"]),
+            Some("0.3".into())
+        );
+        assert_eq!(choose(ValueStrategy::Min, &["2", "-1"]), Some("-1".into()));
+        assert_eq!(choose(ValueStrategy::Max, &["2", "fast"]), None);
+        assert_eq!(choose(ValueStrategy::Last, &["a", "b"]), Some("b".into()));
+        assert_eq!(
+            choose(ValueStrategy::PreferMod("mod0".into()), &["a", "b"]),
+            Some("a".into())
+        );
+        assert_eq!(
+            choose(ValueStrategy::PreferMod("other".into()), &["a", "b"]),
+            None
+        );
+        assert_eq!(
+            choose(ValueStrategy::ConcatUnique, &["a b", "b c"]),
+            Some("a b c".into())
+        );
+    }
+}
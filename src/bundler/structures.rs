@@ -1,16 +1,870 @@
-use std::collections::BTreeMap;
+use super::error::{DataErrorKind, ParseFailure, TextLocation};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::Path,
+};
+use thiserror::Error;
 
+mod buffs;
+mod chain;
 mod darkest;
 mod json;
+mod library;
 mod localization;
 
+pub use buffs::{BuffPath, BuffsFile, BuffsParseError};
+pub use chain::{align, is_chain, merge_chains, Aligned};
+pub use darkest::{DarkestParseError, DarkestPath, EntryId, GenericDarkestFile};
+pub use library::{LibraryFile, LibraryParseError, LibraryPath, Schema};
+pub use localization::{LocPath, StringsTable};
+
+/// Parse error which can tell where in the text the parser has stopped.
+trait Locate: Display {
+    fn location(&self, source: &str) -> Option<TextLocation>;
+
+    fn failure(&self, source: &str) -> ParseFailure {
+        ParseFailure {
+            message: self.to_string(),
+            location: self.location(source),
+        }
+    }
+}
+
+impl Locate for serde_json::Error {
+    fn location(&self, source: &str) -> Option<TextLocation> {
+        // Line is zero only for the errors not caused by the input, e.g. the IO ones.
+        match self.line() {
+            0 => None,
+            _ if self.is_eof() => Some(TextLocation::at_offset(source, source.len())),
+            line => Some(TextLocation::at_line(source, line, self.column())),
+        }
+    }
+
+    fn failure(&self, source: &str) -> ParseFailure {
+        let message = self.to_string();
+        let suffix = format!(" at line {} column {}", self.line(), self.column());
+        ParseFailure {
+            message: message.strip_suffix(&suffix).unwrap_or(&message).to_owned(),
+            location: self.location(source),
+        }
+    }
+}
+
 trait MapPath: Ord + Eq {}
 
+/// Path inside the library of definitions, e.g. buffs or trinkets, which are identified by their `id` field.
+trait DefinitionPath: MapPath {
+    /// Id of the definition the value belongs to.
+    fn definition(&self) -> &str;
+    /// Checks whether the path points to the `id` field itself, which is present in every definition.
+    fn is_id(&self) -> bool;
+}
+
+trait MapValue: Clone {
+    /// Checks whether the values mean the same for the game, even if they are written differently.
+    fn same_as(&self, other: &Self) -> bool;
+}
+
 trait BTreeMappable: Sized {
     type Key: MapPath;
-    type Value;
+    type Value: MapValue;
 
     fn map(&self) -> BTreeMap<Self::Key, &Self::Value>;
-    fn map_mut(&mut self) -> BTreeMap<Self::Key, &mut Self::Value>;
     fn clone_with(&self, _: impl FnOnce(&mut BTreeMap<Self::Key, Self::Value>)) -> Self;
 }
+
+/// Collects the flattened nested structure, keeping the path to the current level in one shared buffer,
+/// so that the nested levels don't build their own maps to be copied into the parent ones.
+///
+/// Every value gets its own copy of the path, so the path parts should be cheap to clone, e.g. shared strings.
+struct MapBuilder<P, V> {
+    prefix: Vec<P>,
+    entries: Vec<(Vec<P>, V)>,
+}
+
+impl<P: Clone, V> MapBuilder<P, V> {
+    fn new() -> Self {
+        Self {
+            prefix: vec![],
+            entries: vec![],
+        }
+    }
+
+    fn enter(&mut self, part: P) {
+        self.prefix.push(part);
+    }
+
+    fn exit(&mut self) {
+        self.prefix.pop();
+    }
+
+    /// Adds the value at the current path.
+    fn insert(&mut self, value: V) {
+        self.entries.push((self.prefix.clone(), value));
+    }
+
+    fn finish(self) -> Vec<(Vec<P>, V)> {
+        self.entries
+    }
+}
+
+/// Changes made to the flattened structure: `Some` holds the new (or added) value, `None` marks the removed one.
+pub type Patch<K, V> = BTreeMap<K, Option<V>>;
+/// Patches made by several mods, each tagged with the mod name.
+pub type ModPatches<K, V> = Vec<(String, Patch<K, V>)>;
+
+fn diff<T>(orig: &T, modded: &T) -> Patch<T::Key, T::Value>
+where
+    T: BTreeMappable,
+    T::Key: Clone,
+{
+    let orig = orig.map();
+    let modded = modded.map();
+    let mut patch: Patch<_, _> = orig
+        .iter()
+        .filter_map(|(key, value)| match modded.get(key) {
+            Some(modded) if modded.same_as(value) => None,
+            Some(modded) => Some((key.clone(), Some((*modded).clone()))),
+            None => Some((key.clone(), None)),
+        })
+        .collect();
+    patch.extend(
+        modded
+            .into_iter()
+            .filter(|(key, _)| !orig.contains_key(key))
+            .map(|(key, value)| (key, Some(value.clone()))),
+    );
+    patch
+}
+
+fn apply<T: BTreeMappable>(orig: &T, patch: Patch<T::Key, T::Value>) -> T {
+    orig.clone_with(|map| {
+        for (key, value) in patch {
+            match value {
+                Some(value) => {
+                    map.insert(key, value);
+                }
+                None => {
+                    map.remove(&key);
+                }
+            }
+        }
+    })
+}
+
+/// Merges the patches made by several mods.
+///
+/// Returns the merged non-conflicting changes, and, for every mod participating in conflict,
+/// the list of its conflicting changes.
+fn merge<K: Ord + Clone, V: MapValue>(
+    patches: ModPatches<K, V>,
+) -> (Patch<K, V>, ModPatches<K, V>) {
+    let mut by_key: BTreeMap<K, Vec<(usize, Option<V>)>> = BTreeMap::new();
+    let mut names = Vec::with_capacity(patches.len());
+    for (index, (name, patch)) in patches.into_iter().enumerate() {
+        names.push(name);
+        for (key, value) in patch {
+            by_key.entry(key).or_default().push((index, value));
+        }
+    }
+
+    let mut merged = Patch::new();
+    let mut conflicts: Vec<Patch<K, V>> = names.iter().map(|_| Patch::new()).collect();
+    for (key, mut changes) in by_key {
+        let same = |first: &Option<V>, second: &Option<V>| match (first, second) {
+            (Some(first), Some(second)) => first.same_as(second),
+            (None, None) => true,
+            _ => false,
+        };
+        if changes.iter().all(|(_, value)| same(value, &changes[0].1)) {
+            merged.insert(key, changes.swap_remove(0).1);
+        } else {
+            for (index, value) in changes {
+                conflicts[index].insert(key.clone(), value);
+            }
+        }
+    }
+    let conflicts = names
+        .into_iter()
+        .zip(conflicts)
+        .filter(|(_, patch)| !patch.is_empty())
+        .collect();
+    (merged, conflicts)
+}
+
+/// Merges the changes to the library of definitions, like the generic merge does.
+///
+/// The definition added by several mods, however, must be added by all of them in the same way;
+/// otherwise, the merged definition would mix the fields from different ones,
+/// so all its fields are reported as conflicting.
+fn merge_definitions<K: DefinitionPath + Clone, V: MapValue + PartialEq>(
+    patches: ModPatches<K, V>,
+) -> (Patch<K, V>, ModPatches<K, V>) {
+    let mut added: BTreeMap<&str, Vec<Patch<K, V>>> = BTreeMap::new();
+    for (_, patch) in &patches {
+        let ids = patch
+            .iter()
+            .filter(|(path, value)| path.is_id() && value.is_some())
+            .map(|(path, _)| path.definition());
+        for id in ids {
+            let definition = patch
+                .iter()
+                .filter(|(path, _)| path.definition() == id)
+                .map(|(path, value)| (path.clone(), value.clone()))
+                .collect();
+            added.entry(id).or_default().push(definition);
+        }
+    }
+    let clashing: BTreeSet<String> = added
+        .into_iter()
+        .filter(|(_, definitions)| definitions.iter().any(|other| *other != definitions[0]))
+        .map(|(id, _)| id.to_owned())
+        .collect();
+
+    let names: Vec<_> = patches.iter().map(|(name, _)| name.clone()).collect();
+    let (rest, mut clashes): (ModPatches<_, _>, Vec<Patch<_, _>>) = patches
+        .into_iter()
+        .map(|(name, patch)| {
+            let (clash, rest) = patch
+                .into_iter()
+                .partition(|(path, _)| clashing.contains(path.definition()));
+            ((name, rest), clash)
+        })
+        .unzip();
+    // The field left out of the added definition is absent from it, so the definition resolved value by value
+    // can't get the fields of another mod's version.
+    let fields: BTreeSet<K> = clashes
+        .iter()
+        .flat_map(|patch| patch.keys().cloned())
+        .collect();
+    for patch in &mut clashes {
+        let added: BTreeSet<String> = patch
+            .iter()
+            .filter(|(path, value)| path.is_id() && value.is_some())
+            .map(|(path, _)| path.definition().to_owned())
+            .collect();
+        for path in fields
+            .iter()
+            .filter(|path| added.contains(path.definition()))
+        {
+            patch.entry(path.clone()).or_insert(None);
+        }
+    }
+    let (merged, conflicts) = merge(rest);
+    let mut conflicts: BTreeMap<_, _> = conflicts.into_iter().collect();
+    let conflicts = names
+        .into_iter()
+        .zip(clashes)
+        .filter_map(|(name, mut patch)| {
+            patch.extend(conflicts.remove(&name).unwrap_or_default());
+            Some((name, patch)).filter(|(_, patch)| !patch.is_empty())
+        })
+        .collect();
+    (merged, conflicts)
+}
+
+#[derive(Debug, Error)]
+pub enum InspectError {
+    #[error(
+        "Files of this type have no structure known to the bundler; they are merged line by line"
+    )]
+    UnknownFormat,
+    #[error("Failed to parse .darkest file")]
+    Darkest(#[from] DarkestParseError),
+    #[error("Failed to parse JSON file")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to parse strings table")]
+    Strings(#[from] serde_xml_rs::Error),
+    #[error("Failed to parse buffs library")]
+    Buffs(#[from] BuffsParseError),
+    #[error("Failed to parse library of definitions")]
+    Library(#[from] LibraryParseError),
+}
+
+/// Parses the file into the structure chosen by its extension and lists every value in it,
+/// exactly as the bundler sees them when diffing and merging.
+pub fn inspect(path: &Path, text: &str) -> Result<Vec<(String, String)>, InspectError> {
+    match path.extension().and_then(std::ffi::OsStr::to_str) {
+        Some("darkest") => Ok(GenericDarkestFile::parse(text)?
+            .map()
+            .into_iter()
+            .map(|(path, value)| (path.to_string(), path.render_value(value)))
+            .collect()),
+        Some("json") => Ok(json::JsonFile::parse(text)?
+            .map()
+            .into_iter()
+            .map(|(path, value)| (json::render_path(&path), value.to_string()))
+            .collect()),
+        Some("xml") if is_strings_table(path) => Ok(StringsTable::parse(text)?
+            .map()
+            .into_iter()
+            .map(|(path, value)| (path.to_string(), value.clone()))
+            .collect()),
+        _ => Err(InspectError::UnknownFormat),
+    }
+}
+
+/// Value of the structured file, as it is shown in the [preview](super::preview) of the bundle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PreviewValue {
+    /// What the value describes, e.g. `combat_skill: "smite"`, the buff id or the language of the strings table.
+    pub entity: String,
+    pub field: String,
+    /// Address of the value in the [changes of the mods](StructureChangeset::entries);
+    /// the set-like values are changed item by item, each one addressed by this path followed by the item.
+    pub item: String,
+    /// The value as it is written in these changes.
+    pub value: String,
+    /// Parts of the list values, e.g. the effects of the skill, so that they can be compared one by one.
+    pub parts: Vec<String>,
+}
+
+/// Lists every value of the structured file, grouped by the entity it belongs to, in the file order.
+///
+/// Unlike [`inspect`], buffs and other libraries of definitions are listed by the definition id,
+/// and the lists are kept whole, as they are merged.
+pub fn preview_values(path: &Path, text: &str) -> Result<Vec<PreviewValue>, InspectError> {
+    fn json_parts(value: &serde_json::Value) -> Vec<String> {
+        match value {
+            serde_json::Value::Array(items) => items.iter().map(ToString::to_string).collect(),
+            value => vec![value.to_string()],
+        }
+    }
+    let definitions = |definitions: Vec<(&str, &serde_json::Map<String, serde_json::Value>)>| {
+        definitions
+            .into_iter()
+            .flat_map(|(id, fields)| {
+                fields.iter().map(move |(field, value)| PreviewValue {
+                    entity: id.to_owned(),
+                    field: field.clone(),
+                    // Library paths without the set item are written the same way as the buff ones.
+                    item: BuffPath {
+                        id: id.to_owned(),
+                        field: field.clone(),
+                    }
+                    .to_string(),
+                    value: value.to_string(),
+                    parts: json_parts(value),
+                })
+            })
+            .collect()
+    };
+    match path.extension().and_then(std::ffi::OsStr::to_str) {
+        Some("darkest") => Ok(GenericDarkestFile::parse(text)?
+            .paths()
+            .map(|(path, value)| PreviewValue {
+                entity: format!("{}: {}", path.key, path.entry),
+                field: format!(".{}", path.subkey),
+                item: path.to_string(),
+                value: path.render_value(value),
+                parts: value.clone(),
+            })
+            .collect()),
+        Some("json") if is_buffs_library(path) => {
+            Ok(definitions(BuffsFile::parse(text)?.definitions().collect()))
+        }
+        Some("json") if is_library(path) => Ok(definitions(
+            LibraryFile::parse_file(path, text)?.definitions().collect(),
+        )),
+        Some("json") => Ok(json::JsonFile::parse(text)?
+            .map()
+            .into_iter()
+            .map(|(path, value)| {
+                let (entity, field) = match path.split_last() {
+                    Some((field, entity)) => (
+                        json::render_path(entity),
+                        json::render_path(std::slice::from_ref(field)),
+                    ),
+                    None => (String::new(), String::new()),
+                };
+                PreviewValue {
+                    entity,
+                    field,
+                    item: json::render_path(&path),
+                    value: value.to_string(),
+                    parts: vec![value.to_string()],
+                }
+            })
+            .collect()),
+        Some("xml") if is_strings_table(path) => Ok(StringsTable::parse(text)?
+            .map()
+            .into_iter()
+            .map(|(path, value)| PreviewValue {
+                entity: path.language.clone(),
+                field: path.id.clone(),
+                item: path.to_string(),
+                value: value.clone(),
+                parts: vec![value.clone()],
+            })
+            .collect()),
+        _ => Err(InspectError::UnknownFormat),
+    }
+}
+
+/// Changes to the file which we were able to parse into some known structure.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum StructureChangeset {
+    Darkest(#[serde(with = "pairs")] Patch<DarkestPath, Vec<String>>),
+    Strings(#[serde(with = "pairs")] Patch<LocPath, String>),
+    Buffs(#[serde(with = "pairs")] Patch<BuffPath, serde_json::Value>),
+    Library(#[serde(with = "pairs")] Patch<LibraryPath, serde_json::Value>),
+}
+
+/// Stores the patch as the list of pairs, since its keys are not strings.
+mod pairs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<K, V, S>(patch: &BTreeMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(patch)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Vec::<(K, V)>::deserialize(deserializer).map(|pairs| pairs.into_iter().collect())
+    }
+}
+
+/// Checks whether the file is a localization table, judging by its name.
+pub fn is_strings_table(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".string_table.xml")
+}
+
+/// Checks whether the file is a buffs library, judging by its name.
+pub fn is_buffs_library(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".buffs.json")
+}
+
+/// Checks whether the file is an override, which the game layers on top of the `.info.darkest` with the same name.
+pub fn is_override(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".override.darkest")
+}
+
+/// Checks whether the file is a library of definitions, such as trinkets or quirks, judging by its name;
+/// see [`Schema`].
+pub fn is_library(path: &Path) -> bool {
+    Schema::of(path).is_some()
+}
+
+/// Merges the files added by several mods into one, if the files of this kind can be merged;
+/// see [`merge_overrides`]; the buffs and other libraries of definitions are merged by the definition id.
+pub fn merge_added(path: &Path, texts: &[(String, String)]) -> Option<String> {
+    if is_override(path) {
+        merge_overrides(path, texts)
+    } else if is_buffs_library(path) {
+        merge_libraries(path, BuffsFile::default(), BuffsFile::parse, texts)
+    } else if let Some(schema) = Schema::of(path) {
+        let parse = |text: &str| LibraryFile::parse(schema, text);
+        merge_libraries(path, LibraryFile::empty(schema), parse, texts)
+    } else {
+        None
+    }
+}
+
+/// Merges the libraries of definitions, e.g. buffs or trinkets, added by several mods into one.
+///
+/// Every definition is added to the `empty` library, so the same definition added by several mods is kept once,
+/// and the different definitions with the same id make the libraries unmergeable (`None` is returned).
+fn merge_libraries<T, E>(
+    path: &Path,
+    empty: T,
+    parse: impl Fn(&str) -> Result<T, E>,
+    libraries: &[(String, String)],
+) -> Option<String>
+where
+    T: BTreeMappable + Display,
+    T::Key: DefinitionPath + Clone,
+    T::Value: PartialEq,
+    E: Display,
+{
+    let mut parsed = vec![];
+    let mut patches = vec![];
+    for (name, text) in libraries {
+        match parse(text) {
+            Ok(file) => {
+                patches.push((name.clone(), diff(&empty, &file)));
+                parsed.push(file);
+            }
+            Err(err) => {
+                warn!(
+                    "{:?}: failed to parse library from mod {}: {}",
+                    path, name, err
+                );
+                return None;
+            }
+        }
+    }
+    let (merged, conflicts) = merge_definitions(patches);
+    if conflicts.is_empty() {
+        let base = parsed.into_iter().next().unwrap_or(empty);
+        Some(apply(&base, merged).to_string())
+    } else {
+        None
+    }
+}
+
+/// Override files added by several mods, as the changes to the empty file, since overrides contain only the entries they change.
+pub struct MergedOverrides {
+    /// The first override, so that at least its entries stay in their original order.
+    base: GenericDarkestFile,
+    pub merged: Patch<DarkestPath, Vec<String>>,
+    /// Values set differently by several mods.
+    pub conflicts: ModPatches<DarkestPath, Vec<String>>,
+}
+
+impl MergedOverrides {
+    /// Merges the overrides, given as `(mod name, text)`; returns `None` if any of them can't be parsed.
+    pub fn new(path: &Path, overrides: &[(String, String)]) -> Option<Self> {
+        let empty = GenericDarkestFile::default();
+        let mut parsed = vec![];
+        let mut patches = vec![];
+        for (name, text) in overrides {
+            match GenericDarkestFile::parse(text) {
+                Ok(file) => {
+                    patches.push((name.clone(), diff(&empty, &file)));
+                    parsed.push(file);
+                }
+                Err(err) => {
+                    warn!(
+                        "{:?}: failed to parse override from mod {}: {}",
+                        path, name, err
+                    );
+                    return None;
+                }
+            }
+        }
+        let (merged, conflicts) = merge(patches);
+        Some(Self {
+            base: parsed.into_iter().next().unwrap_or(empty),
+            merged,
+            conflicts,
+        })
+    }
+
+    /// Writes the merged override, with the `resolved` values in place of the conflicting ones.
+    pub fn render(self, resolved: Patch<DarkestPath, Vec<String>>) -> String {
+        let mut patch = self.merged;
+        patch.extend(resolved);
+        apply(&self.base, patch).to_string()
+    }
+}
+
+/// Merges the override files added by several mods into one.
+///
+/// Returns `None` if any of them can't be parsed or if they change the same values differently;
+/// see [`MergedOverrides`].
+pub fn merge_overrides(path: &Path, overrides: &[(String, String)]) -> Option<String> {
+    let overrides = MergedOverrides::new(path, overrides)?;
+    if overrides.conflicts.is_empty() {
+        Some(overrides.render(Patch::new()))
+    } else {
+        None
+    }
+}
+
+impl StructureChangeset {
+    /// Calculates structural diff between two versions of file.
+    ///
+    /// Returns `None` if the file format is not known, or if the original can't be parsed,
+    /// so that the caller could fall back to the line-based diff. If the original can be parsed,
+    /// but the modded version can't, the latter is probably broken (e.g. truncated), and the failure is returned.
+    pub fn diff(path: &Path, orig: &str, modded: &str) -> Result<Option<Self>, ParseFailure> {
+        fn parse_both<T, E: Locate>(
+            path: &Path,
+            parse: impl Fn(&str) -> Result<T, E>,
+            orig: &str,
+            modded: &str,
+        ) -> Result<Option<(T, T)>, ParseFailure> {
+            let orig = match parse(orig) {
+                Ok(orig) => orig,
+                Err(err) => {
+                    warn!(
+                        "{:?}: failed to parse original file, falling back to line diff: {}",
+                        path, err
+                    );
+                    return Ok(None);
+                }
+            };
+            match parse(modded) {
+                Ok(modded) => Ok(Some((orig, modded))),
+                Err(err) => {
+                    warn!("{:?}: failed to parse modded file: {}", path, err);
+                    Err(err.failure(modded))
+                }
+            }
+        }
+        Ok(match path.extension().and_then(std::ffi::OsStr::to_str) {
+            Some("darkest") => parse_both(path, GenericDarkestFile::parse, orig, modded)?
+                .map(|(orig, modded)| Self::Darkest(diff(&orig, &modded))),
+            Some("xml") if is_strings_table(path) => {
+                parse_both(path, StringsTable::parse, orig, modded)?
+                    .map(|(orig, modded)| Self::Strings(diff(&orig, &modded)))
+            }
+            Some("json") if is_buffs_library(path) => {
+                parse_both(path, BuffsFile::parse, orig, modded)?
+                    .map(|(orig, modded)| Self::Buffs(diff(&orig, &modded)))
+            }
+            Some("json") if is_library(path) => {
+                let parse = |text: &str| LibraryFile::parse_file(path, text);
+                parse_both(path, parse, orig, modded)?
+                    .map(|(orig, modded)| Self::Library(diff(&orig, &modded)))
+            }
+            Some("json") => {
+                // Other JSON files are merged line by line, but should still be valid JSON.
+                let parse = |text: &str| serde_json::from_str::<serde::de::IgnoredAny>(text);
+                parse_both(path, parse, orig, modded)?;
+                None
+            }
+            _ => None,
+        })
+    }
+
+    /// Applies the changes to the original file.
+    ///
+    /// Fails if the original can't be parsed, which means that the changes were made to another file.
+    pub fn apply(self, path: &Path, orig: &str) -> Result<String, DataErrorKind> {
+        fn parse<T, E: Display>(
+            path: &Path,
+            parse: impl Fn(&str) -> Result<T, E>,
+            text: &str,
+        ) -> Result<T, DataErrorKind> {
+            parse(text).map_err(|err| {
+                warn!("{:?}: failed to parse original file: {}", path, err);
+                DataErrorKind::Unparsable(err.to_string())
+            })
+        }
+        Ok(match self {
+            Self::Darkest(patch) => {
+                apply(&parse(path, GenericDarkestFile::parse, orig)?, patch).to_string()
+            }
+            Self::Strings(patch) => {
+                apply(&parse(path, StringsTable::parse, orig)?, patch).to_string()
+            }
+            Self::Buffs(patch) => apply(&parse(path, BuffsFile::parse, orig)?, patch).to_string(),
+            Self::Library(patch) => {
+                let file = parse(path, |text| LibraryFile::parse_file(path, text), orig)?;
+                apply(&file, patch).to_string()
+            }
+        })
+    }
+
+    /// Lists the changes as the pairs of the changed item and its new value, `None` meaning the removed one.
+    pub fn entries(&self) -> Vec<(String, Option<String>)> {
+        match self {
+            Self::Darkest(patch) => patch
+                .iter()
+                .map(|(path, value)| {
+                    (
+                        path.to_string(),
+                        value.as_ref().map(|value| path.render_value(value)),
+                    )
+                })
+                .collect(),
+            Self::Strings(patch) => patch
+                .iter()
+                .map(|(path, value)| (path.to_string(), value.clone()))
+                .collect(),
+            Self::Buffs(patch) => patch
+                .iter()
+                .map(|(path, value)| {
+                    (
+                        path.to_string(),
+                        value.as_ref().map(|value| value.to_string()),
+                    )
+                })
+                .collect(),
+            Self::Library(patch) => patch
+                .iter()
+                .map(|(path, value)| {
+                    (
+                        path.to_string(),
+                        value.as_ref().map(|value| path.render_value(value)),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub fn merge(changes: Vec<(String, Self)>) -> (Option<Self>, Vec<(String, Self)>) {
+        type Merged<K, V> = (Patch<K, V>, ModPatches<K, V>);
+        fn wrap<K: Ord + Clone, V: MapValue>(
+            patches: ModPatches<K, V>,
+            merge: impl FnOnce(ModPatches<K, V>) -> Merged<K, V>,
+            variant: impl Fn(Patch<K, V>) -> StructureChangeset,
+        ) -> (
+            Option<StructureChangeset>,
+            Vec<(String, StructureChangeset)>,
+        ) {
+            let (merged, conflicts) = merge(patches);
+            (
+                Some(merged)
+                    .filter(|merged| !merged.is_empty())
+                    .map(&variant),
+                conflicts
+                    .into_iter()
+                    .map(|(name, patch)| (name, variant(patch)))
+                    .collect(),
+            )
+        }
+        // Changes to the same path always have the same kind, since it is chosen by the file name.
+        match changes.first() {
+            Some((_, Self::Strings(_))) => wrap(
+                changes
+                    .into_iter()
+                    .map(|(name, changes)| match changes {
+                        Self::Strings(patch) => (name, patch),
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+                merge,
+                Self::Strings,
+            ),
+            Some((_, Self::Buffs(_))) => wrap(
+                changes
+                    .into_iter()
+                    .map(|(name, changes)| match changes {
+                        Self::Buffs(patch) => (name, patch),
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+                merge_definitions,
+                Self::Buffs,
+            ),
+            Some((_, Self::Library(_))) => wrap(
+                changes
+                    .into_iter()
+                    .map(|(name, changes)| match changes {
+                        Self::Library(patch) => (name, patch),
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+                merge_definitions,
+                Self::Library,
+            ),
+            _ => wrap(
+                changes
+                    .into_iter()
+                    .map(|(name, changes)| match changes {
+                        Self::Darkest(patch) => (name, patch),
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+                merge,
+                Self::Darkest,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl MapValue for i32 {
+        fn same_as(&self, other: &Self) -> bool {
+            self == other
+        }
+    }
+
+    #[test]
+    fn overrides() {
+        let path = Path::new("heroes/crusader/crusader.override.darkest");
+        let first = ("First".to_string(), "hp: .amount 40\n".to_string());
+        let second = (
+            "Second".to_string(),
+            "combat_skill: .id \"smite\" .level 0 .atk 95%\n".to_string(),
+        );
+        assert_eq!(
+            merge_overrides(path, &[first.clone(), second]).unwrap(),
+            "hp: .amount 40\n\ncombat_skill: .id \"smite\" .atk 95% .level 0\n"
+        );
+        let third = ("Third".to_string(), "hp: .amount 50\n".to_string());
+        assert_eq!(merge_overrides(path, &[first, third]), None);
+    }
+
+    #[test]
+    fn truncated_files() {
+        let location = |path: &str, original: &str, modded: &str| {
+            StructureChangeset::diff(Path::new(path), original, modded)
+                .map(|_| ())
+                .unwrap_err()
+                .location
+                .map(|location| (location.line, location.column, location.remainder))
+        };
+        let end = |line, column| Some((line, column, String::new()));
+
+        let darkest = "hp: .amount 33\ncombat_skill: .id \"smite\" .level 0\n";
+        let path = "heroes/crusader/crusader.info.darkest";
+        assert_eq!(location(path, darkest, &darkest[..35]), end(2, 21));
+        let garbage = darkest.replace("\ncombat", "\n}}}\ncombat");
+        assert_eq!(
+            location(path, darkest, &garbage),
+            Some((2, 1, "}}}\ncombat_skill: .id \"smite\" .level 0\n".into()))
+        );
+        // The original which can't be parsed is merged line by line, as before.
+        assert!(matches!(
+            StructureChangeset::diff(Path::new(path), "}}}", darkest),
+            Ok(None)
+        ));
+
+        let json = "{\n  \"buffs\": [\n    {\"id\": \"ZEAL\", \"amount\": 2}\n  ]\n}\n";
+        let buffs = "shared/buffs/base.buffs.json";
+        assert_eq!(location(buffs, json, &json[..30]), end(3, 16));
+        assert_eq!(
+            location("raid/settings.json", json, &json[..30]),
+            end(3, 16)
+        );
+
+        let strings = concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+            "<root>\n",
+            "  <language id=\"english\">\n",
+            "    <entry id=\"str_hero\"><![CDATA[Crusader]]></entry>\n",
+            "  </language>\n",
+            "</root>\n"
+        );
+        let path = "localization/heroes.string_table.xml";
+        assert_eq!(location(path, strings, &strings[..100]), end(4, 29));
+    }
+
+    #[test]
+    fn merge_disjoint() {
+        let first: Patch<i32, i32> = vec![(1, Some(1)), (2, None)].into_iter().collect();
+        let second: Patch<i32, i32> = vec![(3, Some(3)), (2, None)].into_iter().collect();
+        let (merged, conflicts) = merge(vec![("first".into(), first), ("second".into(), second)]);
+        assert!(conflicts.is_empty());
+        assert_eq!(
+            merged,
+            vec![(1, Some(1)), (2, None), (3, Some(3))]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn merge_conflicting() {
+        let first: Patch<i32, i32> = vec![(1, Some(1)), (2, Some(2))].into_iter().collect();
+        let second: Patch<i32, i32> = vec![(2, None)].into_iter().collect();
+        let (merged, conflicts) = merge(vec![("first".into(), first), ("second".into(), second)]);
+        assert_eq!(merged, vec![(1, Some(1))].into_iter().collect());
+        assert_eq!(
+            conflicts,
+            vec![
+                ("first".into(), vec![(2, Some(2))].into_iter().collect()),
+                ("second".into(), vec![(2, None)].into_iter().collect()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use super::{
+    super::error::{ParseFailure, TextLocation},
+    BTreeMappable, DefinitionPath, Locate, MapPath,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+};
+use thiserror::Error;
+
+/// Address of the buff field: the buff id and the field name, e.g. `stat_type` or `rule_data`.
+#[derive(Clone, PartialOrd, PartialEq, Ord, Eq, Debug, Serialize, Deserialize)]
+pub struct BuffPath {
+    pub id: String,
+    pub field: String,
+}
+impl MapPath for BuffPath {}
+
+impl DefinitionPath for BuffPath {
+    fn definition(&self) -> &str {
+        &self.id
+    }
+
+    fn is_id(&self) -> bool {
+        self.field == "id"
+    }
+}
+
+impl Display for BuffPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.id, self.field)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BuffsParseError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("No \"buffs\" list in the root object")]
+    NoBuffs,
+    #[error("Buff #{0} has no string id")]
+    NoId(usize),
+    #[error("Buff {0:?} is defined twice")]
+    DuplicateId(String),
+}
+
+impl Locate for BuffsParseError {
+    fn location(&self, source: &str) -> Option<TextLocation> {
+        match self {
+            Self::Json(err) => err.location(source),
+            _ => None,
+        }
+    }
+
+    fn failure(&self, source: &str) -> ParseFailure {
+        match self {
+            Self::Json(err) => err.failure(source),
+            err => ParseFailure {
+                message: err.to_string(),
+                location: None,
+            },
+        }
+    }
+}
+
+/// Contents of the buffs library, e.g. `shared/buffs/base.buffs.json`: every buff, by its id.
+///
+/// Buffs keep their order in file; the fields of the root object other than the buffs list are kept as is.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BuffsFile {
+    root: Map<String, Value>,
+    buffs: Vec<(String, Map<String, Value>)>,
+}
+
+impl BuffsFile {
+    pub fn parse(source: &str) -> Result<Self, BuffsParseError> {
+        let mut root: Map<String, Value> = serde_json::from_str(source)?;
+        let list = match root.remove("buffs") {
+            Some(Value::Array(list)) => list,
+            _ => return Err(BuffsParseError::NoBuffs),
+        };
+        let mut ids = HashSet::new();
+        let mut buffs = Vec::with_capacity(list.len());
+        for (index, buff) in list.into_iter().enumerate() {
+            let fields = match buff {
+                Value::Object(fields) => fields,
+                _ => return Err(BuffsParseError::NoId(index)),
+            };
+            let id = match fields.get("id") {
+                Some(Value::String(id)) => id.clone(),
+                _ => return Err(BuffsParseError::NoId(index)),
+            };
+            if !ids.insert(id.clone()) {
+                return Err(BuffsParseError::DuplicateId(id));
+            }
+            buffs.push((id, fields));
+        }
+        Ok(Self { root, buffs })
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.buffs.iter().map(|(id, _)| id.as_str())
+    }
+
+    /// Every buff definition, as the game reads it, by its id.
+    pub fn definitions(&self) -> impl Iterator<Item = (&str, &Map<String, Value>)> {
+        self.buffs.iter().map(|(id, fields)| (id.as_str(), fields))
+    }
+}
+
+impl Display for BuffsFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut root = self.root.clone();
+        let buffs = self
+            .buffs
+            .iter()
+            .map(|(_, fields)| Value::Object(fields.clone()))
+            .collect();
+        root.insert("buffs".into(), Value::Array(buffs));
+        let text = serde_json::to_string_pretty(&root).map_err(|_| std::fmt::Error)?;
+        writeln!(f, "{}", text)
+    }
+}
+
+impl BTreeMappable for BuffsFile {
+    type Key = BuffPath;
+    type Value = Value;
+
+    fn map(&self) -> BTreeMap<BuffPath, &Value> {
+        self.buffs
+            .iter()
+            .flat_map(|(id, fields)| {
+                fields.iter().map(move |(field, value)| {
+                    let path = BuffPath {
+                        id: id.clone(),
+                        field: field.clone(),
+                    };
+                    (path, value)
+                })
+            })
+            .collect()
+    }
+
+    fn clone_with(&self, f: impl FnOnce(&mut BTreeMap<BuffPath, Value>)) -> Self {
+        let mut map = self
+            .map()
+            .into_iter()
+            .map(|(path, value)| (path, value.clone()))
+            .collect();
+        f(&mut map);
+        let mut by_id: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+        for (path, value) in map {
+            by_id.entry(path.id).or_default().insert(path.field, value);
+        }
+        // Existing buffs stay in place, new ones are added at the end, ordered by id.
+        let mut buffs: Vec<_> = self
+            .buffs
+            .iter()
+            .filter_map(|(id, _)| by_id.remove_entry(id))
+            .collect();
+        buffs.extend(by_id);
+        Self {
+            root: self.root.clone(),
+            buffs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{apply, diff, merge_definitions};
+    use super::*;
+
+    const BASE: &str = r#"{
+        "version": 1,
+        "buffs": [
+            {"id": "ZEAL", "stat_type": "combat_stat_add", "stat_sub_type": "damage_low", "amount": 2},
+            {"id": "BLESS", "stat_type": "resistance", "stat_sub_type": "stun", "amount": 0.1, "custom": [1, 2]}
+        ]
+    }"#;
+
+    fn modded(extra: &str) -> BuffsFile {
+        let text = BASE.replace(
+            "\"custom\": [1, 2]}",
+            &format!("\"custom\": [1, 2]}}{}", extra),
+        );
+        BuffsFile::parse(&text).unwrap()
+    }
+
+    #[test]
+    fn roundtrip_keeps_unknown_fields() {
+        let file = BuffsFile::parse(BASE).unwrap();
+        let written = file.clone_with(|_| {}).to_string();
+        assert_eq!(BuffsFile::parse(&written).unwrap(), file);
+        assert!(written.contains("\"custom\""));
+        assert!(written.contains("\"version\": 1"));
+        assert_eq!(file.ids().collect::<Vec<_>>(), vec!["ZEAL", "BLESS"]);
+    }
+
+    #[test]
+    fn duplicates_are_rejected() {
+        let text = r#"{"buffs": [{"id": "ZEAL"}, {"id": "ZEAL", "amount": 1}]}"#;
+        assert!(matches!(
+            BuffsFile::parse(text),
+            Err(BuffsParseError::DuplicateId(id)) if id == "ZEAL"
+        ));
+    }
+
+    #[test]
+    fn added_buffs() {
+        let base = BuffsFile::parse(BASE).unwrap();
+        let rage = r#", {"id": "RAGE", "stat_type": "combat_stat_add", "amount": 5}"#;
+        let other_rage = r#", {"id": "RAGE", "stat_type": "combat_stat_add", "amount": 6}"#;
+        let focus = r#", {"id": "FOCUS", "stat_type": "combat_stat_add", "amount": 1}"#;
+
+        // Same buff from two mods is merged silently, different ones are merged together.
+        let (merged, conflicts) = merge_definitions(vec![
+            ("first".into(), diff(&base, &modded(rage))),
+            (
+                "second".into(),
+                diff(&base, &modded(&format!("{}{}", rage, focus))),
+            ),
+        ]);
+        assert!(conflicts.is_empty());
+        let result = apply(&base, merged);
+        assert_eq!(
+            result.ids().collect::<Vec<_>>(),
+            vec!["ZEAL", "BLESS", "FOCUS", "RAGE"]
+        );
+
+        // Same id with different definitions is a conflict as a whole.
+        let (merged, conflicts) = merge_definitions(vec![
+            ("first".into(), diff(&base, &modded(rage))),
+            ("second".into(), diff(&base, &modded(other_rage))),
+        ]);
+        assert!(merged.is_empty());
+        let names: Vec<_> = conflicts.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["first", "second"]);
+        assert_eq!(conflicts[0].1.len(), 3);
+
+        // The field left out of one version is absent there, not taken from the other one.
+        let longer_rage =
+            r#", {"id": "RAGE", "stat_type": "combat_stat_add", "amount": 6, "duration": 2}"#;
+        let (_, conflicts) = merge_definitions(vec![
+            ("first".into(), diff(&base, &modded(rage))),
+            ("second".into(), diff(&base, &modded(longer_rage))),
+        ]);
+        let duration = BuffPath {
+            id: "RAGE".into(),
+            field: "duration".into(),
+        };
+        assert_eq!(conflicts[0].1.get(&duration), Some(&None));
+        assert_eq!(conflicts[1].1.len(), 4);
+    }
+}
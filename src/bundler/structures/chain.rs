@@ -0,0 +1,258 @@
+//! Three-way merge of the ordered lists, like the chains of skill effects, changed by several mods.
+
+/// Subkeys holding the ordered chains of effects, as `(entry type, subkey)`.
+const CHAIN_SUBKEYS: &[(&str, &str)] = &[
+    ("combat_skill", "effect"),
+    ("combat_move_skill", "effect"),
+    ("riposte_skill", "effect"),
+];
+
+/// Checks whether the subkey of `.darkest` entry holds the chain of effects, which can be merged by [`merge_chains`].
+pub fn is_chain(key: &str, subkey: &str) -> bool {
+    CHAIN_SUBKEYS.contains(&(key, subkey))
+}
+
+/// Continuous change to the original list: the items `start..end` are replaced by `items`;
+/// `start == end` means the pure insertion before the item `start`.
+#[derive(Clone, Debug, PartialEq)]
+struct Hunk {
+    start: usize,
+    end: usize,
+    items: Vec<String>,
+}
+
+impl Hunk {
+    fn is_insertion(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Finds the changes made to the `original` list by the `modded` one, aligning them by their longest common subsequence.
+fn hunks(original: &[String], modded: &[String]) -> Vec<Hunk> {
+    let (n, m) = (original.len(), modded.len());
+    // common[i][j] is the length of the longest common subsequence of `original[i..]` and `modded[j..]`.
+    let mut common = vec![vec![0; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = if original[i] == modded[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut hunks = vec![];
+    let mut current: Option<Hunk> = None;
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && original[i] == modded[j] {
+            hunks.extend(current.take());
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let hunk = current.get_or_insert_with(|| Hunk {
+            start: i,
+            end: i,
+            items: vec![],
+        });
+        if j < m && (i == n || common[i][j + 1] >= common[i + 1][j]) {
+            hunk.items.push(modded[j].clone());
+            j += 1;
+        } else {
+            i += 1;
+            hunk.end = i;
+        }
+    }
+    hunks.extend(current);
+    hunks
+}
+
+/// Item of two lists aligned against each other, see [`align`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aligned<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Aligns the `modded` list against the `original` one by their longest common subsequence,
+/// so that only the items which really changed are marked as removed or added.
+pub fn align<'a>(original: &'a [String], modded: &'a [String]) -> Vec<Aligned<'a>> {
+    let mut aligned = vec![];
+    let mut index = 0;
+    let mut modded_index = 0;
+    for hunk in hunks(original, modded) {
+        for item in &original[index..hunk.start] {
+            aligned.push(Aligned::Same(item));
+        }
+        modded_index += hunk.start - index;
+        aligned.extend(
+            original[hunk.start..hunk.end]
+                .iter()
+                .map(|item| Aligned::Removed(item)),
+        );
+        aligned.extend(
+            modded[modded_index..modded_index + hunk.items.len()]
+                .iter()
+                .map(|item| Aligned::Added(item)),
+        );
+        modded_index += hunk.items.len();
+        index = hunk.end;
+    }
+    aligned.extend(original[index..].iter().map(|item| Aligned::Same(item)));
+    aligned
+}
+
+/// Checks whether the changes made by different mods can be applied together.
+fn compatible(first: &Hunk, second: &Hunk, len: usize) -> bool {
+    if first == second {
+        return true;
+    }
+    match (first.is_insertion(), second.is_insertion()) {
+        // Items added at the same place are ordered only at the ends of the list: appended or prepended.
+        (true, true) => first.start != second.start || first.start == 0 || first.start == len,
+        // Items can be added right before or after the replaced ones, but not inside them.
+        (true, false) => first.start <= second.start || first.start >= second.end,
+        (false, true) => second.start <= first.start || second.start >= first.end,
+        (false, false) => first.end <= second.start || second.end <= first.start,
+    }
+}
+
+/// Merges the versions of the `original` list made by several mods, given in the mods order.
+///
+/// Changes made to the different parts of the list are all applied; the items added by several mods
+/// to its end (or start) are all kept, in the mods order. Returns `None` if the mods change the same items
+/// differently, or add different items at the same place in the middle of the list.
+pub fn merge_chains(original: &[String], versions: &[&[String]]) -> Option<Vec<String>> {
+    let mut all: Vec<Hunk> = vec![];
+    for version in versions {
+        for hunk in hunks(original, version) {
+            if !all
+                .iter()
+                .all(|other| compatible(other, &hunk, original.len()))
+            {
+                return None;
+            }
+            if !all.contains(&hunk) {
+                all.push(hunk);
+            }
+        }
+    }
+    let mut merged = vec![];
+    let mut index = 0;
+    loop {
+        for hunk in all
+            .iter()
+            .filter(|hunk| hunk.is_insertion() && hunk.start == index)
+        {
+            merged.extend(hunk.items.iter().cloned());
+        }
+        match all
+            .iter()
+            .find(|hunk| !hunk.is_insertion() && hunk.start == index)
+        {
+            Some(hunk) => {
+                merged.extend(hunk.items.iter().cloned());
+                index = hunk.end;
+            }
+            None if index < original.len() => {
+                merged.push(original[index].clone());
+                index += 1;
+            }
+            None => return Some(merged),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: &str) -> Vec<String> {
+        items.split_whitespace().map(String::from).collect()
+    }
+
+    fn merge(original: &str, versions: &[&str]) -> Option<String> {
+        let versions: Vec<_> = versions.iter().map(|version| list(version)).collect();
+        let versions: Vec<_> = versions.iter().map(Vec::as_slice).collect();
+        merge_chains(&list(original), &versions).map(|merged| merged.join(" "))
+    }
+
+    #[test]
+    fn appends_are_combined() {
+        assert_eq!(
+            merge("Stun1 Bleed1", &["Stun1 Bleed1 A", "Stun1 Bleed1 B"]),
+            Some("Stun1 Bleed1 A B".into())
+        );
+        assert_eq!(
+            merge("Stun1", &["A Stun1", "Stun1 B", "A Stun1"]),
+            Some("A Stun1 B".into())
+        );
+    }
+
+    #[test]
+    fn removed_tail_and_append() {
+        assert_eq!(
+            merge(
+                "Stun1 Bleed1 Mark",
+                &["Stun1 Bleed1", "Stun1 Bleed1 Mark B"]
+            ),
+            Some("Stun1 Bleed1 B".into())
+        );
+        assert_eq!(
+            merge(
+                "Stun1 Bleed1 Mark",
+                &["Stun1 Bleed1", "A Stun1 Bleed1 Mark"]
+            ),
+            Some("A Stun1 Bleed1".into())
+        );
+        // Changes to the different parts of the list.
+        assert_eq!(
+            merge(
+                "Stun1 Bleed1 Mark Debuff",
+                &["Stun2 Bleed1 Mark Debuff", "Stun1 Bleed1 Mark Blight"]
+            ),
+            Some("Stun2 Bleed1 Mark Blight".into())
+        );
+    }
+
+    #[test]
+    fn aligned_lists() {
+        let (original, modded) = (list("Stun1 Bleed1 Mark"), list("A Stun1 Bleed2 Mark B"));
+        assert_eq!(
+            align(&original, &modded),
+            vec![
+                Aligned::Added("A"),
+                Aligned::Same("Stun1"),
+                Aligned::Removed("Bleed1"),
+                Aligned::Added("Bleed2"),
+                Aligned::Same("Mark"),
+                Aligned::Added("B"),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_item_replaced_differently() {
+        assert_eq!(
+            merge(
+                "Stun1 Bleed1 Mark",
+                &["Stun1 Bleed2 Mark", "Stun1 Bleed3 Mark"]
+            ),
+            None
+        );
+        assert_eq!(
+            merge("Stun1 Bleed1 Mark", &["Stun1 Mark", "Stun1 Bleed3 Mark"]),
+            None
+        );
+        assert_eq!(merge("Stun1 Mark", &["Stun1 A Mark", "Stun1 B Mark"]), None);
+        assert_eq!(
+            merge(
+                "Stun1 Bleed1 Mark",
+                &["Stun1 Bleed2 Mark", "Stun1 Bleed2 Mark"]
+            ),
+            Some("Stun1 Bleed2 Mark".into())
+        );
+    }
+}
@@ -1,10 +1,66 @@
-use super::BTreeMappable;
+use super::{
+    super::error::{ParseFailure, TextLocation},
+    BTreeMappable, Locate, MapValue,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{Display, Formatter},
+};
+use thiserror::Error;
 
 #[derive(Clone, Debug, Default)]
 struct DarkestEntry(Vec<(String, Vec<String>)>);
 
+/// Whether the values in the source are quoted, e.g. the bare `DEATHS_DOOR_BUFF` or the quoted `"1"`,
+/// so that they are written back as they were.
+///
+/// Values added by mods are written like the other items of the same value, e.g. the bare mode added
+/// to `.valid_modes human`, or else like the same subkey in the other entries; if there are none, `is_bare_value` guesses.
 #[derive(Clone, Debug, Default)]
-struct DarkestFile(Vec<(String, DarkestEntry)>);
+struct Quoting {
+    values: HashMap<String, bool>,
+    /// Quoting of the values which are not numbers, as `(entry type, subkey)`.
+    subkeys: HashMap<(String, String), bool>,
+}
+
+impl Quoting {
+    fn record(&mut self, value: &str, quoted: bool) {
+        // If the same value is written both ways, the first one wins.
+        self.values.entry(value.to_owned()).or_insert(quoted);
+    }
+
+    /// Remembers how the subkeys of the entry are quoted, the first entry of the type winning.
+    fn record_subkeys(&mut self, key: &str, entry: &DarkestEntry, entry_quoting: &Quoting) {
+        for (subkey, values) in &entry.0 {
+            if let Some(value) = values.iter().find(|value| !is_bare_value(value)) {
+                self.subkeys
+                    .entry((key.to_owned(), subkey.clone()))
+                    .or_insert_with(|| entry_quoting.is_quoted(key, subkey, values, value));
+            }
+        }
+    }
+
+    /// Checks whether the `value`, one of the `items` of the subkey, is to be written in quotes.
+    fn is_quoted(&self, key: &str, subkey: &str, items: &[String], value: &str) -> bool {
+        match self.values.get(value) {
+            Some(quoted) => *quoted,
+            None if is_bare_value(value) => false,
+            // Only the identifiers can be written bare, e.g. not the ones with spaces.
+            None if !is_ident(value) => true,
+            None => items
+                .iter()
+                .filter(|item| !is_bare_value(item))
+                .find_map(|item| self.values.get(item))
+                .or_else(|| self.subkeys.get(&(key.to_owned(), subkey.to_owned())))
+                .copied()
+                .unwrap_or(true),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct DarkestFile(Vec<(String, DarkestEntry)>, Quoting);
 
 macro_rules! explode {
     ($with:ident) => {
@@ -19,6 +75,79 @@ macro_rules! explode {
 
 impl super::MapPath for (String, String) {}
 
+/// Number which remembers whether it was written as percent (`50%`) or as ratio (`0.5`).
+///
+/// The game treats both forms the same, so they must not be seen as a change, but the original
+/// form is kept in the output, since the modders diffing the bundle care about it.
+#[derive(Clone, Copy, Debug)]
+pub struct PercentValue {
+    pub value: f32,
+    pub written_as_percent: bool,
+}
+
+impl PercentValue {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.strip_suffix('%') {
+            Some(percent) => percent.parse::<f32>().ok().map(|percent| Self {
+                value: percent / 100.0,
+                written_as_percent: true,
+            }),
+            None => value.parse().ok().map(|value| Self {
+                value,
+                written_as_percent: false,
+            }),
+        }
+    }
+
+    /// Compares the values with the relative tolerance, so that the float re-serialization
+    /// artifacts (like `0.3` vs `0.30000001`) are not treated as changes.
+    pub fn approx_eq(&self, other: &Self, tolerance: f32) -> bool {
+        let scale = self.value.abs().max(other.value.abs());
+        (self.value - other.value).abs() <= scale * tolerance
+    }
+}
+
+/// Default relative tolerance used when comparing the numbers from `.darkest` files.
+pub const FLOAT_TOLERANCE: f32 = 1e-6;
+
+impl Display for PercentValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.written_as_percent {
+            // Rounded to hide the float error introduced by the division in `parse`.
+            let percent = (f64::from(self.value) * 100.0 * 1e4).round() / 1e4;
+            write!(f, "{}%", percent)
+        } else {
+            write!(f, "{}", self.value)
+        }
+    }
+}
+
+impl PartialEq for PercentValue {
+    /// Only the numeric value is compared, not the notation.
+    fn eq(&self, other: &Self) -> bool {
+        self.approx_eq(other, FLOAT_TOLERANCE)
+    }
+}
+
+impl MapValue for Vec<String> {
+    fn same_as(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.iter().zip(other).all(|(first, second)| {
+                if first == second {
+                    return true;
+                }
+                // Integers (e.g. ids and counters) must match exactly.
+                if let (Ok(first), Ok(second)) = (first.parse::<i64>(), second.parse::<i64>()) {
+                    return first == second;
+                }
+                match (PercentValue::parse(first), PercentValue::parse(second)) {
+                    (Some(first), Some(second)) => first == second,
+                    _ => false,
+                }
+            })
+    }
+}
+
 impl BTreeMappable for DarkestFile {
     type Key = (String, String);
     type Value = Vec<String>;
@@ -26,10 +155,6 @@ impl BTreeMappable for DarkestFile {
         self.0.iter().flat_map(explode!(iter)).collect()
     }
 
-    fn map_mut(&mut self) -> std::collections::BTreeMap<Self::Key, &mut Self::Value> {
-        self.0.iter_mut().flat_map(explode!(iter_mut)).collect()
-    }
-
     fn clone_with(
         &self,
         f: impl FnOnce(&mut std::collections::BTreeMap<Self::Key, Self::Value>),
@@ -50,12 +175,397 @@ impl BTreeMappable for DarkestFile {
             }
             (v.last_mut().unwrap().1).0.push((subkey, value));
         }
-        Self(v)
+        Self(v, self.1.clone())
+    }
+}
+
+/// Identifies the entry among all entries of the same type in the file.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum EntryId {
+    /// Entry has an `.id` subkey, but no `.level`. Since several entries can share the same id,
+    /// the second field counts the previous entries with this id.
+    Id(String, usize),
+    /// Entry has an `.id` and a `.level`, e.g. the `combat_skill`s for different levels. Such entries are told apart
+    /// by their level, so that the mod inserting a level before the others doesn't shift them;
+    /// the last field counts the previous entries with the same id and level.
+    LevelId(String, String, usize),
+    /// Entry has an `.id` and is used only in some of the hero modes, e.g. the skill of the transformed hero.
+    /// The variants are told apart by their `.level` and counted among the variants of the same level,
+    /// not by the modes themselves, so that the mod adding a mode to the skill changes only its `.valid_modes`.
+    ModeId(String, String, usize),
+    /// Entry has no `.id`, so it is identified by its position among the entries of the same type.
+    Index(usize),
+}
+
+impl Display for EntryId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EntryId::Id(id, 0) => write!(f, "\"{}\"", id),
+            EntryId::Id(id, occurence) => write!(f, "\"{}\" #{}", id, occurence),
+            EntryId::LevelId(id, level, 0) => write!(f, "\"{}\" (level {})", id, level),
+            EntryId::LevelId(id, level, occurence) => {
+                write!(f, "\"{}\" (level {}) #{}", id, level, occurence)
+            }
+            EntryId::ModeId(id, level, 0) => {
+                write!(f, "\"{}\" (mode variant, level {})", id, level)
+            }
+            EntryId::ModeId(id, level, occurence) => {
+                write!(
+                    f,
+                    "\"{}\" (mode variant, level {}) #{}",
+                    id, level, occurence
+                )
+            }
+            EntryId::Index(index) => write!(f, "#{}", index),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct DarkestPath {
+    pub key: String,
+    pub entry: EntryId,
+    pub subkey: String,
+    /// Item of the [set-like](is_set) value; such value is addressed item by item, and `None` is used only if it is empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item: Option<String>,
+}
+
+impl Display for DarkestPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} .{}", self.key, self.entry, self.subkey)?;
+        match &self.item {
+            Some(item) => write!(f, " \"{}\"", item),
+            None => Ok(()),
+        }
+    }
+}
+
+impl DarkestPath {
+    /// The value as it is shown to the user: the item itself for the set items, which have no value of their own.
+    pub fn render_value(&self, value: &[String]) -> String {
+        match &self.item {
+            Some(item) => item.clone(),
+            None => value.join(" "),
+        }
+    }
+}
+
+/// Subkeys holding the unordered sets of ids, as `(entry type, subkey)`, `*` matching any entry type.
+///
+/// Sets made of separate entries, like `tag: .id "religious"` or `extra_stack_limit: .id "..."`,
+/// are already addressed by their ids, so only the sets written in one value are listed here.
+const SET_SUBKEYS: &[(&str, &str)] = &[
+    ("deaths_door", "buffs"),
+    ("deaths_door", "recovery_buffs"),
+    ("deaths_door", "recovery_heart_attack_buffs"),
+    ("death_reaction", "buff_ids"),
+    ("incompatible_party_member", "hero_tag"),
+    ("*", "valid_modes"),
+    ("*", "tags"),
+];
+
+/// Checks whether the order of values in the subkey doesn't matter, so that every item is addressed separately;
+/// this way the items added and removed by different mods are merged together, and only the same item
+/// added by one mod and removed by another is a conflict.
+pub fn is_set(key: &str, subkey: &str) -> bool {
+    SET_SUBKEYS
+        .iter()
+        .any(|(set_key, set_subkey)| (*set_key == "*" || *set_key == key) && *set_subkey == subkey)
+}
+
+/// Value stored at the path of the set item, which is fully described by the path.
+static SET_ITEM: Vec<String> = Vec::new();
+
+/// Takes the value of the subkey at `path` out of the map, collecting the set items into one value:
+/// first the `original` items which are still there, then the added ones.
+fn take_value(
+    map: &mut BTreeMap<DarkestPath, Vec<String>>,
+    path: &DarkestPath,
+    original: &[String],
+) -> Option<Vec<String>> {
+    let mut path = DarkestPath {
+        item: None,
+        ..path.clone()
+    };
+    let whole = map.remove(&path);
+    let mut items = vec![];
+    for item in original {
+        path.item = Some(item.clone());
+        if map.remove(&path).is_some() {
+            items.push(item.clone());
+        }
+    }
+    path.item = Some(String::new());
+    let added: Vec<_> = map
+        .range(path.clone()..)
+        .take_while(|(added, _)| {
+            added.key == path.key && added.entry == path.entry && added.subkey == path.subkey
+        })
+        .map(|(added, _)| added.clone())
+        .collect();
+    for added in added {
+        map.remove(&added);
+        items.extend(added.item);
+    }
+    match (whole, items.is_empty()) {
+        (None, true) => None,
+        (whole, _) => Some(whole.into_iter().flatten().chain(items).collect()),
+    }
+}
+
+impl super::MapPath for DarkestPath {}
+
+/// Fallback representation for the `.darkest` files which have no dedicated data type.
+///
+/// Every entry is addressed by its type and either its `.id` or its position, so that the changes
+/// to different entries (and to the different subkeys of one entry) can be merged independently.
+#[derive(Clone, Debug, Default)]
+pub struct GenericDarkestFile(DarkestFile);
+
+impl GenericDarkestFile {
+    fn entry_ids(&self) -> Vec<EntryId> {
+        let mut counters: HashMap<(&str, EntryId), usize> = HashMap::new();
+        (self.0)
+            .0
+            .iter()
+            .map(|(key, entry)| {
+                let value = |name: &str| {
+                    entry
+                        .0
+                        .iter()
+                        .find(|(subkey, _)| subkey == name)
+                        .map(|(_, value)| value.join(" "))
+                };
+                // Entries are counted among the ones which differ only by this count.
+                let kind = match (value("id"), value("level")) {
+                    (Some(id), level) if value("valid_modes").is_some() => {
+                        EntryId::ModeId(id, level.unwrap_or_default(), 0)
+                    }
+                    (Some(id), Some(level)) => EntryId::LevelId(id, level, 0),
+                    (Some(id), None) => EntryId::Id(id, 0),
+                    (None, _) => EntryId::Index(0),
+                };
+                let counter = counters.entry((key, kind.clone())).or_default();
+                let index = *counter;
+                *counter += 1;
+                match kind {
+                    EntryId::ModeId(id, level, _) => EntryId::ModeId(id, level, index),
+                    EntryId::LevelId(id, level, _) => EntryId::LevelId(id, level, index),
+                    EntryId::Id(id, _) => EntryId::Id(id, index),
+                    EntryId::Index(_) => EntryId::Index(index),
+                }
+            })
+            .collect()
+    }
+
+    /// Every value in the file, along with its path, in order of appearance.
+    ///
+    /// Values are listed as they are written, even the set-like ones, which are split into items by [`map`](BTreeMappable::map).
+    pub fn paths(&self) -> impl Iterator<Item = (DarkestPath, &Vec<String>)> {
+        (self.0)
+            .0
+            .iter()
+            .zip(self.entry_ids())
+            .flat_map(|((key, entry), id)| {
+                entry.0.iter().map(move |(subkey, value)| {
+                    let path = DarkestPath {
+                        key: key.clone(),
+                        entry: id.clone(),
+                        subkey: subkey.clone(),
+                        item: None,
+                    };
+                    (path, value)
+                })
+            })
+    }
+}
+
+impl BTreeMappable for GenericDarkestFile {
+    type Key = DarkestPath;
+    type Value = Vec<String>;
+
+    fn map(&self) -> BTreeMap<Self::Key, &Self::Value> {
+        let mut map = BTreeMap::new();
+        for (path, value) in self.paths() {
+            if is_set(&path.key, &path.subkey) && !value.is_empty() {
+                for item in value {
+                    let path = DarkestPath {
+                        item: Some(item.clone()),
+                        ..path.clone()
+                    };
+                    map.insert(path, &SET_ITEM);
+                }
+            } else {
+                map.insert(path, value);
+            }
+        }
+        map
+    }
+
+    fn clone_with(&self, f: impl FnOnce(&mut BTreeMap<Self::Key, Self::Value>)) -> Self {
+        let mut map: BTreeMap<_, _> = self
+            .map()
+            .into_iter()
+            .map(|(path, value)| (path, value.clone()))
+            .collect();
+        f(&mut map);
+
+        // Every subkey which is still in the map is written back into its original place;
+        // subkeys added to the existing entries go to the end of these entries.
+        let mut entries = vec![];
+        for ((key, entry), id) in (self.0).0.iter().zip(self.entry_ids()) {
+            let mut path = DarkestPath {
+                key: key.clone(),
+                entry: id,
+                subkey: String::new(),
+                item: None,
+            };
+            let mut items = vec![];
+            for (subkey, original) in &entry.0 {
+                path.subkey = subkey.clone();
+                if let Some(value) = take_value(&mut map, &path, original) {
+                    items.push((subkey.clone(), value));
+                }
+            }
+            path.subkey = String::new();
+            let added: Vec<_> = map
+                .range(path.clone()..)
+                .take_while(|(added, _)| added.key == path.key && added.entry == path.entry)
+                .map(|(added, _)| added.clone())
+                .collect();
+            for added in added {
+                if let Some(value) = take_value(&mut map, &added, &[]) {
+                    items.push((added.subkey, value));
+                }
+            }
+            // Entry with every subkey removed is removed itself.
+            if !items.is_empty() {
+                entries.push((key.clone(), DarkestEntry(items)));
+            }
+        }
+
+        // Everything left belongs to the entries added by mods. The game reads the entries of the same type together
+        // (e.g. the skill levels), so they go right after the last entry of their type, or to the end if there is none.
+        // Their subkeys are written in the order they have in the existing entries of the same type.
+        let mut order: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (key, entry) in &(self.0).0 {
+            let known = order.entry(key).or_default();
+            for (subkey, _) in &entry.0 {
+                if !known.contains(&subkey.as_str()) {
+                    known.push(subkey);
+                }
+            }
+        }
+        let mut last: Option<(String, EntryId)> = None;
+        let mut position = entries.len();
+        while let Some(path) = map.keys().next().cloned() {
+            let current = Some((path.key.clone(), path.entry.clone()));
+            if last != current {
+                last = current;
+                position = entries
+                    .iter()
+                    .rposition(|(key, _)| *key == path.key)
+                    .map_or(entries.len(), |index| index + 1);
+                entries.insert(position, (path.key.clone(), DarkestEntry(vec![])));
+            }
+            // The id goes first for readability, whatever its place in the existing entries is.
+            let rank = |subkey: &str| {
+                let position = order
+                    .get(path.key.as_str())
+                    .and_then(|known| known.iter().position(|known| *known == subkey))
+                    .unwrap_or(usize::MAX);
+                (subkey != "id", position)
+            };
+            let items = &mut (entries[position].1).0;
+            let index = items
+                .iter()
+                .position(|(subkey, _)| rank(subkey) > rank(&path.subkey))
+                .unwrap_or(items.len());
+            let value = take_value(&mut map, &path, &[]).unwrap_or_default();
+            items.insert(index, (path.subkey, value));
+        }
+        Self(DarkestFile(entries, (self.0).1.clone()))
+    }
+}
+
+fn is_bare_value(value: &str) -> bool {
+    let number = value.strip_suffix('%').unwrap_or(value);
+    let number = number.strip_prefix('-').unwrap_or(number);
+    let is_digits = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+    let is_number = number.splitn(2, '.').all(is_digits);
+    is_number || value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false")
+}
+
+/// Checks whether the value can be written without quotes as the identifier.
+fn is_ident(value: &str) -> bool {
+    let mut chars = value.chars();
+    matches!(chars.next(), Some(first) if first.is_alphabetic())
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+impl Display for DarkestFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut last_key = None;
+        for (key, entry) in &self.0 {
+            // Entries of different types are separated with empty line, like in the vanilla files.
+            if last_key.is_some() && last_key != Some(key) {
+                writeln!(f)?;
+            }
+            last_key = Some(key);
+            write!(f, "{}:", key)?;
+            for (subkey, values) in &entry.0 {
+                write!(f, " .{}", subkey)?;
+                for value in values {
+                    if self.1.is_quoted(key, subkey, values, value) {
+                        write!(f, " \"{}\"", value)?;
+                    } else {
+                        write!(f, " {}", value)?;
+                    }
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for GenericDarkestFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Error)]
+/// The message starts with the position, as the one of combine does.
+#[error("{message}")]
+pub struct DarkestParseError {
+    position: usize,
+    message: String,
+}
+
+impl Locate for DarkestParseError {
+    fn location(&self, source: &str) -> Option<TextLocation> {
+        Some(TextLocation::at_offset(source, self.position))
+    }
+
+    /// Combine's message lists every expectation on its own line, after the position; these are joined in one line.
+    fn failure(&self, source: &str) -> ParseFailure {
+        let message = self
+            .message
+            .lines()
+            .filter(|line| !line.starts_with("Parse error at"))
+            .collect::<Vec<_>>()
+            .join("; ");
+        ParseFailure {
+            message,
+            location: self.location(source),
+        }
     }
 }
 
 mod parser {
-    use super::{DarkestEntry, DarkestFile};
+    use super::{DarkestEntry, DarkestFile, DarkestParseError, GenericDarkestFile, Quoting};
     use combine::{
         choice, eof, many, many1, one_of, optional,
         parser::{
@@ -124,7 +634,8 @@ mod parser {
 
     enum ItemStepResult {
         Key(String),
-        Value(String),
+        /// Value and whether it was quoted.
+        Value((String, bool)),
     }
 
     impl<Input> Parser<Input> for ItemsParser<Input>
@@ -132,7 +643,7 @@ mod parser {
         Input: Stream<Token = char>,
         Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
     {
-        type Output = Vec<(String, Vec<String>)>;
+        type Output = Vec<(String, Vec<(String, bool)>)>;
         type PartialState = ();
 
         fn parse_lazy(
@@ -226,7 +737,8 @@ mod parser {
                 .map(|(first, rest): (char, String)| format!("{}{}", first, rest))
         }
 
-        fn value<Input>() -> impl Parser<Input, Output = String>
+        /// Single value, along with whether it was quoted.
+        fn value<Input>() -> impl Parser<Input, Output = (String, bool)>
         where
             Input: Stream<Token = char>,
             Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
@@ -251,15 +763,35 @@ mod parser {
                         format!("{}{}{}{}", minus, first, second, percent)
                     })
             };
-            choice((Self::ident(), quoted_string, number()))
+            choice((
+                Self::ident().map(|ident| (ident, false)),
+                quoted_string.map(|s| (s, true)),
+                number().map(|number| (number, false)),
+            ))
         }
 
-        fn parser<Input>() -> impl Parser<Input, Output = (String, Self)>
+        fn parser<Input>() -> impl Parser<Input, Output = (String, (Self, Quoting))>
         where
             Input: Stream<Token = char>,
             Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
         {
-            let pairs = ItemsParser::<Input>::new().map(Self);
+            let pairs = ItemsParser::<Input>::new().map(|items| {
+                let mut quoting = Quoting::default();
+                let items = items
+                    .into_iter()
+                    .map(|(subkey, values)| {
+                        let values = values
+                            .into_iter()
+                            .map(|(value, quoted)| {
+                                quoting.record(&value, quoted);
+                                value
+                            })
+                            .collect();
+                        (subkey, values)
+                    })
+                    .collect();
+                (Self(items), quoting)
+            });
 
             key_value(
                 Self::key().message("Key parser failed in entry parser"),
@@ -280,7 +812,46 @@ mod parser {
                     DarkestEntry::parser().message("Entry parser failed in file parser"),
                     skip_many(skipped()),
                 ))
-                .map(Self)
+                .map(|entries: Vec<(String, (DarkestEntry, Quoting))>| {
+                    let mut quoting = Quoting::default();
+                    let entries = entries
+                        .into_iter()
+                        .map(|(key, (entry, entry_quoting))| {
+                            quoting.record_subkeys(&key, &entry, &entry_quoting);
+                            for (value, quoted) in entry_quoting.values {
+                                quoting.values.entry(value).or_insert(quoted);
+                            }
+                            (key, entry)
+                        })
+                        .collect();
+                    Self(entries, quoting)
+                })
+        }
+    }
+
+    impl GenericDarkestFile {
+        pub fn parse(source: &str) -> Result<Self, DarkestParseError> {
+            use combine::EasyParser;
+            match DarkestFile::parser().easy_parse(source) {
+                Ok((file, rest)) if rest.trim().is_empty() => Ok(Self(file)),
+                Ok((_, rest)) => {
+                    let position = source.len() - rest.len();
+                    Err(DarkestParseError {
+                        position,
+                        message: format!(
+                            "Parse error at {}\nUnexpected content after the last entry",
+                            position
+                        ),
+                    })
+                }
+                Err(err) => {
+                    let position = err.position.translate_position(source);
+                    Err(DarkestParseError {
+                        position,
+                        message: err.map_position(|_| position).to_string(),
+                    })
+                }
+            }
         }
     }
 
@@ -366,3 +937,498 @@ mod parser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::{apply, diff, merge};
+    use super::*;
+
+    const HERO: &str = r#"
+combat_skill: .id "smite" .level 0 .atk 85% .effect "Stun 1"
+combat_skill: .id "smite" .level 1 .atk 90% .effect "Stun 2"
+tag: .id "religious"
+generation: .is_generation_enabled true
+generation: .number_of_positive_quirks_min 1
+"#;
+
+    fn path(key: &str, entry: EntryId, subkey: &str) -> DarkestPath {
+        DarkestPath {
+            key: key.into(),
+            entry,
+            subkey: subkey.into(),
+            item: None,
+        }
+    }
+
+    #[test]
+    fn percent_notation_roundtrip() {
+        for value in &["40%", "0.4", "12.5%", "-5%", "100"] {
+            assert_eq!(&PercentValue::parse(value).unwrap().to_string(), value);
+        }
+        assert_eq!(PercentValue::parse("40%"), PercentValue::parse("0.4"));
+
+        let orig = GenericDarkestFile::parse(
+            "weapon: .atk 0% .crit 0.05 .dmg 5 8\nresistances: .stun 40% .poison 0.3\n",
+        )
+        .unwrap();
+        // Same numbers in the other notation are not a change.
+        let same = GenericDarkestFile::parse(
+            "weapon: .atk 0 .crit 5% .dmg 5 8\nresistances: .stun 0.4 .poison 30%\n",
+        )
+        .unwrap();
+        assert!(diff(&orig, &same).is_empty());
+
+        let modded = GenericDarkestFile::parse(
+            "weapon: .atk 0 .crit 10% .dmg 5 8\nresistances: .stun 0.4 .poison 0.5\n",
+        )
+        .unwrap();
+        let patch = diff(&orig, &modded);
+        assert_eq!(patch.len(), 2);
+        // Unchanged values keep the original notation, changed ones use the notation of the mod.
+        assert_eq!(
+            apply(&orig, patch).to_string(),
+            "weapon: .atk 0% .crit 10% .dmg 5 8\n\nresistances: .stun 40% .poison 0.5\n"
+        );
+    }
+
+    #[test]
+    fn float_tolerance() {
+        let same =
+            |first: &str, second: &str| vec![first.to_string()].same_as(&vec![second.into()]);
+        assert!(same("0.3", "0.30000001"));
+        assert!(same("0.3", &(0.1f64 + 0.2f64).to_string()));
+        assert!(same("33.3%", "0.333"));
+        assert!(same("0", "0.0"));
+        assert!(!same("0.3", "0.31"));
+        assert!(!same("0", "0.000001"));
+        // Integers are compared exactly, even where f32 can't tell them apart.
+        assert!(!same("16777216", "16777217"));
+        assert!(!same("value", "value2"));
+    }
+
+    #[test]
+    fn reserialized_hero_has_no_changes() {
+        const FULL_HERO: &str = r#"
+resistances: .stun 40% .poison 30% .bleed 30% .disease 30% .move 40% .debuff 30% .death_blow 67% .trap 10%
+weapon: .name "crusader_weapon_0" .atk 0% .dmg 6 12 .crit 0% .spd 1
+weapon: .name "crusader_weapon_1" .atk 0% .dmg 7 14 .crit 1% .spd 1
+armour: .name "crusader_armour_0" .def 5% .prot 0 .hp 33 .spd 0
+armour: .name "crusader_armour_1" .def 10% .prot 0 .hp 40 .spd 0
+combat_skill: .id "smite" .level 0 .type "melee" .atk 85% .dmg 0% .crit 0% .launch 21 .target 12
+combat_skill: .id "smite" .level 1 .type "melee" .atk 90% .dmg 0.1 .crit 0.01 .launch 21 .target 12
+crit: .effects "Crusader Crit Buff"
+deaths_door: .buffs DEATHS_DOOR_BUFF .recovery_buffs DEATHS_DOOR_RECOVERY_BUFF
+"#;
+        let orig = GenericDarkestFile::parse(FULL_HERO).unwrap();
+        // Every number goes through f32 and is written back at the f64 precision, as some tools do.
+        let reserialized = orig.clone_with(|map| {
+            for value in map.values_mut().flat_map(|values| values.iter_mut()) {
+                if let Some(number) = PercentValue::parse(value) {
+                    if number.written_as_percent {
+                        *value = format!("{}%", f64::from(number.value) * 100.0);
+                    } else if value.contains('.') {
+                        *value = f64::from(number.value).to_string();
+                    }
+                }
+            }
+        });
+        let reserialized = GenericDarkestFile::parse(&reserialized.to_string()).unwrap();
+        assert_ne!(orig.to_string(), reserialized.to_string());
+        assert!(diff(&orig, &reserialized).is_empty());
+    }
+
+    #[test]
+    fn entries_keyed_by_id_and_index() {
+        let file = GenericDarkestFile::parse(HERO).unwrap();
+        let map = file.map();
+        assert_eq!(
+            map[&path(
+                "combat_skill",
+                EntryId::LevelId("smite".into(), "1".into(), 0),
+                "atk"
+            )],
+            &vec!["90%".to_string()]
+        );
+        assert_eq!(
+            map[&path(
+                "generation",
+                EntryId::Index(1),
+                "number_of_positive_quirks_min"
+            )],
+            &vec!["1".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_error_has_one_position() {
+        for broken in &["tag .id \"religious\"\n", "tag: .id \"religious\n"] {
+            let message = GenericDarkestFile::parse(broken).unwrap_err().to_string();
+            assert_eq!(message.matches("Parse error at").count(), 1, "{}", message);
+        }
+    }
+
+    #[test]
+    fn inserted_level_does_not_shift_others() {
+        let orig = GenericDarkestFile::parse(
+            "combat_skill: .id \"smite\" .level 1 .atk 90%\ncombat_skill: .id \"smite\" .level 2 .atk 95%\n",
+        )
+        .unwrap();
+        let modded = GenericDarkestFile::parse(
+            "combat_skill: .id \"smite\" .level 0 .atk 85%\ncombat_skill: .id \"smite\" .level 1 .atk 90%\ncombat_skill: .id \"smite\" .level 2 .atk 95%\n",
+        )
+        .unwrap();
+        let entry = |level: &str| EntryId::LevelId("smite".into(), level.into(), 0);
+        let patch = diff(&orig, &modded);
+        assert_eq!(patch.len(), 3);
+        assert!(patch.keys().all(|path| path.entry == entry("0")));
+    }
+
+    #[test]
+    fn write_and_parse_back() {
+        let file = GenericDarkestFile::parse(HERO).unwrap();
+        let written = file.to_string();
+        let parsed = GenericDarkestFile::parse(&written).unwrap();
+        assert_eq!(file.map(), parsed.map());
+        assert!(written.contains(r#"combat_skill: .id "smite" .level 0 .atk 85% .effect "Stun 1""#));
+    }
+
+    #[test]
+    fn patch_keeps_order_and_appends_new() {
+        let file = GenericDarkestFile::parse(HERO).unwrap();
+        let patched = file.clone_with(|map| {
+            map.insert(
+                path(
+                    "combat_skill",
+                    EntryId::LevelId("smite".into(), "0".into(), 0),
+                    "atk",
+                ),
+                vec!["95%".into()],
+            );
+            map.remove(&path("tag", EntryId::Id("religious".into(), 0), "id"));
+            map.insert(
+                path("tag", EntryId::Id("outsider".into(), 0), "id"),
+                vec!["outsider".into()],
+            );
+        });
+        assert_eq!(
+            patched.to_string(),
+            r#"combat_skill: .id "smite" .level 0 .atk 95% .effect "Stun 1"
+combat_skill: .id "smite" .level 1 .atk 90% .effect "Stun 2"
+
+generation: .is_generation_enabled true
+generation: .number_of_positive_quirks_min 1
+
+tag: .id "outsider"
+"#
+        );
+    }
+
+    #[test]
+    fn map_order_is_stable() {
+        let first = GenericDarkestFile::parse(HERO).unwrap();
+        let second = GenericDarkestFile::parse(HERO).unwrap();
+        let keys =
+            |file: &GenericDarkestFile| format!("{:?}", file.map().keys().collect::<Vec<_>>());
+        assert_eq!(keys(&first), keys(&second));
+        assert_eq!(first.to_string(), second.to_string());
+    }
+
+    #[test]
+    fn untouched_entries_keep_subkey_order() {
+        let skills = "combat_skill: .level 0 .id \"smite\" .atk 85%\ntag: .id \"religious\"\n";
+        let file = GenericDarkestFile::parse(skills).unwrap();
+        let patched = file.clone_with(|map| {
+            map.insert(
+                path("tag", EntryId::Id("outsider".into(), 0), "id"),
+                vec!["outsider".into()],
+            );
+            map.insert(
+                path(
+                    "combat_skill",
+                    EntryId::LevelId("bash".into(), "0".into(), 0),
+                    "level",
+                ),
+                vec!["0".into()],
+            );
+            map.insert(
+                path(
+                    "combat_skill",
+                    EntryId::LevelId("bash".into(), "0".into(), 0),
+                    "id",
+                ),
+                vec!["bash".into()],
+            );
+        });
+        assert_eq!(
+            patched.to_string(),
+            "combat_skill: .level 0 .id \"smite\" .atk 85%\ncombat_skill: .id \"bash\" .level 0\n\ntag: .id \"religious\"\ntag: .id \"outsider\"\n"
+        );
+    }
+
+    #[test]
+    fn skill_order_survives_patch() {
+        // Skills are listed by the game in the file order, which is not the order of their ids.
+        let skills = r#"combat_skill: .id "smite" .level 0 .atk 85%
+combat_skill: .id "zealous_accusation" .level 0 .atk 85%
+combat_skill: .id "battle_heal" .level 0 .atk 0%
+combat_skill: .id "holy_lance" .level 0 .atk 85%
+"#;
+        let file = GenericDarkestFile::parse(skills).unwrap();
+        let patched = file.clone_with(|map| {
+            map.insert(
+                path(
+                    "combat_skill",
+                    EntryId::LevelId("battle_heal".into(), "0".into(), 0),
+                    "atk",
+                ),
+                vec!["5%".into()],
+            );
+        });
+        let patched = GenericDarkestFile::parse(&patched.to_string()).unwrap();
+        let ids: Vec<_> = (patched.0)
+            .0
+            .iter()
+            .map(|(_, entry)| entry.0[0].1.join(" "))
+            .collect();
+        assert_eq!(
+            ids,
+            ["smite", "zealous_accusation", "battle_heal", "holy_lance"]
+        );
+        assert_eq!(patched.to_string(), skills.replace("0%", "5%"));
+    }
+
+    #[test]
+    fn added_entries_join_their_type() {
+        let file = GenericDarkestFile::parse(HERO).unwrap();
+        let patched = file.clone_with(|map| {
+            for (subkey, value) in [("id", "smite"), ("level", "2"), ("atk", "95%")] {
+                map.insert(
+                    path(
+                        "combat_skill",
+                        EntryId::LevelId("smite".into(), "2".into(), 0),
+                        subkey,
+                    ),
+                    vec![value.into()],
+                );
+            }
+            map.insert(
+                path("tag", EntryId::Id("outsider".into(), 0), "id"),
+                vec!["outsider".into()],
+            );
+        });
+        let written = patched.to_string();
+        assert_eq!(
+            written,
+            r#"combat_skill: .id "smite" .level 0 .atk 85% .effect "Stun 1"
+combat_skill: .id "smite" .level 1 .atk 90% .effect "Stun 2"
+combat_skill: .id "smite" .level 2 .atk 95%
+
+tag: .id "religious"
+tag: .id "outsider"
+
+generation: .is_generation_enabled true
+generation: .number_of_positive_quirks_min 1
+"#
+        );
+        // What is written is read back the same, percents included.
+        assert_eq!(
+            GenericDarkestFile::parse(&written).unwrap().map(),
+            patched.map()
+        );
+    }
+
+    #[test]
+    fn written_files_are_read_back_the_same() {
+        const VALUES: &[&str] = &[
+            "85%",
+            "-40%",
+            "0.05",
+            "12",
+            "True",
+            "DEATHS_DOOR_BUFF",
+            "\"1\"",
+            "\"~12\"",
+            "\"Stun 1\" \"Stun 2\"",
+            "6 12",
+        ];
+        const SUBKEYS: &[&str] = &["level", "atk", "dmg", "buffs", "tags", "effect"];
+        // Files are generated from the fixed seed, so that every failure can be reproduced.
+        let mut seed = 1u32;
+        let mut next = |bound: usize| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as usize % bound
+        };
+        let mut generate = || {
+            let mut text = String::new();
+            for _ in 0..=next(6) {
+                text.push_str(["combat_skill", "tag", "weapon", "deaths_door"][next(4)]);
+                text.push(':');
+                if next(2) == 0 {
+                    text.push_str(&format!(" .id \"id_{}\"", next(3)));
+                }
+                for (index, subkey) in SUBKEYS.iter().enumerate() {
+                    // Entry can't be empty, so the last subkey is there if nothing else is.
+                    if next(2) == 0 || (index == SUBKEYS.len() - 1 && text.ends_with(':')) {
+                        text.push_str(&format!(" .{} {}", subkey, VALUES[next(VALUES.len())]));
+                    }
+                }
+                text.push('\n');
+            }
+            GenericDarkestFile::parse(&text).unwrap()
+        };
+        for _ in 0..200 {
+            let (orig, modded) = (generate(), generate());
+            let written = GenericDarkestFile::parse(&orig.to_string()).unwrap();
+            assert_eq!(written.map(), orig.map(), "{}", orig);
+            // Patched file, with the entries and subkeys added and removed, is read back the same too.
+            let patched = apply(&orig, diff(&orig, &modded));
+            let written = GenericDarkestFile::parse(&patched.to_string()).unwrap();
+            assert_eq!(written.map(), patched.map(), "{}\n{}", orig, modded);
+        }
+    }
+
+    #[test]
+    fn mode_variants_stay_apart() {
+        const MODED: &str = r#"combat_skill: .id "rend" .level 0 .valid_modes human .atk 80%
+combat_skill: .id "rend" .level 0 .valid_modes beast .atk 90%
+combat_skill: .id "rend" .level 1 .valid_modes human .atk 85%
+combat_skill: .id "rend" .level 1 .valid_modes beast .atk 95%
+"#;
+        let orig = GenericDarkestFile::parse(MODED).unwrap();
+        let entry = |level: &str, variant| EntryId::ModeId("rend".into(), level.into(), variant);
+        let atk = |level, variant| path("combat_skill", entry(level, variant), "atk");
+        let map = orig.map();
+        assert_eq!(map[&atk("1", 1)], &vec!["95%".to_string()]);
+        assert_eq!(map[&atk("1", 0)], &vec!["85%".to_string()]);
+
+        // The mod lists the variants in another order, changing only one of them.
+        let modded = GenericDarkestFile::parse(
+            r#"combat_skill: .id "rend" .level 0 .valid_modes human .atk 80%
+combat_skill: .id "rend" .level 1 .valid_modes human .atk 85%
+combat_skill: .id "rend" .level 0 .valid_modes beast .atk 90%
+combat_skill: .id "rend" .level 1 .valid_modes beast .atk 99%
+"#,
+        )
+        .unwrap();
+        let patch = diff(&orig, &modded);
+        assert_eq!(
+            patch.into_iter().collect::<Vec<_>>(),
+            vec![(atk("1", 1), Some(vec!["99%".to_string()]))]
+        );
+        let patched = apply(&orig, diff(&orig, &modded));
+        assert_eq!(patched.to_string(), MODED.replace(".atk 95%", ".atk 99%"));
+        assert_eq!(
+            GenericDarkestFile::parse(&patched.to_string())
+                .unwrap()
+                .map(),
+            patched.map()
+        );
+
+        // One mod lets the skill be used in another mode, the other one changes the same skill.
+        let with_mode = MODED.replace(
+            ".valid_modes beast .atk 95%",
+            ".valid_modes beast wolf .atk 95%",
+        );
+        let with_atk = MODED.replace(".atk 95%", ".atk 99%");
+        let patch = |modded: &str| diff(&orig, &GenericDarkestFile::parse(modded).unwrap());
+        let (merged, conflicts) = merge(vec![
+            ("first".into(), patch(&with_mode)),
+            ("second".into(), patch(&with_atk)),
+        ]);
+        assert!(conflicts.is_empty());
+        assert_eq!(
+            apply(&orig, merged).to_string(),
+            MODED.replace(
+                ".valid_modes beast .atk 95%",
+                ".valid_modes beast wolf .atk 99%"
+            )
+        );
+    }
+
+    #[test]
+    fn vanilla_skill_roundtrip() {
+        let skill = r#"combat_skill: .id "zealous_accusation" .level 0 .type "ranged" .atk 85% .dmg -40% .crit -5% .launch 21 .target "~12" .is_crit_valid True .effect "Accusation Light 1" "Crusader Zeal Stun 1"
+
+deaths_door: .buffs DEATHS_DOOR_BUFF .recovery_buffs DEATHS_DOOR_RECOVERY_BUFF
+
+tag: .id "1"
+"#;
+        let file = GenericDarkestFile::parse(skill).unwrap();
+        assert_eq!(file.to_string(), skill);
+        let effects = path(
+            "combat_skill",
+            EntryId::LevelId("zealous_accusation".into(), "0".into(), 0),
+            "effect",
+        );
+        assert_eq!(
+            file.map()[&effects],
+            &vec![
+                "Accusation Light 1".to_string(),
+                "Crusader Zeal Stun 1".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn set_items_merge_independently() {
+        let orig =
+            "deaths_door: .buffs DD_0 DD_1 .recovery_buffs DD_RECOVERY\ntag: .id \"light\"\n";
+        let patch = |modded: &str| {
+            diff(
+                &GenericDarkestFile::parse(orig).unwrap(),
+                &GenericDarkestFile::parse(modded).unwrap(),
+            )
+        };
+        let first = patch(
+            "deaths_door: .buffs DD_0 DD_1 DD_A .recovery_buffs DD_RECOVERY\ntag: .id \"light\"\ntag: .id \"outsider\"\n",
+        );
+        let second = patch(
+            "deaths_door: .buffs DD_B DD_0 DD_1 .recovery_buffs DD_RECOVERY\ntag: .id \"light\"\ntag: .id \"religious\"\n",
+        );
+        let third = patch("deaths_door: .buffs DD_1 .recovery_buffs DD_RECOVERY\n");
+        // Every change is a separate item, not the whole list.
+        let buffs = |item: &str| DarkestPath {
+            item: Some(item.into()),
+            ..path("deaths_door", EntryId::Index(0), "buffs")
+        };
+        assert_eq!(first.get(&buffs("DD_A")), Some(&Some(vec![])));
+        assert_eq!(third.get(&buffs("DD_0")), Some(&None));
+
+        let (merged, conflicts) = merge(vec![
+            ("first".into(), first),
+            ("second".into(), second),
+            ("third".into(), third),
+        ]);
+        assert!(conflicts.is_empty());
+        assert_eq!(
+            apply(&GenericDarkestFile::parse(orig).unwrap(), merged).to_string(),
+            "deaths_door: .buffs DD_1 DD_A DD_B .recovery_buffs DD_RECOVERY\n\ntag: .id \"outsider\"\ntag: .id \"religious\"\n"
+        );
+
+        // Only the item added by one mod and removed by another is a conflict.
+        let added = vec![(buffs("X"), Some(vec![])), (buffs("Y"), Some(vec![]))];
+        let removed = vec![(buffs("X"), None), (buffs("DD_0"), None)];
+        let (merged, conflicts) = merge(vec![
+            ("first".into(), added.into_iter().collect()),
+            ("second".into(), removed.into_iter().collect()),
+        ]);
+        assert_eq!(
+            merged.keys().collect::<Vec<_>>(),
+            vec![&buffs("DD_0"), &buffs("Y")]
+        );
+        assert_eq!(conflicts.len(), 2);
+        for (_, conflict) in conflicts {
+            assert_eq!(conflict.keys().collect::<Vec<_>>(), vec![&buffs("X")]);
+        }
+
+        // Added items are quoted like the existing items of the same value, not of the same subkey elsewhere.
+        let orig = "death_reaction: .buff_ids \"A\"\ndeath_reaction: .buff_ids B C\n";
+        let modded = "death_reaction: .buff_ids \"A\"\ndeath_reaction: .buff_ids B C D\n";
+        let (orig, target) = (
+            GenericDarkestFile::parse(orig).unwrap(),
+            GenericDarkestFile::parse(modded).unwrap(),
+        );
+        let patched = apply(&orig, diff(&orig, &target));
+        assert_eq!(patched.to_string(), modded);
+    }
+}
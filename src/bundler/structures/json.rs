@@ -1,11 +1,12 @@
-use super::BTreeMappable;
+use super::{BTreeMappable, MapBuilder, MapValue};
 use serde_json::{Map, Value};
-use std::{collections::BTreeMap, iter::once};
+use std::{collections::BTreeMap, iter::once, rc::Rc};
 
+/// Part of the path to the JSON value; the keys are shared by the paths of every value nested under them.
 #[derive(Clone, PartialOrd, PartialEq, Ord, Eq, Debug)]
-enum JsonPathPart {
+pub enum JsonPathPart {
     Index(usize),
-    Key(String),
+    Key(Rc<str>),
 }
 impl From<usize> for JsonPathPart {
     fn from(index: usize) -> Self {
@@ -19,110 +20,90 @@ impl From<&str> for JsonPathPart {
 }
 impl From<&String> for JsonPathPart {
     fn from(key: &String) -> Self {
-        Self::Key(key.into())
+        Self::Key(key.as_str().into())
     }
 }
 impl From<String> for JsonPathPart {
     fn from(key: String) -> Self {
-        Self::Key(key)
+        Self::Key(key.into())
     }
 }
 
 type JsonPath = Vec<JsonPathPart>;
 impl super::MapPath for JsonPath {}
 
-struct JsonFile(Value);
+/// Renders the path in the dotted form, e.g. `buffs.0.id`.
+pub fn render_path(path: &[JsonPathPart]) -> String {
+    path.iter()
+        .map(|part| match part {
+            JsonPathPart::Index(index) => index.to_string(),
+            JsonPathPart::Key(key) => key.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
 
-fn flatten(prefix: JsonPath, value: &Value) -> Vec<(JsonPath, &Value)> {
-    match value {
-        v @ Value::Null | v @ Value::Bool(_) | v @ Value::Number(_) | v @ Value::String(_) => {
-            vec![(prefix, v)]
-        }
-        Value::Array(arr) => arr
-            .iter()
-            .enumerate()
-            .flat_map(move |(index, value)| {
-                flatten(
-                    prefix
-                        .clone()
-                        .into_iter()
-                        .chain(once(index.into()))
-                        .collect(),
-                    value,
-                )
-            })
-            .collect(),
-        Value::Object(obj) => obj
-            .iter()
-            .flat_map(move |(key, value)| {
-                flatten(
-                    prefix.clone().into_iter().chain(once(key.into())).collect(),
-                    value,
-                )
-            })
-            .collect(),
+pub struct JsonFile(Value);
+
+impl JsonFile {
+    pub fn parse(source: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(source).map(Self)
     }
 }
 
-fn flatten_mut(prefix: JsonPath, value: &mut Value) -> Vec<(JsonPath, &mut Value)> {
-    match value {
-        v @ Value::Null | v @ Value::Bool(_) | v @ Value::Number(_) | v @ Value::String(_) => {
-            vec![(prefix, v)]
+fn flatten(value: &Value) -> Vec<(JsonPath, &Value)> {
+    fn walk<'a>(builder: &mut MapBuilder<JsonPathPart, &'a Value>, value: &'a Value) {
+        match value {
+            Value::Array(arr) => {
+                for (index, value) in arr.iter().enumerate() {
+                    builder.enter(index.into());
+                    walk(builder, value);
+                    builder.exit();
+                }
+            }
+            Value::Object(obj) => {
+                for (key, value) in obj {
+                    builder.enter(key.into());
+                    walk(builder, value);
+                    builder.exit();
+                }
+            }
+            value => builder.insert(value),
         }
-        Value::Array(arr) => arr
-            .iter_mut()
-            .enumerate()
-            .flat_map(move |(index, value)| {
-                flatten_mut(
-                    prefix
-                        .clone()
-                        .into_iter()
-                        .chain(once(index.into()))
-                        .collect(),
-                    value,
-                )
-            })
-            .collect(),
-        Value::Object(obj) => obj
-            .iter_mut()
-            .flat_map(move |(key, value)| {
-                flatten_mut(
-                    prefix.clone().into_iter().chain(once(key.into())).collect(),
-                    value,
-                )
-            })
-            .collect(),
     }
+    let mut builder = MapBuilder::new();
+    walk(&mut builder, value);
+    builder.finish()
 }
 
-fn flatten_owned(prefix: JsonPath, value: Value) -> Vec<(JsonPath, Value)> {
-    match value {
-        v @ Value::Null | v @ Value::Bool(_) | v @ Value::Number(_) | v @ Value::String(_) => {
-            vec![(prefix, v)]
+fn flatten_owned(value: Value) -> Vec<(JsonPath, Value)> {
+    fn walk(builder: &mut MapBuilder<JsonPathPart, Value>, value: Value) {
+        match value {
+            Value::Array(arr) => {
+                for (index, value) in arr.into_iter().enumerate() {
+                    builder.enter(index.into());
+                    walk(builder, value);
+                    builder.exit();
+                }
+            }
+            Value::Object(obj) => {
+                for (key, value) in obj {
+                    builder.enter(key.into());
+                    walk(builder, value);
+                    builder.exit();
+                }
+            }
+            value => builder.insert(value),
         }
-        Value::Array(arr) => arr
-            .into_iter()
-            .enumerate()
-            .flat_map(move |(index, value)| {
-                flatten_owned(
-                    prefix
-                        .clone()
-                        .into_iter()
-                        .chain(once(index.into()))
-                        .collect(),
-                    value,
-                )
-            })
-            .collect(),
-        Value::Object(obj) => obj
-            .into_iter()
-            .flat_map(move |(key, value)| {
-                flatten_owned(
-                    prefix.clone().into_iter().chain(once(key.into())).collect(),
-                    value,
-                )
-            })
-            .collect(),
+    }
+    let mut builder = MapBuilder::new();
+    walk(&mut builder, value);
+    builder.finish()
+}
+
+impl MapValue for Value {
+    fn same_as(&self, other: &Self) -> bool {
+        self == other
     }
 }
 
@@ -130,13 +111,10 @@ impl BTreeMappable for JsonFile {
     type Key = JsonPath;
     type Value = Value;
     fn map(&self) -> BTreeMap<Self::Key, &Self::Value> {
-        flatten(vec![], &self.0).into_iter().collect()
-    }
-    fn map_mut(&mut self) -> BTreeMap<Self::Key, &mut Self::Value> {
-        flatten_mut(vec![], &mut self.0).into_iter().collect()
+        flatten(&self.0).into_iter().collect()
     }
     fn clone_with(&self, f: impl FnOnce(&mut BTreeMap<Self::Key, Self::Value>)) -> Self {
-        let mut map = flatten_owned(vec![], self.0.clone()).into_iter().collect();
+        let mut map = flatten_owned(self.0.clone()).into_iter().collect();
         f(&mut map);
 
         debug_assert!(!map.is_empty());
@@ -150,7 +128,9 @@ impl BTreeMappable for JsonFile {
             for part in path.clone().into_iter().rev() {
                 match part {
                     JsonPathPart::Index(_) => value = Value::Array(vec![value]),
-                    JsonPathPart::Key(key) => value = Value::Object(once((key, value)).collect()),
+                    JsonPathPart::Key(key) => {
+                        value = Value::Object(once((key.to_string(), value)).collect())
+                    }
                 }
             }
             let mut dest = &mut root;
@@ -172,8 +152,8 @@ impl BTreeMappable for JsonFile {
                         }
                     }
                     JsonPathPart::Key(ref key) => {
-                        value = value.as_object_mut().unwrap().remove(key).unwrap();
-                        match dest.get_mut(key) {
+                        value = value.as_object_mut().unwrap().remove(&**key).unwrap();
+                        match dest.get_mut(&**key) {
                             Some(item) => {
                                 dest = item;
                                 insertion_path.push_str(&format!(
@@ -197,7 +177,7 @@ impl BTreeMappable for JsonFile {
                     arr[index] = value;
                 }
                 (Some(JsonPathPart::Key(ref key)), Some(Value::Object(obj))) => {
-                    obj.insert(key.clone(), value);
+                    obj.insert(key.to_string(), value);
                 }
                 (key, value) => panic!(
                     "JSON was modified incompatibly: key {:?} is going to be inserted into {:?}",
@@ -214,6 +194,51 @@ mod tests {
 
     use super::*;
 
+    /// Flattening as it was done before [`MapBuilder`], level by level; kept to check that the result is the same.
+    fn flatten_by_levels(prefix: JsonPath, value: &Value) -> Vec<(JsonPath, &Value)> {
+        match value {
+            Value::Array(arr) => arr
+                .iter()
+                .enumerate()
+                .flat_map(|(index, value)| {
+                    let mut path = prefix.clone();
+                    path.push(index.into());
+                    flatten_by_levels(path, value)
+                })
+                .collect(),
+            Value::Object(obj) => obj
+                .iter()
+                .flat_map(|(key, value)| {
+                    let mut path = prefix.clone();
+                    path.push(key.into());
+                    flatten_by_levels(path, value)
+                })
+                .collect(),
+            value => vec![(prefix, value)],
+        }
+    }
+
+    #[test]
+    fn same_as_flattening_by_levels() {
+        let json = r#"{
+            "buffs": [
+                {"id": "ZEAL", "amount": 2, "rule_data": {"float": 0, "string": ""}},
+                {"id": "BLESS", "tags": [[1, 2], [], {}], "nothing": null}
+            ],
+            "empty": {},
+            "flag": true
+        }"#;
+        let value: Value = json.parse().unwrap();
+        assert_eq!(flatten(&value), flatten_by_levels(vec![], &value));
+        let owned: Vec<_> = flatten(&value)
+            .into_iter()
+            .map(|(path, value)| (path, value.clone()))
+            .collect();
+        assert_eq!(flatten_owned(value.clone()), owned);
+        let primitive = Value::from(1);
+        assert_eq!(flatten(&primitive), vec![(vec![], &primitive)]);
+    }
+
     #[test]
     fn flatten_and_rebuild() {
         let json = r#"{
@@ -0,0 +1,582 @@
+use super::{
+    super::error::{ParseFailure, TextLocation},
+    BTreeMappable, DefinitionPath, Locate, MapPath,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    path::Path,
+};
+use thiserror::Error;
+
+/// Layout of the library of definitions: where the definitions are listed and which fields are not plain values.
+#[derive(Debug, PartialEq)]
+pub struct Schema {
+    /// Kind of the definitions, for the messages, e.g. `trinkets`.
+    pub name: &'static str,
+    /// End of the file name, which tells the kind of the library.
+    suffix: &'static str,
+    /// Names of the lists holding the definitions; the library has the first one present in its root object.
+    lists: &'static [&'static str],
+    /// Fields holding the unordered lists of ids, so that the items added by different mods are all kept.
+    sets: &'static [&'static str],
+    /// Fields holding the ordered lists, which are merged like the chains of skill effects.
+    chains: &'static [&'static str],
+}
+
+/// Trinkets libraries, e.g. `trinkets/base.entries.trinkets.json`, listing the trinkets themselves,
+/// their rarities or their sets.
+pub static TRINKETS: Schema = Schema {
+    name: "trinkets",
+    suffix: ".trinkets.json",
+    lists: &["entries", "rarities", "sets"],
+    sets: &["buffs", "hero_class_requirements"],
+    chains: &[],
+};
+
+/// Quirks library, `shared/quirk/quirk_library.json`.
+pub static QUIRKS: Schema = Schema {
+    name: "quirks",
+    suffix: "quirk_library.json",
+    lists: &["quirks"],
+    sets: &["incompatible_quirks", "buffs"],
+    chains: &[],
+};
+
+/// Camping skills, e.g. `raid/camping/default.camping_skills.json`.
+pub static CAMPING_SKILLS: Schema = Schema {
+    name: "camping skills",
+    suffix: ".camping_skills.json",
+    lists: &["skills"],
+    sets: &["hero_classes"],
+    chains: &["effects"],
+};
+
+const SCHEMAS: &[&Schema] = &[&TRINKETS, &QUIRKS, &CAMPING_SKILLS];
+
+impl Schema {
+    /// Finds the kind of the library by the file name; `None` if the file is not a known library.
+    pub fn of(path: &Path) -> Option<&'static Self> {
+        let name = path.to_string_lossy();
+        SCHEMAS
+            .iter()
+            .copied()
+            .find(|schema| name.ends_with(schema.suffix))
+    }
+
+    /// Checks whether the order of items in the field doesn't matter, so that every item is addressed separately.
+    pub fn is_set(&self, field: &str) -> bool {
+        self.sets.contains(&field)
+    }
+
+    /// Checks whether the field holds the ordered list which can be merged by [`merge_chains`].
+    ///
+    /// [`merge_chains`]: super::merge_chains
+    pub fn is_chain(&self, field: &str) -> bool {
+        self.chains.contains(&field)
+    }
+
+    /// Items of the set-like value, if it is a non-empty list of strings.
+    fn set_items(&self, field: &str, value: &Value) -> Option<Vec<String>> {
+        if !self.is_set(field) {
+            return None;
+        }
+        let items = value
+            .as_array()?
+            .iter()
+            .map(|item| item.as_str().map(str::to_owned))
+            .collect::<Option<Vec<_>>>()?;
+        Some(items).filter(|items| !items.is_empty())
+    }
+}
+
+/// Address of the field of definition: its id, the field name, e.g. `rarity` or `curio_tag`,
+/// and the item for the [set-like](Schema::is_set) fields.
+#[derive(Clone, PartialOrd, PartialEq, Ord, Eq, Debug, Serialize, Deserialize)]
+pub struct LibraryPath {
+    pub id: String,
+    pub field: String,
+    /// Item of the set-like field; such field is addressed item by item, and `None` is used only if it is empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item: Option<String>,
+}
+impl MapPath for LibraryPath {}
+
+impl DefinitionPath for LibraryPath {
+    fn definition(&self) -> &str {
+        &self.id
+    }
+
+    fn is_id(&self) -> bool {
+        self.field == "id"
+    }
+}
+
+impl Display for LibraryPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.id, self.field)?;
+        match &self.item {
+            Some(item) => write!(f, " \"{}\"", item),
+            None => Ok(()),
+        }
+    }
+}
+
+impl LibraryPath {
+    /// The value as it is shown to the user: the item itself for the set items, which have no value of their own.
+    pub fn render_value(&self, value: &Value) -> String {
+        match &self.item {
+            Some(item) => item.clone(),
+            None => value.to_string(),
+        }
+    }
+}
+
+/// Value stored at the path of the set item, which is fully described by the path.
+static SET_ITEM: Value = Value::Null;
+
+#[derive(Debug, Error)]
+pub enum LibraryParseError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("No {} list in the root object", .0.join(" or "))]
+    NoList(&'static [&'static str]),
+    #[error("Definition #{0} has no string id")]
+    NoId(usize),
+    #[error("{0:?} is defined twice")]
+    DuplicateId(String),
+    #[error("Not a known library of definitions")]
+    UnknownKind,
+}
+
+impl Locate for LibraryParseError {
+    fn location(&self, source: &str) -> Option<TextLocation> {
+        match self {
+            Self::Json(err) => err.location(source),
+            _ => None,
+        }
+    }
+
+    fn failure(&self, source: &str) -> ParseFailure {
+        match self {
+            Self::Json(err) => err.failure(source),
+            err => ParseFailure {
+                message: err.to_string(),
+                location: None,
+            },
+        }
+    }
+}
+
+/// Contents of the library of definitions, e.g. trinkets or quirks: every definition, by its id.
+///
+/// Definitions keep their order in file; the fields of the root object other than the list are kept as is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LibraryFile {
+    schema: &'static Schema,
+    root: Map<String, Value>,
+    list: String,
+    definitions: Vec<(String, Map<String, Value>)>,
+}
+
+impl LibraryFile {
+    /// Library with no definitions, listed in the first list of the schema.
+    pub fn empty(schema: &'static Schema) -> Self {
+        Self {
+            schema,
+            root: Map::new(),
+            list: schema.lists[0].to_owned(),
+            definitions: vec![],
+        }
+    }
+
+    pub fn parse(schema: &'static Schema, source: &str) -> Result<Self, LibraryParseError> {
+        let mut root: Map<String, Value> = serde_json::from_str(source)?;
+        let (list, items) = schema
+            .lists
+            .iter()
+            .find_map(|list| match root.remove(*list) {
+                Some(Value::Array(items)) => Some((list.to_string(), items)),
+                _ => None,
+            })
+            .ok_or(LibraryParseError::NoList(schema.lists))?;
+        let mut ids = HashSet::new();
+        let mut definitions = Vec::with_capacity(items.len());
+        for (index, item) in items.into_iter().enumerate() {
+            let fields = match item {
+                Value::Object(fields) => fields,
+                _ => return Err(LibraryParseError::NoId(index)),
+            };
+            let id = match fields.get("id") {
+                Some(Value::String(id)) => id.clone(),
+                _ => return Err(LibraryParseError::NoId(index)),
+            };
+            if !ids.insert(id.clone()) {
+                return Err(LibraryParseError::DuplicateId(id));
+            }
+            definitions.push((id, fields));
+        }
+        Ok(Self {
+            schema,
+            root,
+            list,
+            definitions,
+        })
+    }
+
+    /// Parses the library of the kind chosen by the file name, see [`Schema::of`].
+    pub fn parse_file(path: &Path, source: &str) -> Result<Self, LibraryParseError> {
+        let schema = Schema::of(path).ok_or(LibraryParseError::UnknownKind)?;
+        Self::parse(schema, source)
+    }
+
+    pub fn schema(&self) -> &'static Schema {
+        self.schema
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.definitions.iter().map(|(id, _)| id.as_str())
+    }
+
+    /// Every definition, as the game reads it, by its id.
+    pub fn definitions(&self) -> impl Iterator<Item = (&str, &Map<String, Value>)> {
+        self.definitions
+            .iter()
+            .map(|(id, fields)| (id.as_str(), fields))
+    }
+
+    /// Every value in the file, along with its path; set-like fields are listed item by item.
+    pub fn paths(&self) -> Vec<(LibraryPath, &Value)> {
+        let mut paths = vec![];
+        for (id, fields) in &self.definitions {
+            for (field, value) in fields {
+                let path = LibraryPath {
+                    id: id.clone(),
+                    field: field.clone(),
+                    item: None,
+                };
+                match self.schema.set_items(field, value) {
+                    Some(items) => paths.extend(items.into_iter().map(|item| {
+                        let path = LibraryPath {
+                            item: Some(item),
+                            ..path.clone()
+                        };
+                        (path, &SET_ITEM)
+                    })),
+                    None => paths.push((path, value)),
+                }
+            }
+        }
+        paths
+    }
+}
+
+impl Display for LibraryFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut root = self.root.clone();
+        let definitions = self
+            .definitions
+            .iter()
+            .map(|(_, fields)| Value::Object(fields.clone()))
+            .collect();
+        root.insert(self.list.clone(), Value::Array(definitions));
+        let text = serde_json::to_string_pretty(&root).map_err(|_| std::fmt::Error)?;
+        writeln!(f, "{}", text)
+    }
+}
+
+impl BTreeMappable for LibraryFile {
+    type Key = LibraryPath;
+    type Value = Value;
+
+    fn map(&self) -> BTreeMap<LibraryPath, &Value> {
+        self.paths().into_iter().collect()
+    }
+
+    fn clone_with(&self, f: impl FnOnce(&mut BTreeMap<LibraryPath, Value>)) -> Self {
+        let mut map = self
+            .map()
+            .into_iter()
+            .map(|(path, value)| (path, value.clone()))
+            .collect();
+        f(&mut map);
+        let mut by_id: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+        // The whole value goes before its items, since `None` is ordered first.
+        for (path, value) in map {
+            let fields = by_id.entry(path.id).or_default();
+            match path.item {
+                Some(item) => {
+                    let items = fields
+                        .entry(path.field)
+                        .or_insert_with(|| Value::Array(vec![]));
+                    if let Value::Array(items) = items {
+                        items.push(Value::String(item));
+                    }
+                }
+                None => {
+                    fields.insert(path.field, value);
+                }
+            }
+        }
+        // Items still in the set keep their original order, the added ones go after them, ordered by name.
+        // The set which lost all its items is kept empty, since it has no path of its own to be removed with.
+        let original: BTreeMap<_, _> = self.definitions.iter().cloned().collect();
+        for (id, fields) in &mut by_id {
+            if let Some(before) = original.get(id) {
+                for (field, value) in before {
+                    if self.schema.set_items(field, value).is_some() && !fields.contains_key(field)
+                    {
+                        fields.insert(field.clone(), Value::Array(vec![]));
+                    }
+                }
+            }
+            for (field, value) in fields.iter_mut() {
+                let before = original.get(id).and_then(|fields| fields.get(field));
+                if let (true, Value::Array(items), Some(Value::Array(before))) =
+                    (self.schema.is_set(field), value, before)
+                {
+                    items.sort_by_key(|item| {
+                        before
+                            .iter()
+                            .position(|old| old == item)
+                            .unwrap_or(usize::MAX)
+                    });
+                }
+            }
+        }
+        // Existing definitions stay in place, new ones are added at the end, ordered by id.
+        let mut definitions: Vec<_> = self
+            .definitions
+            .iter()
+            .filter_map(|(id, _)| by_id.remove_entry(id))
+            .collect();
+        definitions.extend(by_id);
+        Self {
+            schema: self.schema,
+            root: self.root.clone(),
+            list: self.list.clone(),
+            definitions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{apply, diff, merge_definitions};
+    use super::*;
+
+    const BASE: &str = r#"{
+        "entries": [
+            {"id": "sun_ring", "buffs": ["SUN_RING_1", "SUN_RING_2"], "hero_class_requirements": [], "rarity": "common", "price": 2500, "limit": 1, "origin_dungeon": ""},
+            {"id": "holy_orb", "buffs": ["HOLY_ORB_1"], "hero_class_requirements": ["vestal"], "rarity": "rare", "price": 7500, "limit": 1, "origin_dungeon": "crypts"}
+        ]
+    }"#;
+
+    fn modded(from: &str, to: &str) -> LibraryFile {
+        assert!(BASE.contains(from));
+        LibraryFile::parse(&TRINKETS, &BASE.replace(from, to)).unwrap()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let file = LibraryFile::parse(&TRINKETS, BASE).unwrap();
+        let written = file.clone_with(|_| {}).to_string();
+        assert_eq!(LibraryFile::parse(&TRINKETS, &written).unwrap(), file);
+        assert_eq!(file.ids().collect::<Vec<_>>(), vec!["sun_ring", "holy_orb"]);
+
+        let rarities = r#"{"rarities": [{"id": "common", "color": "ffffff"}]}"#;
+        let file = LibraryFile::parse(&TRINKETS, rarities).unwrap();
+        assert!(file.clone_with(|_| {}).to_string().contains("\"rarities\""));
+        assert!(matches!(
+            LibraryFile::parse(&TRINKETS, r#"{"trinkets": []}"#),
+            Err(LibraryParseError::NoList(_))
+        ));
+    }
+
+    #[test]
+    fn buffs_are_merged_as_sets() {
+        let base = LibraryFile::parse(&TRINKETS, BASE).unwrap();
+        let first = diff(
+            &base,
+            &modded(
+                r#"["SUN_RING_1", "SUN_RING_2"]"#,
+                r#"["SUN_RING_1", "SUN_RING_2", "EXTRA_A"]"#,
+            ),
+        );
+        let second = diff(
+            &base,
+            &modded(
+                r#"["SUN_RING_1", "SUN_RING_2"]"#,
+                r#"["EXTRA_B", "SUN_RING_2"]"#,
+            ),
+        );
+        let (merged, conflicts) =
+            merge_definitions(vec![("first".into(), first), ("second".into(), second)]);
+        assert!(conflicts.is_empty());
+        let result = apply(&base, merged).to_string();
+        let result = LibraryFile::parse(&TRINKETS, &result).unwrap();
+        let buffs = result
+            .definitions
+            .iter()
+            .find(|(id, _)| id == "sun_ring")
+            .map(|(_, fields)| fields["buffs"].clone())
+            .unwrap();
+        assert_eq!(
+            buffs,
+            serde_json::json!(["SUN_RING_2", "EXTRA_A", "EXTRA_B"])
+        );
+    }
+
+    #[test]
+    fn same_field_conflicts() {
+        let base = LibraryFile::parse(&TRINKETS, BASE).unwrap();
+        let added = r#", {"id": "new_charm", "buffs": [], "rarity": "uncommon", "price": 100}"#;
+        let first = diff(&base, &modded("\"price\": 2500", "\"price\": 3000"));
+        let second = diff(
+            &base,
+            &modded(
+                "\"price\": 7500, \"limit\": 1, \"origin_dungeon\": \"crypts\"}",
+                &format!(
+                    "\"price\": 7500, \"limit\": 1, \"origin_dungeon\": \"crypts\"}}{}",
+                    added
+                ),
+            ),
+        );
+        let third = diff(&base, &modded("\"price\": 2500", "\"price\": 2000"));
+
+        let (merged, conflicts) = merge_definitions(vec![
+            ("first".into(), first.clone()),
+            ("second".into(), second),
+        ]);
+        assert!(conflicts.is_empty());
+        let result = apply(&base, merged);
+        assert_eq!(
+            result.ids().collect::<Vec<_>>(),
+            vec!["sun_ring", "holy_orb", "new_charm"]
+        );
+
+        let (merged, conflicts) =
+            merge_definitions(vec![("first".into(), first), ("third".into(), third)]);
+        assert!(merged.is_empty());
+        let price = LibraryPath {
+            id: "sun_ring".into(),
+            field: "price".into(),
+            item: None,
+        };
+        assert_eq!(
+            conflicts,
+            vec![
+                (
+                    "first".into(),
+                    vec![(price.clone(), Some(3000.into()))]
+                        .into_iter()
+                        .collect()
+                ),
+                (
+                    "third".into(),
+                    vec![(price, Some(2000.into()))].into_iter().collect()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn quirks() {
+        let base = r#"{"quirks": [
+            {"id": "kleptomaniac", "is_positive": false, "is_disease": false, "classification": "mental",
+             "incompatible_quirks": ["generous"], "curio_tag": "Treasure", "curio_tag_chance": 0.5,
+             "buffs": ["KLEPTO_1"], "show_explicit_curio_tag": true}
+        ]}"#;
+        let parse = |text: &str| {
+            LibraryFile::parse_file(Path::new("shared/quirk/quirk_library.json"), text)
+        };
+        let orig = parse(base).unwrap();
+        assert_eq!(orig.schema(), &QUIRKS);
+        let modded = |from: &str, to: &str| {
+            assert!(base.contains(from));
+            diff(&orig, &parse(&base.replace(from, to)).unwrap())
+        };
+        let quirk = |id: &str| {
+            format!(
+                r#""show_explicit_curio_tag": true}}, {{"id": "{}", "is_positive": true, "is_disease": false, "buffs": [], "incompatible_quirks": ["kleptomaniac"]}}"#,
+                id
+            )
+        };
+
+        // Different quirks and different items of the same sets are all kept.
+        let (merged, conflicts) = merge_definitions(vec![
+            (
+                "first".into(),
+                modded(r#""show_explicit_curio_tag": true}"#, &quirk("lucky")),
+            ),
+            (
+                "second".into(),
+                modded(r#""show_explicit_curio_tag": true}"#, &quirk("clumsy")),
+            ),
+            (
+                "third".into(),
+                modded(r#"["generous"]"#, r#"["generous", "thrifty"]"#),
+            ),
+        ]);
+        assert!(conflicts.is_empty());
+        let result = apply(&orig, merged).to_string();
+        let result = parse(&result).unwrap();
+        assert_eq!(
+            result.ids().collect::<Vec<_>>(),
+            vec!["kleptomaniac", "clumsy", "lucky"]
+        );
+        assert_eq!(
+            result.definitions[0].1["incompatible_quirks"],
+            serde_json::json!(["generous", "thrifty"])
+        );
+
+        let (_, conflicts) = merge_definitions(vec![
+            ("first".into(), modded("0.5", "0.75")),
+            ("second".into(), modded("0.5", "0.25")),
+        ]);
+        let chances: Vec<_> = conflicts
+            .iter()
+            .flat_map(|(name, patch)| {
+                patch
+                    .iter()
+                    .map(move |(path, value)| (name.as_str(), path.to_string(), value.clone()))
+            })
+            .collect();
+        assert_eq!(
+            chances,
+            vec![
+                (
+                    "first",
+                    "kleptomaniac.curio_tag_chance".into(),
+                    Some(0.75.into())
+                ),
+                (
+                    "second",
+                    "kleptomaniac.curio_tag_chance".into(),
+                    Some(0.25.into())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn camping_skills_roundtrip() {
+        let text = r#"{"configuration": {"class_specific_number_of_classes_threshold": 1}, "skills": [
+            {"id": "encourage", "level": 0, "cost": 2, "use_limit": 1, "hero_classes": ["crusader", "vestal"],
+             "effects": [{"selection": "individual", "type": "stress_heal_amount", "amount": 15}]}
+        ]}"#;
+        let path = Path::new("raid/camping/default.camping_skills.json");
+        let file = LibraryFile::parse_file(path, text).unwrap();
+        assert_eq!(file.schema(), &CAMPING_SKILLS);
+        assert!(file.schema().is_chain("effects"));
+        let written = file.clone_with(|_| {}).to_string();
+        assert_eq!(LibraryFile::parse_file(path, &written).unwrap(), file);
+        assert!(written.contains("\"configuration\""));
+        assert!(matches!(
+            LibraryFile::parse_file(Path::new("raid/camping/default.json"), text),
+            Err(LibraryParseError::UnknownKind)
+        ));
+    }
+}
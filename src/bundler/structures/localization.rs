@@ -1 +1,564 @@
+use super::{
+    super::{
+        encoding::XmlDeclaration,
+        error::{ParseFailure, TextLocation},
+    },
+    BTreeMappable, Locate, MapPath, MapValue,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
+    fmt::Display,
+};
 
+impl Locate for serde_xml_rs::Error {
+    /// Location of the syntax error; it's found in the sanitized text, so it might be off by a line or two
+    /// if the XML declaration was moved.
+    fn location(&self, source: &str) -> Option<TextLocation> {
+        use xml::common::Position;
+        match self {
+            serde_xml_rs::Error::Syntax { source: err } => {
+                let position = err.position();
+                Some(TextLocation::at_line(
+                    source,
+                    position.row as usize + 1,
+                    position.column as usize + 1,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn failure(&self, source: &str) -> ParseFailure {
+        let message = match self {
+            serde_xml_rs::Error::Syntax { source: err } => err.msg().to_owned(),
+            err => err.to_string(),
+        };
+        ParseFailure {
+            message,
+            location: self.location(source),
+        }
+    }
+}
+
+/// Address of the localized string: the language and the entry id.
+#[derive(Clone, PartialOrd, PartialEq, Ord, Eq, Debug, Serialize, Deserialize)]
+pub struct LocPath {
+    pub language: String,
+    pub id: String,
+}
+impl MapPath for LocPath {}
+
+impl Display for LocPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.language, self.id)
+    }
+}
+
+impl MapValue for String {
+    fn same_as(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+#[derive(Deserialize)]
+struct RawEntry {
+    id: String,
+    #[serde(rename = "$value", default)]
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct RawLanguage {
+    id: String,
+    #[serde(rename = "entry", default)]
+    entries: Vec<RawEntry>,
+}
+
+#[derive(Deserialize)]
+struct RawTable {
+    #[serde(rename = "language", default)]
+    languages: Vec<RawLanguage>,
+}
+
+/// Contents of the `*.string_table.xml`: every localized string, by language and entry id.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StringsTable(BTreeMap<String, BTreeMap<String, String>>);
+
+/// Fixes the things the game accepts, but the XML parser doesn't.
+///
+/// - The declaration must be at the very start, and its version must be 1.x;
+///   some mods have it after an empty line, or declare version 2.0.
+/// - Comments can't contain `--`, but some mods use `<!--- ... --->` (spanning several lines, too).
+fn sanitize(source: &str) -> Cow<'_, str> {
+    let mut source = Cow::Borrowed(source);
+    if let Some(decl) = XmlDeclaration::find(&source) {
+        let mut fixed = source[decl.range.clone()].to_owned();
+        if let Some(version) = decl.version {
+            let value = &source[version.clone()];
+            let major = value.split('.').next().unwrap_or_default();
+            if matches!(major.parse::<u32>(), Ok(major) if major > 1) {
+                warn!(
+                    "XML declares unsupported version {}, replacing with 1.0",
+                    value
+                );
+                let start = version.start - decl.range.start;
+                fixed.replace_range(start..start + value.len(), "1.0");
+            }
+        }
+        let prefix = &source[..decl.range.start];
+        if fixed != source[decl.range.clone()] || !prefix.is_empty() {
+            if !prefix.trim().is_empty() {
+                warn!("XML declaration is not at the start of the file, moving it there");
+            }
+            let rest = format!("{}{}", prefix.trim(), &source[decl.range.end..]);
+            source = Cow::Owned(fixed + &rest);
+        }
+    }
+    while let Some(start) = source.find("<!---") {
+        let end = match source[start..].find("--->") {
+            Some(end) => start + end + "--->".len(),
+            None => break,
+        };
+        debug!("Removing invalid comment: {}", &source[start..end]);
+        source.to_mut().replace_range(start..end, "");
+    }
+    source
+}
+
+/// Entry defined more than once in the same language, with different text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Duplicate {
+    pub path: LocPath,
+    /// Text of the first definition, which is used by the game.
+    pub kept: String,
+    pub ignored: String,
+}
+
+impl StringsTable {
+    pub fn parse(source: &str) -> Result<Self, serde_xml_rs::Error> {
+        Self::parse_with_duplicates(source).map(|(table, _)| table)
+    }
+
+    /// Parses the table, keeping the first definition of every entry, like the game does,
+    /// and listing the other definitions which differ from it.
+    pub fn parse_with_duplicates(
+        source: &str,
+    ) -> Result<(Self, Vec<Duplicate>), serde_xml_rs::Error> {
+        let raw: RawTable = serde_xml_rs::from_str(&sanitize(source))?;
+        let mut table = Self::default();
+        let mut duplicates = vec![];
+        for language in raw.languages {
+            let entries = table.0.entry(language.id.clone()).or_default();
+            for entry in language.entries {
+                match entries.entry(entry.id) {
+                    Entry::Vacant(vacant) => {
+                        vacant.insert(entry.text);
+                    }
+                    Entry::Occupied(occupied) if *occupied.get() == entry.text => {
+                        debug!(
+                            "Entry {} is defined twice with the same text",
+                            occupied.key()
+                        );
+                    }
+                    Entry::Occupied(occupied) => duplicates.push(Duplicate {
+                        path: LocPath {
+                            language: language.id.clone(),
+                            id: occupied.key().clone(),
+                        },
+                        kept: occupied.get().clone(),
+                        ignored: entry.text,
+                    }),
+                }
+            }
+        }
+        Ok((table, duplicates))
+    }
+}
+
+impl StringsTable {
+    /// Ids of every entry, in every language; the same id is listed once for each language defining it.
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .values()
+            .flat_map(|entries| entries.keys().map(String::as_str))
+    }
+
+    /// Drops the languages which are not listed, filling the entries missing in the kept ones
+    /// with the English text, which is what the game would show for them.
+    ///
+    /// Returns the ids of filled entries, by language.
+    pub fn retain_languages(
+        &mut self,
+        languages: &BTreeSet<String>,
+    ) -> BTreeMap<String, Vec<String>> {
+        const FALLBACK: &str = "english";
+        let fallback = self.0.get(FALLBACK).cloned().unwrap_or_default();
+        self.0.retain(|language, _| languages.contains(language));
+        let mut filled = BTreeMap::new();
+        for (language, entries) in self
+            .0
+            .iter_mut()
+            .filter(|(language, _)| *language != FALLBACK)
+        {
+            for (id, text) in &fallback {
+                if !entries.contains_key(id) {
+                    entries.insert(id.clone(), text.clone());
+                    filled
+                        .entry(language.clone())
+                        .or_insert_with(Vec::new)
+                        .push(id.clone());
+                }
+            }
+        }
+        filled
+    }
+}
+
+fn escape_attribute(value: &str) -> Cow<'_, str> {
+    if !value.contains(&['&', '<', '"'][..]) {
+        return Cow::Borrowed(value);
+    }
+    Cow::Owned(
+        value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('"', "&quot;"),
+    )
+}
+
+impl Display for StringsTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(f, "<root>")?;
+        for (language, entries) in &self.0 {
+            writeln!(f, "<language id=\"{}\">", escape_attribute(language))?;
+            for (id, text) in entries {
+                // CDATA section can't contain its own end, so the text is split right inside it.
+                let text = text.replace("]]>", "]]]]><![CDATA[>");
+                writeln!(
+                    f,
+                    "<entry id=\"{}\"><![CDATA[{}]]></entry>",
+                    escape_attribute(id),
+                    text
+                )?;
+            }
+            writeln!(f, "</language>")?;
+        }
+        writeln!(f, "</root>")
+    }
+}
+
+impl BTreeMappable for StringsTable {
+    type Key = LocPath;
+    type Value = String;
+
+    fn map(&self) -> BTreeMap<LocPath, &String> {
+        self.0
+            .iter()
+            .flat_map(|(language, entries)| {
+                entries.iter().map(move |(id, text)| {
+                    let path = LocPath {
+                        language: language.clone(),
+                        id: id.clone(),
+                    };
+                    (path, text)
+                })
+            })
+            .collect()
+    }
+
+    fn clone_with(&self, f: impl FnOnce(&mut BTreeMap<LocPath, String>)) -> Self {
+        // Cloned in one pass, without the intermediate map of references.
+        let mut map = self
+            .0
+            .iter()
+            .flat_map(|(language, entries)| {
+                entries.iter().map(move |(id, text)| {
+                    let path = LocPath {
+                        language: language.clone(),
+                        id: id.clone(),
+                    };
+                    (path, text.clone())
+                })
+            })
+            .collect();
+        f(&mut map);
+        // Paths are ordered by language, so every language is filled at once, without looking it up.
+        let mut table = Self::default();
+        let mut current: Option<(String, BTreeMap<String, String>)> = None;
+        for (path, text) in map {
+            match &mut current {
+                Some((language, entries)) if *language == path.language => {
+                    entries.insert(path.id, text);
+                }
+                _ => {
+                    let next = (path.language, std::iter::once((path.id, text)).collect());
+                    if let Some((language, entries)) = current.replace(next) {
+                        table.0.insert(language, entries);
+                    }
+                }
+            }
+        }
+        if let Some((language, entries)) = current {
+            table.0.insert(language, entries);
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<root>
+<language id="english">
+<entry id="str_smite"><![CDATA[Smite]]></entry>
+<entry id="str_smite_desc"><![CDATA[Deals <b>damage</b>
+to the enemy]]></entry>
+<entry id="str_empty"></entry>
+</language>
+<language id="russian">
+<entry id="str_smite"><![CDATA[Кара]]></entry>
+</language>
+</root>
+"#;
+
+    #[test]
+    fn roundtrip() {
+        let table = StringsTable::parse(TABLE).unwrap();
+        let map = table.map();
+        let path = |language: &str, id: &str| LocPath {
+            language: language.into(),
+            id: id.into(),
+        };
+        assert_eq!(map.len(), 4);
+        assert_eq!(
+            map[&path("english", "str_smite_desc")],
+            "Deals <b>damage</b>\nto the enemy"
+        );
+        assert_eq!(map[&path("english", "str_empty")], "");
+        assert_eq!(map[&path("russian", "str_smite")], "Кара");
+        assert_eq!(StringsTable::parse(&table.to_string()).unwrap(), table);
+    }
+
+    #[test]
+    fn hostile_text_is_escaped() {
+        let table = StringsTable::parse(TABLE).unwrap().clone_with(|map| {
+            let path = |id: &str| LocPath {
+                language: "schinese".into(),
+                id: id.into(),
+            };
+            map.insert(path("str_cdata"), "Ends ]]> here, <b>twice</b> ]]>".into());
+            map.insert(path("str_\"quoted\" & <odd>"), "Text".into());
+        });
+        let written = table.to_string();
+        assert!(
+            written.contains("<language id=\"schinese\">"),
+            "{}",
+            written
+        );
+        assert_eq!(StringsTable::parse(&written).unwrap(), table);
+    }
+
+    #[test]
+    fn sanitized_declaration() {
+        let fixed = |text: &str| sanitize(text).into_owned();
+        let good = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<root/>";
+        assert!(matches!(sanitize(good), Cow::Borrowed(_)));
+        assert_eq!(
+            fixed("<?xml version=\"1.10\" encoding=\"utf-8\"?><root/>"),
+            "<?xml version=\"1.10\" encoding=\"utf-8\"?><root/>"
+        );
+        assert_eq!(
+            fixed("\n<?xml version='10.0' encoding='UTF-16'?>\n<root/>"),
+            "<?xml version='1.0' encoding='UTF-16'?>\n<root/>"
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        let (table, duplicates) = StringsTable::parse_with_duplicates(include_str!(
+            "localization/duplicates.string_table.xml"
+        ))
+        .unwrap();
+        let map = table.map();
+        let path = |language: &str, id: &str| LocPath {
+            language: language.into(),
+            id: id.into(),
+        };
+        assert_eq!(map.len(), 4);
+        assert_eq!(map[&path("english", "str_smite")], "Smite");
+        assert_eq!(map[&path("english", "str_bulwark")], "Bulwark of Faith");
+        assert_eq!(
+            duplicates
+                .iter()
+                .map(|dup| (
+                    dup.path.to_string(),
+                    dup.kept.as_str(),
+                    dup.ignored.as_str()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("[english] str_smite".into(), "Smite", "Smite!"),
+                ("[english] str_smite".into(), "Smite", "Holy Smite"),
+                (
+                    "[english] str_bulwark".into(),
+                    "Bulwark of Faith",
+                    "Bulwark"
+                ),
+                ("[french] str_smite".into(), "Châtiment", "Frappe"),
+            ]
+        );
+    }
+
+    #[test]
+    fn language_filter() {
+        let mut table = StringsTable::parse(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<root>
+<language id="english">
+<entry id="str_smite"><![CDATA[Smite]]></entry>
+<entry id="str_bulwark"><![CDATA[Bulwark of Faith]]></entry>
+</language>
+<language id="french">
+<entry id="str_smite"><![CDATA[Châtiment]]></entry>
+</language>
+<language id="schinese">
+<entry id="str_smite"><![CDATA[重击]]></entry>
+</language>
+</root>
+"#,
+        )
+        .unwrap();
+        let languages = vec!["schinese".to_string()].into_iter().collect();
+        let filled = table.retain_languages(&languages);
+        assert_eq!(
+            filled,
+            vec![("schinese".into(), vec!["str_bulwark".into()])]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(
+            table.to_string(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<root>
+<language id="schinese">
+<entry id="str_bulwark"><![CDATA[Bulwark of Faith]]></entry>
+<entry id="str_smite"><![CDATA[重击]]></entry>
+</language>
+</root>
+"#
+        );
+    }
+
+    #[test]
+    fn workshop_quirks() {
+        let table = StringsTable::parse(include_str!("localization/version_2.string_table.xml"));
+        assert_eq!(table.unwrap().map().len(), 2);
+        let table = StringsTable::parse(include_str!(
+            "localization/pseudo_comments.string_table.xml"
+        ));
+        assert_eq!(table.unwrap().map().len(), 2);
+    }
+
+    /// Rebuilding as it was done before, through the map of references and the lookup of every language.
+    fn clone_by_lookup(
+        table: &StringsTable,
+        f: impl FnOnce(&mut BTreeMap<LocPath, String>),
+    ) -> StringsTable {
+        let mut map = table
+            .map()
+            .into_iter()
+            .map(|(path, text)| (path, text.clone()))
+            .collect();
+        f(&mut map);
+        let mut rebuilt = StringsTable::default();
+        for (path, text) in map {
+            rebuilt
+                .0
+                .entry(path.language)
+                .or_default()
+                .insert(path.id, text);
+        }
+        rebuilt
+    }
+
+    fn large_table(strings: usize) -> StringsTable {
+        let mut table = StringsTable::default();
+        for language in &["english", "french", "german", "russian", "schinese"] {
+            let entries = table.0.entry(language.to_string()).or_default();
+            for index in 0..strings / 5 {
+                entries.insert(
+                    format!("str_{}", index),
+                    format!("{} text {}", language, index),
+                );
+            }
+        }
+        table
+    }
+
+    #[test]
+    fn same_as_rebuilding_by_lookup() {
+        let table = StringsTable::parse(TABLE).unwrap();
+        let change = |map: &mut BTreeMap<LocPath, String>| {
+            map.remove(&LocPath {
+                language: "english".into(),
+                id: "str_empty".into(),
+            });
+            map.insert(
+                LocPath {
+                    language: "french".into(),
+                    id: "str_smite".into(),
+                },
+                "Châtiment".into(),
+            );
+        };
+        assert_eq!(table.clone_with(change), clone_by_lookup(&table, change));
+        let large = large_table(1000);
+        assert_eq!(large.clone_with(|_| {}), clone_by_lookup(&large, |_| {}));
+    }
+
+    #[test]
+    fn large_table_is_rebuilt_with_changes() {
+        let table = large_table(10_000);
+        let change = |map: &mut BTreeMap<LocPath, String>| {
+            for language in &["english", "schinese"] {
+                let path = |id: &str| LocPath {
+                    language: language.to_string(),
+                    id: id.into(),
+                };
+                map.remove(&path("str_0"));
+                map.insert(path("str_1"), "changed".into());
+                map.insert(path("str_new"), "added".into());
+            }
+        };
+        let rebuilt = table.clone_with(change);
+        assert_eq!(rebuilt, clone_by_lookup(&table, change));
+        assert_eq!(rebuilt.map().len(), 10_000);
+        assert_eq!(rebuilt.0["english"]["str_1"], "changed");
+        assert_eq!(rebuilt.0["french"]["str_1"], "french text 1");
+    }
+
+    /// Not a real benchmark, but shows the difference on the table of the full game's size;
+    /// run with `cargo test --release -- --ignored strings_rebuild_timing --nocapture`.
+    #[test]
+    #[ignore]
+    fn strings_rebuild_timing() {
+        let table = large_table(50_000);
+        let start = std::time::Instant::now();
+        let by_lookup = clone_by_lookup(&table, |_| {});
+        let lookup_time = start.elapsed();
+        let start = std::time::Instant::now();
+        let rebuilt = table.clone_with(|_| {});
+        let rebuilt_time = start.elapsed();
+        println!(
+            "50k strings: {:?} by lookup, {:?} in one pass",
+            lookup_time, rebuilt_time
+        );
+        assert_eq!(by_lookup, rebuilt);
+    }
+}
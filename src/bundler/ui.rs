@@ -0,0 +1,836 @@
+use super::{
+    error::{BundlerError, DeploymentError, Unresolved},
+    preview::BundlePreview,
+    progress::{Cancelled, Progress, ProgressEvent},
+    resolve::{ConflictSummary, ResolveBackend, Review},
+    validate, Bundle,
+};
+use crate::{
+    loader::{GlobalData, Mod, ModProject},
+    profile::{MergeStrategy, Output, Profile},
+};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use cursive::{
+    align::HAlign,
+    theme::{BaseColor, Color},
+    traits::{Finder, Nameable, Resizable, Scrollable},
+    utils::markup::StyledString,
+    views::{
+        Button, Checkbox, Dialog, EditView, LinearLayout, Panel, ProgressBar, SelectView, TextArea,
+        TextView,
+    },
+    Cursive,
+};
+use log::*;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("Background thread panicked, stopping: {0}")]
+struct PanicError(String);
+
+pub fn bundle(cursive: &mut Cursive) {
+    // Mods are ordered as they were selected, since this order defines their priority.
+    let order: Vec<PathBuf> = cursive
+        .call_on_name("Selected", |list: &mut SelectView<Mod>| {
+            list.iter()
+                .map(|(_, the_mod)| the_mod.path.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    let by_priority = cursive
+        .call_on_name("Priority strategy", |checkbox: &mut Checkbox| {
+            checkbox.is_checked()
+        })
+        .unwrap_or_default();
+    let mut global_data: GlobalData = cursive.take_user_data().expect("No data was set");
+    global_data
+        .mods
+        .sort_by_key(|the_mod| order.iter().position(|path| *path == the_mod.path));
+    global_data.strategy = if by_priority {
+        MergeStrategy::Priority
+    } else {
+        MergeStrategy::Ask
+    };
+    start(cursive, global_data);
+}
+
+/// Runs the bundling in background, with the mods list being already prepared.
+pub fn start(cursive: &mut Cursive, global_data: GlobalData) {
+    let (progress, events) = Progress::new();
+    let on_cancel = progress.clone();
+    crate::screen(
+        cursive,
+        Dialog::around(
+            LinearLayout::vertical()
+                // Space added so that the view is always rendered, even when this is not specified.
+                .child(TextView::new(" ").with_name("Loading part"))
+                .child(TextView::new(" ").with_name("Loading filename"))
+                .child(ProgressBar::new().with_name("Loading progress")),
+        )
+        .title("Loading vanilla game data...")
+        .button("Cancel", move |cursive| {
+            on_cancel.cancel();
+            cursive.call_on_name("Loading dialog", |dialog: &mut Dialog| {
+                dialog.set_title("Cancelling...");
+            });
+        })
+        .with_name("Loading dialog"),
+    );
+    info!("Bundling progress dialog shown");
+
+    let on_file_read = cursive.cb_sink().clone();
+    let mut on_error = on_file_read.clone();
+    forward_to_ui(on_file_read.clone(), events);
+    std::thread::spawn(move || {
+        info!("Starting background thread");
+        let thread = std::thread::spawn(move || {
+            let mut on_file_read = on_file_read;
+            if let Err(err) = do_bundle(&mut on_file_read, &progress, global_data) {
+                crate::run_update(&mut on_file_read, move |cursive| {
+                    crate::error(cursive, &err);
+                });
+                std::thread::yield_now(); // to let cursive run update immediately
+            };
+        });
+        info!("Waiting on the background thread");
+        if let Err(panic_info) = thread.join() {
+            let msg = match panic_info.downcast_ref::<&'static str>() {
+                Some(s) => *s,
+                None => match panic_info.downcast_ref::<String>() {
+                    Some(s) => &s[..],
+                    None => "Box<Any>",
+                },
+            }
+            .to_string();
+            crate::run_update(&mut on_error, move |cursive| {
+                crate::error(cursive, &PanicError(msg));
+            });
+        } else {
+            info!("Background thread exited successfully");
+        }
+    });
+}
+
+fn do_bundle(
+    on_file_read: &mut cursive::CbSink,
+    progress: &Progress,
+    global_data: GlobalData,
+) -> Result<(), BundlerError> {
+    let selected: Vec<Mod> = global_data
+        .mods
+        .into_iter()
+        .filter(|the_mod| the_mod.selected)
+        .collect();
+    let bundle = Bundle::builder()
+        .game_root(global_data.game.path())
+        .mods(selected.iter().cloned())
+        .strategy(global_data.strategy)
+        .resolutions(global_data.resolutions)
+        .languages(global_data.languages.clone())
+        .rules(global_data.rules)
+        .value_rules(global_data.value_rules.clone())
+        .baseline(global_data.baseline)
+        .backend(CursiveBackend(on_file_read.clone()))
+        .progress(progress.clone())
+        .run()?;
+
+    // Only the first warnings are shown here, the rest are in the report file.
+    const SHOWN_WARNINGS: usize = 10;
+    let has_warnings = !bundle.report.warnings.is_empty();
+    let warnings = match bundle.report.warnings.len() {
+        0 => String::new(),
+        count => {
+            let mut text = format!("\n\n{} possible problems found in the merged data:", count);
+            for warning in bundle.report.warnings.iter().take(SHOWN_WARNINGS) {
+                text.push_str(&format!("\n  {}", warning));
+            }
+            if count > SHOWN_WARNINGS {
+                text.push_str(&format!("\n  ...and {} more", count - SHOWN_WARNINGS));
+            }
+            text
+        }
+    };
+    let summary = format!(
+        "{}\n\n{}{}",
+        render_mods(bundle.projects()),
+        bundle.assets_summary(),
+        warnings
+    );
+    let resolutions = bundle.resolutions.clone();
+    let rules = bundle.rules.clone();
+    let baseline = bundle.baseline.clone();
+
+    if !review_preview(on_file_read, &bundle.preview) {
+        info!("Bundle was rejected after the preview");
+        return Err(Cancelled.into());
+    }
+    info!("Deploying generated mod to the \"mods\" directory");
+    let mods_path = global_data.game.mods();
+    let output = deploy(on_file_read, bundle, &mods_path, global_data.output)?;
+
+    let bundle_path = mods_path.join(&output.directory);
+    let mut summary = format!(
+        "{}\n\nFull report is saved to {}",
+        summary,
+        bundle_path.join("report.txt").to_string_lossy()
+    );
+    if has_warnings {
+        summary.push_str(&format!(
+            "\nPossible problems are listed in {}",
+            bundle_path.join(validate::REPORT_FILE).to_string_lossy()
+        ));
+    }
+    let game = global_data.game;
+    let strategy = global_data.strategy;
+    let languages = global_data.languages;
+    let value_rules = global_data.value_rules;
+    crate::run_update(on_file_read, move |cursive| {
+        crate::screen(
+            cursive,
+            Dialog::around(TextView::new(format!("Bundle ready!\n\n{}", summary)))
+                .button("Save profile as...", move |cursive| {
+                    match Profile::new(
+                        &game,
+                        &selected,
+                        strategy,
+                        resolutions.clone(),
+                        Some(output.clone()),
+                        languages.clone(),
+                        rules.clone(),
+                    )
+                    .map(|profile| Profile {
+                        value_rules: value_rules.clone(),
+                        baseline: baseline.clone(),
+                        ..profile
+                    }) {
+                        Ok(profile) => crate::select::ask_for_path(
+                            cursive,
+                            "Save profile to file",
+                            move |cursive, path| {
+                                crate::select::save_profile(cursive, &profile, &path)
+                            },
+                        ),
+                        Err(err) => cursive
+                            .add_layer(Dialog::info(format!("Failed to create profile: {}", err))),
+                    }
+                })
+                .button("OK", Cursive::quit),
+        );
+    });
+    Ok(())
+}
+
+fn render_mods(projects: &[ModProject]) -> String {
+    let mut text = "Bundled mods:".to_string();
+    for project in projects {
+        text.push_str(&format!("\n  {}", project.title));
+        if !project.tags().is_empty() {
+            text.push_str(&format!(" [{}]", project.tags().join(", ")));
+        }
+    }
+    text
+}
+
+fn shorten_path(mut path: String) -> String {
+    const LOG_PATH_LEN: usize = 120;
+
+    if path.len() < LOG_PATH_LEN {
+        path.chars()
+            .chain(std::iter::repeat(' '))
+            .take(LOG_PATH_LEN)
+            .collect()
+    } else {
+        // https://users.rust-lang.org/t/take-last-n-characters-from-string/44638
+        let len = path
+            .char_indices()
+            .rev()
+            .nth((LOG_PATH_LEN - 3) - 1)
+            .map_or(0, |(idx, _)| idx);
+        let _ = path.drain(0..len);
+        format!("...{}", path)
+    }
+}
+
+fn show_event(cursive: &mut Cursive, event: ProgressEvent) {
+    cursive.call_on_name("Loading dialog", |dialog: &mut Dialog| match event {
+        ProgressEvent::Phase(name) => {
+            dialog.set_title(format!("{}...", name));
+            dialog.call_on_name("Loading part", |text: &mut TextView| {
+                text.set_content(" ");
+            });
+            dialog.call_on_name("Loading filename", |text: &mut TextView| {
+                text.set_content(" ");
+            });
+            dialog.call_on_name("Loading progress", |bar: &mut ProgressBar| {
+                bar.set_value(0);
+            });
+        }
+        ProgressEvent::Part(name) => {
+            dialog.call_on_name("Loading part", |text: &mut TextView| {
+                text.set_content(name);
+            });
+        }
+        ProgressEvent::File {
+            action,
+            path,
+            done,
+            total,
+        } => {
+            dialog.call_on_name("Loading filename", |text: &mut TextView| {
+                text.set_content(format!(
+                    "{} ({}/{}): <ROOT>/{}",
+                    action,
+                    done,
+                    total,
+                    shorten_path(path)
+                ));
+            });
+            // The bar has the default range of 0..=100.
+            dialog.call_on_name("Loading progress", |bar: &mut ProgressBar| {
+                bar.set_value(done * 100 / total.max(1));
+            });
+        }
+    });
+}
+
+/// Forwards the progress events to the UI, until the pipeline drops its reporting ends.
+fn forward_to_ui(mut sink: cursive::CbSink, receiver: Receiver<ProgressEvent>) {
+    std::thread::spawn(move || {
+        for event in receiver {
+            crate::run_update(&mut sink, move |cursive| show_event(cursive, event));
+        }
+        debug!("Progress channel closed");
+    });
+}
+
+/// Asks the user about every conflict, showing the dialogs through the cursive callback sink.
+struct CursiveBackend(cursive::CbSink);
+
+fn render_line_choice(line: String, mod_name: String) -> impl cursive::View {
+    Panel::new(
+        LinearLayout::horizontal()
+            .child(TextView::new(line.clone()).full_width())
+            .child(Button::new("Use this", move |cursive| {
+                let line = line.clone();
+                cursive.call_on_name("Line resolve edit", move |edit: &mut TextArea| {
+                    edit.set_content(line)
+                });
+            })),
+    )
+    .title(mod_name)
+    .title_position(HAlign::Left)
+}
+
+impl ResolveBackend for CursiveBackend {
+    fn choose(&mut self, question: &str, options: &[String]) -> Result<usize, Unresolved> {
+        let (sender, receiver) = bounded(0);
+        let text = question.to_owned();
+        let options: Vec<_> = options.iter().cloned().zip(0..).collect();
+        crate::run_update(&mut self.0, move |cursive| {
+            crate::push_screen(
+                cursive,
+                Dialog::around(LinearLayout::vertical().child(TextView::new(text)).child(
+                    Panel::new(SelectView::new().with_all(options).on_submit(
+                        move |cursive, index| {
+                            cursive.pop_layer();
+                            let _ = sender.send(*index);
+                        },
+                    )),
+                )),
+            );
+        });
+        Ok(receiver
+            .recv()
+            .expect("Sender was dropped without sending anything"))
+    }
+
+    fn edit_line(&mut self, title: &str, lines: &[(String, String)]) -> Result<String, Unresolved> {
+        let (sender, receiver) = bounded(0);
+        let title = title.to_owned();
+        let lines = lines.to_vec();
+        crate::run_update(&mut self.0, move |cursive| {
+            let mut layout = LinearLayout::vertical();
+            lines
+                .into_iter()
+                .for_each(|(name, line)| layout.add_child(render_line_choice(line, name)));
+            crate::push_screen(
+                cursive,
+                Dialog::around(
+                    layout.child(TextArea::new().with_name("Line resolve edit").full_width()),
+                )
+                .title(title)
+                .button("Resolve", move |cursive| {
+                    let value = cursive
+                        .call_on_name("Line resolve edit", |edit: &mut TextArea| {
+                            edit.get_content().to_owned()
+                        })
+                        .unwrap();
+                    cursive.pop_layer();
+                    sender.send(value).unwrap();
+                })
+                .h_align(HAlign::Center),
+            );
+        });
+        Ok(receiver
+            .recv()
+            .expect("Sender was dropped without sending anything"))
+    }
+
+    fn compare_strings(
+        &mut self,
+        question: &str,
+        strings: &[(String, String)],
+        options: &[String],
+    ) -> Result<usize, Unresolved> {
+        // Long strings are scrolled inside their panels, so that every version stays visible.
+        const STRING_HEIGHT: usize = 12;
+
+        let (sender, receiver) = bounded(0);
+        let title = question.to_owned();
+        let strings = strings.to_vec();
+        let options: Vec<_> = options.iter().cloned().zip(0..).collect();
+        crate::run_update(&mut self.0, move |cursive| {
+            let mut versions = LinearLayout::horizontal();
+            for (name, text) in strings {
+                versions.add_child(
+                    Panel::new(TextView::new(text).scrollable().max_height(STRING_HEIGHT))
+                        .title(name)
+                        .title_position(HAlign::Left)
+                        .full_width(),
+                );
+            }
+            crate::push_screen(
+                cursive,
+                Dialog::around(
+                    LinearLayout::vertical()
+                        .child(TextView::new(title))
+                        .child(versions)
+                        .child(Panel::new(SelectView::new().with_all(options).on_submit(
+                            move |cursive, index| {
+                                cursive.pop_layer();
+                                let _ = sender.send(*index);
+                            },
+                        ))),
+                ),
+            );
+        });
+        Ok(receiver
+            .recv()
+            .expect("Sender was dropped without sending anything"))
+    }
+
+    fn can_preview(&self) -> bool {
+        true
+    }
+
+    fn preview(&mut self, title: &str, text: &str, highlighted: &[usize]) {
+        let (sender, receiver) = bounded(0);
+        let title = title.to_owned();
+        let mut styled = StyledString::new();
+        for (index, line) in text.lines().enumerate() {
+            if highlighted.contains(&index) {
+                styled.append_styled(line, Color::Light(BaseColor::Yellow));
+            } else {
+                styled.append_plain(line);
+            }
+            styled.append_plain("\n");
+        }
+        crate::run_update(&mut self.0, move |cursive| {
+            crate::push_screen(
+                cursive,
+                Dialog::around(
+                    LinearLayout::vertical()
+                        .child(TextView::new(
+                            "Highlighted lines depend on the conflicts not resolved yet",
+                        ))
+                        .child(Panel::new(TextView::new(styled).scrollable())),
+                )
+                .title(title)
+                .button("Back", move |cursive| {
+                    cursive.pop_layer();
+                    let _ = sender.send(());
+                })
+                .h_align(HAlign::Center),
+            );
+        });
+        receiver
+            .recv()
+            .expect("Sender was dropped without sending anything")
+    }
+
+    fn can_compose(&self) -> bool {
+        true
+    }
+
+    fn compose_list(
+        &mut self,
+        title: &str,
+        base: &[String],
+        proposals: &[(String, Vec<String>)],
+    ) -> Result<Vec<String>, Unresolved> {
+        let (sender, receiver) = bounded(0);
+        let title = title.to_owned();
+        let sources: Vec<_> = std::iter::once(("Original".to_owned(), base.to_vec()))
+            .chain(proposals.iter().cloned())
+            .collect();
+        crate::run_update(&mut self.0, move |cursive| {
+            let mut columns = LinearLayout::horizontal();
+            for (source, items) in sources {
+                let mut select = SelectView::new();
+                for item in items {
+                    select.add_item(item.clone(), (source.clone(), item));
+                }
+                let select = select.on_submit(|cursive, (source, item): &(String, String)| {
+                    add_composed(cursive, source, item)
+                });
+                columns.add_child(Panel::new(select.scrollable()).title(source).full_width());
+            }
+            crate::push_screen(
+                cursive,
+                Dialog::around(
+                    LinearLayout::vertical()
+                        .child(TextView::new(
+                            "Select the effects in order they should be applied; select the composed one to remove it",
+                        ))
+                        .child(columns)
+                        .child(
+                            Panel::new(
+                                SelectView::<String>::new()
+                                    .on_submit(|cursive, _: &String| {
+                                        cursive.call_on_name(
+                                            "Composed effects",
+                                            |list: &mut SelectView<String>| {
+                                                if let Some(index) = list.selected_id() {
+                                                    list.remove_item(index);
+                                                }
+                                            },
+                                        );
+                                    })
+                                    .with_name("Composed effects")
+                                    .scrollable(),
+                            )
+                            .title("Composed list"),
+                        )
+                        .child(
+                            LinearLayout::horizontal()
+                                .child(
+                                    EditView::new()
+                                        .on_submit(|cursive, item| {
+                                            add_composed(cursive, "manual", item);
+                                            cursive.call_on_name(
+                                                "Manual effect",
+                                                |edit: &mut EditView| edit.set_content(""),
+                                            );
+                                        })
+                                        .with_name("Manual effect")
+                                        .full_width(),
+                                )
+                                .child(Button::new("Add", |cursive| {
+                                    let item = cursive
+                                        .call_on_name("Manual effect", |edit: &mut EditView| {
+                                            let item = edit.get_content();
+                                            edit.set_content("");
+                                            item
+                                        })
+                                        .unwrap();
+                                    add_composed(cursive, "manual", &item);
+                                })),
+                        ),
+                )
+                .title(title)
+                .button("Clear", |cursive| {
+                    cursive.call_on_name("Composed effects", |list: &mut SelectView<String>| {
+                        list.clear()
+                    });
+                })
+                .button("Done", move |cursive| {
+                    let items = cursive
+                        .call_on_name("Composed effects", |list: &mut SelectView<String>| {
+                            list.iter().map(|(_, item)| item.clone()).collect()
+                        })
+                        .unwrap();
+                    cursive.pop_layer();
+                    let _ = sender.send(items);
+                })
+                .h_align(HAlign::Center),
+            );
+        });
+        Ok(receiver
+            .recv()
+            .expect("Sender was dropped without sending anything"))
+    }
+
+    fn can_review(&self) -> bool {
+        true
+    }
+
+    fn review_conflicts(&mut self, files: &[ConflictSummary]) -> Result<Review, Unresolved> {
+        let (sender, receiver) = bounded(0);
+        let files = files.to_vec();
+        crate::run_update(&mut self.0, move |cursive| {
+            let mods: Vec<String> = files
+                .iter()
+                .flat_map(|file| file.mods.iter().cloned())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+            let mut list = SelectView::new();
+            for file in files {
+                let label = format!(
+                    "{:>5}  {}  ({})",
+                    file.items,
+                    file.path.to_string_lossy(),
+                    file.mods.join(", ")
+                );
+                list.add_item(label, file);
+            }
+            let on_file = sender.clone();
+            let list = list.on_submit(move |cursive, file: &ConflictSummary| {
+                let actions = std::iter::once((
+                    "Resolve this file first".to_owned(),
+                    Review::First(file.path.clone()),
+                ))
+                .chain(file.mods.iter().map(|name| {
+                    (
+                        format!("Prefer {} everywhere in this file", name),
+                        Review::Prefer(file.path.clone(), name.clone()),
+                    )
+                }))
+                .collect();
+                let title = file.path.to_string_lossy().into_owned();
+                review_action(cursive, title, actions, on_file.clone());
+            });
+            let on_remove = sender.clone();
+            crate::push_screen(
+                cursive,
+                Dialog::around(
+                    LinearLayout::vertical()
+                        .child(TextView::new(
+                            "Conflicts left to resolve, by the number of conflicting items; select the file to decide on it",
+                        ))
+                        .child(Panel::new(list.scrollable())),
+                )
+                .title("Conflicts")
+                .button("Remove mod", move |cursive| {
+                    let actions = mods
+                        .iter()
+                        .map(|name| (name.clone(), Review::RemoveMod(name.clone())))
+                        .collect();
+                    let title = "Remove mod and merge the rest again".to_owned();
+                    review_action(cursive, title, actions, on_remove.clone());
+                })
+                .button("Start resolving", move |cursive| {
+                    cursive.pop_layer();
+                    let _ = sender.send(Review::Proceed);
+                })
+                .h_align(HAlign::Center),
+            );
+        });
+        Ok(receiver
+            .recv()
+            .expect("Sender was dropped without sending anything"))
+    }
+}
+
+/// Asks for the action on the conflicts reviewed; choosing one closes the review screen as well.
+fn review_action(
+    cursive: &mut Cursive,
+    title: String,
+    actions: Vec<(String, Review)>,
+    sender: Sender<Review>,
+) {
+    crate::push_screen(
+        cursive,
+        Dialog::around(SelectView::new().with_all(actions).on_submit(
+            move |cursive, review: &Review| {
+                cursive.pop_layer();
+                cursive.pop_layer();
+                let _ = sender.send(review.clone());
+            },
+        ))
+        .title(title)
+        .dismiss_button("Back"),
+    );
+}
+
+/// Appends the effect to the list being composed, marked with its source, unless it's already there.
+fn add_composed(cursive: &mut Cursive, source: &str, item: &str) {
+    let item = item.trim();
+    if item.is_empty() {
+        return;
+    }
+    cursive.call_on_name("Composed effects", |list: &mut SelectView<String>| {
+        if list.iter().all(|(_, chosen)| chosen != item) {
+            list.add_item(format!("{}  [{}]", item, source), item.to_owned());
+        }
+    });
+}
+
+#[derive(Copy, Clone)]
+enum OverwriteChoice {
+    Overwrite,
+    Retry,
+    Cancel,
+}
+
+/// Shows what the bundle changes in the vanilla game, asking whether to write it.
+fn review_preview(sink: &mut cursive::CbSink, preview: &BundlePreview) -> bool {
+    let (sender, receiver) = bounded(0);
+    let text = preview.render();
+    let on_cancel = sender.clone();
+    crate::run_update(sink, move |cursive| {
+        crate::push_screen(
+            cursive,
+            Dialog::around(Panel::new(TextView::new(text).scrollable()))
+                .title("Changes made by the bundle")
+                .button("Write bundle", move |cursive| {
+                    cursive.pop_layer();
+                    let _ = sender.send(true);
+                })
+                .button("Cancel", move |cursive| {
+                    cursive.pop_layer();
+                    let _ = on_cancel.send(false);
+                })
+                .h_align(HAlign::Center),
+        );
+    });
+    receiver
+        .recv()
+        .expect("Sender was dropped without sending anything")
+}
+
+/// Asks for the bundle name and directory, if they're not known yet, and deploys the bundle.
+fn deploy(
+    sink: &mut cursive::CbSink,
+    bundle: Bundle,
+    mods_root: &Path,
+    output: Option<Output>,
+) -> Result<Output, DeploymentError> {
+    // When the output is taken from the profile, we're rebuilding the existing bundle,
+    // so it is expected to be overwritten.
+    let rebuild = output.is_some();
+    let output = output.unwrap_or_else(|| ask_for_props(sink));
+    let mod_path = mods_root.join(&output.directory);
+
+    // This is possibly subject for TOCTOU attack, but in this case the user seems to have a problem somewhere else
+    if mod_path.exists() {
+        let choice = if rebuild {
+            OverwriteChoice::Overwrite
+        } else {
+            ask_for_overwrite(sink, &mod_path)
+        };
+        match choice {
+            OverwriteChoice::Overwrite => {
+                info!("Existing mod bundle will be overwritten");
+            }
+            OverwriteChoice::Cancel => return Err(DeploymentError::AlreadyExists),
+            OverwriteChoice::Retry => {
+                if mod_path.exists() {
+                    return Err(DeploymentError::AlreadyExists);
+                }
+            }
+        }
+    }
+    bundle.deploy(mods_root, &output)?;
+    Ok(output)
+}
+
+fn ask_for_props(sink: &mut cursive::CbSink) -> Output {
+    let (sender, receiver) = bounded(0);
+
+    crate::run_update(sink, move |cursive| {
+        crate::push_screen(
+            cursive,
+            Dialog::around(
+                LinearLayout::vertical()
+                    .child(
+                        Panel::new(
+                            EditView::new()
+                                .on_edit(|cursive, name, _| {
+                                    cursive.call_on_name("Mod directory", |edit: &mut EditView| {
+                                        edit.set_content(name.to_lowercase().replace(' ', "_"));
+                                    });
+                                })
+                                .content("Generated bundle")
+                                .with_name("Mod name")
+                                .full_width(),
+                        )
+                        .title("Mod name"),
+                    )
+                    .child(
+                        Panel::new(
+                            EditView::new()
+                                .content("generated_bundle")
+                                .with_name("Mod directory")
+                                .full_width(),
+                        )
+                        .title("Mod directory"),
+                    ),
+            )
+            .title("Deployment parameters")
+            .button("Clear", |cursive| {
+                let _ =
+                    cursive.call_on_name("Mod name", |view: &mut EditView| view.set_content(""));
+                let _ = cursive
+                    .call_on_name("Mod directory", |view: &mut EditView| view.set_content(""));
+            })
+            .button("Deploy!", move |cursive| {
+                let name = cursive
+                    .call_on_name("Mod name", |view: &mut EditView| view.get_content())
+                    .unwrap();
+                let dir = cursive
+                    .call_on_name("Mod directory", |view: &mut EditView| view.get_content())
+                    .unwrap();
+                sender
+                    .send(Output {
+                        name: name.to_string(),
+                        directory: dir.to_string(),
+                    })
+                    .unwrap();
+            }),
+        )
+    });
+
+    receiver
+        .recv()
+        .expect("Sender was dropped without sending anything")
+}
+
+fn send_choice(sender: &Sender<OverwriteChoice>, choice: OverwriteChoice) -> impl Fn(&mut Cursive) {
+    let sender = sender.clone();
+    move |cursive| {
+        cursive.pop_layer();
+        let _ = sender.send(choice);
+    }
+}
+
+fn ask_for_overwrite(sink: &mut cursive::CbSink, path: &Path) -> OverwriteChoice {
+    use OverwriteChoice::*;
+    let (sender, receiver) = bounded(0);
+    let path = path.to_owned();
+    crate::run_update(sink, move |cursive| {
+        crate::push_screen(
+            cursive,
+            Dialog::around(TextView::new(format!(
+                "Target directory {} already exists!
+Choose your action:
+- overwrite existing folder;
+- rename/move it manually and retry deploying (it will fail if folder still exists);
+- cancel mod bundling process entirely.",
+                path.to_string_lossy()
+            )))
+            .button("Overwrite", send_choice(&sender, Overwrite))
+            .button("Retry", send_choice(&sender, Retry))
+            .button("Cancel", send_choice(&sender, Cancel))
+            .h_align(HAlign::Center),
+        )
+    });
+
+    receiver
+        .recv()
+        .expect("Sender was dropped without sending anything")
+}
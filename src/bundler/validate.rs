@@ -0,0 +1,433 @@
+use super::{
+    diff::{DataNodeContent, DataTree},
+    structures::{is_strings_table, BuffsFile, GenericDarkestFile, StringsTable},
+};
+use log::*;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// Problem in the bundled data which doesn't stop the bundling, but might break something in game,
+/// e.g. the reference which points to nothing.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ValidationWarning {
+    pub path: PathBuf,
+    /// Place in the file, e.g. the entry and subkey holding the dangling reference; `-` for the whole file.
+    pub item: String,
+    pub message: String,
+    /// Mods which changed the file, i.e. the ones which could have introduced the problem.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mods: Vec<String>,
+}
+
+impl std::fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} [{}]: {}",
+            self.path.to_string_lossy(),
+            self.item,
+            self.message
+        )?;
+        if !self.mods.is_empty() {
+            write!(f, " (changed by {})", self.mods.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// File with the validation results, written into the bundle along with the full report.
+pub const REPORT_FILE: &str = "bundle_report.txt";
+
+/// Lists the warnings, one per line, for the [`REPORT_FILE`].
+pub fn render(warnings: &[ValidationWarning]) -> String {
+    let mut text = String::from(
+        "Possible problems in the merged data.\n\
+         The bundle is written regardless, since some references may point to the files the bundler doesn't read.\n\n",
+    );
+    if warnings.is_empty() {
+        text.push_str("none\n");
+    }
+    for warning in warnings {
+        text.push_str(&format!("{}\n", warning));
+    }
+    text
+}
+
+const SKILL_KEYS: &[&str] = &["combat_skill", "combat_move_skill", "riposte_skill"];
+
+/// Everything the data files can refer to, collected from the whole bundle.
+#[derive(Default)]
+struct Definitions {
+    effects: BTreeSet<String>,
+    buffs: BTreeSet<String>,
+    modes: BTreeSet<String>,
+    tags: BTreeSet<String>,
+    /// Ids of the localized strings, in any language.
+    strings: BTreeSet<String>,
+}
+
+fn unix_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Text files as the game would see them: the bundled version if there is one, vanilla otherwise.
+fn final_texts<'a>(original: &'a DataTree, bundle: &'a DataTree) -> BTreeMap<&'a Path, &'a str> {
+    original
+        .iter()
+        .filter(|(path, _)| !bundle.contains_key(*path))
+        .chain(bundle.iter())
+        .filter_map(|(path, node)| match node.content() {
+            DataNodeContent::Text(text) => Some((path.as_path(), text.as_str())),
+            DataNodeContent::Binary => None,
+        })
+        .collect()
+}
+
+fn first_values<'a>(
+    file: &'a GenericDarkestFile,
+    key: &'a str,
+    subkey: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    file.paths()
+        .filter(move |(path, _)| path.key == key && path.subkey == subkey)
+        .filter_map(|(_, value)| value.first().cloned())
+}
+
+/// Class of the hero defined by the file, if it is the hero's info file, e.g. `crusader` for `heroes/crusader/crusader.info.darkest`.
+fn hero_class(name: &str) -> Option<&str> {
+    let file = name.strip_prefix("heroes/")?.rsplit('/').next()?;
+    file.strip_suffix(".info.darkest")
+}
+
+/// Buffs referenced by every quirk in the quirks library, as `(quirk id, buff id)`.
+fn quirk_buffs(text: &str) -> Option<Vec<(String, String)>> {
+    let json: serde_json::Value = serde_json::from_str(text).ok()?;
+    let mut buffs = vec![];
+    for quirk in json.get("quirks")?.as_array()? {
+        let id = quirk.get("id").and_then(serde_json::Value::as_str)?;
+        let list = quirk.get("buffs").and_then(serde_json::Value::as_array);
+        for buff in list.into_iter().flatten() {
+            if let Some(buff) = buff.as_str() {
+                buffs.push((id.to_owned(), buff.to_owned()));
+            }
+        }
+    }
+    Some(buffs)
+}
+
+/// Checks that every effect, buff, mode and hero tag referenced by the bundled data is defined somewhere,
+/// that the bundled heroes and their skills have names in the strings tables,
+/// and that no buff is defined differently in several libraries.
+///
+/// Checks against an empty set of definitions are skipped, since this means that the definitions
+/// are not a part of the game data we have (e.g. the game itself was not extracted fully).
+pub fn validate(original: &DataTree, bundle: &DataTree) -> Vec<ValidationWarning> {
+    let texts = final_texts(original, bundle);
+    let mut defs = Definitions::default();
+    let mut darkest = vec![];
+    let mut buff_files = vec![];
+    let mut quirks = vec![];
+    for (path, text) in texts {
+        let name = unix_path(path);
+        if name.ends_with(".darkest") {
+            match GenericDarkestFile::parse(text) {
+                Ok(file) => {
+                    if name.ends_with(".effects.darkest") {
+                        defs.effects.extend(first_values(&file, "effect", "name"));
+                    }
+                    if name.ends_with(".info.darkest") {
+                        defs.modes.extend(first_values(&file, "mode", "id"));
+                        defs.tags.extend(first_values(&file, "tag", "id"));
+                    }
+                    darkest.push((path, file));
+                }
+                Err(err) => warn!("[validate] {:?}: failed to parse, skipping: {}", path, err),
+            }
+        } else if name.starts_with("shared/buffs/") && name.ends_with(".json") {
+            match BuffsFile::parse(text) {
+                Ok(file) => {
+                    defs.buffs.extend(file.ids().map(String::from));
+                    buff_files.push((path, file));
+                }
+                Err(err) => warn!(
+                    "[validate] {:?}: unexpected buffs library format: {}",
+                    path, err
+                ),
+            }
+        } else if is_strings_table(path) {
+            match StringsTable::parse(text) {
+                Ok(table) => defs.strings.extend(table.ids().map(String::from)),
+                Err(err) => warn!("[validate] {:?}: failed to parse, skipping: {}", path, err),
+            }
+        } else if name.starts_with("shared/quirk/") && name.ends_with(".json") {
+            match quirk_buffs(text) {
+                Some(buffs) => quirks.push((path, buffs)),
+                None => warn!("[validate] {:?}: unexpected quirks library format", path),
+            }
+        }
+    }
+    debug!(
+        "[validate] Found {} effects, {} buffs, {} modes, {} hero tags, {} strings",
+        defs.effects.len(),
+        defs.buffs.len(),
+        defs.modes.len(),
+        defs.tags.len(),
+        defs.strings.len()
+    );
+
+    let mut warnings = vec![];
+    for (path, file) in &darkest {
+        for (item, values) in file.paths() {
+            let subkey = item.subkey.as_str();
+            let checks: &[(&str, &BTreeSet<String>)] = if SKILL_KEYS.contains(&item.key.as_str()) {
+                match subkey {
+                    "effect" => &[("effect", &defs.effects)],
+                    "valid_modes" => &[("mode", &defs.modes)],
+                    // Either the mode or the effect applied in this mode.
+                    "valid_modes_and_effects" => {
+                        &[("mode", &defs.modes), ("effect", &defs.effects)]
+                    }
+                    _ => &[],
+                }
+            } else if item.key == "effect" && subkey == "riposte_effect" {
+                &[("effect", &defs.effects)]
+            } else if subkey == "buffs" || subkey.ends_with("_buffs") {
+                &[("buff", &defs.buffs)]
+            } else if item.key == "incompatible_party_member" && subkey == "hero_tag" {
+                &[("hero tag", &defs.tags)]
+            } else {
+                &[]
+            };
+            if checks.is_empty() || checks.iter().any(|(_, defined)| defined.is_empty()) {
+                continue;
+            }
+            for value in values {
+                if !checks.iter().any(|(_, defined)| defined.contains(value)) {
+                    let kind: Vec<_> = checks.iter().map(|(kind, _)| *kind).collect();
+                    warnings.push(ValidationWarning {
+                        path: path.to_path_buf(),
+                        item: item.to_string(),
+                        message: format!("unknown {} \"{}\"", kind.join(" or "), value),
+                        mods: vec![],
+                    });
+                }
+            }
+        }
+    }
+    // Vanilla heroes are named in the game's own tables, which we might not read, so only the bundled ones are checked.
+    if !defs.strings.is_empty() {
+        for (path, file) in &darkest {
+            let name = unix_path(path);
+            let class = match hero_class(&name) {
+                Some(class) if bundle.contains_key(*path) => class,
+                _ => continue,
+            };
+            let mut expected = vec![("-".to_owned(), format!("hero_class_name_{}", class))];
+            let mut skills = BTreeSet::new();
+            for (item, values) in file.paths() {
+                if item.key == "combat_skill" && item.subkey == "id" {
+                    if let Some(skill) = values.first().filter(|skill| skills.insert(*skill)) {
+                        expected.push((
+                            item.to_string(),
+                            format!("combat_skill_name_{}_{}", class, skill),
+                        ));
+                    }
+                }
+            }
+            for (item, id) in expected {
+                if !defs.strings.contains(&id) {
+                    warnings.push(ValidationWarning {
+                        path: path.to_path_buf(),
+                        item,
+                        message: format!("no string \"{}\" in any strings table", id),
+                        mods: vec![],
+                    });
+                }
+            }
+        }
+    }
+    if !defs.buffs.is_empty() {
+        for (path, buffs) in &quirks {
+            for (quirk, buff) in buffs {
+                if !defs.buffs.contains(buff) {
+                    warnings.push(ValidationWarning {
+                        path: path.to_path_buf(),
+                        item: format!("{} .buffs", quirk),
+                        message: format!("unknown buff \"{}\"", buff),
+                        mods: vec![],
+                    });
+                }
+            }
+        }
+    }
+    // The game keeps only one buff with the given id, so the other definitions silently stop working.
+    // Vanilla is trusted, so only the clashes involving the bundled files are reported.
+    let mut definitions = BTreeMap::new();
+    for (path, file) in &buff_files {
+        for (id, fields) in file.definitions() {
+            match definitions.get(id) {
+                None => {
+                    definitions.insert(id, (*path, fields));
+                }
+                Some((other, other_fields)) => {
+                    let bundled = bundle.contains_key(*path) || bundle.contains_key(*other);
+                    if bundled && *other_fields != fields {
+                        warnings.push(ValidationWarning {
+                            path: path.to_path_buf(),
+                            item: id.to_owned(),
+                            message: format!(
+                                "buff \"{}\" is already defined differently in {}",
+                                id,
+                                unix_path(other)
+                            ),
+                            mods: vec![],
+                        });
+                    }
+                }
+            }
+        }
+    }
+    for warning in &warnings {
+        warn!("[validate] {}", warning);
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::diff::DataNode;
+    use super::*;
+
+    fn tree(files: &[(&str, &str)]) -> DataTree {
+        files
+            .iter()
+            .map(|(path, text)| (PathBuf::from(path), DataNode::new(path, text.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn dangling_references() {
+        let original = tree(&[
+            (
+                "effects/base.effects.darkest",
+                "effect: .name \"Stun 1\" .target \"target\" .stun 1\n",
+            ),
+            (
+                "shared/buffs/base.buffs.json",
+                r#"{"buffs": [{"id": "CRUSADER_BUFF", "stat_type": "hp_heal_percent"}]}"#,
+            ),
+            (
+                "heroes/crusader/crusader.info.darkest",
+                "combat_skill: .id \"smite\" .effect \"Stun 1\"\n",
+            ),
+        ]);
+        assert_eq!(validate(&original, &DataTree::new()), vec![]);
+
+        let bundle = tree(&[(
+            "heroes/crusader/crusader.info.darkest",
+            "combat_skill: .id \"smite\" .effect \"Stun 1\" \"Stun 2\"\n\
+             deaths_door: .buffs CRUSADER_BUFF MISSING_BUFF\n",
+        )]);
+        assert_eq!(
+            validate(&original, &bundle),
+            vec![
+                ValidationWarning {
+                    path: "heroes/crusader/crusader.info.darkest".into(),
+                    item: "combat_skill: \"smite\" .effect".into(),
+                    message: "unknown effect \"Stun 2\"".into(),
+                    mods: vec![],
+                },
+                ValidationWarning {
+                    path: "heroes/crusader/crusader.info.darkest".into(),
+                    item: "deaths_door: #0 .buffs".into(),
+                    message: "unknown buff \"MISSING_BUFF\"".into(),
+                    mods: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn buff_clashes_and_quirks() {
+        let original = tree(&[
+            (
+                "shared/buffs/base.buffs.json",
+                r#"{"buffs": [{"id": "ZEAL", "amount": 1}]}"#,
+            ),
+            (
+                "shared/quirk/quirk_library.json",
+                r#"{"quirks": [{"id": "zealous", "buffs": ["ZEAL", "FERVOR"]}]}"#,
+            ),
+        ]);
+        let bundle = tree(&[
+            (
+                "shared/buffs/first.buffs.json",
+                r#"{"buffs": [{"id": "ZEAL", "amount": 1}, {"id": "RAGE", "amount": 2}]}"#,
+            ),
+            (
+                "shared/buffs/second.buffs.json",
+                r#"{"buffs": [{"id": "RAGE", "amount": 3}]}"#,
+            ),
+        ]);
+        assert_eq!(
+            validate(&original, &bundle),
+            vec![
+                ValidationWarning {
+                    path: "shared/quirk/quirk_library.json".into(),
+                    item: "zealous .buffs".into(),
+                    message: "unknown buff \"FERVOR\"".into(),
+                    mods: vec![],
+                },
+                ValidationWarning {
+                    path: "shared/buffs/second.buffs.json".into(),
+                    item: "RAGE".into(),
+                    message: "buff \"RAGE\" is already defined differently in shared/buffs/first.buffs.json".into(),
+                    mods: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_strings() {
+        let original = tree(&[
+            (
+                "localization/heroes.string_table.xml",
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<root>\n<language id=\"english\">\n\
+                 <entry id=\"hero_class_name_crusader\"><![CDATA[Crusader]]></entry>\n\
+                 <entry id=\"combat_skill_name_crusader_smite\"><![CDATA[Smite]]></entry>\n\
+                 </language>\n</root>\n",
+            ),
+            (
+                "heroes/crusader/crusader.info.darkest",
+                "combat_skill: .id \"smite\" .level 0\n",
+            ),
+            (
+                "heroes/vestal/vestal.info.darkest",
+                "combat_skill: .id \"judgement\"\n",
+            ),
+        ]);
+        assert_eq!(validate(&original, &DataTree::new()), vec![]);
+
+        let bundle = tree(&[(
+            "heroes/crusader/crusader.info.darkest",
+            "combat_skill: .id \"smite\" .level 0\n\
+             combat_skill: .id \"smite\" .level 1\n\
+             combat_skill: .id \"zealous_accusation\" .level 0\n",
+        )]);
+        let warnings = validate(&original, &bundle);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].message,
+            "no string \"combat_skill_name_crusader_zealous_accusation\" in any strings table"
+        );
+
+        let mut warning = warnings[0].clone();
+        warning.mods = vec!["Zealots".into()];
+        assert!(warning.to_string().ends_with("(changed by Zealots)"));
+        assert!(render(&[warning]).contains("combat_skill_name_crusader_zealous_accusation"));
+    }
+}
@@ -0,0 +1,472 @@
+//! Headless commands, which don't need the terminal UI.
+
+use crate::{
+    bundler::{
+        cache::DiffCache,
+        compare::{compare as compare_bundles, render as render_comparison},
+        diff::DiffTree,
+        encoding::decode,
+        error::{BundlerError, CompareError, DeploymentError},
+        resolve::OpenQuestion,
+        structures::{inspect as inspect_file, InspectError},
+    },
+    loader::{read_profile, read_updated_profile, LoadModsError},
+    profile::{Output, Profile, ProfileError},
+    Bundle,
+};
+use log::*;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error(transparent)]
+    Load(#[from] LoadModsError),
+    #[error(transparent)]
+    Bundle(#[from] BundlerError),
+    #[error(transparent)]
+    Deploy(#[from] DeploymentError),
+    #[error(transparent)]
+    Compare(#[from] CompareError),
+    #[error("Failed to update the profile")]
+    Profile(#[from] ProfileError),
+    #[error("Output path {0:?} must end with the bundle directory name")]
+    BadOutput(PathBuf),
+    #[error("Output directory {0:?} is not empty; use --force to replace it")]
+    NotEmpty(PathBuf),
+    #[error("{1} questions about the conflicts were left unanswered; they are listed with their options in {0:?}")]
+    Unanswered(PathBuf, usize),
+    #[error("Failed to read {0:?}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("Failed to write {0:?}")]
+    Write(PathBuf, #[source] std::io::Error),
+    #[error("Failed to inspect {0:?}")]
+    Inspect(PathBuf, #[source] InspectError),
+    #[cfg(feature = "ui")]
+    #[error("Failed to watch for changes")]
+    Watch(#[source] notify::Error),
+    #[cfg(feature = "ui")]
+    #[error("Stopped receiving the file changes")]
+    WatchStopped,
+}
+
+/// Builds the bundle from profile into the `out` directory, without asking anything.
+///
+/// Conflicts are resolved by the profile strategy and resolutions; if any conflict remains, nothing is written,
+/// and every question left unanswered is listed next to the profile (see [`unanswered_path`]).
+/// If `incremental` is set, the changes are cached next to the profile (see [`cache_path`]),
+/// and only the files changed since the previous run are compared with the game again.
+/// The existing non-empty `out` directory is replaced only if `options.force` is set.
+/// If the game files changed by the mods were updated since the profile was saved, the bundling fails,
+/// unless `options.accept_game_update` is set; then the profile is updated to the current game files.
+pub fn bundle(profile: &Path, out: &Path, options: &BundleOptions) -> Result<String, CliError> {
+    let (summary, warnings) = build(profile, out, options)?;
+    Ok(std::iter::once(summary)
+        .chain(
+            warnings
+                .into_iter()
+                .map(|warning| format!("warning: {}", warning)),
+        )
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Settings of the headless [`bundle`] and [`watch`] which are not stored in the profile.
+#[derive(Debug, Clone, Default)]
+pub struct BundleOptions {
+    pub incremental: bool,
+    pub force: bool,
+    /// Image to be copied as the preview of the bundle; a placeholder is generated if not set.
+    pub preview: Option<PathBuf>,
+    /// Proceed if the game files changed since the profile was saved, dropping the saved answers about them.
+    pub accept_game_update: bool,
+    /// Leave out the mod files which can't be parsed, instead of failing.
+    pub skip_broken: bool,
+    /// File to write the [preview](crate::bundler::preview::BundlePreview) of the changes made by the bundle into.
+    pub diff_out: Option<PathBuf>,
+}
+
+/// Where the incremental [`bundle`] keeps its cache for the given profile.
+pub fn cache_path(profile: &Path) -> PathBuf {
+    profile.with_extension("cache.json")
+}
+
+/// Where [`bundle`] lists the questions left unanswered by the given profile.
+pub fn unanswered_path(profile: &Path) -> PathBuf {
+    profile.with_extension("unanswered.json")
+}
+
+/// Lists the questions in the file next to the profile, so that the answers can be copied into its resolutions.
+fn write_unanswered(profile: &Path, questions: &[OpenQuestion]) -> CliError {
+    let path = unanswered_path(profile);
+    let text = serde_json::to_string_pretty(questions).expect("Questions are always serializable");
+    match std::fs::write(&path, text) {
+        Ok(()) => CliError::Unanswered(path, questions.len()),
+        Err(err) => CliError::Write(path, err),
+    }
+}
+
+/// Splits the output path into the mods directory and the bundle directory name.
+fn split_output(out: &Path) -> Result<(PathBuf, String), CliError> {
+    let directory = out
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| CliError::BadOutput(out.into()))?;
+    let mods_root = match out.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+        _ => PathBuf::from("."),
+    };
+    Ok((mods_root, directory))
+}
+
+/// Builds and deploys the bundle, returning the one-line summary and the warnings.
+fn build(
+    profile: &Path,
+    out: &Path,
+    options: &BundleOptions,
+) -> Result<(String, Vec<String>), CliError> {
+    let incremental = options.incremental;
+    let is_empty = |dir: &Path| std::fs::read_dir(dir).map_or(true, |mut dir| dir.next().is_none());
+    if !options.force && !is_empty(out) {
+        return Err(CliError::NotEmpty(out.into()));
+    }
+    // The incremental build is made exactly to pick up the mod updates, so they aren't an error here.
+    let global_data = if incremental {
+        read_updated_profile(profile)?
+    } else {
+        read_profile(profile)?
+    };
+    let (mods_root, directory) = split_output(out)?;
+    let output = Output {
+        name: global_data
+            .output
+            .map(|output| output.name)
+            .unwrap_or_else(|| directory.clone()),
+        directory,
+    };
+
+    // There's nobody to ask, so the game update is either accepted beforehand or fails the bundling.
+    let mut resolutions = global_data.resolutions;
+    let drift = if options.accept_game_update {
+        global_data
+            .baseline
+            .drift(global_data.game.path(), &resolutions)
+    } else {
+        vec![]
+    };
+    let invalidated: Vec<_> = drift
+        .iter()
+        .flat_map(|file| file.resolutions.iter().cloned())
+        .collect();
+    for question in &invalidated {
+        resolutions.remove(question);
+    }
+    let mut builder = Bundle::builder()
+        .game_root(global_data.game.path())
+        .mods(global_data.mods)
+        .strategy(global_data.strategy)
+        .resolutions(resolutions)
+        .languages(global_data.languages)
+        .rules(global_data.rules)
+        .value_rules(global_data.value_rules)
+        .preview_icon(options.preview.clone())
+        .skip_broken(options.skip_broken)
+        .collect_unanswered(true);
+    if !options.accept_game_update {
+        builder = builder.baseline(global_data.baseline);
+    }
+    if incremental {
+        builder = builder.cache(DiffCache::load(&cache_path(profile)));
+    }
+    let mut bundle = match builder.run() {
+        Err(BundlerError::Unanswered(questions)) => {
+            return Err(write_unanswered(profile, &questions))
+        }
+        result => result?,
+    };
+    // The questions listed by the previous run are answered now.
+    let _ = std::fs::remove_file(unanswered_path(profile));
+    let mut summary = format!(
+        "Bundled {} mods into {}: {} files, {} conflicts resolved",
+        bundle.report.mods.len(),
+        out.to_string_lossy(),
+        bundle.data.len(),
+        bundle.report.conflicts.len()
+    );
+    if incremental {
+        summary.push_str(&format!(
+            ", {} files reused from cache, {} compared",
+            bundle.cache_stats.reused, bundle.cache_stats.compared
+        ));
+    }
+    if !drift.is_empty() {
+        summary.push_str(&format!(
+            ", {} game files changed since the profile was saved",
+            drift.len()
+        ));
+        bundle.report.drift = drift;
+    }
+    if !bundle.report.skipped.is_empty() {
+        summary.push_str(&format!(
+            ", {} broken files skipped",
+            bundle.report.skipped.len()
+        ));
+    }
+    let skipped = bundle.report.skipped.iter().map(|file| {
+        format!(
+            "skipped {} in mod {}: {}",
+            file.path.to_string_lossy(),
+            file.mod_name,
+            file.reason
+        )
+    });
+    let warnings = skipped
+        .chain(bundle.report.warnings.iter().map(ToString::to_string))
+        .collect();
+    if let Some(path) = &options.diff_out {
+        std::fs::write(path, bundle.preview.render())
+            .map_err(|err| CliError::Write(path.clone(), err))?;
+    }
+    let cache = bundle.cache.take();
+    let baseline = Some(bundle.baseline.clone()).filter(|_| !bundle.report.drift.is_empty());
+    info!("Deploying bundle into {:?}", out);
+    bundle.deploy(&mods_root, &output)?;
+    if let Some(baseline) = baseline {
+        info!("Recording the updated game files in profile {:?}", profile);
+        let mut saved = Profile::load(profile)?;
+        saved.baseline = baseline;
+        for question in &invalidated {
+            saved.resolutions.remove(question);
+        }
+        saved.save(profile)?;
+    }
+    if let Some(cache) = cache {
+        let path = cache_path(profile);
+        cache
+            .save(&path)
+            .map_err(|err| CliError::Write(path, err))?;
+    }
+    Ok((summary, warnings))
+}
+
+/// Rebuilds the bundle incrementally every time the profile, the game or any of the mods changes.
+///
+/// Runs until interrupted, printing one line per rebuild; the failed rebuild is reported, but doesn't stop watching.
+/// The `options` are applied as is to the first build only: the later ones are always incremental
+/// and replace the bundle written before.
+#[cfg(feature = "ui")]
+pub fn watch(profile: &Path, out: &Path, options: BundleOptions) -> Result<(), CliError> {
+    use notify::{RecursiveMode, Watcher};
+
+    let (sender, events) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(CliError::Watch)?;
+    // The bundle and the cache are written on every rebuild, and they must not trigger the next one.
+    let (mods_root, directory) = split_output(out)?;
+    let mods_root = std::fs::canonicalize(&mods_root).unwrap_or(mods_root);
+    let mut ignored = vec![
+        mods_root.join(&directory),
+        mods_root.join(format!(".{}.partial", directory)),
+        std::fs::canonicalize(profile)
+            .map(|profile| cache_path(&profile))
+            .unwrap_or_else(|_| cache_path(profile)),
+    ];
+    // The preview is rewritten on every rebuild too; it may not exist yet, so only its directory is resolved.
+    if let Some(path) = &options.diff_out {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+            _ => PathBuf::from("."),
+        };
+        let dir = std::fs::canonicalize(&dir).unwrap_or(dir);
+        ignored.extend(path.file_name().map(|name| dir.join(name)));
+    }
+    let mut options = BundleOptions {
+        incremental: true,
+        ..options
+    };
+    loop {
+        // The profile is read again every time, so that the added and removed mods are watched too.
+        let watched = watched_paths(profile);
+        for path in &watched {
+            debug!("[watch] Watching {:?}", path);
+            watcher
+                .watch(path, RecursiveMode::Recursive)
+                .map_err(CliError::Watch)?;
+        }
+        let line = match build(profile, out, &options) {
+            Ok((summary, warnings)) => format!("{}, {} warnings", summary, warnings.len()),
+            Err(err) => format!("error: {}", err),
+        };
+        println!("[{}] {}", chrono::Local::now().format("%H:%M:%S"), line);
+        options.force = true;
+
+        wait_for_changes(&events, &ignored)?;
+        for path in &watched {
+            if let Err(err) = watcher.unwatch(path) {
+                debug!("[watch] Failed to unwatch {:?}: {}", path, err);
+            }
+        }
+    }
+}
+
+/// The profile itself, the game and every mod in it, as far as they exist.
+#[cfg(feature = "ui")]
+fn watched_paths(profile: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![profile.to_owned()];
+    match crate::profile::Profile::load(profile) {
+        Ok(loaded) => {
+            paths.push(loaded.game);
+            paths.extend(loaded.mods.into_iter().map(|the_mod| the_mod.path));
+        }
+        Err(err) => warn!("[watch] Failed to read profile, watching only it: {}", err),
+    }
+    paths
+        .into_iter()
+        .filter_map(|path| std::fs::canonicalize(path).ok())
+        .collect()
+}
+
+/// Blocks until something outside of the `ignored` paths changes,
+/// then waits for the changes to settle, so that the mod update causes only one rebuild.
+#[cfg(feature = "ui")]
+fn wait_for_changes(
+    events: &std::sync::mpsc::Receiver<notify::Result<notify::Event>>,
+    ignored: &[PathBuf],
+) -> Result<(), CliError> {
+    use std::time::Duration;
+
+    let relevant = |event: notify::Result<notify::Event>| match event {
+        Ok(event) => {
+            !event.kind.is_access()
+                && event
+                    .paths
+                    .iter()
+                    .any(|path| !ignored.iter().any(|ignored| path.starts_with(ignored)))
+        }
+        Err(err) => {
+            warn!("[watch] Error while watching: {}", err);
+            false
+        }
+    };
+    loop {
+        let event = events.recv().map_err(|_| CliError::WatchStopped)?;
+        if relevant(event) {
+            break;
+        }
+    }
+    loop {
+        match events.recv_timeout(Duration::from_millis(500)) {
+            Ok(_) => continue,
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => return Ok(()),
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(CliError::WatchStopped)
+            }
+        }
+    }
+}
+
+/// Escapes the value, so that every change takes exactly one line.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+/// Renders the changes in stable, tab-separated format: `<path> <item> <change> <value>`,
+/// one change per line, ordered by path and item; `-` stands for the empty field.
+pub fn render_diff(diff: &DiffTree) -> String {
+    let mut lines = vec![];
+    for (path, node) in diff {
+        let path = path.to_string_lossy().replace('\\', "/");
+        for entry in node.entries() {
+            let value = match (entry.change, entry.value) {
+                ("added", Some(text)) => format!("{} lines", text.lines().count()),
+                (_, Some(value)) => escape(&value),
+                (_, None) => "-".into(),
+            };
+            let item = entry.item.as_deref().unwrap_or("-");
+            lines.push(format!("{}\t{}\t{}\t{}", path, item, entry.change, value));
+        }
+    }
+    lines.join("\n")
+}
+
+pub fn diff(the_mod: &Path, game: &Path) -> Result<String, CliError> {
+    let diff = crate::bundler::diff_mod(game, the_mod)?;
+    Ok(render_diff(&diff))
+}
+
+/// Lists what differs between the `old` and the `new` bundle, one change per line, tab-separated:
+/// `<path> <item> <added|removed|changed> <mods>`, with the mods whose changes differ between the bundles.
+pub fn compare(old: &Path, new: &Path) -> Result<String, CliError> {
+    Ok(render_comparison(&compare_bundles(old, new)?))
+}
+
+/// Lists every value of the file, as the bundler sees it: `<item> <value>`, tab-separated.
+///
+/// The file is decoded the same way the bundler reads it, so that the UTF-16 strings tables are read too.
+pub fn inspect(file: &Path) -> Result<String, CliError> {
+    let bytes = std::fs::read(file).map_err(|err| CliError::Io(file.into(), err))?;
+    let xml = file.extension().and_then(std::ffi::OsStr::to_str) == Some("xml");
+    let decoded = decode(&bytes, xml);
+    if decoded.guessed {
+        warn!(
+            "{:?} is not valid UTF-8, decoded as {}",
+            file, decoded.encoding
+        );
+    }
+    let items =
+        inspect_file(file, &decoded.text).map_err(|err| CliError::Inspect(file.into(), err))?;
+    Ok(items
+        .into_iter()
+        .map(|(item, value)| format!("{}\t{}", item, escape(&value)))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::diff::{DataNode, DataTree, DataTreeExt};
+
+    fn tree(path: &str, text: &str) -> DataTree {
+        vec![(PathBuf::from(path), DataNode::new(path, text.to_string()))]
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn diff_format() {
+        let hero = "heroes/crusader/crusader.info.darkest";
+        let text = "dungeon/torch.txt";
+        let mut orig = tree(hero, "hp: .amount 33\nspeed: .amount 1 .note \"a b\"\n");
+        orig.extend(tree(text, "first\nsecond\nthird"));
+        let mut modded = tree(hero, "hp: .amount 40\nspeed: .note \"a b\"\n");
+        modded.extend(tree(text, "first\nchanged\tline\nthird"));
+        assert_eq!(
+            render_diff(&orig.diff(modded).unwrap()),
+            "dungeon/torch.txt\tline 2\treplaced\tchanged\\tline
+heroes/crusader/crusader.info.darkest\thp: #0 .amount\tset\t40
+heroes/crusader/crusader.info.darkest\tspeed: #0 .amount\tremoved\t-"
+        );
+    }
+
+    #[test]
+    fn inspect_decodes_like_bundler() {
+        let dir = std::env::temp_dir().join("ddmb_cli_inspect");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let hero = dir.join("hero.info.darkest");
+        std::fs::write(&hero, b"hp: .name \"Caf\xe9\"\n").unwrap();
+        assert_eq!(inspect(&hero).unwrap(), "hp: #0 .name\tCaf\u{e9}");
+
+        let table = dir.join("dialogue.string_table.xml");
+        let text = "<?xml version=\"1.0\" encoding=\"UTF-16\"?>\n<root>\n<language id=\"english\">\n<entry id=\"str_cafe\"><![CDATA[Caf\u{e9}]]></entry>\n</language>\n</root>\n";
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        std::fs::write(&table, bytes).unwrap();
+        assert!(inspect(&table).unwrap().ends_with("\tCaf\u{e9}"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
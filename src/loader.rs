@@ -1,32 +1,202 @@
-use cursive::Cursive;
+use crate::{
+    bundler::{baseline::Baseline, classify::ModSummary, rules::ValueRule},
+    paths::GameRoot,
+    profile::{MergeStrategy, Output, PreferRules, Profile, ProfileError, Resolutions, StaleMod},
+};
+#[cfg(feature = "ui")]
+use cursive::{views::EditView, Cursive};
 use log::*;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
 use thiserror::Error;
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone)]
-pub struct Project {
-    #[serde(rename = "Title")]
+struct ProjectTags {
+    #[serde(rename = "Tags", default)]
+    tags: Vec<String>,
+}
+
+/// Metadata of the mod, as it is stored in its `project.xml`.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct ModProject {
+    #[serde(rename = "Title", default)]
     pub title: String,
+    #[serde(rename = "Tags", default)]
+    tags: ProjectTags,
+    #[serde(rename = "VersionMajor", default)]
+    version_major: String,
+    #[serde(rename = "VersionMinor", default)]
+    version_minor: String,
+    #[serde(rename = "PreviewIconFile", default)]
+    preview_icon: String,
+}
+
+impl ModProject {
+    fn parse(reader: impl std::io::Read) -> Result<Self, serde_xml_rs::Error> {
+        serde_xml_rs::from_reader(reader)
+    }
+
+    /// Loads the `project.xml` from the mod directory.
+    ///
+    /// If it is missing or broken, or if it has no title, the directory name is used as the title.
+    pub fn load(dir: &Path) -> Self {
+        let path = dir.join("project.xml");
+        let project = match std::fs::File::open(&path) {
+            Ok(file) => Self::parse(file)
+                .map_err(|err| warn!("Broken project.xml in {:?}: {}", dir, err))
+                .unwrap_or_default(),
+            Err(err) => {
+                warn!("Failed to open {:?}: {}", path, err);
+                Self::default()
+            }
+        };
+        if project.title.trim().is_empty() {
+            let title = dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| dir.to_string_lossy().into_owned());
+            info!("Using directory name as the title of mod {:?}", dir);
+            Self { title, ..project }
+        } else {
+            project
+        }
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags.tags
+    }
+
+    /// Path to the preview image, if the mod has any.
+    pub fn preview(&self, dir: &Path) -> Option<PathBuf> {
+        match self.preview_icon.trim() {
+            "" => None,
+            icon => Some(dir.join(icon)),
+        }
+    }
+
+    pub fn version(&self) -> Option<String> {
+        match (self.version_major.trim(), self.version_minor.trim()) {
+            ("", "") => None,
+            (major, "") => Some(major.to_owned()),
+            (major, minor) => Some(format!("{}.{}", major, minor)),
+        }
+    }
 }
 
-#[derive(Default, Debug, Clone)]
+/// Where the mod was found.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModSource {
+    /// Subscribed through Steam Workshop.
+    Workshop,
+    /// Installed manually into the game's `mods` folder.
+    Local,
+}
+
+#[derive(Debug, Clone)]
 pub struct Mod {
     pub selected: bool,
     pub path: PathBuf,
-    project: Project,
+    pub source: ModSource,
+    project: ModProject,
 }
 impl Mod {
+    pub fn load(path: PathBuf, source: ModSource) -> Self {
+        let project = ModProject::load(&path);
+        info!(
+            "Loaded {:?} mod \"{}\" from directory {}",
+            source,
+            project.title,
+            path.to_string_lossy()
+        );
+        Mod {
+            selected: false,
+            path,
+            source,
+            project,
+        }
+    }
     pub fn name(&self) -> &str {
         &self.project.title
     }
+    /// Name to be shown in the mods lists, with the local mods being explicitly marked.
+    pub fn label(&self) -> String {
+        match self.source {
+            ModSource::Workshop => self.name().to_owned(),
+            ModSource::Local => format!("[local] {}", self.name()),
+        }
+    }
+    /// Multi-line description of the mod, shown when it is highlighted in the list.
+    pub fn details(&self) -> String {
+        let mut details = format!(
+            "{}\nDirectory: {}",
+            self.name(),
+            self.path.to_string_lossy()
+        );
+        if let Some(version) = self.project.version() {
+            details.push_str(&format!("\nVersion: {}", version));
+        }
+        if !self.project.tags().is_empty() {
+            details.push_str(&format!("\nTags: {}", self.project.tags().join(", ")));
+        }
+        if let Some(preview) = self.project.preview(&self.path) {
+            details.push_str(&format!("\nPreview: {}", preview.to_string_lossy()));
+        }
+        match ModSummary::scan(self.name(), &self.path) {
+            Ok(summary) => {
+                details.push_str(&format!("\nContents: {}", summary.describe()));
+                // The full list is in the bundle report; here, it's only a hint.
+                for path in summary.unrecognized.iter().take(5) {
+                    details.push_str(&format!("\n  unrecognized: {}", path.to_string_lossy()));
+                }
+                if summary.unrecognized.len() > 5 {
+                    let more = summary.unrecognized.len() - 5;
+                    details.push_str(&format!("\n  ...and {} more", more));
+                }
+            }
+            Err(err) => warn!("Failed to list files of mod {:?}: {}", self.path, err),
+        }
+        details
+    }
+    pub fn project(&self) -> &ModProject {
+        &self.project
+    }
 }
 
 pub struct GlobalData {
-    pub base_path: PathBuf,
+    pub game: GameRoot,
     pub mods: Vec<Mod>,
+    pub strategy: MergeStrategy,
+    /// Resolutions loaded from the profile, to be reused instead of asking the user.
+    pub resolutions: Resolutions,
+    pub output: Option<Output>,
+    /// Languages to keep in the bundled localization, `None` meaning all of them.
+    pub languages: Option<BTreeSet<String>>,
+    pub rules: PreferRules,
+    pub value_rules: Vec<ValueRule>,
+    /// Game files recorded in the profile, to be checked for changes before bundling.
+    pub baseline: Baseline,
 }
 
+impl GlobalData {
+    pub fn new(game: GameRoot, mods: Vec<Mod>) -> Self {
+        Self {
+            game,
+            mods,
+            strategy: MergeStrategy::default(),
+            resolutions: Resolutions::new(),
+            output: None,
+            languages: None,
+            rules: PreferRules::new(),
+            value_rules: vec![],
+            baseline: Baseline::default(),
+        }
+    }
+}
+
+#[cfg(feature = "ui")]
 pub fn mods_list(cursive: &mut Cursive) -> &mut [Mod] {
     &mut cursive
         .user_data::<GlobalData>()
@@ -35,54 +205,165 @@ pub fn mods_list(cursive: &mut Cursive) -> &mut [Mod] {
 }
 
 #[derive(Debug, Error)]
-enum LoadModsError {
+pub enum LoadModsError {
     #[error("Failed to load mods data due to IO error")]
     Io(#[from] std::io::Error),
-    #[error("Broken XML in mod directory {1}")]
-    XML(#[source] serde_xml_rs::Error, PathBuf),
+    #[error("Darkest Dungeon installation not found in {0}")]
+    NoGame(PathBuf),
+    #[error(transparent)]
+    Profile(#[from] ProfileError),
+}
+
+#[cfg(feature = "ui")]
+pub fn load_paths(cursive: &mut Cursive) {
+    let base_path = cursive
+        .call_on_name("Library path", |edit: &mut EditView| edit.get_content())
+        .unwrap();
+    let game_path = cursive
+        .call_on_name("Game path", |edit: &mut EditView| edit.get_content())
+        .unwrap();
+    load_path(cursive, &base_path, &game_path);
 }
 
-pub fn load_path(cursive: &mut Cursive, base_path: &str) {
+#[cfg(feature = "ui")]
+fn load_path(cursive: &mut Cursive, base_path: &str, game_path: &str) {
     info!("Loading Steam library from path: {}", base_path);
-    let base_path = base_path.into();
-    let path = crate::paths::workshop(&base_path);
-    let dir = match std::fs::read_dir(path) {
-        Ok(dir) => dir,
-        Err(error) => {
-            crate::error(cursive, &error);
+    let game = match GameRoot::new(game_path) {
+        Some(game) => game,
+        None => {
+            crate::error(cursive, &LoadModsError::NoGame(game_path.into()));
             return;
         }
     };
-    let mods = match dir
-        .map(|item| {
-            item.map_err(LoadModsError::Io).and_then(|entry| {
-                let path = entry.path();
-                let file = std::fs::File::open(path.join("project.xml"))?;
-                match serde_xml_rs::from_reader::<_, Project>(file) {
-                    Ok(project) => {
-                        info!(
-                            "Successfully parsed mod \"{}\" from directory {}",
-                            project.title,
-                            path.to_string_lossy()
-                        );
-                        Ok(Mod {
-                            selected: false,
-                            path,
-                            project,
-                        })
-                    }
-                    Err(error) => Err(LoadModsError::XML(error, path)),
-                }
-            })
-        })
-        .collect::<Result<Vec<_>, _>>()
-    {
+    info!("Using game installation from path: {}", game_path);
+    let workshop = discover(&crate::paths::workshop(base_path), ModSource::Workshop);
+    let local = discover(&game.mods(), ModSource::Local);
+    let mods = match workshop.and_then(|mut mods| {
+        mods.extend(local?);
+        Ok(mods)
+    }) {
         Ok(mods) => mods,
         Err(error) => {
             crate::error(cursive, &error);
             return;
         }
     };
-    cursive.set_user_data(GlobalData { base_path, mods });
+    cursive.set_user_data(GlobalData::new(game, mods));
     crate::select::render_lists(cursive);
 }
+
+#[cfg(feature = "ui")]
+/// Loads everything from the profile and starts bundling immediately.
+pub fn load_profile(cursive: &mut Cursive, path: &Path) {
+    match read_profile(path) {
+        Ok(global_data) => crate::bundler::start(cursive, global_data),
+        Err(error) => crate::error(cursive, &error),
+    }
+}
+
+/// Loads the profile, checking that the game and every mod in it are still usable.
+pub fn read_profile(path: &Path) -> Result<GlobalData, LoadModsError> {
+    read_profile_with(path, false)
+}
+
+/// Same as [`read_profile`], but accepts the mods changed since the profile was saved;
+/// only the missing ones are an error. Used when rebuilding the bundle after the mods update.
+pub fn read_updated_profile(path: &Path) -> Result<GlobalData, LoadModsError> {
+    read_profile_with(path, true)
+}
+
+fn read_profile_with(path: &Path, allow_changed: bool) -> Result<GlobalData, LoadModsError> {
+    let profile = Profile::load(path)?;
+    let game =
+        GameRoot::new(&profile.game).ok_or_else(|| LoadModsError::NoGame(profile.game.clone()))?;
+    let stale: Vec<_> = profile
+        .stale()
+        .into_iter()
+        .filter(|stale| match stale {
+            StaleMod::Changed(path) if allow_changed => {
+                info!("Mod {:?} changed since the profile was saved", path);
+                false
+            }
+            _ => true,
+        })
+        .collect();
+    if !stale.is_empty() {
+        return Err(ProfileError::Stale(stale).into());
+    }
+    let mods = profile
+        .mods
+        .into_iter()
+        .map(|the_mod| Mod {
+            selected: true,
+            ..Mod::load(the_mod.path, the_mod.source)
+        })
+        .collect();
+    Ok(GlobalData {
+        strategy: profile.strategy,
+        resolutions: profile.resolutions,
+        output: profile.output,
+        languages: profile.languages,
+        rules: profile.rules,
+        value_rules: profile.value_rules,
+        baseline: profile.baseline,
+        ..GlobalData::new(game, mods)
+    })
+}
+
+/// Lists every mod in the directory.
+///
+/// Missing directory is not an error, since the user might have no mods of this kind.
+pub fn discover(dir: &Path, source: ModSource) -> Result<Vec<Mod>, LoadModsError> {
+    if !dir.is_dir() {
+        info!(
+            "No {:?} mods found: directory {:?} doesn't exist",
+            source, dir
+        );
+        return Ok(vec![]);
+    }
+    std::fs::read_dir(dir)?
+        .filter_map(|item| match item {
+            Ok(entry) if !entry.path().is_dir() => None,
+            item => Some(item),
+        })
+        .map(|item| {
+            item.map_err(LoadModsError::Io)
+                .map(|entry| Mod::load(entry.path(), source))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_project() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<project>
+    <Title>Better Crusader</Title>
+    <Language>english</Language>
+    <VersionMajor>1</VersionMajor>
+    <VersionMinor>4</VersionMinor>
+    <Tags>
+        <Tags>Gameplay Tweaks</Tags>
+        <Tags>Classes</Tags>
+    </Tags>
+</project>"#;
+        let project = ModProject::parse(xml.as_bytes()).unwrap();
+        assert_eq!(project.title, "Better Crusader");
+        assert_eq!(project.tags(), &["Gameplay Tweaks", "Classes"]);
+        assert_eq!(project.version().as_deref(), Some("1.4"));
+    }
+
+    #[test]
+    fn fallback_to_directory_name() {
+        let dir = std::env::temp_dir().join("ddmb_1234567890");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("project.xml"), "<project><Title>Broken").unwrap();
+        assert_eq!(ModProject::load(&dir).title, "ddmb_1234567890");
+        std::fs::remove_file(dir.join("project.xml")).unwrap();
+        assert_eq!(ModProject::load(&dir).title, "ddmb_1234567890");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
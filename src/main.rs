@@ -1,11 +1,79 @@
+use clap::{Parser, Subcommand};
+use darkest_dungeon_mod_bundler::cli;
 use log::LevelFilter;
 use simplelog::{ConfigBuilder, WriteLogger};
-use std::fs::File;
+use std::{error::Error, fs::File, path::PathBuf};
+
+/// Merges several Darkest Dungeon mods into one.
+///
+/// Without the subcommand, starts the interactive UI.
+#[derive(Parser)]
+#[command(name = "ddmb", version)]
+struct Args {
+    /// Collect the full logs into the `log` file, not only the errors.
+    #[arg(long, global = true)]
+    debug: bool,
+    /// Rebuild the bundle from profile, asking only about the conflicts not resolved in it.
+    #[arg(long)]
+    profile: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build the bundle from profile without any questions; fails if any conflict is left unresolved.
+    Bundle {
+        #[arg(long)]
+        profile: PathBuf,
+        /// Directory to write the bundle into.
+        #[arg(long)]
+        out: PathBuf,
+        /// Replace the output directory even if it is not empty.
+        #[arg(long)]
+        force: bool,
+        /// Image to use as the bundle preview in game; a placeholder is generated by default.
+        #[arg(long)]
+        preview: Option<PathBuf>,
+        /// Reuse the results of the previous run for the unchanged files, keeping them next to the profile.
+        #[arg(long)]
+        incremental: bool,
+        /// Proceed even if the game files changed since the profile was saved, recording them in the profile;
+        /// the saved answers about these files are dropped.
+        #[arg(long)]
+        accept_game_update: bool,
+        /// Leave out the mod files which can't be parsed (e.g. half-downloaded ones), listing them in the report,
+        /// instead of failing.
+        #[arg(long)]
+        skip_broken: bool,
+        /// Keep running, rebuilding the bundle every time the game or any of the mods changes.
+        #[arg(long)]
+        watch: bool,
+        /// Write what the bundle changes in the vanilla game into this file, value by value,
+        /// along with the mods responsible for every change.
+        #[arg(long)]
+        diff_out: Option<PathBuf>,
+    },
+    /// Print the changes the mod makes to the vanilla game, one per line, tab-separated.
+    Diff {
+        #[arg(value_name = "MOD")]
+        the_mod: PathBuf,
+        /// Darkest Dungeon installation directory.
+        #[arg(long)]
+        game: PathBuf,
+    },
+    /// Print what differs between two deployed bundles and which mods are responsible for it, tab-separated.
+    Compare { old: PathBuf, new: PathBuf },
+    /// Print every value of the .darkest or .json file, as the bundler sees it.
+    Inspect { file: PathBuf },
+}
 
 fn main() {
-    let log_level = match std::env::args().nth(1).as_deref() {
-        Some("--debug") => LevelFilter::Debug,
-        _ => LevelFilter::Error,
+    let args = Args::parse();
+    let log_level = if args.debug {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Error
     };
 
     WriteLogger::init(
@@ -20,5 +88,51 @@ fn main() {
         File::create("log").unwrap(),
     )
     .unwrap();
-    darkest_dungeon_mod_bundler::run();
+
+    let result = match args.command {
+        None => {
+            darkest_dungeon_mod_bundler::run(args.profile);
+            return;
+        }
+        Some(Command::Bundle {
+            profile,
+            out,
+            force,
+            preview,
+            incremental,
+            accept_game_update,
+            skip_broken,
+            watch,
+            diff_out,
+        }) => {
+            let options = cli::BundleOptions {
+                incremental,
+                force,
+                preview,
+                accept_game_update,
+                skip_broken,
+                diff_out,
+            };
+            if watch {
+                cli::watch(&profile, &out, options).map(|()| String::new())
+            } else {
+                cli::bundle(&profile, &out, &options)
+            }
+        }
+        Some(Command::Diff { the_mod, game }) => cli::diff(&the_mod, &game),
+        Some(Command::Compare { old, new }) => cli::compare(&old, &new),
+        Some(Command::Inspect { file }) => cli::inspect(&file),
+    };
+    match result {
+        Ok(output) => println!("{}", output),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            let mut source = err.source();
+            while let Some(err) = source {
+                eprintln!("  caused by: {}", err);
+                source = err.source();
+            }
+            std::process::exit(1);
+        }
+    }
 }
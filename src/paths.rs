@@ -1,3 +1,4 @@
+use log::*;
 use std::path::{Path, PathBuf};
 
 pub fn workshop(base: impl AsRef<Path>) -> PathBuf {
@@ -7,3 +8,134 @@ pub fn workshop(base: impl AsRef<Path>) -> PathBuf {
 pub fn game(base: impl AsRef<Path>) -> PathBuf {
     base.as_ref().join("steamapps/common/DarkestDungeon")
 }
+
+/// Installation directory of Darkest Dungeon, which holds the vanilla data used as the diff baseline.
+#[derive(Clone, Debug)]
+pub struct GameRoot(PathBuf);
+
+impl GameRoot {
+    /// Checks that the path looks like the game installation (i.e. has the vanilla data in it).
+    pub fn new(path: impl Into<PathBuf>) -> Option<Self> {
+        let path = path.into();
+        if path.join("heroes").is_dir() {
+            Some(Self(path))
+        } else {
+            None
+        }
+    }
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+    pub fn dlc(&self) -> PathBuf {
+        self.0.join("dlc")
+    }
+    pub fn mods(&self) -> PathBuf {
+        self.0.join("mods")
+    }
+}
+
+fn home() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// Default locations of the Steam installation on the supported platforms.
+fn steam_roots() -> Vec<PathBuf> {
+    let mut roots = vec![
+        PathBuf::from(r"C:\Program Files (x86)\Steam"),
+        PathBuf::from(r"C:\Program Files\Steam"),
+    ];
+    if let Some(home) = home() {
+        roots.extend(
+            [
+                ".steam/steam",
+                ".local/share/Steam",
+                ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+                "Library/Application Support/Steam",
+            ]
+            .iter()
+            .map(|path| home.join(path)),
+        );
+    }
+    roots
+}
+
+/// Extracts the library paths from the `libraryfolders.vdf`.
+///
+/// Both the old format (`"1" "D:\\SteamLibrary"`) and the new one (`"path" "D:\\SteamLibrary"`) are supported.
+fn parse_library_folders(vdf: &str) -> Vec<PathBuf> {
+    vdf.lines()
+        .filter_map(|line| {
+            let tokens: Vec<_> = line.split('"').skip(1).step_by(2).collect();
+            match tokens.as_slice() {
+                [key, value]
+                    if (*key == "path" || key.chars().all(|c| c.is_ascii_digit()))
+                        && !value.is_empty()
+                        && !value.chars().all(|c| c.is_ascii_digit()) =>
+                {
+                    Some(PathBuf::from(value.replace(r"\\", r"\")))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Lists every Steam library we can find on this machine, in order of preference.
+pub fn detect_libraries() -> Vec<PathBuf> {
+    let mut libraries = vec![];
+    for root in steam_roots().into_iter().filter(|root| root.is_dir()) {
+        debug!("Found Steam installation at {:?}", root);
+        let vdf = root.join("steamapps/libraryfolders.vdf");
+        let extra = std::fs::read_to_string(&vdf)
+            .map(|vdf| parse_library_folders(&vdf))
+            .unwrap_or_default();
+        for library in std::iter::once(root).chain(extra) {
+            if !libraries.contains(&library) {
+                libraries.push(library);
+            }
+        }
+    }
+    libraries
+}
+
+/// Finds the first Steam library with Darkest Dungeon installed.
+pub fn detect_library() -> Option<PathBuf> {
+    let library = detect_libraries()
+        .into_iter()
+        .find(|library| GameRoot::new(game(library)).is_some());
+    info!("Auto-detected Steam library: {:?}", library);
+    library
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn library_folders() {
+        let vdf = r#"
+"libraryfolders"
+{
+	"contentstatsid"		"123456"
+	"1"		"D:\\OldLibrary"
+	"2"
+	{
+		"path"		"/home/user/SteamLibrary"
+		"apps"
+		{
+			"262060"		"2214515290"
+		}
+	}
+}
+"#;
+        assert_eq!(
+            parse_library_folders(vdf),
+            vec![
+                PathBuf::from(r"D:\OldLibrary"),
+                PathBuf::from("/home/user/SteamLibrary")
+            ]
+        );
+    }
+}
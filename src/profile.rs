@@ -0,0 +1,388 @@
+use crate::{
+    bundler::{baseline::Baseline, rules::ValueRule},
+    loader::{Mod, ModSource},
+    paths::GameRoot,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// How the conflicts between mods are resolved.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Ask the user about every conflict which wasn't resolved before.
+    #[default]
+    Ask,
+    /// Silently use the changes from the mod with the highest priority.
+    Priority,
+}
+
+/// Answers given to the conflict resolution questions, keyed by the question text.
+pub type Resolutions = BTreeMap<String, String>;
+
+/// Pairwise rules "always prefer this mod over that one", keyed by the mod names.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct PreferRules(BTreeSet<(String, String)>);
+
+impl PreferRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn prefer(&mut self, preferred: &str, other: &str) {
+        self.0.insert((preferred.to_owned(), other.to_owned()));
+    }
+
+    /// Checks whether the first mod is preferred over the second one, directly or through other mods.
+    fn prefers(&self, preferred: &str, other: &str) -> bool {
+        let mut seen = BTreeSet::new();
+        let mut queue = vec![preferred];
+        while let Some(current) = queue.pop() {
+            for (_, next) in self.0.iter().filter(|(from, _)| from == current) {
+                if next == other {
+                    return true;
+                }
+                if seen.insert(next.as_str()) {
+                    queue.push(next);
+                }
+            }
+        }
+        false
+    }
+
+    /// Finds the mod which should win the conflict between the given ones.
+    ///
+    /// Returns `None` unless the rules order every pair of mods, without contradictions.
+    pub fn winner(&self, mods: &[String]) -> Option<usize> {
+        let mut winner = None;
+        for (index, the_mod) in mods.iter().enumerate() {
+            let wins_all = mods.iter().enumerate().all(|(other_index, other)| {
+                other_index == index
+                    || (self.prefers(the_mod, other) && !self.prefers(other, the_mod))
+            });
+            if wins_all {
+                winner = Some(index);
+            }
+        }
+        let winner = winner?;
+        // The winner alone is not enough: the rest of mods must be ordered too.
+        let ordered = mods.iter().enumerate().all(|(i, first)| {
+            mods.iter().enumerate().all(|(j, second)| {
+                i == j || self.prefers(first, second) != self.prefers(second, first)
+            })
+        });
+        Some(winner).filter(|_| ordered)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Where the bundle is deployed to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Output {
+    pub name: String,
+    pub directory: String,
+}
+
+/// Mod included into the profile.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProfileMod {
+    pub path: PathBuf,
+    pub source: ModSource,
+    /// Hash of the mod content at the moment the profile was saved.
+    pub hash: String,
+}
+
+impl ProfileMod {
+    pub fn new(the_mod: &Mod) -> Result<Self, ProfileError> {
+        Ok(Self {
+            path: the_mod.path.clone(),
+            source: the_mod.source,
+            hash: content_hash(&the_mod.path)
+                .map_err(|err| ProfileError::Io(the_mod.path.clone(), err))?,
+        })
+    }
+}
+
+/// Saved bundle configuration, which can be used to rebuild the bundle later.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub game: PathBuf,
+    /// Selected mods, in order of their priority (the last one wins).
+    pub mods: Vec<ProfileMod>,
+    #[serde(default)]
+    pub strategy: MergeStrategy,
+    #[serde(default)]
+    pub resolutions: Resolutions,
+    #[serde(default)]
+    pub output: Option<Output>,
+    /// Languages to keep in the bundled localization; all of them, if not set.
+    #[serde(default)]
+    pub languages: Option<BTreeSet<String>>,
+    #[serde(default, skip_serializing_if = "PreferRules::is_empty")]
+    pub rules: PreferRules,
+    /// Rules deciding the conflicting values automatically, consulted before anything else; the first matching one wins.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub value_rules: Vec<ValueRule>,
+    /// Game files changed by the mods when the bundle was made, to notice the game updates.
+    #[serde(default, skip_serializing_if = "Baseline::is_empty")]
+    pub baseline: Baseline,
+}
+
+/// Mod referenced by the profile, which can't be used as is.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StaleMod {
+    Missing(PathBuf),
+    Changed(PathBuf),
+}
+
+impl std::fmt::Display for StaleMod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(path) => write!(f, "{} (not found)", path.to_string_lossy()),
+            Self::Changed(path) => write!(f, "{} (content changed)", path.to_string_lossy()),
+        }
+    }
+}
+
+fn render_stale(stale: &[StaleMod]) -> String {
+    stale
+        .iter()
+        .map(|the_mod| format!("\n  {}", the_mod))
+        .collect()
+}
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error("Failed to access {0:?}")]
+    Io(PathBuf, #[source] std::io::Error),
+    #[error("Profile file {0:?} is malformed")]
+    Format(PathBuf, #[source] serde_json::Error),
+    #[error("Some mods referenced by the profile are stale:{}", render_stale(.0))]
+    Stale(Vec<StaleMod>),
+}
+
+impl Profile {
+    /// Creates the profile for the currently selected mods, calculating their hashes.
+    pub fn new(
+        game: &GameRoot,
+        mods: &[Mod],
+        strategy: MergeStrategy,
+        resolutions: Resolutions,
+        output: Option<Output>,
+        languages: Option<BTreeSet<String>>,
+        rules: PreferRules,
+    ) -> Result<Self, ProfileError> {
+        Ok(Self {
+            game: game.path().to_owned(),
+            mods: mods.iter().map(ProfileMod::new).collect::<Result<_, _>>()?,
+            strategy,
+            resolutions,
+            output,
+            languages,
+            rules,
+            value_rules: vec![],
+            baseline: Baseline::default(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ProfileError> {
+        info!("Loading profile from {:?}", path);
+        let text =
+            std::fs::read_to_string(path).map_err(|err| ProfileError::Io(path.into(), err))?;
+        serde_json::from_str(&text).map_err(|err| ProfileError::Format(path.into(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ProfileError> {
+        info!("Saving profile to {:?}", path);
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|err| ProfileError::Format(path.into(), err))?;
+        std::fs::write(path, text).map_err(|err| ProfileError::Io(path.into(), err))
+    }
+
+    /// Lists the mods which are either missing or were changed since the profile was saved.
+    pub fn stale(&self) -> Vec<StaleMod> {
+        self.mods
+            .iter()
+            .filter_map(|the_mod| {
+                if !the_mod.path.is_dir() {
+                    warn!("Mod from profile not found: {:?}", the_mod.path);
+                    return Some(StaleMod::Missing(the_mod.path.clone()));
+                }
+                match content_hash(&the_mod.path) {
+                    Ok(hash) if hash == the_mod.hash => None,
+                    Ok(hash) => {
+                        warn!(
+                            "Mod {:?} changed: hash was {}, now {}",
+                            the_mod.path, the_mod.hash, hash
+                        );
+                        Some(StaleMod::Changed(the_mod.path.clone()))
+                    }
+                    Err(err) => {
+                        warn!("Failed to hash mod {:?}: {}", the_mod.path, err);
+                        Some(StaleMod::Missing(the_mod.path.clone()))
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// FNV-1a, used instead of the std hasher since the result must be stable between program versions.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Stable fingerprint of the data: its hash along with its length.
+pub(crate) fn fingerprint(bytes: &[u8]) -> String {
+    let mut hasher = Fnv::new();
+    hasher.write(bytes);
+    format!("{:016x}-{}", hasher.0, bytes.len())
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Hash of every file in the mod directory, along with their relative paths.
+pub fn content_hash(dir: &Path) -> std::io::Result<String> {
+    let mut files = vec![];
+    collect_files(dir, &mut files)?;
+    files.sort();
+    let mut hasher = Fnv::new();
+    for file in files {
+        let relative = file.strip_prefix(dir).unwrap_or(&file);
+        hasher.write(relative.to_string_lossy().replace('\\', "/").as_bytes());
+        hasher.write(&[0]);
+        hasher.write(&std::fs::read(&file)?);
+        hasher.write(&[0]);
+    }
+    Ok(format!("{:016x}", hasher.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join("ddmb_profile_roundtrip.json");
+        let profile = Profile {
+            game: "DarkestDungeon".into(),
+            mods: vec![ProfileMod {
+                path: "workshop/123".into(),
+                source: ModSource::Workshop,
+                hash: "0123456789abcdef".into(),
+            }],
+            strategy: MergeStrategy::Priority,
+            resolutions: vec![("Which one?".to_string(), "First".to_string())]
+                .into_iter()
+                .collect(),
+            output: Some(Output {
+                name: "Bundle".into(),
+                directory: "bundle".into(),
+            }),
+            languages: Some(vec!["english".to_string()].into_iter().collect()),
+            rules: {
+                let mut rules = PreferRules::new();
+                rules.prefer("Rebalance", "QoL");
+                rules
+            },
+            value_rules: vec![ValueRule {
+                path: "localization/**".into(),
+                strategy: crate::bundler::rules::ValueStrategy::PreferMod("QoL".into()),
+            }],
+            baseline: Baseline(
+                vec![(
+                    PathBuf::from("heroes/crusader/crusader.info.darkest"),
+                    crate::bundler::baseline::BaselineFile {
+                        fingerprint: fingerprint(b"hp: .amount 33"),
+                        mods: vec!["Rebalance".into()],
+                    },
+                )]
+                .into_iter()
+                .collect(),
+            ),
+        };
+        profile.save(&path).unwrap();
+        assert_eq!(Profile::load(&path).unwrap(), profile);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn prefer_rules() {
+        let mods =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|s| s.to_string()).collect() };
+        let mut rules = PreferRules::new();
+        rules.prefer("A", "B");
+        assert_eq!(rules.winner(&mods(&["B", "A"])), Some(1));
+        // C is not ordered against A and B.
+        assert_eq!(rules.winner(&mods(&["A", "B", "C"])), None);
+        rules.prefer("B", "C");
+        assert_eq!(rules.winner(&mods(&["C", "B", "A"])), Some(2));
+        // Contradicting rules don't let anyone win.
+        rules.prefer("C", "A");
+        assert_eq!(rules.winner(&mods(&["A", "B", "C"])), None);
+        assert_eq!(rules.winner(&mods(&["A", "B"])), None);
+    }
+
+    #[test]
+    fn stale_mods() {
+        let dir = std::env::temp_dir().join("ddmb_profile_stale");
+        std::fs::create_dir_all(dir.join("heroes")).unwrap();
+        std::fs::write(dir.join("heroes/hero.info.darkest"), "hp: .amount 10").unwrap();
+        let hash = content_hash(&dir).unwrap();
+        let profile = Profile {
+            mods: vec![
+                ProfileMod {
+                    path: dir.clone(),
+                    source: ModSource::Local,
+                    hash,
+                },
+                ProfileMod {
+                    path: dir.join("missing"),
+                    source: ModSource::Local,
+                    hash: String::new(),
+                },
+            ],
+            ..Profile::default()
+        };
+        assert_eq!(
+            profile.stale(),
+            vec![StaleMod::Missing(dir.join("missing"))]
+        );
+        std::fs::write(dir.join("heroes/hero.info.darkest"), "hp: .amount 20").unwrap();
+        assert_eq!(
+            profile.stale(),
+            vec![
+                StaleMod::Changed(dir.clone()),
+                StaleMod::Missing(dir.join("missing"))
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,11 +1,18 @@
-use crate::loader::{mods_list, Mod};
+use crate::{
+    loader::{mods_list, GlobalData, Mod},
+    profile::{MergeStrategy, Profile},
+};
 use cursive::{
     traits::{Finder, Nameable, Resizable, Scrollable},
     view::ViewWrapper,
-    views::{Dialog, LinearLayout, Panel, SelectView},
+    views::{Checkbox, Dialog, EditView, LinearLayout, Panel, SelectView, TextView},
     Cursive, Vec2, View,
 };
 use log::*;
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 struct Half<V: View>(V);
 
@@ -40,16 +47,21 @@ pub fn render_lists(cursive: &mut Cursive) {
                 the_mod.name(),
                 the_mod.path.to_string_lossy()
             );
-            (the_mod.name().to_owned(), the_mod)
+            (the_mod.label(), the_mod)
         }))
+        .on_select(show_details)
         .on_submit(do_select)
         .with_name("Available")
         .scrollable();
     available.get_inner_mut().get_mut().sort_by_label();
     let selected = SelectView::<Mod>::new()
+        .on_select(show_details)
         .on_submit(do_deselect)
         .with_name("Selected")
         .scrollable();
+    let filter = EditView::new()
+        .on_edit(|cursive, _, _| refresh_available(cursive))
+        .with_name("Filter");
 
     info!("Rendering lists of available and selected mods for the first time");
     crate::screen(
@@ -57,17 +69,225 @@ pub fn render_lists(cursive: &mut Cursive) {
         Dialog::new()
             .title("Select mods from the list to be bundled")
             .content(
-                LinearLayout::horizontal()
-                    .child(Half(Panel::new(available).title("Available")))
-                    .child(Half(Panel::new(selected).title("Selected"))),
+                LinearLayout::vertical()
+                    .child(
+                        LinearLayout::horizontal()
+                            .child(Half(
+                                LinearLayout::vertical()
+                                    .child(Panel::new(filter).title("Filter"))
+                                    .child(Panel::new(available).title("Available")),
+                            ))
+                            .child(Half(
+                                Panel::new(selected).title("Selected (later ones take priority)"),
+                            )),
+                    )
+                    .child(Panel::new(TextView::new("").with_name("Mod details")).title("Details"))
+                    .child(
+                        LinearLayout::horizontal()
+                            .child(Checkbox::new().with_name("Priority strategy"))
+                            .child(TextView::new(
+                                " Resolve all conflicts by mod priority, without asking",
+                            )),
+                    ),
             )
             .button("Make bundle!", crate::bundler::bundle)
+            .button("Save profile as...", |cursive| {
+                ask_for_path(cursive, "Save profile to file", |cursive, path| {
+                    save_selection(cursive, &path)
+                })
+            })
+            .button("Load profile", |cursive| {
+                ask_for_path(cursive, "Load profile from file", load_selection)
+            })
             .h_align(cursive::align::HAlign::Center)
             .with_name("Mods selection")
             .full_screen(),
     );
 }
 
+fn show_details(cursive: &mut Cursive, item: &Mod) {
+    let details = item.details();
+    cursive.call_on_name("Mod details", |text: &mut TextView| {
+        text.set_content(details)
+    });
+}
+
+/// Fills the "available" list with every unselected mod matching the filter.
+fn refresh_available(cursive: &mut Cursive) {
+    let filter = cursive
+        .call_on_name("Filter", |edit: &mut EditView| edit.get_content())
+        .map(|filter| filter.trim().to_lowercase())
+        .unwrap_or_default();
+    let mods: Vec<_> = mods_list(cursive)
+        .iter()
+        .filter(|the_mod| !the_mod.selected && the_mod.label().to_lowercase().contains(&filter))
+        .cloned()
+        .collect();
+    cursive.call_on_name("Available", |list: &mut SelectView<Mod>| {
+        list.clear();
+        list.add_all(mods.into_iter().map(|the_mod| (the_mod.label(), the_mod)));
+        list.sort_by_label();
+    });
+}
+
+pub fn ask_for_path(
+    cursive: &mut Cursive,
+    title: &str,
+    on_confirm: impl Fn(&mut Cursive, PathBuf) + 'static,
+) {
+    let on_confirm = Rc::new(on_confirm);
+    let confirm = move |cursive: &mut Cursive| {
+        let path = cursive
+            .call_on_name("Profile path", |edit: &mut EditView| edit.get_content())
+            .unwrap();
+        cursive.pop_layer();
+        on_confirm(cursive, PathBuf::from(path.trim()));
+    };
+    let on_submit = confirm.clone();
+    crate::push_screen(
+        cursive,
+        Dialog::around(
+            EditView::new()
+                .on_submit(move |cursive, _| on_submit(cursive))
+                .with_name("Profile path")
+                .min_width(40),
+        )
+        .title(title)
+        .button("OK", confirm)
+        .dismiss_button("Cancel"),
+    );
+}
+
+pub fn save_profile(cursive: &mut Cursive, profile: &Profile, path: &Path) {
+    match profile.save(path) {
+        Ok(()) => cursive.add_layer(Dialog::info(format!(
+            "Profile saved to {}",
+            path.to_string_lossy()
+        ))),
+        Err(err) => {
+            warn!("Failed to save profile: {}", err);
+            cursive.add_layer(Dialog::info(format!("Failed to save profile: {}", err)));
+        }
+    }
+}
+
+fn save_selection(cursive: &mut Cursive, path: &Path) {
+    let order: Vec<PathBuf> = cursive
+        .call_on_name("Selected", |list: &mut SelectView<Mod>| {
+            list.iter()
+                .map(|(_, the_mod)| the_mod.path.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    let strategy = if cursive
+        .call_on_name("Priority strategy", |checkbox: &mut Checkbox| {
+            checkbox.is_checked()
+        })
+        .unwrap_or_default()
+    {
+        MergeStrategy::Priority
+    } else {
+        MergeStrategy::Ask
+    };
+    let global_data = cursive
+        .user_data::<GlobalData>()
+        .expect("Mods data wasn't set");
+    let selected: Vec<Mod> = order
+        .iter()
+        .filter_map(|path| {
+            global_data
+                .mods
+                .iter()
+                .find(|the_mod| the_mod.path == *path)
+                .cloned()
+        })
+        .collect();
+    let profile = Profile::new(
+        &global_data.game,
+        &selected,
+        strategy,
+        global_data.resolutions.clone(),
+        global_data.output.clone(),
+        global_data.languages.clone(),
+        global_data.rules.clone(),
+    )
+    .map(|profile| Profile {
+        value_rules: global_data.value_rules.clone(),
+        baseline: global_data.baseline.clone(),
+        ..profile
+    });
+    match profile {
+        Ok(profile) => save_profile(cursive, &profile, path),
+        Err(err) => {
+            warn!("Failed to create profile: {}", err);
+            cursive.add_layer(Dialog::info(format!("Failed to create profile: {}", err)));
+        }
+    }
+}
+
+fn load_selection(cursive: &mut Cursive, path: PathBuf) {
+    let profile = match Profile::load(&path) {
+        Ok(profile) => profile,
+        Err(err) => {
+            warn!("Failed to load profile: {}", err);
+            cursive.add_layer(Dialog::info(format!("Failed to load profile: {}", err)));
+            return;
+        }
+    };
+    let stale = profile.stale();
+    let global_data = cursive
+        .user_data::<GlobalData>()
+        .expect("Mods data wasn't set");
+    if global_data.game.path() != profile.game {
+        warn!(
+            "Profile was saved for the game in {:?}, but {:?} is used now",
+            profile.game,
+            global_data.game.path()
+        );
+    }
+    global_data.resolutions = profile.resolutions;
+    global_data.output = profile.output;
+    global_data.languages = profile.languages;
+    global_data.rules = profile.rules;
+    global_data.value_rules = profile.value_rules;
+    global_data.baseline = profile.baseline;
+    for the_mod in global_data.mods.iter_mut() {
+        the_mod.selected = false;
+    }
+    let mut selected = vec![];
+    for path in profile.mods.into_iter().map(|the_mod| the_mod.path) {
+        if let Some(the_mod) = global_data
+            .mods
+            .iter_mut()
+            .find(|the_mod| the_mod.path == path)
+        {
+            the_mod.selected = true;
+            selected.push(the_mod.clone());
+        }
+    }
+    cursive.call_on_name("Selected", |list: &mut SelectView<Mod>| {
+        list.clear();
+        list.add_all(
+            selected
+                .into_iter()
+                .map(|the_mod| (the_mod.label(), the_mod)),
+        );
+    });
+    let by_priority = profile.strategy == MergeStrategy::Priority;
+    cursive.call_on_name("Priority strategy", |checkbox: &mut Checkbox| {
+        checkbox.set_checked(by_priority)
+    });
+    refresh_available(cursive);
+    if !stale.is_empty() {
+        let stale: Vec<_> = stale.iter().map(ToString::to_string).collect();
+        warn!("Stale mods in the loaded profile: {:?}", stale);
+        cursive.add_layer(Dialog::info(format!(
+            "These mods from the profile are stale, please check the selection:\n{}",
+            stale.join("\n")
+        )));
+    }
+}
+
 fn do_select(cursive: &mut Cursive, item: &Mod) {
     info!("Selecting mod: {}", item.name());
     if let Some(the_mod) = mods_list(cursive)
@@ -96,7 +316,7 @@ fn do_select(cursive: &mut Cursive, item: &Mod) {
             })
         });
         dialog.call_on_name("Selected", |list: &mut SelectView<Mod>| {
-            list.add_item(item.name(), item.clone());
+            list.add_item(item.label(), item.clone());
         });
         cb
     });
@@ -124,11 +344,8 @@ fn do_deselect(cursive: &mut Cursive, item: &Mod) {
         );
     }
 
+    refresh_available(cursive);
     let cb = cursive.call_on_name("Mods selection", |dialog: &mut Dialog| {
-        dialog.call_on_name("Available", |list: &mut SelectView<Mod>| {
-            list.add_item(item.name(), item.clone());
-            list.sort_by_label();
-        });
         dialog.call_on_name("Selected", |list: &mut SelectView<Mod>| {
             let idx = list
                 .iter()
@@ -0,0 +1,98 @@
+use crate::{loader, paths};
+use cursive::{
+    traits::{Nameable, Resizable},
+    views::{Dialog, EditView, LinearLayout, PaddedView, Panel, TextView},
+    Cursive,
+};
+use log::*;
+use std::{error::Error, path::PathBuf};
+
+pub fn push_screen<T: cursive::View>(cursive: &mut Cursive, view: T) {
+    cursive.add_layer(PaddedView::lrtb(1, 1, 1, 1, view).max_width(cursive.screen_size().x - 10));
+}
+pub fn screen<T: cursive::View>(cursive: &mut Cursive, view: T) {
+    cursive.pop_layer();
+    push_screen(cursive, view);
+}
+pub fn error(cursive: &mut Cursive, mut err: &(dyn Error + 'static)) {
+    let desc = err.to_string();
+    error!("Error encountered: {}", desc);
+    while let Some(source) = err.source() {
+        info!("Caused by:\n  {}", source);
+        err = source;
+    }
+    screen(
+        cursive,
+        Dialog::around(TextView::new(desc))
+            .button("OK", |cursive| cursive.quit())
+            .title("Error"),
+    );
+}
+
+pub fn run_update<F: FnOnce(&mut Cursive) + 'static + Send>(sink: &mut cursive::CbSink, cb: F) {
+    sink.send(Box::new(cb))
+        .expect("Cursive sink was unexpectedly dropped, this is probably a bug");
+}
+
+/// Starts the UI; if the profile is provided, bundling is started immediately,
+/// and the user is asked only about the conflicts not resolved in profile.
+pub fn run(profile: Option<PathBuf>) {
+    let mut cursive: Cursive = cursive::default();
+
+    if let Some(profile) = profile {
+        info!("Bundling from profile {:?}", profile);
+        loader::load_profile(&mut cursive, &profile);
+        cursive.run();
+        return;
+    }
+
+    info!("Creating initial dialog");
+    let library = paths::detect_library()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let game = if library.is_empty() {
+        String::new()
+    } else {
+        paths::game(&library).to_string_lossy().into_owned()
+    };
+    let dialog = cursive::views::Dialog::new()
+        .content(
+            LinearLayout::vertical()
+                .child(
+                    Panel::new(
+                        EditView::new()
+                            .content(library)
+                            .on_edit(|cursive, library, _| {
+                                let game = paths::game(library);
+                                cursive.call_on_name("Game path", |edit: &mut EditView| {
+                                    edit.set_content(game.to_string_lossy())
+                                });
+                            })
+                            .on_submit(|cursive, _| loader::load_paths(cursive))
+                            .with_name("Library path")
+                            .full_width(),
+                    )
+                    .title("Steam library path"),
+                )
+                .child(
+                    Panel::new(
+                        EditView::new()
+                            .content(game)
+                            .on_submit(|cursive, _| loader::load_paths(cursive))
+                            .with_name("Game path")
+                            .full_width(),
+                    )
+                    .title("Darkest Dungeon installation path"),
+                ),
+        )
+        .title("Steam library and game paths:")
+        .button("List mods", |cursive| {
+            info!("List mods button click");
+            loader::load_paths(cursive);
+        })
+        .full_width();
+    screen(&mut cursive, dialog);
+
+    info!("Starting Cursive");
+    cursive.run();
+}
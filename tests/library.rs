@@ -0,0 +1,1230 @@
+use darkest_dungeon_mod_bundler::{
+    bundler::{
+        cache::DiffCache,
+        classify::ModKind,
+        compare::{compare, render, ChangeKind},
+        diff::DataNodeContent,
+        error::{BundlerError, Unresolved},
+        report::{HeroFileRole, Resolution},
+        resolve::{ConflictSummary, ResolveBackend, Review},
+    },
+    profile::{MergeStrategy, Output},
+    Bundle,
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+const HERO: &str = "heroes/crusader/crusader.info.darkest";
+
+fn write(dir: &Path, content: &str) {
+    let path = dir.join(HERO);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+fn fixture(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+    let root = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_dir_all(&root);
+    let (game, first, second) = (root.join("game"), root.join("first"), root.join("second"));
+    write(&game, "hp: .amount 33\nspeed: .amount 1\nweapon: .atk 0\n");
+    write(&first, "hp: .amount 40\nspeed: .amount 2\nweapon: .atk 0\n");
+    write(
+        &second,
+        "hp: .amount 33\nspeed: .amount 3\nweapon: .atk 5\n",
+    );
+    (game, first, second)
+}
+
+#[test]
+fn two_mods_by_priority() {
+    let (game, first, second) = fixture("ddmb_library_priority");
+    let bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .strategy(MergeStrategy::Priority)
+        .run()
+        .unwrap();
+
+    match bundle.data[Path::new(HERO)].content() {
+        DataNodeContent::Text(text) => {
+            assert_eq!(
+                text,
+                "hp: .amount 40\n\nspeed: .amount 3\n\nweapon: .atk 5\n"
+            )
+        }
+        DataNodeContent::Binary => panic!("Hero file was merged as binary"),
+    }
+    assert_eq!(bundle.report.mods, vec!["first", "second"]);
+    assert_eq!(bundle.report.conflicts.len(), 1);
+
+    let mods = game.join("mods");
+    std::fs::create_dir_all(&mods).unwrap();
+    let output = Output {
+        name: "Bundle".into(),
+        directory: "bundle".into(),
+    };
+    bundle.deploy(&mods, &output).unwrap();
+    assert!(mods.join("bundle").join(HERO).is_file());
+    assert!(mods.join("bundle/report.txt").is_file());
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn priority_is_applied_value_by_value() {
+    let root = std::env::temp_dir().join("ddmb_library_priority_values");
+    let _ = std::fs::remove_dir_all(&root);
+    let dirs: Vec<_> = ["game", "a", "b", "c"]
+        .iter()
+        .map(|name| root.join(name))
+        .collect();
+    write(&dirs[0], "hp: .amount 33\nspeed: .amount 1\n");
+    write(&dirs[1], "hp: .amount 40\nspeed: .amount 1\n");
+    write(&dirs[2], "hp: .amount 50\nspeed: .amount 5\n");
+    write(&dirs[3], "hp: .amount 33\nspeed: .amount 9\n");
+    let bundle = Bundle::builder()
+        .game_root(&dirs[0])
+        .add_mod(&dirs[1])
+        .add_mod(&dirs[2])
+        .add_mod(&dirs[3])
+        .strategy(MergeStrategy::Priority)
+        .run()
+        .unwrap();
+
+    // Every value is taken from the last mod changing it, even if the later mods change only the other values.
+    match bundle.data[Path::new(HERO)].content() {
+        DataNodeContent::Text(text) => assert_eq!(text, "hp: .amount 50\n\nspeed: .amount 9\n"),
+        DataNodeContent::Binary => panic!("Hero file was merged as binary"),
+    }
+    let conflicts: Vec<_> = bundle
+        .report
+        .conflicts
+        .iter()
+        .map(|conflict| {
+            (
+                conflict.item.clone().unwrap(),
+                conflict.mods.join(", "),
+                conflict.value.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        conflicts,
+        vec![
+            ("hp: #0 .amount".to_string(), "a, b".to_string(), "50"),
+            ("speed: #0 .amount".to_string(), "b, c".to_string(), "9"),
+        ]
+    );
+    assert!(bundle
+        .report
+        .conflicts
+        .iter()
+        .all(|conflict| conflict.resolution == Resolution::Priority));
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn unresolved_conflict_fails() {
+    let (game, first, second) = fixture("ddmb_library_unresolved");
+    let result = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .run();
+    assert!(matches!(result, Err(BundlerError::Unresolved(_))));
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn unanswered_questions_are_collected() {
+    let (game, first, second) = fixture("ddmb_library_unanswered");
+    let other = "heroes/vestal/vestal.info.darkest";
+    for (dir, amount) in [(&game, 1), (&first, 2), (&second, 3)] {
+        std::fs::create_dir_all(dir.join("heroes/vestal")).unwrap();
+        std::fs::write(dir.join(other), format!("hp: .amount {}\n", amount)).unwrap();
+    }
+    let run = |resolutions: Vec<(String, String)>| {
+        let result = Bundle::builder()
+            .game_root(&game)
+            .add_mod(&first)
+            .add_mod(&second)
+            .resolutions(resolutions.into_iter().collect())
+            .collect_unanswered(true)
+            .run();
+        match result {
+            Err(BundlerError::Unanswered(questions)) => questions,
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => vec![],
+        }
+    };
+
+    let questions = run(vec![]);
+    // The group question goes on with deciding for every file, so the questions about the files are listed too.
+    assert_eq!(questions.len(), 3, "{:#?}", questions);
+    assert!(questions[0]
+        .question
+        .starts_with("Mods first, second are conflicting in several files"));
+    assert!(questions[1].question.contains(HERO));
+    assert_eq!(
+        questions[1].options,
+        ["first", "second", "Resolve manually"]
+    );
+
+    // Answers are copied as they are, and the answered questions aren't listed anymore.
+    let mut answers = vec![
+        (
+            questions[0].question.clone(),
+            questions[0].options[0].clone(),
+        ),
+        (questions[1].question.clone(), "Resolve manually".to_owned()),
+        (questions[2].question.clone(), "second".to_owned()),
+    ];
+    let questions = run(answers.clone());
+    assert_eq!(questions.len(), 1, "{:#?}", questions);
+    assert!(questions[0].question.contains("speed"));
+    assert_eq!(questions[0].options[..2], ["first: 2", "second: 3"]);
+    answers.push((questions[0].question.clone(), "second: 3".to_owned()));
+    assert!(run(answers).is_empty());
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn duplicate_strings_are_reported() {
+    let (game, first, _) = fixture("ddmb_library_duplicates");
+    // The bundled hero must be named, or the validation would report that too.
+    let table = |entries: &str| {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<root>\n<language id=\"english\">\n\
+             <entry id=\"hero_class_name_crusader\"><![CDATA[Crusader]]></entry>\n{}</language>\n</root>\n",
+            entries
+        )
+    };
+    let loc = "localization/crusader.string_table.xml";
+    std::fs::create_dir_all(game.join("localization")).unwrap();
+    std::fs::create_dir_all(first.join("localization")).unwrap();
+    std::fs::write(
+        game.join(loc),
+        table("<entry id=\"str_smite\"><![CDATA[Smite]]></entry>\n"),
+    )
+    .unwrap();
+    std::fs::write(
+        first.join(loc),
+        table(
+            "<entry id=\"str_smite\"><![CDATA[Holy Smite]]></entry>\n\
+             <entry id=\"str_smite\"><![CDATA[Smite!]]></entry>\n",
+        ),
+    )
+    .unwrap();
+
+    let bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .run()
+        .unwrap();
+    assert!(bundle.report.conflicts.is_empty());
+    let warnings: Vec<_> = bundle
+        .report
+        .warnings
+        .iter()
+        .map(|warning| warning.to_string())
+        .collect();
+    assert_eq!(
+        warnings,
+        vec![format!(
+            "{} [[english] str_smite]: defined several times, \"Holy Smite\" is used instead of \"Smite!\" (changed by first)",
+            Path::new(loc).to_string_lossy()
+        )]
+    );
+    match bundle.data[Path::new(loc)].content() {
+        DataNodeContent::Text(text) => assert!(text.contains("<![CDATA[Holy Smite]]>")),
+        DataNodeContent::Binary => panic!("String table was merged as binary"),
+    }
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn overrides_stay_overrides() {
+    let (game, first, second) = fixture("ddmb_library_overrides");
+    let text = |bundle: &Bundle, path: &str| match bundle.data.get(Path::new(path)) {
+        Some(node) => match node.content() {
+            DataNodeContent::Text(text) => Some(text.clone()),
+            DataNodeContent::Binary => panic!("{} was bundled as binary", path),
+        },
+        None => None,
+    };
+    let role = |bundle: &Bundle, path: &str| {
+        bundle
+            .report
+            .files
+            .iter()
+            .find(|file| file.path == Path::new(path))
+            .and_then(|file| file.role)
+    };
+    let overrides = "heroes/crusader/crusader.override.darkest";
+    std::fs::write(first.join(overrides), "hp: .amount 45\n").unwrap();
+    std::fs::write(second.join(overrides), "weapon: .atk 10\n").unwrap();
+
+    // Both mods ship only the overrides.
+    std::fs::remove_file(first.join(HERO)).unwrap();
+    std::fs::remove_file(second.join(HERO)).unwrap();
+    let bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .run()
+        .unwrap();
+    assert_eq!(text(&bundle, HERO), None);
+    assert_eq!(
+        text(&bundle, overrides).unwrap(),
+        "hp: .amount 45\n\nweapon: .atk 10\n"
+    );
+    assert_eq!(role(&bundle, overrides), Some(HeroFileRole::Override));
+    assert!(bundle.report.conflicts.is_empty());
+
+    // One mod changes the info file, the other one ships the override.
+    write(&first, "hp: .amount 40\nspeed: .amount 1\nweapon: .atk 0\n");
+    std::fs::remove_file(first.join(overrides)).unwrap();
+    let bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .run()
+        .unwrap();
+    assert_eq!(
+        text(&bundle, HERO).unwrap(),
+        "hp: .amount 40\n\nspeed: .amount 1\n\nweapon: .atk 0\n"
+    );
+    assert_eq!(text(&bundle, overrides).unwrap(), "weapon: .atk 10\n");
+    assert_eq!(role(&bundle, HERO), Some(HeroFileRole::Info));
+    assert_eq!(
+        role(&bundle, overrides),
+        Some(HeroFileRole::OverrideOfBundledInfo)
+    );
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn hero_art_is_merged_by_entries() {
+    let (game, first, second) = fixture("ddmb_library_art");
+    let art = "heroes/crusader/crusader.art.darkest";
+    let base = r#"commonfx: .deathdoor "crusader_deathsdoor" .heal "heal_hero"
+combat: .anim "combat" .fx "" .targchestfx "blood_splatter"
+skill: .id "smite" .anim "attack_sword" .fx "" .targchestfx "blood_splatter" .area_pos_offset 0 0
+skill: .id "stunning_blow" .anim "attack_sword" .fx "" .targheadfx "stun"
+"#;
+    std::fs::write(game.join(art), base).unwrap();
+    std::fs::write(
+        first.join(art),
+        base.replace(r#"area_pos_offset 0 0"#, "area_pos_offset 10 -5"),
+    )
+    .unwrap();
+    let added = "skill: .id \"zealous_accusation\" .anim \"attack_scroll\" .fx \"\"\n";
+    std::fs::write(
+        second.join(art),
+        format!(
+            "{}{}",
+            base.replace(r#".targheadfx "stun""#, r#".targheadfx "stun_heavy""#),
+            added
+        ),
+    )
+    .unwrap();
+    let bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .strategy(MergeStrategy::Priority)
+        .run()
+        .unwrap();
+    let text = match bundle.data[Path::new(art)].content() {
+        DataNodeContent::Text(text) => text.clone(),
+        DataNodeContent::Binary => panic!("Art file was merged as binary"),
+    };
+    assert!(text.contains(".area_pos_offset 10 -5"));
+    assert!(text.contains(r#".targheadfx "stun_heavy""#));
+    assert!(text.contains(r#"skill: .id "zealous_accusation""#));
+    // The only conflict is in the info file from the fixture.
+    assert!(bundle
+        .report
+        .conflicts
+        .iter()
+        .all(|conflict| conflict.path == Path::new(HERO)));
+    let file = bundle
+        .report
+        .files
+        .iter()
+        .find(|file| file.path == Path::new(art));
+    assert_eq!(file.unwrap().role, Some(HeroFileRole::Other));
+
+    // Skills are told apart by their ids, even if the mod reorders them; only the same value is a conflict.
+    let lines: Vec<_> = base.lines().collect();
+    let fx = |line: &str, fx: &str| line.replacen(r#".fx """#, &format!(".fx \"{}\"", fx), 1);
+    let first_art = format!(
+        "{}\n{}\n{}\n{}\n",
+        lines[0],
+        lines[1],
+        fx(lines[2], "sword_glow"),
+        lines[3]
+    );
+    std::fs::write(first.join(art), first_art).unwrap();
+    let second_art = format!(
+        "{}\n{}\n{}\n{}\n",
+        lines[0],
+        lines[1],
+        fx(lines[3], "stun_flash"),
+        fx(lines[2], "holy_sword")
+    );
+    std::fs::write(second.join(art), second_art).unwrap();
+    let bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .strategy(MergeStrategy::Priority)
+        .run()
+        .unwrap();
+    let text = match bundle.data[Path::new(art)].content() {
+        DataNodeContent::Text(text) => text.clone(),
+        DataNodeContent::Binary => panic!("Art file was merged as binary"),
+    };
+    assert!(text.contains(r#".fx "holy_sword""#), "{}", text);
+    assert!(text.contains(r#".fx "stun_flash""#), "{}", text);
+    let items: Vec<_> = bundle
+        .report
+        .conflicts
+        .iter()
+        .filter(|conflict| conflict.path == Path::new(art))
+        .map(|conflict| conflict.item.clone().unwrap_or_default())
+        .collect();
+    assert_eq!(items.len(), 1, "{:?}", items);
+    assert!(items[0].contains("smite"), "{:?}", items);
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn value_rules_decide_conflicts() {
+    let (game, first, second) = fixture("ddmb_library_value_rules");
+    write(&game, "resistances: .stun 40% .poison 30% .bleed 30%\n");
+    write(&first, "resistances: .stun 50% .poison 20% .bleed 30%\n");
+    write(&second, "resistances: .stun 60% .poison 35% .bleed 30%\n");
+    let rules = serde_json::from_str(
+        r#"[
+            {"path": "resistances/stun", "strategy": {"prefer-mod": "first"}},
+            {"path": "resistances/*", "strategy": "max"}
+        ]"#,
+    )
+    .unwrap();
+    // Without the rules, the conflicts would fail the bundling, since nobody is asked.
+    let bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .value_rules(rules)
+        .run()
+        .unwrap();
+    match bundle.data[Path::new(HERO)].content() {
+        DataNodeContent::Text(text) => {
+            assert_eq!(text, "resistances: .stun 50% .poison 35% .bleed 30%\n")
+        }
+        DataNodeContent::Binary => panic!("Hero file was merged as binary"),
+    }
+    let decided: Vec<_> = bundle
+        .report
+        .conflicts
+        .iter()
+        .map(|conflict| {
+            assert_eq!(conflict.resolution, Resolution::ValueRule);
+            (
+                conflict.item.clone().unwrap(),
+                conflict.rule.clone().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        decided,
+        vec![
+            (
+                "resistances: #0 .poison".to_string(),
+                "max for resistances/*".to_string()
+            ),
+            (
+                "resistances: #0 .stun".to_string(),
+                "prefer-mod(first) for resistances/stun".to_string()
+            ),
+        ]
+    );
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn effect_chains_are_combined() {
+    let (game, first, second) = fixture("ddmb_library_effect_chains");
+    write(
+        &game,
+        "combat_skill: .id \"smite\" .effect \"Stun 1\" \"Bleed 1\"\n\
+         combat_skill: .id \"bulwark\" .effect \"Prot 1\" \"Mark\"\n",
+    );
+    write(
+        &first,
+        "combat_skill: .id \"smite\" .effect \"Stun 1\" \"Bleed 1\" \"Holy A\"\n\
+         combat_skill: .id \"bulwark\" .effect \"Prot 1\"\n",
+    );
+    write(
+        &second,
+        "combat_skill: .id \"smite\" .effect \"Stun 1\" \"Bleed 1\" \"Holy B\"\n\
+         combat_skill: .id \"bulwark\" .effect \"Prot 1\" \"Mark\" \"Guard\"\n",
+    );
+    // Nobody is asked, so any conflict left would fail the bundling.
+    let bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .run()
+        .unwrap();
+    match bundle.data[Path::new(HERO)].content() {
+        DataNodeContent::Text(text) => assert_eq!(
+            text,
+            "combat_skill: .id \"smite\" .effect \"Stun 1\" \"Bleed 1\" \"Holy A\" \"Holy B\"\n\
+             combat_skill: .id \"bulwark\" .effect \"Prot 1\" \"Guard\"\n"
+        ),
+        DataNodeContent::Binary => panic!("Hero file was merged as binary"),
+    }
+    assert!(bundle
+        .report
+        .conflicts
+        .iter()
+        .all(|conflict| conflict.resolution == Resolution::Combined));
+
+    // Both mods replacing the same effect is still a conflict.
+    write(
+        &first,
+        "combat_skill: .id \"smite\" .effect \"Stun 2\" \"Bleed 1\"\n\
+         combat_skill: .id \"bulwark\" .effect \"Prot 1\" \"Mark\"\n",
+    );
+    write(
+        &second,
+        "combat_skill: .id \"smite\" .effect \"Stun 3\" \"Bleed 1\"\n\
+         combat_skill: .id \"bulwark\" .effect \"Prot 1\" \"Mark\"\n",
+    );
+    let result = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .run();
+    assert!(matches!(result, Err(BundlerError::Unresolved(_))));
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn camping_skill_effects_are_combined() {
+    let (game, first, second) = fixture("ddmb_library_camping_skills");
+    // Only the camping skills are changed, so the hero is left as in the game.
+    std::fs::remove_file(first.join(HERO)).unwrap();
+    std::fs::remove_file(second.join(HERO)).unwrap();
+    let path = "raid/camping/default.camping_skills.json";
+    let skills = |effects: &[&str], cost: u32| {
+        let effects: Vec<_> = effects
+            .iter()
+            .map(|effect| format!(r#"{{"type": "{}", "amount": 10}}"#, effect))
+            .collect();
+        format!(
+            r#"{{"skills": [{{"id": "encourage", "cost": {}, "hero_classes": ["crusader"], "effects": [{}]}}]}}"#,
+            cost,
+            effects.join(", ")
+        )
+    };
+    for (root, text) in &[
+        (&game, skills(&["stress_heal_amount"], 2)),
+        (&first, skills(&["stress_heal_amount", "buff"], 2)),
+        (&second, skills(&["stress_heal_amount", "loot"], 3)),
+    ] {
+        std::fs::create_dir_all(root.join("raid/camping")).unwrap();
+        std::fs::write(root.join(path), text).unwrap();
+    }
+    let bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .run()
+        .unwrap();
+    let written: serde_json::Value = match bundle.data[Path::new(path)].content() {
+        DataNodeContent::Text(text) => serde_json::from_str(text).unwrap(),
+        DataNodeContent::Binary => panic!("Camping skills were merged as binary"),
+    };
+    let expected: serde_json::Value =
+        serde_json::from_str(&skills(&["stress_heal_amount", "buff", "loot"], 3)).unwrap();
+    assert_eq!(written, expected);
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn data_errors_name_the_mod_and_file() {
+    let (game, first, _) = fixture("ddmb_library_data_errors");
+    // Matched to the game file case-insensitively, but read as binary, judging by the extension.
+    std::fs::rename(
+        first.join(HERO),
+        first.join("heroes/crusader/crusader.info.DARKEST"),
+    )
+    .unwrap();
+    let err = match Bundle::builder().game_root(&game).add_mod(&first).run() {
+        Err(BundlerError::Extraction(err)) => err.to_string(),
+        other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+    };
+    assert!(err.contains("in mod first"), "{}", err);
+    assert!(err.contains(HERO), "{}", err);
+    assert!(
+        err.contains("the game file is text, but the mod's one is binary"),
+        "{}",
+        err
+    );
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+/// Backend giving the same answer to every question, remembering the questions.
+struct Always(usize, Vec<String>);
+
+impl ResolveBackend for Always {
+    fn choose(&mut self, question: &str, _: &[String]) -> Result<usize, Unresolved> {
+        self.1.push(question.to_owned());
+        Ok(self.0)
+    }
+    fn edit_line(&mut self, title: &str, _: &[(String, String)]) -> Result<String, Unresolved> {
+        Err(Unresolved(title.to_owned()))
+    }
+}
+
+#[test]
+fn truncated_files_are_skipped() {
+    let (game, first, second) = fixture("ddmb_library_truncated");
+    write(
+        &second,
+        "hp: .amount 33\nspeed: .amount 3\nweapon: .id \"sw",
+    );
+    std::fs::create_dir_all(second.join("raid")).unwrap();
+    std::fs::write(second.join("raid/notes.txt"), "Read me\n").unwrap();
+    let run = |answer| {
+        Bundle::builder()
+            .game_root(&game)
+            .add_mod(&first)
+            .add_mod(&second)
+            .backend(Always(answer, vec![]))
+            .run()
+    };
+
+    let err = match Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .run()
+    {
+        Err(BundlerError::Extraction(err)) => err.to_string(),
+        other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+    };
+    assert!(err.contains("in mod second"), "{}", err);
+    assert!(err.contains("line 3, column 16"), "{}", err);
+
+    // Skipping the file keeps the rest of the mod.
+    let bundle = run(0).unwrap();
+    assert!(bundle.data.contains_key(Path::new("raid/notes.txt")));
+    assert_eq!(bundle.report.mods, vec!["first", "second"]);
+    assert_eq!(bundle.report.skipped.len(), 1);
+    let skipped = &bundle.report.skipped[0];
+    assert_eq!(skipped.path, Path::new(HERO));
+    assert_eq!(skipped.mod_name, "second");
+    assert!(!skipped.whole_mod);
+    assert!(bundle.report.render().contains("Skipped files:"));
+    match bundle.data[Path::new(HERO)].content() {
+        DataNodeContent::Text(text) => assert!(text.contains("speed: .amount 2"), "{}", text),
+        DataNodeContent::Binary => panic!("Hero file was merged as binary"),
+    }
+
+    // Skipping the mod leaves nothing from it.
+    let bundle = run(1).unwrap();
+    assert!(!bundle.data.contains_key(Path::new("raid/notes.txt")));
+    assert_eq!(bundle.report.mods, vec!["first"]);
+    assert!(bundle.report.skipped[0].whole_mod);
+
+    assert!(matches!(run(2), Err(BundlerError::Extraction(_))));
+
+    // Without asking, every broken file is skipped.
+    let bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .skip_broken(true)
+        .run()
+        .unwrap();
+    assert_eq!(bundle.report.mods, vec!["first", "second"]);
+    assert_eq!(bundle.report.skipped.len(), 1);
+    assert!(!bundle.report.skipped[0].whole_mod);
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+/// Every file of the deployed bundle, with its content;
+/// `modfiles.txt` is skipped, since it has the time of writing.
+fn deployed(bundle: Bundle, mods: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+    let output = Output {
+        name: "Bundle".into(),
+        directory: "bundle".into(),
+    };
+    bundle.deploy(mods, &output).unwrap();
+    let mut files = BTreeMap::new();
+    let mut dirs = vec![mods.join("bundle")];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if !path.ends_with("modfiles.txt") {
+                let content = std::fs::read(&path).unwrap();
+                files.insert(path.strip_prefix(mods).unwrap().to_owned(), content);
+            }
+        }
+    }
+    files
+}
+
+#[test]
+fn deployed_mod_is_complete() {
+    let (game, first, second) = fixture("ddmb_library_deploy");
+    let mods = game.join("mods");
+    std::fs::create_dir_all(&mods).unwrap();
+    let bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .strategy(MergeStrategy::Priority)
+        .run()
+        .unwrap();
+    let files = deployed(bundle, &mods);
+    let bundle_dir = mods.join("bundle");
+
+    let project = String::from_utf8(files[Path::new("bundle/project.xml")].clone()).unwrap();
+    assert!(project.contains("<Title>Bundle</Title>"));
+    assert!(project.contains("- first\n- second"));
+    assert!(project.contains(&format!(
+        "<UpdateDetails>Built with Darkest Dungeon mod bundler {}</UpdateDetails>",
+        env!("CARGO_PKG_VERSION")
+    )));
+    assert!(files[Path::new("bundle/preview_icon.png")].starts_with(b"\x89PNG"));
+
+    // Every other file is listed with its size.
+    let manifest = std::fs::read_to_string(bundle_dir.join("modfiles.txt")).unwrap();
+    let lines: Vec<_> = manifest.lines().collect();
+    let listed: BTreeMap<_, _> = lines
+        .chunks(3)
+        .map(|entry| (entry[0], entry[1].parse::<usize>().unwrap()))
+        .collect();
+    let expected: BTreeMap<_, _> = files
+        .iter()
+        .map(|(path, content)| {
+            let path = path.strip_prefix("bundle").unwrap().to_str().unwrap();
+            (path, content.len())
+        })
+        .collect();
+    assert_eq!(listed, expected);
+    assert!(listed.contains_key(HERO));
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn compared_bundles_name_the_updated_mods() {
+    let (game, first, second) = fixture("ddmb_library_compare");
+    let mods = game.join("mods");
+    std::fs::create_dir_all(&mods).unwrap();
+    let third = game.parent().unwrap().join("third");
+    let build = |directory: &str, list: &[&Path]| {
+        let mut builder = Bundle::builder()
+            .game_root(&game)
+            .strategy(MergeStrategy::Priority);
+        for path in list {
+            builder = builder.add_mod(*path);
+        }
+        let output = Output {
+            name: "Bundle".into(),
+            directory: directory.into(),
+        };
+        builder.run().unwrap().deploy(&mods, &output).unwrap();
+        mods.join(directory)
+    };
+    let old = build("old", &[&first, &second]);
+
+    // The second mod is updated, and the third one, with its own file, is added.
+    write(
+        &second,
+        "hp: .amount 33\nspeed: .amount 4\nweapon: .atk 5\n",
+    );
+    let notes = "heroes/crusader/crusader.notes.txt";
+    std::fs::create_dir_all(third.join("heroes/crusader")).unwrap();
+    std::fs::write(third.join(notes), "Third mod").unwrap();
+    let new = build("new", &[&first, &second, &third]);
+
+    let changes = compare(&old, &new).unwrap();
+    let summary: Vec<_> = changes
+        .iter()
+        .map(|change| {
+            let path = change.path.to_string_lossy().replace('\\', "/");
+            (path, change.item.clone(), change.kind, change.mods.clone())
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (
+                HERO.to_string(),
+                Some("speed: #0 .amount".to_string()),
+                ChangeKind::Changed,
+                vec!["second".to_string()]
+            ),
+            (
+                notes.to_string(),
+                None,
+                ChangeKind::Added,
+                vec!["third".to_string()]
+            ),
+        ]
+    );
+    assert_eq!(
+        render(&changes),
+        format!(
+            "{}\tspeed: #0 .amount\tchanged\tsecond\n{}\t-\tadded\tthird",
+            HERO, notes
+        )
+    );
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn cached_run_matches_cold_run() {
+    let (game, first, second) = fixture("ddmb_library_cache");
+    let mods = game.join("mods");
+    std::fs::create_dir_all(&mods).unwrap();
+    let cache_file = game.parent().unwrap().join("profile.cache.json");
+    let run = |mods: &[&Path], cache: Option<DiffCache>| {
+        let mut builder = Bundle::builder()
+            .game_root(&game)
+            .strategy(MergeStrategy::Priority)
+            .skip_broken(true);
+        for path in mods {
+            builder = builder.add_mod(*path);
+        }
+        if let Some(cache) = cache {
+            builder = builder.cache(cache);
+        }
+        builder.run().unwrap()
+    };
+    let cached = |list: &[&Path]| {
+        let mut bundle = run(list, Some(DiffCache::load(&cache_file)));
+        bundle.cache.take().unwrap().save(&cache_file).unwrap();
+        let stats = (bundle.cache_stats.reused, bundle.cache_stats.compared);
+        (deployed(bundle, &mods), stats)
+    };
+
+    let (files, stats) = cached(&[&first, &second]);
+    assert_eq!(stats, (0, 2));
+    assert_eq!(files, deployed(run(&[&first, &second], None), &mods));
+
+    let (files, stats) = cached(&[&first, &second]);
+    assert_eq!(stats, (2, 0));
+    assert_eq!(files, deployed(run(&[&first, &second], None), &mods));
+
+    write(&first, "hp: .amount 50\nspeed: .amount 2\nweapon: .atk 0\n");
+    let (files, stats) = cached(&[&first, &second]);
+    assert_eq!(stats, (1, 1));
+    assert_eq!(files, deployed(run(&[&first, &second], None), &mods));
+
+    // Removed mod doesn't leak into the bundle through the cache.
+    let (files, stats) = cached(&[&first]);
+    assert_eq!(stats, (1, 0));
+    assert_eq!(files, deployed(run(&[&first], None), &mods));
+
+    // The game update invalidates everything compared with it.
+    write(&game, "hp: .amount 35\nspeed: .amount 1\nweapon: .atk 0\n");
+    let (files, stats) = cached(&[&first]);
+    assert_eq!(stats, (0, 1));
+    assert_eq!(files, deployed(run(&[&first], None), &mods));
+
+    // Mod read again without its broken file still reuses the cached changes.
+    let hellion = "heroes/hellion/hellion.info.darkest";
+    for (dir, text) in [(&game, "hp: .amount 20\n"), (&first, "hp: .id \"sw")] {
+        std::fs::create_dir_all(dir.join("heroes/hellion")).unwrap();
+        std::fs::write(dir.join(hellion), text).unwrap();
+    }
+    let (files, stats) = cached(&[&first]);
+    assert_eq!(stats, (1, 0));
+    assert_eq!(files, deployed(run(&[&first], None), &mods));
+
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn parallel_run_matches_sequential_run() {
+    let (game, first, second) = fixture("ddmb_library_parallel");
+    let root = game.parent().unwrap().to_owned();
+    let mut list = vec![first, second];
+    // Mods adding the same hero with different casing, so that the file name depends on the mods order.
+    for index in 0..6 {
+        let the_mod = root.join(format!("mod_{}", index));
+        let hero = if index % 2 == 0 { "NewHero" } else { "newhero" };
+        let path = the_mod.join(format!("heroes/{0}/{0}.info.darkest", hero));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, format!("hp: .amount {}\n", index)).unwrap();
+        write(
+            &the_mod,
+            &format!(
+                "hp: .amount {}\nspeed: .amount 1\nweapon: .atk 0\n",
+                50 + index
+            ),
+        );
+        list.push(the_mod);
+    }
+    let mods = game.join("mods");
+    std::fs::create_dir_all(&mods).unwrap();
+    let run = |threads: usize| {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let bundle = pool.install(|| {
+            let mut builder = Bundle::builder()
+                .game_root(&game)
+                .strategy(MergeStrategy::Priority);
+            for path in &list {
+                builder = builder.add_mod(path);
+            }
+            builder.run().unwrap()
+        });
+        assert_eq!(bundle.report.mods.len(), list.len());
+        deployed(bundle, &mods)
+    };
+
+    let sequential = run(1);
+    assert!(sequential.contains_key(Path::new("bundle/heroes/NewHero/NewHero.info.darkest")));
+    for _ in 0..3 {
+        assert_eq!(run(8), sequential);
+    }
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn text_conflicts_are_listed_in_mods_order() {
+    let (game, _, _) = fixture("ddmb_library_text_order");
+    let root = game.parent().unwrap().to_owned();
+    let notes = "scripts/notes.txt";
+    std::fs::create_dir_all(game.join("scripts")).unwrap();
+    std::fs::write(game.join(notes), "first line\nsecond line\nthird line\n").unwrap();
+    let mut list = vec![];
+    for index in 0..6 {
+        let the_mod = root.join(format!("mod_{}", 5 - index));
+        std::fs::create_dir_all(the_mod.join("scripts")).unwrap();
+        let changed = format!("first line\nsecond line {}\nthird line\n", index);
+        std::fs::write(the_mod.join(notes), changed).unwrap();
+        std::fs::write(
+            the_mod.join("scripts/added.txt"),
+            format!("added {}\n", index),
+        )
+        .unwrap();
+        list.push(the_mod);
+    }
+    let run = || {
+        let mut builder = Bundle::builder().game_root(&game).collect_unanswered(true);
+        for path in &list {
+            builder = builder.add_mod(path);
+        }
+        match builder.run() {
+            Err(BundlerError::Unanswered(questions)) => questions,
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Conflicts were not reported"),
+        }
+    };
+
+    let questions = run();
+    let mods: Vec<_> = (0..6).map(|index| format!("mod_{}", 5 - index)).collect();
+    for file in &["added.txt", "notes.txt"] {
+        let question = questions
+            .iter()
+            .find(|question| question.question.contains(file))
+            .unwrap_or_else(|| panic!("No question about {}: {:#?}", file, questions));
+        assert_eq!(question.options[..6], mods[..]);
+    }
+    for _ in 0..3 {
+        assert_eq!(format!("{:?}", run()), format!("{:?}", questions));
+    }
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn preview_points_at_the_changing_mod() {
+    let (game, _, _) = fixture("ddmb_library_preview");
+    let root = game.parent().unwrap().to_owned();
+    let vestal = "heroes/vestal/vestal.info.darkest";
+    let info = |stun: &str, effects: &str| {
+        format!(
+            "resistances: .stun {} .poison 30%\ncombat_skill: .id \"judgement\" .effect {}\n",
+            stun, effects
+        )
+    };
+    let add = |name: &str, path: &str, text: &str| {
+        let the_mod = root.join(name);
+        std::fs::create_dir_all(the_mod.join(path).parent().unwrap()).unwrap();
+        std::fs::write(the_mod.join(path), text).unwrap();
+        the_mod
+    };
+    add("game", vestal, &info("40%", "\"Stun 1\" \"Mark\""));
+    let list = vec![
+        add("stun_resist", vestal, &info("60%", "\"Stun 1\" \"Mark\"")),
+        add(
+            "judgement",
+            vestal,
+            &info("40%", "\"Stun 1\" \"Bleed 1\" \"Mark\""),
+        ),
+        add(
+            "crusader",
+            HERO,
+            "hp: .amount 40\nspeed: .amount 1\nweapon: .atk 0\n",
+        ),
+        add("scripts", "scripts/notes.txt", "notes\n"),
+        add("empty", "scripts/empty.txt", ""),
+    ];
+    let mut builder = Bundle::builder().game_root(&game);
+    for path in &list {
+        builder = builder.add_mod(path);
+    }
+    let bundle = builder.run().unwrap();
+    assert_eq!(bundle.report.mods.len(), 5);
+
+    let preview = bundle.preview.render();
+    assert!(
+        preview.contains(&format!(
+            "  {} - changed by stun_resist, judgement\n    resistances: #0\n      .stun: 40% -> 60%  [stun_resist]\n",
+            vestal
+        )),
+        "{}",
+        preview
+    );
+    assert!(preview.contains(
+        "      .effect:  [judgement]\n          Stun 1\n        + Bleed 1\n          Mark\n"
+    ));
+    assert!(preview.contains("      .amount: 33 -> 40  [crusader]\n"));
+    assert!(preview.contains("  scripts/notes.txt - added by scripts\n      1 lines\n"));
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn game_update_is_detected() {
+    let (game, first, second) = fixture("ddmb_library_baseline");
+    let vestal = "heroes/vestal/vestal.info.darkest";
+    for (dir, hp) in [(&game, 24), (&second, 30)] {
+        std::fs::create_dir_all(dir.join("heroes/vestal")).unwrap();
+        std::fs::write(dir.join(vestal), format!("hp: .amount {}\n", hp)).unwrap();
+    }
+    let cache_file = game.parent().unwrap().join("profile.cache.json");
+    let run = |backend: Always, resolutions, baseline| {
+        Bundle::builder()
+            .game_root(&game)
+            .add_mod(&first)
+            .add_mod(&second)
+            .backend(backend)
+            .resolutions(resolutions)
+            .baseline(baseline)
+            .cache(DiffCache::load(&cache_file))
+            .run()
+    };
+
+    let mut bundle = run(Always(0, vec![]), Default::default(), Default::default()).unwrap();
+    bundle.cache.take().unwrap().save(&cache_file).unwrap();
+    let (resolutions, baseline) = (bundle.resolutions, bundle.baseline);
+    assert_eq!(baseline.0.len(), 2);
+    assert_eq!(baseline.0[Path::new(HERO)].mods, vec!["first", "second"]);
+
+    write(&game, "hp: .amount 35\nspeed: .amount 1\nweapon: .atk 0\n");
+    let drift = match run(Always(1, vec![]), resolutions.clone(), baseline.clone()) {
+        Err(BundlerError::BaselineDrift(drift)) => drift,
+        other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+    };
+    assert_eq!(drift.len(), 1);
+    assert_eq!(drift[0].path, Path::new(HERO));
+    assert_eq!(drift[0].mods, vec!["first", "second"]);
+    assert_eq!(
+        drift[0].resolutions,
+        resolutions.keys().cloned().collect::<Vec<_>>()
+    );
+
+    // Proceeding compares only the changed file again, and asks again about its conflict.
+    let bundle = run(Always(0, vec![]), resolutions, baseline).unwrap();
+    assert_eq!(
+        (bundle.cache_stats.reused, bundle.cache_stats.compared),
+        (1, 2)
+    );
+    assert_eq!(bundle.report.drift, drift);
+    assert!(bundle
+        .report
+        .render()
+        .contains("Game files changed since the previous run:"));
+    assert_eq!(bundle.report.conflicts[0].resolution, Resolution::User);
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+/// Backend making the planned decisions on the reviewed conflicts, then proceeding to resolve the rest.
+struct Reviewer(Vec<Review>);
+
+impl ResolveBackend for Reviewer {
+    fn choose(&mut self, question: &str, _: &[String]) -> Result<usize, Unresolved> {
+        Err(Unresolved(question.to_owned()))
+    }
+    fn edit_line(&mut self, title: &str, _: &[(String, String)]) -> Result<String, Unresolved> {
+        Err(Unresolved(title.to_owned()))
+    }
+    fn can_review(&self) -> bool {
+        true
+    }
+    fn review_conflicts(&mut self, _: &[ConflictSummary]) -> Result<Review, Unresolved> {
+        Ok(if self.0.is_empty() {
+            Review::Proceed
+        } else {
+            self.0.remove(0)
+        })
+    }
+}
+
+#[test]
+fn conflicts_are_reviewed_first() {
+    let (game, first, second) = fixture("ddmb_library_review");
+    let third = game.parent().unwrap().join("third");
+    write(&third, "hp: .amount 33\nspeed: .amount 4\nweapon: .atk 7\n");
+    let vestal = "heroes/vestal/vestal.info.darkest";
+    for (dir, hp) in [(&game, 24), (&second, 30), (&third, 28)] {
+        std::fs::create_dir_all(dir.join("heroes/vestal")).unwrap();
+        std::fs::write(dir.join(vestal), format!("hp: .amount {}\n", hp)).unwrap();
+    }
+    // The third mod also has a broken file, which is skipped, and a file which is not valid UTF-8.
+    let hellion = "heroes/hellion/hellion.info.darkest";
+    for (dir, text) in [(&game, "hp: .amount 20\n"), (&third, "hp: .id \"sw")] {
+        std::fs::create_dir_all(dir.join("heroes/hellion")).unwrap();
+        std::fs::write(dir.join(hellion), text).unwrap();
+    }
+    std::fs::create_dir_all(third.join("raid")).unwrap();
+    std::fs::write(third.join("raid/notes.txt"), b"Caf\xe9\n").unwrap();
+    // Without the third mod, only the first and the second are left conflicting in the hero file.
+    let reviews = vec![
+        Review::RemoveMod("third".into()),
+        Review::Prefer(HERO.into(), "first".into()),
+    ];
+    let mut bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&second)
+        .add_mod(&third)
+        .backend(Reviewer(reviews))
+        .skip_broken(true)
+        .cache(DiffCache::default())
+        .run()
+        .unwrap();
+
+    assert_eq!(bundle.report.mods, vec!["first", "second"]);
+    assert_eq!(bundle.report.contents.len(), 2);
+    assert_eq!(bundle.report.conflicts.len(), 1);
+    assert_eq!(bundle.report.conflicts[0].resolution, Resolution::User);
+    match bundle.data[Path::new(HERO)].content() {
+        DataNodeContent::Text(text) => assert!(text.contains("speed: .amount 2"), "{}", text),
+        DataNodeContent::Binary => panic!("Hero file was merged as binary"),
+    }
+    // Nothing is left from the removed mod, neither in the report nor in the cache.
+    assert!(bundle.report.skipped.is_empty());
+    assert!(bundle.report.warnings.is_empty());
+    let cache_file = game.parent().unwrap().join("profile.cache.json");
+    bundle.cache.take().unwrap().save(&cache_file).unwrap();
+    let cache = std::fs::read_to_string(&cache_file).unwrap();
+    assert!(cache.contains("second") && !cache.contains("third"));
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn reviewed_preference_keeps_other_values() {
+    let root = std::env::temp_dir().join("ddmb_library_review_values");
+    let _ = std::fs::remove_dir_all(&root);
+    let dirs: Vec<_> = ["game", "a", "b", "c"]
+        .iter()
+        .map(|name| root.join(name))
+        .collect();
+    write(&dirs[0], "hp: .amount 33\nspeed: .amount 1\n");
+    write(&dirs[1], "hp: .amount 40\nspeed: .amount 1\n");
+    write(&dirs[2], "hp: .amount 50\nspeed: .amount 5\n");
+    write(&dirs[3], "hp: .amount 33\nspeed: .amount 9\n");
+    // The first mod doesn't change the speed, so its conflict is left for the next choice.
+    let reviews = vec![
+        Review::Prefer(HERO.into(), "a".into()),
+        Review::Prefer(HERO.into(), "c".into()),
+    ];
+    let bundle = Bundle::builder()
+        .game_root(&dirs[0])
+        .add_mod(&dirs[1])
+        .add_mod(&dirs[2])
+        .add_mod(&dirs[3])
+        .backend(Reviewer(reviews))
+        .run()
+        .unwrap();
+
+    match bundle.data[Path::new(HERO)].content() {
+        DataNodeContent::Text(text) => assert_eq!(text, "hp: .amount 40\n\nspeed: .amount 9\n"),
+        DataNodeContent::Binary => panic!("Hero file was merged as binary"),
+    }
+    let conflicts: Vec<_> = bundle
+        .report
+        .conflicts
+        .iter()
+        .map(|conflict| (conflict.mods.join(", "), conflict.resolution))
+        .collect();
+    assert_eq!(
+        conflicts,
+        vec![
+            ("a, b".to_string(), Resolution::User),
+            ("b, c".to_string(), Resolution::User),
+        ]
+    );
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn asset_only_mods_are_classified() {
+    let (game, first, _) = fixture("ddmb_library_classify");
+    let reskin = game.parent().unwrap().join("reskin");
+    let sprite = "heroes/crusader/anim/crusader.sprite.idle.png";
+    std::fs::create_dir_all(reskin.join("heroes/crusader/anim")).unwrap();
+    std::fs::write(reskin.join(sprite), b"sprite").unwrap();
+    std::fs::write(reskin.join("heroes/crusader/anim/source.psd"), b"layers").unwrap();
+    let empty = game.parent().unwrap().join("empty");
+    std::fs::create_dir_all(&empty).unwrap();
+
+    let bundle = Bundle::builder()
+        .game_root(&game)
+        .add_mod(&first)
+        .add_mod(&reskin)
+        .add_mod(&empty)
+        .run()
+        .unwrap();
+    let kinds: Vec<_> = bundle
+        .report
+        .contents
+        .iter()
+        .map(|summary| (summary.name.as_str(), summary.kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("first", ModKind::Data),
+            ("reskin", ModKind::AssetsOnly),
+            ("empty", ModKind::Empty)
+        ]
+    );
+    assert_eq!(
+        bundle.report.contents[1].unrecognized,
+        vec![PathBuf::from("heroes/crusader/anim/source.psd")]
+    );
+    assert!(bundle.data.contains_key(Path::new(sprite)));
+    assert!(bundle
+        .report
+        .render()
+        .contains("reskin - assets only: 1 assets, 1 unrecognized files"));
+    std::fs::remove_dir_all(game.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,87 @@
+//! Memory used while comparing the mod with the game, measured by the counting allocator.
+//!
+//! Kept apart from the other tests, since the allocator counts everything done by the test process.
+
+use darkest_dungeon_mod_bundler::bundler::diff_mod;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// System allocator which tracks the number of allocated bytes and its maximum.
+struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(current, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+#[test]
+fn mod_files_are_dropped_after_diffing() {
+    const FILES: usize = 40;
+    const LINES: usize = 100;
+    let root = std::env::temp_dir().join("ddmb_memory_streaming");
+    let _ = std::fs::remove_dir_all(&root);
+    let (game, the_mod) = (root.join("game"), root.join("mod"));
+    let text = |file: usize, changed: Option<usize>| -> String {
+        (0..LINES)
+            .map(|line| match changed {
+                Some(changed) if changed == line => format!("changed line {} of {}\n", line, file),
+                // Long lines, so that the changes to every line take much less memory than the line itself.
+                _ => format!(
+                    "original line {} of the file {}: {}\n",
+                    line,
+                    file,
+                    "-".repeat(200)
+                ),
+            })
+            .collect()
+    };
+    let mut game_size = 0;
+    for file in 0..FILES {
+        let path = format!("heroes/file_{}.txt", file);
+        let original = text(file, None);
+        game_size += original.len();
+        for (dir, content) in [(&game, original), (&the_mod, text(file, Some(file)))] {
+            std::fs::create_dir_all(dir.join("heroes")).unwrap();
+            std::fs::write(dir.join(&path), content).unwrap();
+        }
+    }
+
+    // Few threads, so that only a couple of mod files are in memory at once.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(2)
+        .build()
+        .unwrap();
+    let before = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let diff = pool.install(|| diff_mod(&game, &the_mod).unwrap());
+    let peak = PEAK.load(Ordering::SeqCst) - before;
+
+    assert_eq!(diff.len(), FILES);
+    // The game data is kept as a whole, while the mod is not.
+    assert!(
+        peak < game_size * 3 / 2,
+        "Peak memory usage is {} bytes, while the game data is {} bytes",
+        peak,
+        game_size
+    );
+    std::fs::remove_dir_all(&root).unwrap();
+}